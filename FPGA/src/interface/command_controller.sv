@@ -6,10 +6,14 @@ module command_controller #(
     input                       i_clk,
     input                       i_rx,
     input   [ROC_OUTPUTS-1:0]   i_roc_outputs,
+    input   [31:0]              i_tick_count,
+    input                       i_step_ack,
     output  [ROC_INPUTS-1:0]    o_roc_inputs,
     output  reg                 o_tx,
     output  [31:0]              o_roc_tps,
-    output  reg                 o_roc_en
+    output  reg                 o_roc_en,
+    output                      o_step_mode,
+    output                      o_step_req
 );
     /*---------------------------------
                 PARAMETERS
@@ -29,6 +33,8 @@ module command_controller #(
         CMD_LOAD_ROM            = 8'hC6, 
         CMD_DEBUG_LED           = 8'hC7,
         CMD_FAIL_ACK            = 8'hC8,
+        CMD_GET_TICKS           = 8'hC9,
+        CMD_STEP                = 8'hCA,
         EOC                     = 8'hA5;
 
     /*---------------------------------
@@ -98,6 +104,45 @@ module command_controller #(
 
     reg[23:0]       r_output_i  = 24'd0;
 
+    // Stepping hands the tick clock to the host, the mode is left again by
+    // the next CMD_SET_RTPS. Every step toggles r_step_req, the tick clock
+    // domain toggles i_step_ack back once it ran the tick.
+    reg             r_step_mode = 1'b0;
+    reg             r_step_req  = 1'b0;
+    reg[1:0]        r_step_ack  = 2'd0;
+    reg[31:0]       r_steps     = 32'd0;
+    assign          o_step_mode = r_step_mode;
+    assign          o_step_req  = r_step_req;
+
+    wire            step_pending;
+    assign          step_pending = r_step_req != r_step_ack[1];
+
+    always @(posedge i_clk) begin
+        r_step_ack              <= {r_step_ack[0], i_step_ack};
+    end
+
+    /*---------------------------------
+              TICK COUNTER SYNC
+    ---------------------------------*/
+    // i_tick_count is gray coded in the tick clock domain, so only one bit
+    // changes at a time and it can be passed through a plain synchronizer
+    reg[31:0]       r_ticks_meta;
+    reg[31:0]       r_ticks_gray;
+    reg[31:0]       r_ticks;
+
+    always @(posedge i_clk) begin
+        r_ticks_meta            <= i_tick_count;
+        r_ticks_gray            <= r_ticks_meta;
+    end
+
+    wire[31:0]      ticks_bin;
+    genvar g;
+    generate
+        for (g = 0; g < 32; g = g + 1) begin : gray_to_bin
+            assign  ticks_bin[g] = ^r_ticks_gray[31:g];
+        end
+    endgenerate
+
     always @(posedge i_clk) begin
         case (r_state)
             s_IDLE        : begin
@@ -128,7 +173,7 @@ module command_controller #(
             s_CMD_CHECK   : begin
                 if (r_cmd[BYTES_PER_COMMANDS-1] == EOC && 
                         r_cmd[0] >= CMD_RESET && 
-                        r_cmd[0] <= CMD_FAIL_ACK)
+                        r_cmd[0] <= CMD_STEP)
                     r_state             <= s_TX_WAIT;
                 else 
                     r_state             <= s_FAILSAFE;
@@ -164,6 +209,7 @@ module command_controller #(
                     end
                     CMD_SET_RTPS    : begin
                         r_tps           <= four_byte;
+                        r_step_mode     <= 1'b0;
                         r_state         <= s_END;
                     end 
                     CMD_LOAD_ROM    : begin
@@ -172,6 +218,39 @@ module command_controller #(
                     CMD_FAIL_ACK    : begin
                         r_state         <= s_END;
                     end
+                    CMD_GET_TICKS   : begin
+                        if (r_output_i == 24'd0) begin
+                            r_ticks     <= ticks_bin;
+                            r_output_i  <= 24'd1;
+                        end
+                        else if (r_output_i > 24'd4) begin
+                            r_state     <= s_END;
+                        end
+                        else begin
+                            r_tx_data   <= r_ticks[7:0];
+                            r_tx_start  <= 1'b1;
+                            r_ticks     <= r_ticks >> 8;
+                            r_output_i  <= r_output_i + 1;
+                            r_state     <= s_TX_WAIT;
+                        end
+                    end
+                    CMD_STEP        : begin
+                        if (r_output_i == 24'd0) begin
+                            r_step_mode <= 1'b1;
+                            r_steps     <= four_byte;
+                            r_output_i  <= 24'd1;
+                        end
+                        else if (step_pending) begin
+                            r_state     <= s_CMD_PROCESS;
+                        end
+                        else if (r_steps == 32'd0) begin
+                            r_state     <= s_END;
+                        end
+                        else begin
+                            r_step_req  <= ~r_step_req;
+                            r_steps     <= r_steps - 1;
+                        end
+                    end
                     default         : begin
                         r_state         <= s_FAILSAFE;
                     end
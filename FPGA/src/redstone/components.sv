@@ -103,3 +103,77 @@ module comp #(
 	end
 			
 endmodule
+
+// A part of a collapsed repeater chain: a repeater of `pulse` ticks followed
+// by a shift register for the remaining `t - pulse` ticks
+module delay #(
+	parameter 	pulse,
+	parameter 	t,
+	parameter 	state
+) (
+	input 		i_clk,
+	input 		i_in,
+	output 		o_out
+);
+
+	wire shaped;
+
+	repeater #(.t(pulse), .state(state), .lock_out(0), .lockable(0)) shaper (.i_clk(i_clk), .i_in(i_in), .i_lock(1'b0), .o_out(shaped));
+
+	generate
+		if (t == pulse) begin
+			assign o_out = shaped;
+		end
+
+		else if (t - pulse == 1) begin
+			reg line = state;
+
+			assign o_out = line;
+
+			always @(posedge i_clk) begin
+				line <= shaped;
+			end
+		end
+
+		else begin
+			reg [t-pulse-1:0] line = {t-pulse{state}};
+
+			assign o_out = line[t-pulse-1];
+
+			always @(posedge i_clk) begin
+				line <= {line[t-pulse-2:0], shaped};
+			end
+		end
+	endgenerate
+
+endmodule
+
+// Pulses for two ticks, two ticks after `i_in` changed. `seen` is the initial
+// input. Changes are missed while a pulse is pending or on, and in the tick the
+// pulse ends unless the input ticks after the observer (`late`).
+module observer #(
+	parameter 	state,
+	parameter 	seen,
+	parameter 	late
+) (
+	input 		i_clk,
+	input 		i_in,
+	output 		o_out
+);
+
+	reg last = seen;
+	reg [1:0] phase = state ? 2'd3 : 2'd0;
+	reg blind = 0;
+
+	assign o_out = phase[1];
+
+	always @(posedge i_clk) begin
+		last <= i_in;
+		blind <= ~late & (phase == 2'd3);
+		if (phase != 2'd0)
+			phase <= phase + 2'd1;
+		else if (i_in != last && ~blind)
+			phase <= 2'd1;
+	end
+
+endmodule
@@ -13,8 +13,16 @@ module tps_clk_div #(
     wire[REF_CLK_SIZE-1:0]  first_xor;
     assign                  first_xor = r_sum ^ i_tps;    
 
+    // An all ones rate can't be produced by the accumulator (the msb would
+    // barely move), so it selects free running: one tick every two clocks
+    wire                    free_run;
+    assign                  free_run = &i_tps;
+
     always @(posedge i_clk) begin
-        o_clk               <= r_sum[REF_CLK_SIZE-1];
+        if (free_run)
+            o_clk           <= ~o_clk;
+        else
+            o_clk           <= r_sum[REF_CLK_SIZE-1];
         r_sum               <= r_sum ^ r_carry ^ i_tps;
         r_carry             <= ((r_sum & i_tps) | (first_xor & r_carry)) << 1;
     end
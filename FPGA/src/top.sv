@@ -18,7 +18,11 @@ module top (
     wire[ROC_OUTPUTS-1:0]   roc_outputs;
 
     wire[31:0]              roc_tps;
+    reg[31:0]               roc_tick_count  = 32'd0;
+    reg[31:0]               roc_tick_gray   = 32'd0;
     wire                    roc_clk_en;
+    wire                    roc_step_mode;
+    wire                    roc_step_req;
 
     command_controller #(
         .ROC_INPUTS(ROC_INPUTS),
@@ -28,20 +32,45 @@ module top (
         .i_clk(i_clk),
         .i_rx(i_RX),
         .i_roc_outputs(roc_outputs),
+        .i_tick_count(roc_tick_gray),
+        .i_step_ack(roc_step_ack),
 
         .o_roc_inputs(roc_inputs),
         .o_tx(o_TX),
         .o_roc_tps(roc_tps),
-        .o_roc_en(roc_clk_en)
+        .o_roc_en(roc_clk_en),
+        .o_step_mode(roc_step_mode),
+        .o_step_req(roc_step_req)
     );
 
     /*---------------------------------
                 TPS DIVIDER
     ---------------------------------*/
-    wire                    roc_tps_clk;
+    reg                     roc_tps_clk     = 1'b0;
+    wire                    roc_div_clk;
     wire                    tick_clk;
 
-    assign o_tick = roc_tps_clk;
+    // Hybrid builds step the redstone from the host instead of the divider.
+    // Both sources are turned into enables in the tick_clk domain and the
+    // tick is a register, so switching between them can't glitch the clock.
+    reg[1:0]                roc_step_mode_sync  = 2'd0;
+    reg[2:0]                roc_step_sync       = 3'd0;
+    reg                     roc_div_last        = 1'b0;
+    wire                    roc_step_en;
+    wire                    roc_div_en;
+    wire                    roc_step_ack;
+
+    assign roc_step_en  = roc_step_sync[2] != roc_step_sync[1];
+    assign roc_div_en   = roc_div_clk & ~roc_div_last;
+    assign roc_step_ack = roc_step_sync[2];
+    assign o_tick       = roc_tps_clk;
+
+    always @(posedge tick_clk) begin
+        roc_step_mode_sync  <= {roc_step_mode_sync[0], roc_step_mode};
+        roc_step_sync       <= {roc_step_sync[1:0], roc_step_req};
+        roc_div_last        <= roc_div_clk;
+        roc_tps_clk         <= roc_step_mode_sync[1] ? roc_step_en : roc_div_en;
+    end
 
     tick_clk (
 		.refclk(i_clk),
@@ -55,9 +84,18 @@ module top (
         .i_tps(roc_tps),
         .i_en(roc_clk_en),
 
-        .o_clk(roc_tps_clk)
+        .o_clk(roc_div_clk)
     );
 
+    /*---------------------------------
+               TICK COUNTER
+    ---------------------------------*/
+    // Counts redstone ticks so the host can measure the achieved rtps
+    always @(posedge roc_tps_clk) begin
+        roc_tick_count          <= roc_tick_count + 1;
+        roc_tick_gray           <= (roc_tick_count + 1) ^ ((roc_tick_count + 1) >> 1);
+    end
+
     /*---------------------------------
            REDSTONE IMPLEMENTATION
     ---------------------------------*/
@@ -1,11 +1,43 @@
 // This build script is used to move all fpga related files to the release folder
+// and to list the recorded simulation tests for tests/test.rs
 
 use std::{env, fs, path::PathBuf};
 use serde_json::{from_str, Value};
 
 const COPY_DIR: &'static str = "FPGA/";
+const SIM_TESTS_DIR: &'static str = "tests/roc/sim_tests";
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", COPY_DIR);
+    println!("cargo:rerun-if-changed={}", SIM_TESTS_DIR);
+
+    copy_fpga_files();
+    write_sim_tests();
+}
+
+/// Writes a `sim_tests!` invocation with every case directory, so recordings
+/// run as tests without being listed by hand
+fn write_sim_tests() {
+    let mut cases: Vec<String> = fs::read_dir(SIM_TESTS_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    cases.sort();
+
+    let mut invocation = String::from("sim_tests! {\n");
+    for case in cases {
+        invocation += &format!("    {},\n", case);
+    }
+    invocation += "}\n";
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("sim_tests.rs");
+    fs::write(out, invocation).unwrap();
+}
+
+fn copy_fpga_files() {
     // Read the release_manifest.json file
     let manifest_file = fs::read_to_string("FPGA/release_manifest.json").unwrap();
     let manifest_json: Value = from_str(&manifest_file).expect("Bad JSON");
@@ -0,0 +1,39 @@
+//! Build worker for FPGA synthesis, see `mchprs_backend::fpga::worker`.
+//!
+//! Usage: roc-worker [--listen ADDR] [--workdir DIR] [--quartus BIN_DIR] [--mock]
+
+use mchprs_backend::fpga::worker::{Toolchain, Worker};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn main() {
+    let mut listen = "0.0.0.0:7878".to_string();
+    let mut workdir = PathBuf::from("roc-worker");
+    let mut quartus = PathBuf::from(r"C:\intelFPGA_lite\23.1std\quartus\bin64");
+    let mut mock = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = args.next().expect("--listen needs an address"),
+            "--workdir" => workdir = args.next().expect("--workdir needs a directory").into(),
+            "--quartus" => quartus = args.next().expect("--quartus needs a directory").into(),
+            "--mock" => mock = true,
+            _ => {
+                eprintln!("Unrecognized option: {}", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let toolchain = if mock {
+        Toolchain::Mock
+    } else {
+        Toolchain::Quartus { bin_dir: quartus }
+    };
+
+    let listener = TcpListener::bind(&listen).expect("could not bind listen address");
+    println!("roc-worker listening on {}", listener.local_addr().unwrap());
+    Arc::new(Worker::new(toolchain, workdir)).serve(listener);
+}
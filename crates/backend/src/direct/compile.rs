@@ -1,16 +1,16 @@
-use mchprs_redpiler::compile_graph::{CompileGraph, LinkType, NodeIdx};
-use mchprs_redpiler::CompilerOptions;
+use mchprs_redpiler::compile_graph::{logic_sources, CompileGraph, LinkType, NodeIdx};
+use mchprs_redpiler::{logic, CompilerOptions};
 use itertools::Itertools;
 use mchprs_blocks::blocks::{Block, Instrument};
 use mchprs_blocks::BlockPos;
-use mchprs_world::TickEntry;
+use mchprs_world::{TickEntry, TickPriority};
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
 use tracing::trace;
 
-use super::node::{ForwardLink, Node, NodeId, NodeInput, NodeType, Nodes, NonMaxU8};
+use super::node::{DelayLine, DelayStage, ForwardLink, LogicCircuit, Node, NodeId, NodeInput, NodeType, Nodes, NonMaxU8};
 use super::DirectBackend;
 
 #[derive(Debug, Default)]
@@ -27,6 +27,8 @@ fn compile_node(
     nodes_len: usize,
     nodes_map: &FxHashMap<NodeIdx, usize>,
     noteblock_info: &mut Vec<(BlockPos, Instrument, u32)>,
+    delay_lines: &mut Vec<DelayLine>,
+    logic_circuits: &mut Vec<LogicCircuit>,
     stats: &mut FinalGraphStats,
 ) -> Node {
     let node = &graph[node_idx];
@@ -103,7 +105,8 @@ fn compile_node(
             mode,
             far_input,
             facing_diode,
-            states: None,
+            // Only the FPGA assembler uses the discrete output states
+            states: _,
         } => NodeType::Comparator {
             mode: *mode,
             far_input: far_input.map(|value| NonMaxU8::new(value).unwrap()),
@@ -121,9 +124,51 @@ fn compile_node(
             noteblock_info.push((node.block.unwrap().0, *instrument, *note));
             NodeType::NoteBlock { noteblock_id }
         }
-        _ => {panic!()}
+        CNodeType::Delay { stages } => {
+            let line = delay_lines.len().try_into().unwrap();
+            let last = stages.len() - 1;
+            let stages = stages
+                .iter()
+                .enumerate()
+                .map(|(i, stage)| DelayStage {
+                    delay: stage.delay,
+                    facing_diode: stage.facing_diode,
+                    powered: node.state.powered,
+                    pending_tick: false,
+                    block: stage
+                        .block
+                        .filter(|_| i != last)
+                        .map(|(pos, id)| (pos, Block::from_id(id))),
+                    changed: false,
+                })
+                .collect();
+            delay_lines.push(DelayLine::new(stages));
+            NodeType::Delay { line }
+        }
+        CNodeType::Logic { gates } => {
+            let circuit = logic_circuits.len().try_into().unwrap();
+            let stages = logic::pending_outputs(gates)
+                .iter()
+                .fold(0, |stages, &on| stages << 1 | on as u32);
+            let mut sources = [None; logic::MAX_INPUTS];
+            for (input, source) in logic_sources(graph, node_idx) {
+                let idx = nodes_map[&source];
+                assert!(idx < nodes_len);
+                // Safety: bounds checked
+                sources[input] = Some(unsafe { NodeId::from_index(idx) });
+            }
+            logic_circuits.push(LogicCircuit {
+                table: logic::truth_table(gates),
+                depth: logic::depth(gates) as u8,
+                stages,
+                sources,
+            });
+            NodeType::Logic { circuit }
+        }
+        CNodeType::Observer => NodeType::Observer,
     };
 
+    let observed = default_inputs.ss_counts[1..].iter().any(|&count| count > 0);
     Node {
         ty,
         default_inputs,
@@ -132,9 +177,11 @@ fn compile_node(
         powered: node.state.powered,
         output_power: node.state.output_strength,
         locked: node.state.repeater_locked,
+        observed,
         pending_tick: false,
         changed: false,
-        is_io: node.is_input || node.is_output,
+        // Probes are flushed with the inputs and outputs to stay observable
+        is_io: node.is_input || node.is_output || node.annotations.probe,
     }
 }
 
@@ -162,6 +209,8 @@ pub fn compile(
                 nodes_len,
                 &nodes_map,
                 &mut backend.noteblock_info,
+                &mut backend.delay_lines,
+                &mut backend.logic_circuits,
                 &mut stats,
             )
         })
@@ -174,6 +223,7 @@ pub fn compile(
         .map(|node| node.block.map(|(pos, id)| (pos, Block::from_id(id))))
         .collect();
     backend.nodes = Nodes::new(nodes);
+    backend.annotations = graph.node_weights().map(|node| node.annotations.clone()).collect();
 
     // Create a mapping from block pos to backend NodeId
     for i in 0..backend.blocks.len() {
@@ -192,6 +242,20 @@ pub fn compile(
         }
     }
 
+    // Logic nodes shift on their own until settled, like the register on the FPGA
+    for i in 0..nodes_len {
+        let node_id = backend.nodes.get(i);
+        if let NodeType::Logic { circuit } = backend.nodes[node_id].ty {
+            let circuit = backend.logic_circuits[circuit as usize];
+            let settled = circuit.is_settled(circuit.inputs(&backend.nodes));
+            let node = &mut backend.nodes[node_id];
+            if !node.pending_tick && !settled {
+                backend.scheduler.schedule_tick(node_id, 1, TickPriority::Normal);
+                node.pending_tick = true;
+            }
+        }
+    }
+
     // Dot file output
     if options.export_dot_graph {
         std::fs::write("backend_graph.dot", format!("{}", backend)).unwrap();
@@ -8,7 +8,8 @@ mod update;
 use crate::fpga::compiler::DeviceConfig;
 
 use super::JITBackend;
-use mchprs_redpiler::compile_graph::CompileGraph;
+use itertools::Itertools;
+use mchprs_redpiler::compile_graph::{Annotations, CompileGraph};
 use mchprs_redpiler::{block_powered_mut, CompilerOptions};
 use mchprs_blocks::block_entities::BlockEntity;
 use mchprs_blocks::blocks::{Block, ComparatorMode, Instrument};
@@ -16,7 +17,7 @@ use mchprs_blocks::BlockPos;
 use mchprs_redstone::{bool_to_ss, noteblock};
 use mchprs_world::World;
 use mchprs_world::{TickEntry, TickPriority};
-use node::{Node, NodeId, NodeType, Nodes};
+use node::{DelayLine, LogicCircuit, Node, NodeId, NodeType, Nodes};
 use rustc_hash::FxHashMap;
 use std::{fmt, mem};
 use tracing::{debug, warn};
@@ -36,6 +37,8 @@ impl Queues {
 struct TickScheduler {
     queues_deque: [Queues; Self::NUM_QUEUES],
     pos: usize,
+    /// Ticks run since the compile
+    tick: u64,
 }
 
 impl TickScheduler {
@@ -72,6 +75,7 @@ impl TickScheduler {
 
     fn queues_this_tick(&mut self) -> Queues {
         self.pos = (self.pos + 1) % Self::NUM_QUEUES;
+        self.tick += 1;
         mem::take(&mut self.queues_deque[self.pos])
     }
 
@@ -115,6 +119,12 @@ pub struct DirectBackend {
     scheduler: TickScheduler,
     events: Vec<Event>,
     noteblock_info: Vec<(BlockPos, Instrument, u32)>,
+    /// Names, buses and flags from signs, by node
+    annotations: Vec<Annotations>,
+    /// State of the `Delay` nodes, by line
+    delay_lines: Vec<DelayLine>,
+    /// State of the `Logic` nodes, by circuit
+    logic_circuits: Vec<LogicCircuit>,
 }
 
 impl DirectBackend {
@@ -160,12 +170,69 @@ impl DirectBackend {
                 &mut self.scheduler,
                 &mut self.events,
                 &mut self.nodes,
+                &mut self.delay_lines,
+                &mut self.logic_circuits,
                 update,
             );
         }
     }
 }
 
+impl DirectBackend {
+    /// Drives a synthetic lever standing in for a signal from the FPGA
+    pub(crate) fn set_boundary_input(&mut self, node_idx: usize, powered: bool) {
+        let node_id = self.nodes.get(node_idx);
+        if self.nodes[node_id].powered != powered {
+            self.set_node(node_id, powered, bool_to_ss(powered));
+        }
+    }
+
+    pub(crate) fn is_powered(&self, node_idx: usize) -> bool {
+        self.nodes[self.nodes.get(node_idx)].powered
+    }
+
+    pub(crate) fn has_node_at(&self, pos: BlockPos) -> bool {
+        self.pos_map.contains_key(&pos)
+    }
+
+    /// Puts the lever, button or pressure plate at `pos` in the given state.
+    /// Returns false if there is no such input there. Buttons stay pressed
+    /// until they are released the same way.
+    pub fn set_input(&mut self, pos: BlockPos, powered: bool) -> bool {
+        let Some(&node_id) = self.pos_map.get(&pos) else {
+            return false;
+        };
+        let node = &self.nodes[node_id];
+        if !matches!(node.ty, NodeType::Lever | NodeType::Button | NodeType::PressurePlate) {
+            return false;
+        }
+        if node.powered != powered {
+            self.set_node(node_id, powered, bool_to_ss(powered));
+        }
+        true
+    }
+
+    /// Whether the lamp or trapdoor at `pos` is receiving power. Unlike the block
+    /// itself this ignores the lamp's off delay, matching an FPGA output.
+    pub fn output_driven(&self, pos: BlockPos) -> Option<bool> {
+        let node = &self.nodes[*self.pos_map.get(&pos)?];
+        match node.ty {
+            NodeType::Lamp | NodeType::Trapdoor => Some(get_bool_input(node)),
+            _ => None,
+        }
+    }
+
+    /// The signal strength a lamp or trapdoor at `pos` receives, or the one
+    /// any other node there outputs
+    pub fn signal_strength(&self, pos: BlockPos) -> Option<u8> {
+        let node = &self.nodes[*self.pos_map.get(&pos)?];
+        Some(match node.ty {
+            NodeType::Lamp | NodeType::Trapdoor => last_index_positive(&node.default_inputs.ss_counts) as u8,
+            _ => node.output_power,
+        })
+    }
+}
+
 impl JITBackend for DirectBackend {
     fn inspect(&mut self, pos: BlockPos) {
         let Some(node_id) = self.pos_map.get(&pos) else {
@@ -173,7 +240,15 @@ impl JITBackend for DirectBackend {
             return;
         };
 
-        debug!("Node {:?}: {:#?}", node_id, self.nodes[*node_id]);
+        match self.annotations.get(node_id.index()).filter(|a| **a != Annotations::default()) {
+            Some(annotations) => debug!("Node {:?} {:?}: {:#?}", node_id, annotations, self.nodes[*node_id]),
+            None => debug!("Node {:?}: {:#?}", node_id, self.nodes[*node_id]),
+        }
+        match self.nodes[*node_id].ty {
+            NodeType::Delay { line } => debug!("{:#?}", self.delay_lines[line as usize]),
+            NodeType::Logic { circuit } => debug!("{:#?}", self.logic_circuits[circuit as usize]),
+            _ => {}
+        }
     }
 
     fn reset<W: World>(&mut self, world: &mut W, io_only: bool) {
@@ -196,10 +271,23 @@ impl JITBackend for DirectBackend {
                 world.set_block(pos, block);
             }
         }
+        // Flushes skip the repeaters inside collapsed chains when only IO is shown
+        if io_only {
+            for stage in self.delay_lines.iter().flat_map(|line| &line.stages) {
+                if let Some((pos, block)) = stage.block {
+                    world.set_block(pos, block);
+                }
+            }
+        }
 
         self.pos_map.clear();
+        self.annotations.clear();
         self.noteblock_info.clear();
         self.events.clear();
+        // Pulses still inside a collapsed chain or merged torches are lost. The inner torches
+        // of merged torches have no block to flush, they keep the state they were compiled in.
+        self.delay_lines.clear();
+        self.logic_circuits.clear();
     }
 
     fn on_use_block(&mut self, pos: BlockPos) {
@@ -274,6 +362,17 @@ impl JITBackend for DirectBackend {
             }
             node.changed = false;
         }
+        for stage in self.delay_lines.iter_mut().flat_map(|line| &mut line.stages) {
+            if stage.changed && !io_only {
+                if let Some((pos, block)) = &mut stage.block {
+                    if let Some(powered) = block_powered_mut(block) {
+                        *powered = stage.powered;
+                    }
+                    world.set_block(*pos, *block);
+                }
+            }
+            stage.changed = false;
+        }
     }
 
     fn compile(
@@ -292,8 +391,8 @@ impl JITBackend for DirectBackend {
         self.scheduler.has_pending_ticks()
     }
 
-    fn set_rtps(&mut self, _rtps: u32) { }
-    fn run(&mut self) { }
+    fn set_rtps(&mut self, _rtps: Option<u32>) { }
+    fn run(&mut self) -> Result<(), String> { Ok(()) }
     fn stop(&mut self) { }
 }
 
@@ -309,6 +408,17 @@ fn set_node_locked(node: &mut Node, locked: bool) {
     node.changed = true;
 }
 
+/// Priority of a repeater's tick to turn `on` or off
+fn repeater_priority(facing_diode: bool, on: bool) -> TickPriority {
+    if facing_diode {
+        TickPriority::Highest
+    } else if !on {
+        TickPriority::Higher
+    } else {
+        TickPriority::High
+    }
+}
+
 fn schedule_tick(
     scheduler: &mut TickScheduler,
     node_id: NodeId,
@@ -388,6 +498,15 @@ impl fmt::Display for DirectBackend {
                 NodeType::Wire => format!("Wire"),
                 NodeType::Constant => format!("Constant({})", node.output_power),
                 NodeType::NoteBlock { .. } => format!("NoteBlock"),
+                NodeType::Delay { line } => {
+                    let stages = &self.delay_lines[line as usize].stages;
+                    format!("Delay({})", stages.iter().map(|stage| stage.delay).join(", "))
+                }
+                NodeType::Logic { circuit } => {
+                    let circuit = &self.logic_circuits[circuit as usize];
+                    format!("Logic({:#010x}, depth {})", circuit.table, circuit.depth)
+                }
+                NodeType::Observer => format!("Observer"),
             };
             let pos = if let Some((pos, _)) = self.blocks[id] {
                 format!("{}, {}, {}", pos.x, pos.y, pos.z)
@@ -1,4 +1,7 @@
-use mchprs_blocks::blocks::ComparatorMode;
+use mchprs_blocks::blocks::{Block, ComparatorMode};
+use mchprs_blocks::BlockPos;
+use mchprs_world::TickPriority;
+use mchprs_redpiler::logic;
 use smallvec::SmallVec;
 use std::num::NonZeroU8;
 use std::ops::{Index, IndexMut};
@@ -128,6 +131,105 @@ pub enum NodeType {
     NoteBlock {
         noteblock_id: u16,
     },
+    /// A collapsed repeater chain, its state is in `DirectBackend::delay_lines`
+    Delay {
+        line: u32,
+    },
+    /// Merged torches, their state is in `DirectBackend::logic_circuits`
+    Logic {
+        circuit: u32,
+    },
+    Observer,
+}
+
+/// State of a [`NodeType::Delay`]. Every repeater of the chain is replayed with the ticks
+/// it would schedule, so the node behaves exactly like the chain did. The last repeater is
+/// the node's own block, the others are flushed from their stage.
+#[derive(Debug, Clone, Default)]
+pub struct DelayLine {
+    pub stages: Vec<DelayStage>,
+    /// Ticks scheduled for the stages as their priority and the stage, in the order they were
+    /// scheduled. Slot `tick % len` holds the ticks due at `tick`, no stage schedules further
+    /// ahead than the longest delay.
+    slots: Vec<Vec<(TickPriority, usize)>>,
+}
+
+impl DelayLine {
+    pub fn new(stages: Vec<DelayStage>) -> DelayLine {
+        let len = stages.iter().map(|stage| stage.delay as usize).max().unwrap_or(0) + 1;
+        DelayLine { stages, slots: vec![Vec::new(); len] }
+    }
+
+    pub fn schedule(&mut self, at: u64, priority: TickPriority, stage: usize) {
+        let len = self.slots.len() as u64;
+        self.slots[(at % len) as usize].push((priority, stage));
+    }
+
+    /// Removes the tick due `now` the scheduler runs first: the highest priority, then the
+    /// one scheduled first
+    pub fn take_due(&mut self, now: u64) -> Option<usize> {
+        let len = self.slots.len() as u64;
+        let slot = &mut self.slots[(now % len) as usize];
+        let (i, _) = slot.iter().enumerate().min_by_key(|(_, (priority, _))| *priority)?;
+        Some(slot.remove(i).1)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DelayStage {
+    pub delay: u8,
+    pub facing_diode: bool,
+    pub powered: bool,
+    pub pending_tick: bool,
+    /// The repeater in the world, `None` for the last stage whose block is the node's
+    pub block: Option<(BlockPos, Block)>,
+    pub changed: bool,
+}
+
+/// State of a [`NodeType::Logic`]. Like on the FPGA, the function of the inputs is shifted
+/// through a register as deep as the torches were, the node outputs its last stage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogicCircuit {
+    /// Output for every state of the inputs, see [`logic::truth_table`]
+    pub table: u32,
+    pub depth: u8,
+    /// Bit `depth - 1` is the output, lower bits are on their way to it
+    pub stages: u32,
+    /// The node behind every input, unused inputs have none
+    pub sources: [Option<NodeId>; logic::MAX_INPUTS],
+}
+
+impl LogicCircuit {
+    fn mask(self) -> u32 {
+        u32::MAX >> (32 - self.depth as u32)
+    }
+
+    fn function(self, inputs: u8) -> bool {
+        self.table & 1 << inputs != 0
+    }
+
+    pub fn output(self) -> bool {
+        self.stages & 1 << (self.depth - 1) != 0
+    }
+
+    /// Moves every stage one tick closer to the output and takes in the function of `inputs`
+    pub fn shift(&mut self, inputs: u8) {
+        self.stages = (self.stages << 1 | self.function(inputs) as u32) & self.mask();
+    }
+
+    /// Whether every stage holds the function of `inputs`, so shifting changes nothing
+    pub fn is_settled(self, inputs: u8) -> bool {
+        self.stages == if self.function(inputs) { self.mask() } else { 0 }
+    }
+
+    /// The inputs that are on, bit `i` being input `i`. The sources are either off or at 15.
+    pub fn inputs(&self, nodes: &Nodes) -> u8 {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.is_some_and(|id| nodes[id].powered))
+            .fold(0, |bits, (i, _)| bits | 1 << i)
+    }
 }
 
 #[repr(align(16))]
@@ -161,6 +263,8 @@ pub struct Node {
     pub powered: bool,
     /// Only for repeaters
     pub locked: bool,
+    /// Only for observers, whether the observed node was on at the last update
+    pub observed: bool,
     pub output_power: u8,
     pub changed: bool,
     pub pending_tick: bool,
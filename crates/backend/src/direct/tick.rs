@@ -1,4 +1,5 @@
 use super::node::NodeId;
+use super::update::update_delay_stage;
 use super::*;
 
 impl DirectBackend {
@@ -60,7 +61,69 @@ impl DirectBackend {
                     self.set_node(node_id, false, 0);
                 }
             }
+            NodeType::Observer => {
+                if node.powered {
+                    self.set_node(node_id, false, 0);
+                } else {
+                    // Like in the world, observers facing this one schedule their ticks first
+                    self.set_node(node_id, true, 15);
+                    let node = &mut self.nodes[node_id];
+                    schedule_tick(&mut self.scheduler, node_id, node, 2, TickPriority::Normal);
+                }
+            }
+            NodeType::Delay { line } => {
+                // The scheduler runs ticks due now by priority, then in the order they were scheduled
+                let now = self.scheduler.tick;
+                let delay_line = &mut self.delay_lines[line as usize];
+                let Some(stage) = delay_line.take_due(now) else {
+                    return;
+                };
+                let should_be_powered = match stage {
+                    0 => get_bool_input(node),
+                    _ => delay_line.stages[stage - 1].powered,
+                };
+
+                let stage_ref = &mut delay_line.stages[stage];
+                stage_ref.pending_tick = false;
+                if stage_ref.powered && !should_be_powered {
+                    self.set_delay_stage(node_id, line, stage, false);
+                } else if !stage_ref.powered {
+                    if !should_be_powered {
+                        stage_ref.pending_tick = true;
+                        let delay = stage_ref.delay;
+                        delay_line.schedule(now + delay as u64, TickPriority::Higher, stage);
+                        self.scheduler.schedule_tick(node_id, delay as usize, TickPriority::Higher);
+                    }
+                    self.set_delay_stage(node_id, line, stage, true);
+                }
+            }
+            NodeType::Logic { circuit } => {
+                // Shifts every tick until the function of the inputs fills the register
+                let circuit = &mut self.logic_circuits[circuit as usize];
+                let inputs = circuit.inputs(&self.nodes);
+                circuit.shift(inputs);
+                if !circuit.is_settled(inputs) {
+                    schedule_tick(&mut self.scheduler, node_id, &mut self.nodes[node_id], 1, TickPriority::Normal);
+                }
+                let should_be_powered = circuit.output();
+                let node = &self.nodes[node_id];
+                if node.powered != should_be_powered {
+                    self.set_node(node_id, should_be_powered, bool_to_ss(should_be_powered));
+                }
+            }
             _ => {} //unreachable!("Node {:?} should not be ticked!", node.ty),
         }
     }
+
+    /// Sets a stage of a `Delay` node, the last one sets the node itself
+    fn set_delay_stage(&mut self, node_id: NodeId, line: u32, stage: usize, powered: bool) {
+        let delay_line = &mut self.delay_lines[line as usize];
+        delay_line.stages[stage].powered = powered;
+        delay_line.stages[stage].changed = true;
+        if stage + 1 == delay_line.stages.len() {
+            self.set_node(node_id, powered, bool_to_ss(powered));
+        } else {
+            update_delay_stage(&mut self.scheduler, delay_line, node_id, stage + 1, powered);
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use mchprs_world::TickPriority;
 
-use super::node::{NodeId, NodeType};
+use super::node::{DelayLine, LogicCircuit, NodeId, NodeType};
 use super::*;
 
 #[inline(always)]
@@ -8,6 +8,8 @@ pub(super) fn update_node(
     scheduler: &mut TickScheduler,
     events: &mut Vec<Event>,
     nodes: &mut Nodes,
+    delay_lines: &mut [DelayLine],
+    logic_circuits: &mut [LogicCircuit],
     node_id: NodeId,
 ) {
     let node = &mut nodes[node_id];
@@ -27,16 +29,23 @@ pub(super) fn update_node(
 
             let should_be_powered = get_bool_input(node);
             if should_be_powered != node.powered {
-                let priority = if facing_diode {
-                    TickPriority::Highest
-                } else if !should_be_powered {
-                    TickPriority::Higher
-                } else {
-                    TickPriority::High
-                };
+                let priority = repeater_priority(facing_diode, should_be_powered);
                 schedule_tick(scheduler, node_id, node, delay as usize, priority);
             }
         }
+        NodeType::Delay { line } => {
+            let should_be_powered = get_bool_input(node);
+            update_delay_stage(scheduler, &mut delay_lines[line as usize], node_id, 0, should_be_powered);
+        }
+        NodeType::Logic { circuit } => {
+            if node.pending_tick {
+                return;
+            }
+            let circuit = logic_circuits[circuit as usize];
+            if !circuit.is_settled(circuit.inputs(nodes)) {
+                schedule_tick(scheduler, node_id, &mut nodes[node_id], 1, TickPriority::Normal);
+            }
+        }
         NodeType::Torch => {
             if node.pending_tick {
                 return;
@@ -93,6 +102,17 @@ pub(super) fn update_node(
                 node.changed = true;
             }
         }
+        NodeType::Observer => {
+            // Observers only notice the observed node turning on or off
+            let observed = get_bool_input(node);
+            if observed == node.observed {
+                return;
+            }
+            node.observed = observed;
+            if !node.powered && !node.pending_tick {
+                schedule_tick(scheduler, node_id, node, 2, TickPriority::Normal);
+            }
+        }
         NodeType::NoteBlock { noteblock_id } => {
             let should_be_powered = get_bool_input(node);
             if node.powered != should_be_powered {
@@ -105,3 +125,22 @@ pub(super) fn update_node(
         _ => {} // unreachable!("Node {:?} should not be updated!", node.ty),
     }
 }
+
+/// Updates a stage of a `Delay` node like the repeater it replaces
+pub(super) fn update_delay_stage(
+    scheduler: &mut TickScheduler,
+    line: &mut DelayLine,
+    node_id: NodeId,
+    stage: usize,
+    should_be_powered: bool,
+) {
+    let stage_ref = &mut line.stages[stage];
+    if stage_ref.pending_tick || should_be_powered == stage_ref.powered {
+        return;
+    }
+    stage_ref.pending_tick = true;
+    let priority = repeater_priority(stage_ref.facing_diode, should_be_powered);
+    let delay = stage_ref.delay;
+    line.schedule(scheduler.tick + delay as u64, priority, stage);
+    scheduler.schedule_tick(node_id, delay as usize, priority);
+}
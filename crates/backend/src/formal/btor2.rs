@@ -0,0 +1,447 @@
+//! A small BTOR2 builder. Nodes are bit vectors of at most 64 bits and are
+//! only ever added after their operands, so a model can be evaluated in order.
+
+use std::fmt::Write;
+
+/// Index of a node in a [`Btor2`] model
+pub type Nid = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Input,
+    State,
+    Const(u64),
+    Not,
+    Redor,
+    Slice(u32, u32),
+    Sext(u32),
+    Concat,
+    And,
+    Or,
+    Xor,
+    Sub,
+    Eq,
+    Ult,
+    Ugt,
+    Ite,
+}
+
+struct Node {
+    op: Op,
+    width: u32,
+    args: Vec<Nid>,
+    symbol: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Btor2 {
+    nodes: Vec<Node>,
+    inits: Vec<(Nid, Nid)>,
+    nexts: Vec<(Nid, Nid)>,
+    bads: Vec<(Nid, String)>,
+    outputs: Vec<(Nid, String)>,
+}
+
+fn mask(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+impl Btor2 {
+    fn add(&mut self, op: Op, width: u32, args: Vec<Nid>, symbol: Option<String>) -> Nid {
+        debug_assert!(args.iter().all(|&a| a < self.nodes.len()));
+        self.nodes.push(Node { op, width, args, symbol });
+        self.nodes.len() - 1
+    }
+
+    pub fn width(&self, nid: Nid) -> u32 {
+        self.nodes[nid].width
+    }
+
+    pub fn input(&mut self, width: u32, symbol: &str) -> Nid {
+        self.add(Op::Input, width, vec![], Some(symbol.to_string()))
+    }
+
+    /// A state starting at `init`
+    pub fn state(&mut self, width: u32, init: u64, symbol: &str) -> Nid {
+        let state = self.add(Op::State, width, vec![], Some(symbol.to_string()));
+        let init = self.constant(width, init);
+        self.inits.push((state, init));
+        state
+    }
+
+    pub fn next(&mut self, state: Nid, value: Nid) {
+        debug_assert_eq!(self.width(state), self.width(value));
+        self.nexts.push((state, value));
+    }
+
+    /// A named property that must never hold
+    pub fn bad(&mut self, cond: Nid, name: &str) {
+        self.bads.push((cond, name.to_string()));
+    }
+
+    /// A named value printed in witnesses
+    pub fn output(&mut self, nid: Nid, name: &str) {
+        self.outputs.push((nid, name.to_string()));
+    }
+
+    pub fn constant(&mut self, width: u32, value: u64) -> Nid {
+        self.add(Op::Const(value & mask(width)), width, vec![], None)
+    }
+
+    pub fn not(&mut self, a: Nid) -> Nid {
+        self.add(Op::Not, self.width(a), vec![a], None)
+    }
+
+    /// 1 if any bit of `a` is set
+    pub fn redor(&mut self, a: Nid) -> Nid {
+        self.add(Op::Redor, 1, vec![a], None)
+    }
+
+    /// Bits `upper` down to `lower` of `a`
+    pub fn slice(&mut self, a: Nid, upper: u32, lower: u32) -> Nid {
+        self.add(Op::Slice(upper, lower), upper - lower + 1, vec![a], None)
+    }
+
+    /// `a` sign extended by `by` bits, which repeats a single bit
+    pub fn sext(&mut self, a: Nid, by: u32) -> Nid {
+        if by == 0 {
+            return a;
+        }
+        self.add(Op::Sext(by), self.width(a) + by, vec![a], None)
+    }
+
+    /// `high` followed by `low`
+    pub fn concat(&mut self, high: Nid, low: Nid) -> Nid {
+        self.add(Op::Concat, self.width(high) + self.width(low), vec![high, low], None)
+    }
+
+    fn binary(&mut self, op: Op, a: Nid, b: Nid) -> Nid {
+        debug_assert_eq!(self.width(a), self.width(b));
+        let width = match op {
+            Op::Eq | Op::Ult | Op::Ugt => 1,
+            _ => self.width(a),
+        };
+        self.add(op, width, vec![a, b], None)
+    }
+
+    pub fn and(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::And, a, b)
+    }
+
+    pub fn or(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Or, a, b)
+    }
+
+    pub fn xor(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Xor, a, b)
+    }
+
+    pub fn sub(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Sub, a, b)
+    }
+
+    pub fn eq(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Eq, a, b)
+    }
+
+    pub fn ult(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Ult, a, b)
+    }
+
+    pub fn ugt(&mut self, a: Nid, b: Nid) -> Nid {
+        self.binary(Op::Ugt, a, b)
+    }
+
+    pub fn ite(&mut self, cond: Nid, then: Nid, other: Nid) -> Nid {
+        debug_assert_eq!(self.width(cond), 1);
+        debug_assert_eq!(self.width(then), self.width(other));
+        self.add(Op::Ite, self.width(then), vec![cond, then, other], None)
+    }
+
+    /// The model in the text format read by btormc, pono and friends
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let mut widths: Vec<u32> = self.nodes.iter().map(|n| n.width).collect();
+        widths.sort_unstable();
+        widths.dedup();
+        let sort = |width: u32| widths.binary_search(&width).unwrap() + 1;
+        for (i, width) in widths.iter().enumerate() {
+            writeln!(text, "{} sort bitvec {}", i + 1, width).unwrap();
+        }
+
+        let base = widths.len() + 1;
+        let id = |nid: Nid| nid + base;
+        for (nid, node) in self.nodes.iter().enumerate() {
+            let args = node.args.iter().map(|&a| id(a).to_string()).collect::<Vec<_>>().join(" ");
+            let line = match node.op {
+                Op::Input => format!("input {}", sort(node.width)),
+                Op::State => format!("state {}", sort(node.width)),
+                Op::Const(value) => format!("constd {} {}", sort(node.width), value),
+                Op::Slice(upper, lower) => format!("slice {} {} {} {}", sort(node.width), args, upper, lower),
+                Op::Sext(by) => format!("sext {} {} {}", sort(node.width), args, by),
+                op => {
+                    let name = match op {
+                        Op::Not => "not",
+                        Op::Redor => "redor",
+                        Op::Concat => "concat",
+                        Op::And => "and",
+                        Op::Or => "or",
+                        Op::Xor => "xor",
+                        Op::Sub => "sub",
+                        Op::Eq => "eq",
+                        Op::Ult => "ult",
+                        Op::Ugt => "ugt",
+                        Op::Ite => "ite",
+                        _ => unreachable!(),
+                    };
+                    format!("{} {} {}", name, sort(node.width), args)
+                }
+            };
+            match &node.symbol {
+                Some(symbol) => writeln!(text, "{} {} {}", id(nid), line, symbol).unwrap(),
+                None => writeln!(text, "{} {}", id(nid), line).unwrap(),
+            }
+        }
+
+        let mut next = base + self.nodes.len();
+        for (state, init) in &self.inits {
+            writeln!(text, "{} init {} {} {}", next, sort(self.width(*state)), id(*state), id(*init)).unwrap();
+            next += 1;
+        }
+        for (state, value) in &self.nexts {
+            writeln!(text, "{} next {} {} {}", next, sort(self.width(*state)), id(*state), id(*value)).unwrap();
+            next += 1;
+        }
+        for (nid, name) in &self.outputs {
+            writeln!(text, "{} output {} {}", next, id(*nid), name).unwrap();
+            next += 1;
+        }
+        for (cond, name) in &self.bads {
+            writeln!(text, "{} bad {} {}", next, id(*cond), name).unwrap();
+            next += 1;
+        }
+        text
+    }
+}
+
+/// Steps a [`Btor2`] model without a model checker. Inputs keep their value
+/// until they are set again.
+pub struct Simulation<'a> {
+    model: &'a Btor2,
+    values: Vec<u64>,
+    inputs: Vec<u64>,
+    states: Vec<u64>,
+    /// Inputs or states changed since the last `eval`
+    dirty: bool,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(model: &'a Btor2) -> Simulation<'a> {
+        let len = model.nodes.len();
+        let mut states = vec![0; len];
+        for (state, init) in &model.inits {
+            let Op::Const(value) = model.nodes[*init].op else {
+                unreachable!("states start at constants");
+            };
+            states[*state] = value;
+        }
+        Simulation { model, values: vec![0; len], inputs: vec![0; len], states, dirty: true }
+    }
+
+    pub fn set(&mut self, input: Nid, value: u64) {
+        debug_assert_eq!(self.model.nodes[input].op, Op::Input);
+        self.inputs[input] = value & mask(self.model.width(input));
+        self.dirty = true;
+    }
+
+    /// Value of `nid` in the current step
+    pub fn get(&mut self, nid: Nid) -> u64 {
+        self.eval();
+        self.values[nid]
+    }
+
+    /// Moves every state to its next value
+    pub fn step(&mut self) {
+        self.eval();
+        for (state, value) in &self.model.nexts {
+            self.states[*state] = self.values[*value];
+        }
+        self.dirty = true;
+    }
+
+    /// Names of the bad properties that currently hold
+    pub fn bad(&mut self) -> Vec<&'a str> {
+        self.eval();
+        self.model.bads.iter().filter(|(cond, _)| self.values[*cond] != 0).map(|(_, name)| name.as_str()).collect()
+    }
+
+    fn eval(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        for (nid, node) in self.model.nodes.iter().enumerate() {
+            let arg = |i: usize| self.values[node.args[i]];
+            let value = match node.op {
+                Op::Input => self.inputs[nid],
+                Op::State => self.states[nid],
+                Op::Const(value) => value,
+                Op::Not => !arg(0),
+                Op::Redor => (arg(0) != 0) as u64,
+                Op::Slice(_, lower) => arg(0) >> lower,
+                Op::Sext(_) => {
+                    let width = self.model.width(node.args[0]);
+                    if arg(0) >> (width - 1) & 1 == 1 {
+                        arg(0) | !mask(width)
+                    } else {
+                        arg(0)
+                    }
+                }
+                Op::Concat => arg(0) << self.model.width(node.args[1]) | arg(1),
+                Op::And => arg(0) & arg(1),
+                Op::Or => arg(0) | arg(1),
+                Op::Xor => arg(0) ^ arg(1),
+                Op::Sub => arg(0).wrapping_sub(arg(1)),
+                Op::Eq => (arg(0) == arg(1)) as u64,
+                Op::Ult => (arg(0) < arg(1)) as u64,
+                Op::Ugt => (arg(0) > arg(1)) as u64,
+                Op::Ite => {
+                    if arg(0) != 0 {
+                        arg(1)
+                    } else {
+                        arg(2)
+                    }
+                }
+            };
+            self.values[nid] = value & mask(node.width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustc_hash::FxHashMap;
+
+    /// Reads back the text written by `to_text`
+    fn from_text(text: &str) -> Btor2 {
+        let mut btor = Btor2::default();
+        let mut sorts = FxHashMap::default();
+        let mut nids = FxHashMap::default();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split(' ').collect();
+            let num = |i: usize| words[i].parse::<u64>().unwrap();
+            let nid = |i: usize| nids[&words[i]];
+            match words[1] {
+                "sort" => {
+                    sorts.insert(words[0], num(3) as u32);
+                    continue;
+                }
+                "init" => btor.inits.push((nid(3), nid(4))),
+                "next" => btor.nexts.push((nid(3), nid(4))),
+                "output" => btor.outputs.push((nid(2), words[3].to_string())),
+                "bad" => btor.bads.push((nid(2), words[3].to_string())),
+                name => {
+                    let width = sorts[&words[2]];
+                    let (op, args, symbol) = match name {
+                        "input" => (Op::Input, 3, words.get(3)),
+                        "state" => (Op::State, 3, words.get(3)),
+                        "constd" => (Op::Const(num(3)), 3, None),
+                        "slice" => (Op::Slice(num(4) as u32, num(5) as u32), 4, None),
+                        "sext" => (Op::Sext(num(4) as u32), 4, None),
+                        _ => {
+                            let op = match name {
+                                "not" => Op::Not,
+                                "redor" => Op::Redor,
+                                "concat" => Op::Concat,
+                                "and" => Op::And,
+                                "or" => Op::Or,
+                                "xor" => Op::Xor,
+                                "sub" => Op::Sub,
+                                "eq" => Op::Eq,
+                                "ult" => Op::Ult,
+                                "ugt" => Op::Ugt,
+                                "ite" => Op::Ite,
+                                _ => panic!("unknown line {}", line),
+                            };
+                            (op, words.len(), None)
+                        }
+                    };
+                    let args = (3..args).map(nid).collect();
+                    let added = btor.add(op, width, args, symbol.map(|s| s.to_string()));
+                    nids.insert(words[0], added);
+                }
+            }
+        }
+        btor
+    }
+
+    /// A down counter that is reloaded from `load` at 0 and uses every operator
+    fn counter() -> (Btor2, Nid, Nid) {
+        let mut btor = Btor2::default();
+        let load = btor.input(4, "load");
+        let count = btor.state(4, 3, "count");
+        let one = btor.constant(4, 1);
+        let zero = btor.constant(4, 0);
+        let is_zero = btor.eq(count, zero);
+        let below = btor.sub(count, one);
+        let next = btor.ite(is_zero, load, below);
+        btor.next(count, next);
+
+        let low = btor.slice(count, 1, 0);
+        let high = btor.slice(count, 3, 2);
+        let swapped = btor.concat(low, high);
+        let flipped = btor.not(swapped);
+        let mixed = btor.xor(flipped, count);
+        let any = btor.redor(mixed);
+        let wide = btor.sext(any, 3);
+        let masked = btor.and(wide, load);
+        let either = btor.or(masked, one);
+        let big = btor.ugt(either, one);
+        let small = btor.ult(count, one);
+        btor.output(either, "either");
+        btor.bad(big, "big");
+        btor.bad(small, "small");
+        (btor, load, count)
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let (btor, load, count) = counter();
+        let text = btor.to_text();
+        let read = from_text(&text);
+        assert_eq!(read.to_text(), text);
+
+        let mut sim = Simulation::new(&btor);
+        let mut read_sim = Simulation::new(&read);
+        for step in 0..20 {
+            sim.set(load, step % 7);
+            read_sim.set(load, step % 7);
+            assert_eq!(sim.get(count), read_sim.get(count), "step {}", step);
+            assert_eq!(sim.bad(), read_sim.bad(), "step {}", step);
+            sim.step();
+            read_sim.step();
+        }
+    }
+
+    #[test]
+    fn counts_down() {
+        let (btor, load, count) = counter();
+        let mut sim = Simulation::new(&btor);
+        sim.set(load, 2);
+        let counts: Vec<u64> = (0..6)
+            .map(|_| {
+                let count = sim.get(count);
+                sim.step();
+                count
+            })
+            .collect();
+        assert_eq!(counts, vec![3, 2, 1, 0, 2, 1]);
+        assert_eq!(sim.get(count), 0);
+        assert!(sim.bad().contains(&"small"));
+    }
+}
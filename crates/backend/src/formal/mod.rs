@@ -0,0 +1,706 @@
+//! Formal models of a compile graph for model checkers like btormc or pono.
+//!
+//! The graph is lowered through the [`Netlist`] of the FPGA build, so the model
+//! has its semantics: every step of the model is one redstone tick, inputs are
+//! free to change before any tick and outputs are read before it. A model only
+//! becomes interesting with properties, given in a JSON file:
+//!
+//! ```json
+//! {
+//!   "signals": { "a": "1,2,1", "b": "1,2,3", "x": "5,2,1", "y": "5,2,3" },
+//!   "properties": {
+//!     "exclusive": { "never": "x & y" },
+//!     "follows": { "always": "!a | x" }
+//!   }
+//! }
+//! ```
+//!
+//! Signals are block positions like in `test.json`, or names from `[name]`
+//! signs, and are on while their block has a signal strength above 0. Named
+//! components are signals without being listed. Expressions use `!`, `&`, `^`,
+//! `|` and parentheses. Each property becomes a named `bad` state of the model.
+//!
+//! [`Model::miter`] instead checks that two builds of the same circuit, e.g.
+//! with and without `--optimize`, can't be told apart by their outputs.
+
+mod btor2;
+
+pub use btor2::{Btor2, Nid, Simulation};
+
+use crate::fpga::netlist::{Cell, Netlist};
+use mchprs_blocks::BlockPos;
+use mchprs_redpiler::compile_graph::CompileGraph;
+use mchprs_redpiler::logic;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Widest state the [`Simulation`] can hold
+const MAX_WIDTH: u32 = 64;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PropertyDef {
+    Never(String),
+    Always(String),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PropertyFile {
+    #[serde(default)]
+    signals: BTreeMap<String, String>,
+    properties: BTreeMap<String, PropertyDef>,
+}
+
+impl PropertyFile {
+    pub fn load(path: &Path) -> Result<PropertyFile, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))
+    }
+}
+
+/// A BTOR2 model of one or two builds
+pub struct Model {
+    pub btor: Btor2,
+    /// Free inputs of levers, buttons and pressure plates, by position
+    inputs: FxHashMap<BlockPos, Nid>,
+    /// Signal strength, or on/off for outputs, of every block of the first build
+    probes: FxHashMap<BlockPos, Nid>,
+    /// Positions of the `[name]` signs of the first build
+    names: BTreeMap<String, BlockPos>,
+}
+
+impl Model {
+    pub fn new(graph: &CompileGraph) -> Result<Model, String> {
+        let mut model = Model {
+            btor: Btor2::default(),
+            inputs: FxHashMap::default(),
+            probes: FxHashMap::default(),
+            names: BTreeMap::new(),
+        };
+        for node in graph.node_weights() {
+            if let (Some(name), Some((pos, _))) = (&node.annotations.name, node.block) {
+                model.names.insert(name.clone(), pos);
+            }
+        }
+        model.probes = model.lower(&Netlist::new(graph), "")?;
+        Ok(model)
+    }
+
+    /// Two builds sharing their inputs, with a property per output that is bad
+    /// when the builds disagree on it
+    pub fn miter(a: &CompileGraph, b: &CompileGraph) -> Result<Model, String> {
+        let mut model = Model::new(a)?;
+        let other = model.lower(&Netlist::new(b), "b_")?;
+
+        let mut outputs: Vec<(BlockPos, Nid)> =
+            model.probes.iter().filter(|(_, &nid)| model.btor.width(nid) == 1).map(|(pos, nid)| (*pos, *nid)).collect();
+        outputs.sort_by_key(|(pos, _)| (pos.x, pos.y, pos.z));
+        for (pos, nid) in outputs {
+            let Some(&theirs) = other.get(&pos) else {
+                return Err(format!("the output at {} is missing from the second build", pos));
+            };
+            if model.btor.width(theirs) != 1 {
+                return Err(format!("the block at {} is an output of only one build", pos));
+            }
+            let differs = model.btor.xor(nid, theirs);
+            model.btor.bad(differs, &format!("differs_{}_{}_{}", pos.x, pos.y, pos.z));
+        }
+        Ok(model)
+    }
+
+    pub fn input(&self, pos: BlockPos) -> Option<Nid> {
+        self.inputs.get(&pos).copied()
+    }
+
+    /// The node of the block at `pos`. Outputs are 1 bit wide, everything else
+    /// is a signal strength.
+    pub fn probe(&self, pos: BlockPos) -> Option<Nid> {
+        self.probes.get(&pos).copied()
+    }
+
+    /// The position of the component named `name` by a sign
+    pub fn named(&self, name: &str) -> Option<BlockPos> {
+        self.names.get(name).copied()
+    }
+
+    pub fn add_properties(&mut self, file: &PropertyFile) -> Result<(), String> {
+        let mut positions = Vec::new();
+        for (name, signal) in &file.signals {
+            let pos = match BlockPos::from_str(signal) {
+                Ok(pos) => pos,
+                Err(_) => self.named(signal).ok_or_else(|| format!("{} is neither a block position nor a name", signal))?,
+            };
+            positions.push((name.clone(), pos));
+        }
+        for (name, pos) in &self.names {
+            if !file.signals.contains_key(name) {
+                positions.push((name.clone(), *pos));
+            }
+        }
+
+        let mut signals = FxHashMap::default();
+        for (name, pos) in positions {
+            let Some(nid) = self.probe(pos) else {
+                return Err(format!("{} at {} is not part of the build", name, pos));
+            };
+            let on = if self.btor.width(nid) == 1 { nid } else { self.btor.redor(nid) };
+            self.btor.output(on, &name);
+            signals.insert(name, on);
+        }
+
+        for (name, def) in &file.properties {
+            let (expr, never) = match def {
+                PropertyDef::Never(expr) => (expr, true),
+                PropertyDef::Always(expr) => (expr, false),
+            };
+            let mut parser = Parser { btor: &mut self.btor, signals: &signals, tokens: tokenize(expr)?, at: 0 };
+            let cond = parser.parse().map_err(|e| format!("property {}: {}", name, e))?;
+            let bad = if never { cond } else { self.btor.not(cond) };
+            self.btor.bad(bad, name);
+        }
+        Ok(())
+    }
+
+    /// Adds the cells of `netlist` and returns the probes of its blocks. Inputs
+    /// at the same position are shared with earlier netlists.
+    fn lower(&mut self, netlist: &Netlist, prefix: &str) -> Result<FxHashMap<BlockPos, Nid>, String> {
+        let cells = &netlist.cells;
+        // Every cell drives its last port
+        let mut drivers = FxHashMap::default();
+        for (i, cell) in cells.iter().enumerate() {
+            drivers.insert(cell.connections.last().unwrap()[0], i);
+        }
+
+        // Registers are known up front, so only combinational paths need an order
+        let mut states = FxHashMap::default();
+        // The shift registers after the repeater of a delay, in chunks the model can hold
+        let mut lines: FxHashMap<usize, Vec<Nid>> = FxHashMap::default();
+        for (i, cell) in cells.iter().enumerate() {
+            let state = match cell.ty {
+                "RS_TORCH" => self.btor.state(1, cell.param("INIT") as u64, &format!("{}{}", prefix, cell.name)),
+                "RS_COMPARATOR" => self.btor.state(4, cell.param("INIT") as u64, &format!("{}{}", prefix, cell.name)),
+                "RS_REPEATER" => {
+                    let delay = cell.param("DELAY").max(1);
+                    let init = if cell.param("INIT") == 1 { (1 << delay) - 1 } else { 0 };
+                    self.btor.state(delay, init, &format!("{}{}", prefix, cell.name))
+                }
+                "RS_DELAY" => {
+                    let (pulse, init) = (cell.param("PULSE"), cell.param("INIT") == 1);
+                    let mut left = cell.param("DELAY") - pulse;
+                    let mut chunks = Vec::new();
+                    while left > 0 {
+                        let width = left.min(MAX_WIDTH);
+                        let ones = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+                        let name = format!("{}{}_line{}", prefix, cell.name, chunks.len());
+                        chunks.push(self.btor.state(width, if init { ones } else { 0 }, &name));
+                        left -= width;
+                    }
+                    lines.insert(i, chunks);
+                    let ones = (1 << pulse) - 1;
+                    self.btor.state(pulse, if init { ones } else { 0 }, &format!("{}{}", prefix, cell.name))
+                }
+                "RS_LOGIC" => {
+                    let name = format!("{}{}", prefix, cell.name);
+                    self.btor.state(cell.param("DEPTH"), cell.param("INIT") as u64, &name)
+                }
+                "RS_OBSERVER" => {
+                    // The phase, whether it is blind and the last input, from the top bit
+                    let phase = if cell.param("INIT") == 1 { 3 } else { 0 };
+                    let init = (phase << 2 | cell.param("SEEN")) as u64;
+                    self.btor.state(4, init, &format!("{}{}", prefix, cell.name))
+                }
+                _ => continue,
+            };
+            states.insert(i, state);
+        }
+
+        let mut nets: FxHashMap<usize, Nid> = FxHashMap::default();
+        let mut probes = FxHashMap::default();
+        for i in combinational_order(cells, &drivers)? {
+            let cell = &cells[i];
+            let net = |port: usize| nets[&cell.connections[port][0]];
+            let y = match cell.ty {
+                "RS_INPUT" => {
+                    let input = match cell.pos {
+                        Some(pos) => *self.inputs.entry(pos).or_insert_with(|| self.btor.input(1, &format!("in_{}_{}_{}", pos.x, pos.y, pos.z))),
+                        None => self.btor.input(1, &format!("{}{}", prefix, cell.name)),
+                    };
+                    self.btor.sext(input, 3)
+                }
+                "RS_OUTPUT" => self.btor.redor(net(0)),
+                "RS_CONST" => self.btor.constant(4, cell.param("SS") as u64),
+                "RS_SUB" => {
+                    let a = net(0);
+                    let dist = self.btor.constant(4, cell.param("DIST") as u64);
+                    let zero = self.btor.constant(4, 0);
+                    let left = self.btor.sub(a, dist);
+                    let more = self.btor.ugt(a, dist);
+                    self.btor.ite(more, left, zero)
+                }
+                "RS_MAX" => {
+                    let (a, b) = (net(0), net(1));
+                    let more = self.btor.ugt(a, b);
+                    self.btor.ite(more, a, b)
+                }
+                "RS_TORCH" => self.btor.sext(states[&i], 3),
+                "RS_COMPARATOR" => states[&i],
+                "RS_REPEATER" => {
+                    let out = self.repeater_out(cell, states[&i], || net(1));
+                    self.btor.sext(out, 3)
+                }
+                "RS_DELAY" => {
+                    let last = *lines[&i].last().unwrap_or(&states[&i]);
+                    let width = self.btor.width(last);
+                    let out = self.btor.slice(last, width - 1, width - 1);
+                    self.btor.sext(out, 3)
+                }
+                "RS_LOGIC" => {
+                    let depth = cell.param("DEPTH");
+                    let out = self.btor.slice(states[&i], depth - 1, depth - 1);
+                    self.btor.sext(out, 3)
+                }
+                "RS_OBSERVER" => {
+                    let out = self.btor.slice(states[&i], 3, 3);
+                    self.btor.sext(out, 3)
+                }
+                ty => unreachable!("unknown cell {}", ty),
+            };
+            nets.insert(cell.connections.last().unwrap()[0], y);
+            if let Some(pos) = cell.pos {
+                probes.insert(pos, y);
+            }
+        }
+
+        for (&i, &state) in &states {
+            let cell = &cells[i];
+            let net = |port: usize| nets[&cell.connections[port][0]];
+            let next = match cell.ty {
+                "RS_TORCH" => {
+                    let powered = self.btor.redor(net(1));
+                    self.btor.not(powered)
+                }
+                "RS_COMPARATOR" => {
+                    let (a, b) = (net(1), net(2));
+                    let back = match cell.param("FAR") {
+                        far @ 0..=15 => {
+                            let far = self.btor.constant(4, far as u64);
+                            let full = self.btor.constant(4, 15);
+                            let weak = self.btor.ult(a, full);
+                            self.btor.ite(weak, far, a)
+                        }
+                        _ => a,
+                    };
+                    let zero = self.btor.constant(4, 0);
+                    let kept = if cell.param("SUBTRACT") == 1 { self.btor.sub(back, b) } else { back };
+                    let less = self.btor.ult(back, b);
+                    self.btor.ite(less, zero, kept)
+                }
+                "RS_DELAY" => {
+                    let pulse = self.btor.width(state);
+                    let mut shifted_in = self.btor.slice(state, pulse - 1, pulse - 1);
+                    for &chunk in &lines[&i] {
+                        let width = self.btor.width(chunk);
+                        let next = if width == 1 {
+                            shifted_in
+                        } else {
+                            let rest = self.btor.slice(chunk, width - 2, 0);
+                            self.btor.concat(rest, shifted_in)
+                        };
+                        self.btor.next(chunk, next);
+                        shifted_in = self.btor.slice(chunk, width - 1, width - 1);
+                    }
+                    let on = self.btor.redor(net(1));
+                    self.repeater_shift(state, on)
+                }
+                "RS_LOGIC" => {
+                    let depth = self.btor.width(state);
+                    let f = self.logic_function(cell, &nets);
+                    let rest = self.btor.slice(state, depth - 2, 0);
+                    self.btor.concat(rest, f)
+                }
+                "RS_OBSERVER" => self.observer_next(cell, state, net(1)),
+                _ => self.repeater_next(cell, state, net(1), net(2)),
+            };
+            self.btor.next(state, next);
+        }
+        Ok(probes)
+    }
+
+    /// The minimised `TABLE` of a `RS_LOGIC` over its input bits, so that the miter of a build
+    /// with logic nodes and one without checks the minimisation too
+    fn logic_function(&mut self, cell: &Cell, nets: &FxHashMap<usize, Nid>) -> Nid {
+        let inputs: Vec<Nid> = cell.connections[1]
+            .iter()
+            .map(|bit| match nets.get(bit) {
+                Some(&net) => self.btor.redor(net),
+                None => self.btor.constant(1, 0),
+            })
+            .collect();
+        let mut sum = self.btor.constant(1, 0);
+        for implicant in logic::minimise(cell.param("TABLE")) {
+            let mut product = self.btor.constant(1, 1);
+            for (i, on) in implicant.literals() {
+                let literal = if on { inputs[i] } else { self.btor.not(inputs[i]) };
+                product = self.btor.and(product, literal);
+            }
+            sum = self.btor.or(sum, product);
+        }
+        sum
+    }
+
+    /// Next state of `observer` from `components.sv`
+    fn observer_next(&mut self, cell: &Cell, state: Nid, a: Nid) -> Nid {
+        let on = self.btor.redor(a);
+        let phase = self.btor.slice(state, 3, 2);
+        let blind = self.btor.slice(state, 1, 1);
+        let last = self.btor.slice(state, 0, 0);
+
+        let idle_phase = self.btor.constant(2, 0);
+        let idle = self.btor.eq(phase, idle_phase);
+        let changed = self.btor.xor(on, last);
+        let seeing = self.btor.not(blind);
+        let start = self.btor.and(changed, seeing);
+        let off = self.btor.constant(1, 0);
+        let started = self.btor.concat(off, start);
+        // Adding 1 wraps like subtracting 3
+        let three = self.btor.constant(2, 3);
+        let advanced = self.btor.sub(phase, three);
+        let next_phase = self.btor.ite(idle, started, advanced);
+
+        let next_blind = if cell.param("LATE") == 1 {
+            self.btor.constant(1, 0)
+        } else {
+            self.btor.eq(phase, three)
+        };
+        let low = self.btor.concat(next_blind, on);
+        self.btor.concat(next_phase, low)
+    }
+
+    /// Output of `repeater` from `components.sv`. Only locking repeaters look
+    /// at their input, which `input` adds on demand.
+    fn repeater_out(&mut self, cell: &Cell, buffer: Nid, input: impl FnOnce() -> Nid) -> Nid {
+        let delay = self.btor.width(buffer);
+        let last = self.btor.slice(buffer, delay - 1, delay - 1);
+        if cell.param("LOCK_OUT") == 0 {
+            return last;
+        }
+        let a = input();
+        let on = self.btor.redor(a);
+        if delay == 1 {
+            self.btor.or(last, on)
+        } else {
+            let before = self.btor.slice(buffer, delay - 2, delay - 2);
+            let held = self.btor.and(last, on);
+            self.btor.or(before, held)
+        }
+    }
+
+    /// Next buffer of `repeater` from `components.sv`
+    fn repeater_next(&mut self, cell: &Cell, buffer: Nid, a: Nid, l: Nid) -> Nid {
+        let delay = self.btor.width(buffer);
+        let on = self.btor.redor(a);
+        let shifted = self.repeater_shift(buffer, on);
+        // Repeaters that lock others ignore their own lock
+        if cell.param("LOCK_OUT") == 1 || cell.param("LOCKABLE") == 0 {
+            return shifted;
+        }
+        let locked = self.btor.redor(l);
+        let hold = if delay == 1 {
+            buffer
+        } else {
+            let last = self.btor.slice(buffer, delay - 1, delay - 1);
+            self.btor.sext(last, delay - 1)
+        };
+        self.btor.ite(locked, hold, shifted)
+    }
+
+    /// Next buffer of an unlocked `repeater` whose input is `on`
+    fn repeater_shift(&mut self, buffer: Nid, on: Nid) -> Nid {
+        let delay = self.btor.width(buffer);
+        if delay == 1 {
+            on
+        } else {
+            let last = self.btor.slice(buffer, delay - 1, delay - 1);
+            let first = self.btor.slice(buffer, 0, 0);
+            let rest = self.btor.slice(buffer, delay - 2, 0);
+            let held = self.btor.and(last, on);
+            let held = self.btor.sext(held, delay - 2);
+            let high = self.btor.or(rest, held);
+            let off = self.btor.not(last);
+            let rising = self.btor.and(off, first);
+            let low = self.btor.or(on, rising);
+            self.btor.concat(high, low)
+        }
+    }
+}
+
+/// Orders the cells so every combinational input is computed before it is
+/// used. Registers break loops, except for locking repeaters whose output
+/// follows their input.
+fn combinational_order(cells: &[Cell], drivers: &FxHashMap<usize, usize>) -> Result<Vec<usize>, String> {
+    let inputs = |cell: &Cell| -> Vec<usize> {
+        let ports = match cell.ty {
+            "RS_OUTPUT" | "RS_SUB" => 0..1,
+            "RS_MAX" => 0..2,
+            "RS_REPEATER" if cell.param("LOCK_OUT") == 1 => 1..2,
+            _ => 0..0,
+        };
+        ports.filter_map(|port| drivers.get(&cell.connections[port][0]).copied()).collect()
+    };
+
+    let mut waiting = vec![0; cells.len()];
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); cells.len()];
+    for (i, cell) in cells.iter().enumerate() {
+        for driver in inputs(cell) {
+            waiting[i] += 1;
+            users[driver].push(i);
+        }
+    }
+    let mut ready: Vec<usize> = (0..cells.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut order = Vec::with_capacity(cells.len());
+    while let Some(i) = ready.pop() {
+        order.push(i);
+        for &user in &users[i] {
+            waiting[user] -= 1;
+            if waiting[user] == 0 {
+                ready.push(user);
+            }
+        }
+    }
+    match (0..cells.len()).find(|&i| waiting[i] > 0) {
+        Some(i) => Err(format!("combinational loop through {}", describe(&cells[i]))),
+        None => Ok(order),
+    }
+}
+
+fn describe(cell: &Cell) -> String {
+    match cell.pos {
+        Some(pos) => format!("the {} at {}", cell.ty, pos),
+        None => cell.name.clone(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Op(char),
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '!' | '&' | '|' | '^' | '(' | ')' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_' || **c == '.') {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => return Err(format!("unexpected '{}' in \"{}\"", c, expr)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over `|`, then `^`, then `&`, then `!`
+struct Parser<'a> {
+    btor: &'a mut Btor2,
+    signals: &'a FxHashMap<String, Nid>,
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self) -> Result<Nid, String> {
+        let nid = self.binary(0)?;
+        match self.tokens.get(self.at) {
+            None => Ok(nid),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.tokens.get(self.at) == Some(&Token::Op(op)) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Nid, String> {
+        const OPS: [char; 3] = ['|', '^', '&'];
+        if level == OPS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while self.eat(OPS[level]) {
+            let rhs = self.binary(level + 1)?;
+            lhs = match OPS[level] {
+                '|' => self.btor.or(lhs, rhs),
+                '^' => self.btor.xor(lhs, rhs),
+                _ => self.btor.and(lhs, rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Nid, String> {
+        if self.eat('!') {
+            let nid = self.unary()?;
+            return Ok(self.btor.not(nid));
+        }
+        if self.eat('(') {
+            let nid = self.binary(0)?;
+            if !self.eat(')') {
+                return Err("missing ')'".to_string());
+            }
+            return Ok(nid);
+        }
+        match self.tokens.get(self.at).cloned() {
+            Some(Token::Name(name)) => {
+                self.at += 1;
+                self.signals.get(name.as_str()).copied().ok_or_else(|| format!("unknown signal {}", name))
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+}
+
+/// Writes the model of `graph` with the properties of `properties` to `path`
+pub fn export(graph: &CompileGraph, properties: &Path, path: &Path) -> Result<(), String> {
+    let mut model = Model::new(graph)?;
+    model.add_properties(&PropertyFile::load(properties)?)?;
+    fs::write(path, model.btor.to_text()).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+/// Writes a model to `path` that is bad when `a` and `b` differ in an output
+pub fn export_miter(a: &CompileGraph, b: &CompileGraph, path: &Path) -> Result<(), String> {
+    let model = Model::miter(a, b)?;
+    fs::write(path, model.btor.to_text()).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(btor: &mut Btor2, signals: &FxHashMap<String, Nid>, expr: &str) -> Result<Nid, String> {
+        Parser { btor, signals, tokens: tokenize(expr)?, at: 0 }.parse()
+    }
+
+    #[test]
+    fn tokenize_expressions() {
+        let name = |name: &str| Token::Name(name.to_string());
+        assert_eq!(
+            tokenize(" !a&(b_1 |c.d)^e").unwrap(),
+            vec![Token::Op('!'), name("a"), Token::Op('&'), Token::Op('('), name("b_1"), Token::Op('|'), name("c.d"), Token::Op(')'), Token::Op('^'), name("e")]
+        );
+        assert_eq!(tokenize("").unwrap(), vec![]);
+        assert!(tokenize("a + b").unwrap_err().contains("unexpected '+'"));
+    }
+
+    #[test]
+    fn parser_precedence() {
+        let mut btor = Btor2::default();
+        let inputs: Vec<Nid> = ["a", "b", "c"].iter().map(|name| btor.input(1, name)).collect();
+        let signals: FxHashMap<String, Nid> = ["a", "b", "c"].iter().map(|n| n.to_string()).zip(inputs.iter().copied()).collect();
+        let loose = parse(&mut btor, &signals, "a | b ^ c & !a").unwrap();
+        let grouped = parse(&mut btor, &signals, "!(a | b) ^ c").unwrap();
+
+        let mut sim = Simulation::new(&btor);
+        for bits in 0..8 {
+            let (a, b, c) = (bits & 1, bits >> 1 & 1, bits >> 2 & 1);
+            for (input, value) in inputs.iter().zip([a, b, c]) {
+                sim.set(*input, value);
+            }
+            assert_eq!(sim.get(loose), a | (b ^ (c & (a ^ 1))), "{:03b}", bits);
+            assert_eq!(sim.get(grouped), ((a | b) ^ 1) ^ c, "{:03b}", bits);
+        }
+    }
+
+    #[test]
+    fn parser_errors() {
+        let mut btor = Btor2::default();
+        let signals = FxHashMap::from_iter([("a".to_string(), btor.input(1, "a"))]);
+        assert_eq!(parse(&mut btor, &signals, "a &").unwrap_err(), "unexpected end");
+        assert_eq!(parse(&mut btor, &signals, "(a").unwrap_err(), "missing ')'");
+        assert_eq!(parse(&mut btor, &signals, "z").unwrap_err(), "unknown signal z");
+        assert!(parse(&mut btor, &signals, "a a").unwrap_err().starts_with("unexpected Name"));
+        assert!(parse(&mut btor, &signals, "a)").unwrap_err().starts_with("unexpected Op"));
+    }
+
+    fn property_file(json: &str) -> PropertyFile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// A model with a lever strength at 1,2,1 and a lamp named `x` at 5,2,1
+    fn model() -> (Model, Nid, Nid) {
+        let mut btor = Btor2::default();
+        let lever = btor.input(4, "lever");
+        let lamp = btor.input(1, "lamp");
+        let a = BlockPos::new(1, 2, 1);
+        let x = BlockPos::new(5, 2, 1);
+        let model = Model {
+            btor,
+            inputs: FxHashMap::default(),
+            probes: FxHashMap::from_iter([(a, lever), (x, lamp)]),
+            names: BTreeMap::from([("x".to_string(), x)]),
+        };
+        (model, lever, lamp)
+    }
+
+    #[test]
+    fn add_properties() {
+        let (mut model, lever, lamp) = model();
+        let file = property_file(r#"{ "signals": { "a": "1,2,1" }, "properties": { "follows": { "always": "!a | x" }, "both": { "never": "a & x" } } }"#);
+        model.add_properties(&file).unwrap();
+        let text = model.btor.to_text();
+        assert!(text.contains(" output ") && text.contains(" a\n") && text.contains(" x\n"), "{}", text);
+
+        let mut sim = Simulation::new(&model.btor);
+        for (strength, lit, bad) in [(0, 0, vec![]), (7, 0, vec!["follows"]), (15, 1, vec!["both"]), (0, 1, vec![])] {
+            sim.set(lever, strength);
+            sim.set(lamp, lit);
+            assert_eq!(sim.bad(), bad, "strength {} lit {}", strength, lit);
+        }
+    }
+
+    #[test]
+    fn add_properties_errors() {
+        let (mut model, _, _) = model();
+        let file = property_file(r#"{ "signals": { "a": "nowhere" }, "properties": {} }"#);
+        assert!(model.add_properties(&file).unwrap_err().contains("neither a block position nor a name"));
+        let file = property_file(r#"{ "signals": { "a": "9,9,9" }, "properties": {} }"#);
+        assert!(model.add_properties(&file).unwrap_err().contains("is not part of the build"));
+        let file = property_file(r#"{ "properties": { "broken": { "never": "x &" } } }"#);
+        assert_eq!(model.add_properties(&file).unwrap_err(), "property broken: unexpected end");
+    }
+
+    #[test]
+    fn property_file_load() {
+        let path = std::env::temp_dir().join(format!("property_file_{}.json", std::process::id()));
+        fs::write(&path, r#"{ "properties": { "off": { "never": "x" } } }"#).unwrap();
+        let file = PropertyFile::load(&path).unwrap();
+        assert!(file.signals.is_empty());
+        assert!(matches!(&file.properties["off"], PropertyDef::Never(expr) if expr == "x"));
+
+        fs::write(&path, r#"{ "properties": { "off": { "sometimes": "x" } } }"#).unwrap();
+        assert!(PropertyFile::load(&path).unwrap_err().starts_with("invalid"));
+        fs::remove_file(&path).unwrap();
+        assert!(PropertyFile::load(&path).unwrap_err().starts_with("could not read"));
+    }
+}
@@ -2,25 +2,24 @@ use itertools::Itertools;
 use mchprs_blocks::blocks::{Block, ComparatorMode};
 use petgraph::graph::NodeIndex;
 use petgraph::visit::EdgeRef;
-use mchprs_redpiler::compile_graph::{CompileGraph, LinkType, NodeType};
+use mchprs_redpiler::compile_graph::{delay_segments, logic_sources, observed_node, CompileGraph, LinkType, LogicGate, NodeIdx, NodeType};
+use mchprs_redpiler::logic;
+use mchprs_redpiler::passes::same_tick_cycles::is_locker;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
+use tracing::warn;
 
 pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
 
-    let mut verilog = 
-"module RoC #(
-    parameter OUTPUTS,
-    parameter INPUTS
-) (
-    input                   tick,
-    input   [INPUTS-1:0]    inputs,
-    output  [OUTPUTS-1:0]   outputs
-);\n\n".to_owned();
+    let mut verilog = String::new();
 
     let mut input_id = 0;
     let mut output_id = 0;
+    // Output bit of every lamp and trapdoor, for the wires of annotated nodes
+    let mut output_bits = FxHashMap::default();
 
     for nodeid in graph.node_indices() {
         let node = &graph[nodeid];
@@ -40,6 +39,7 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
                 verilog.push_str(&format!("\tassign outputs[{output_id}] = ({});\n", 
                     get_inputs_str(graph, id, Some(LinkType::Default)))
                 );
+                output_bits.insert(id, output_id);
                 output_id += 1;
             }
             NodeType::Repeater { delay, facing_diode: _ } => {
@@ -47,7 +47,7 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
                 verilog.push_str(&format!("\trepeater #(.t({}), .state(1'b{}), .lock_out({}), .lockable({})) c{} (.i_clk(tick), .i_in({}), .i_lock({}), .o_out(w{}));\n",
                     delay,
                     if state {1} else {0},
-                    if is_locker(graph, id) {1} else {0},
+                    if is_locker(graph, nodeid) {1} else {0},
                     if is_locking(graph, id) {1} else {0},
                     id,
                     get_inputs_str(graph, id, Some(LinkType::Default)),
@@ -55,6 +55,52 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
                     id
                 ));
             }
+            NodeType::Delay { ref stages } => {
+                let segments = delay_segments(stages);
+                let mut input = get_inputs_str(graph, id, Some(LinkType::Default));
+                for (k, (pulse, delay)) in segments.iter().enumerate() {
+                    let (name, output) = if k + 1 == segments.len() {
+                        (format!("c{}", id), format!("w{}", id))
+                    } else {
+                        (format!("c{}_{}", id, k), format!("w{}_{}", id, k))
+                    };
+                    verilog.push_str(&format!("\twire {};\n", output));
+                    verilog.push_str(&format!("\tdelay #(.pulse({}), .t({}), .state(1'b{})) {} (.i_clk(tick), .i_in({}), .o_out({}));\n",
+                        pulse,
+                        delay,
+                        if state {1} else {0},
+                        name,
+                        input,
+                        output
+                    ));
+                    input = output;
+                }
+            }
+            NodeType::Logic { ref gates } => {
+                // A shift register for the depth of the torches, fed by their minimised function
+                let depth = logic::depth(gates);
+                let init: String = logic::pending_outputs(gates).iter().map(|&on| if on {'1'} else {'0'}).collect();
+                verilog.push_str(&format!("\treg [{}:0] c{} = {}'b{};\n", depth - 1, id, depth, init));
+                verilog.push_str(&format!("\twire w{} = c{}[{}];\n", id, id, depth - 1));
+                verilog.push_str(&format!("\talways @(posedge tick) c{} <= {{c{}[{}:0], {}}};\n",
+                    id,
+                    id,
+                    depth - 2,
+                    logic_expr(graph, nodeid, gates)
+                ));
+            }
+            NodeType::Observer => {
+                let (seen, late) = observed_node(graph, nodeid);
+                verilog.push_str(&format!("\twire w{};\n", id));
+                verilog.push_str(&format!("\tobserver #(.state(1'b{}), .seen(1'b{}), .late(1'b{})) c{} (.i_clk(tick), .i_in({}), .o_out(w{}));\n",
+                    if state {1} else {0},
+                    if seen {1} else {0},
+                    if late {1} else {0},
+                    id,
+                    get_inputs_str(graph, id, Some(LinkType::Default)),
+                    id
+                ));
+            }
             NodeType::Torch => {
                 verilog.push_str(&format!("\twire w{};\n", id));
                 verilog.push_str(&format!("\ttorch #(.state(1'b{})) c{} (.i_clk(tick), .i_in({}), .o_out(w{}));\n", 
@@ -84,6 +130,9 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
 
                     match src_node.ty {
                         NodeType::Repeater {..} |
+                        NodeType::Delay {..} |
+                        NodeType::Logic {..} |
+                        NodeType::Observer |
                         NodeType::Button |
                         NodeType::Lever | 
                         NodeType::Torch | 
@@ -340,8 +389,24 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
             }
             _ => ()
         } 
+
+        if node.is_forced_output() {
+            verilog.push_str(&format!("\tassign outputs[{output_id}] = {};\n", on_expr(graph, nodeid, &output_bits)));
+            output_id += 1;
+        }
     }
+    let (bus_ports, wires) = annotated_wires(graph, &output_bits);
+    verilog.push_str(&wires);
     verilog.push_str("endmodule");
+    let verilog = format!(
+"module RoC #(
+    parameter OUTPUTS,
+    parameter INPUTS
+) (
+    input                   tick,
+    input   [INPUTS-1:0]    inputs,
+    output  [OUTPUTS-1:0]   outputs{bus_ports}
+);\n\n{verilog}");
 
     let prefix = path.parent().unwrap();
     std::fs::create_dir_all(prefix).unwrap();
@@ -352,6 +417,115 @@ pub fn generate_verilog(graph: &CompileGraph, path: &Path) {
     }
 }
 
+/// Verilog identifier for a name from a sign, e.g. `cpu.pc0` becomes `cpu_pc0`
+fn sanitize_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Identifiers handed out for names from signs. A name that is repeated or
+/// sanitizes to a taken identifier gets a numbered suffix, so every wire is
+/// declared once.
+#[derive(Default)]
+pub(crate) struct Identifiers(FxHashSet<String>);
+
+impl Identifiers {
+    pub(crate) fn unique(&mut self, prefix: &str, name: &str) -> String {
+        let base = format!("{}{}", prefix, sanitize_name(name));
+        let mut ident = base.clone();
+        let mut suffix = 1;
+        while !self.0.insert(ident.clone()) {
+            suffix += 1;
+            ident = format!("{}_{}", base, suffix);
+        }
+        if suffix > 1 {
+            warn!("{} is already taken, the wire of {} is called {}", base, name, ident);
+        }
+        ident
+    }
+}
+
+/// The minimised function of a `Logic` node as a sum of products of its input wires
+fn logic_expr(graph: &CompileGraph, node: NodeIdx, gates: &[LogicGate]) -> String {
+    let mut wires = [""; logic::MAX_INPUTS].map(String::from);
+    for (input, source) in logic_sources(graph, node) {
+        wires[input] = format!("w{}", source.index());
+    }
+    let products = logic::minimise(logic::truth_table(gates))
+        .into_iter()
+        .map(|implicant| {
+            let literals = implicant
+                .literals()
+                .map(|(i, on)| if on { wires[i].clone() } else { format!("~{}", wires[i]) })
+                .join(" & ");
+            if literals.is_empty() { "1'b1".to_string() } else { format!("({})", literals) }
+        })
+        .join(" | ");
+    if products.is_empty() { "1'b0".to_string() } else { products }
+}
+
+/// Whether `node` is on, i.e. has an output strength above 0
+fn on_expr(graph: &CompileGraph, node: NodeIdx, output_bits: &FxHashMap<usize, usize>) -> String {
+    let id = node.index();
+    match graph[node].ty {
+        NodeType::Repeater { .. } | NodeType::Delay { .. } | NodeType::Logic { .. } | NodeType::Observer | NodeType::Torch | NodeType::Lever | NodeType::Button | NodeType::PressurePlate => {
+            format!("w{}", id)
+        }
+        NodeType::Comparator { states: Some(states), .. } => format!("w{}[{}]", id, ss_to_idx(states, 14)),
+        // Only discretised comparators have a wire per strength
+        NodeType::Comparator { states: None, .. } => {
+            warn!("The comparator w{} has no discrete states, it reads as off", id);
+            "1'b0".to_string()
+        }
+        NodeType::Constant => format!("1'b{}", (graph[node].state.output_strength > 0) as u8),
+        NodeType::Lamp | NodeType::Trapdoor => format!("outputs[{}]", output_bits[&id]),
+        NodeType::Wire | NodeType::NoteBlock { .. } => format!("({})", get_inputs_str(graph, id, Some(LinkType::Default))),
+    }
+}
+
+/// Wires named after the `[name]` and `[probe]` signs of the graph, and one per
+/// bus. Output buses are also ports that mirror their bits. Input buses are only
+/// wires, their levers are still set through `inputs`.
+/// Returns the port declarations and the wires.
+fn annotated_wires(graph: &CompileGraph, output_bits: &FxHashMap<usize, usize>) -> (String, String) {
+    let mut ports = String::new();
+    let mut verilog = String::new();
+    let mut buses: BTreeMap<&str, (bool, bool, BTreeMap<u32, String>)> = BTreeMap::new();
+    let mut identifiers = Identifiers::default();
+    for nodeid in graph.node_indices() {
+        let annotations = &graph[nodeid].annotations;
+        let keep = if annotations.probe { "(* keep *) " } else { "" };
+        let name = match &annotations.name {
+            Some(name) => Some(identifiers.unique("n_", name)),
+            None if annotations.probe => Some(format!("probe{}", nodeid.index())),
+            None => None,
+        };
+        if let Some(name) = name {
+            verilog.push_str(&format!("\t{}wire {} = {};\n", keep, name, on_expr(graph, nodeid, output_bits)));
+        }
+        if let Some((bus, bit)) = &annotations.bus {
+            let (bus_keep, is_input, bits) = buses.entry(bus).or_default();
+            *bus_keep |= annotations.probe;
+            *is_input |= graph[nodeid].is_input;
+            bits.insert(*bit, on_expr(graph, nodeid, output_bits));
+        }
+    }
+    for (bus, (keep, is_input, bits)) in buses {
+        let name = identifiers.unique("bus_", bus);
+        let width = bits.keys().max().unwrap() + 1;
+        let keep = if keep { "(* keep *) " } else { "" };
+        if is_input {
+            verilog.push_str(&format!("\t{}wire [{}:0] {};\n", keep, width - 1, name));
+        } else {
+            ports.push_str(&format!(",\n    {}output  [{}:0] {}", keep, width - 1, name));
+        }
+        for bit in 0..width {
+            let expr = bits.get(&bit).map_or("1'b0", |expr| expr.as_str());
+            verilog.push_str(&format!("\tassign {}[{}] = {};\n", name, bit, expr));
+        }
+    }
+    (ports, verilog)
+}
+
 fn get_out_idx(states: u16, dist: u8) -> Option<u8> {
     let r_dist = 15 - dist - 1;
     let trimmed = states & ((0x1 << (r_dist+1)) - 1);
@@ -385,6 +559,9 @@ fn get_inputs_str (graph: &CompileGraph, node: usize, ty: Option<LinkType>) -> S
 
             match src_node.ty {
                 NodeType::Repeater {..} |
+                NodeType::Delay {..} |
+                NodeType::Logic {..} |
+                NodeType::Observer |
                 NodeType::Button |
                 NodeType::Lever | 
                 NodeType::Torch | 
@@ -418,14 +595,6 @@ fn is_locking (graph: &CompileGraph, node: usize) -> bool {
     false
 }
 
-fn is_locker (graph: &CompileGraph, node: usize) -> bool {
-    for edge in graph.edges_directed((node as u32).into(), petgraph::Direction::Outgoing) {
-        let link = &graph[edge.id()];
-        if link.ty == LinkType::Side && matches!(graph[edge.target()].ty, NodeType::Repeater { .. }) {return true}
-    }
-    false
-}
-
 fn ss_to_idx(states: u16, ss: u8) -> u8 {
     let m = states & (0xFFFF_u32 >> (ss+1)) as u16;
     m.count_ones() as u8
@@ -2,10 +2,15 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::env;
 use std::os::windows::process::CommandExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use serde;
 
+use super::programmer::{programmer_for, ProgramResults, ProgrammerTool};
+
+/// JTAG chain position used when a config has no `chain_index`
+pub const DEFAULT_CHAIN_INDEX: u32 = 2;
+
 
 #[derive(serde::Deserialize, Debug, Clone, Default)]
 pub struct DeviceConfig {
@@ -13,7 +18,22 @@ pub struct DeviceConfig {
     pub device:         String,
     pub family:         String,
     pub command_com:    String,
+    /// Port of the programming cable, used as the cable when `cable` is not set
+    #[serde(default)]
     pub program_com:    String,
+    /// Tool used to load the bitstream
+    #[serde(default)]
+    pub programmer:     ProgrammerTool,
+    /// Programming cable, each programmer has its own default
+    #[serde(default)]
+    pub cable:          Option<String>,
+    /// Position of the FPGA in the JTAG chain, starting at 1. Defaults to
+    /// [`DEFAULT_CHAIN_INDEX`], the FPGA behind the HPS of the DE1-SoC.
+    #[serde(default)]
+    pub chain_index:    Option<u32>,
+    /// Directory holding `quartus_pgm`, it is looked up on the `PATH` without one
+    #[serde(default)]
+    pub quartus_dir:    Option<String>,
     pub pin_assignments:PinAssignments
 }
 
@@ -29,6 +49,25 @@ pub struct PinAssignments {
 
 impl DeviceConfig {
     pub fn create_project(&self, path: &Path, output_cnt: u32, input_cnt: u32) -> bool {
+        if !self.write_project(path, output_cnt, input_cnt) {
+            return false;
+        }
+        let prefix = path.parent().unwrap();
+
+        env::set_var("quartus_sh", r"C:\intelFPGA_lite\23.1std\quartus\bin64");
+
+        let out = Command::new(r"C:\intelFPGA_lite\23.1std\quartus\bin64\quartus_sh")
+            .current_dir(prefix)
+            .args(&["-t", "prj.tcl"])
+            .output()
+            .unwrap();
+        println!("{:?}", String::from_utf8_lossy(&out.stdout));
+
+        return true;
+    }
+
+    /// Writes the project TCL without running it, e.g. for a remote build
+    pub fn write_project(&self, path: &Path, output_cnt: u32, input_cnt: u32) -> bool {
 
         let mut tcl = format!(
         "package require ::quartus::project
@@ -81,16 +120,7 @@ set_location_assignment PIN_{i_clk} -to i_clk\n",
             _ => ()
         };
 
-        env::set_var("quartus_sh", r"C:\intelFPGA_lite\23.1std\quartus\bin64");
-
-        let out = Command::new(r"C:\intelFPGA_lite\23.1std\quartus\bin64\quartus_sh")
-            .current_dir(prefix)
-            .args(&["-t", "prj.tcl"])
-            .output()
-            .unwrap();
-        println!("{:?}", String::from_utf8_lossy(&out.stdout));
-
-        return true;
+        true
     }
 
     pub fn compile (&self, path: &Path) -> CompilerResults {
@@ -106,15 +136,27 @@ set_location_assignment PIN_{i_clk} -to i_clk\n",
     }
 
     pub fn program (&self, path: &Path) -> ProgramResults {
-        let results = ProgramResults{};
-        let out = Command::new(r"C:\intelFPGA_lite\23.1std\quartus\bin64\quartus_pgm")
-            .current_dir(path)
-            .args(&["-c", "DE-SoC [USB-1]", "-m", "jtag", "-o", "p;RoC.sof@2"])
-            .output()
-            .unwrap();
-        println!("{:?}", String::from_utf8_lossy(&out.stdout));
+        programmer_for(self.programmer).program(self, path)
+    }
 
-        results
+    /// The programming cable, if one is configured, falling back to `program_com`
+    pub fn cable (&self) -> Option<&str> {
+        self.cable.as_deref()
+            .filter(|cable| !cable.is_empty())
+            .or(Some(self.program_com.as_str()).filter(|port| !port.is_empty()))
+    }
+
+    /// Path of the quartus tool `name`, inside `quartus_dir` if one is configured
+    pub fn quartus_tool (&self, name: &str) -> PathBuf {
+        match self.quartus_dir.as_deref().filter(|dir| !dir.is_empty()) {
+            Some(dir) => Path::new(dir).join(name),
+            None => PathBuf::from(name),
+        }
+    }
+
+    /// Position of the FPGA in the JTAG chain, starting at 1
+    pub fn chain_index (&self) -> u32 {
+        self.chain_index.unwrap_or(DEFAULT_CHAIN_INDEX)
     }
 }
 
@@ -122,9 +164,7 @@ pub struct CompilerResults {
     pub state: bool
 }
 
-pub struct ProgramResults {
 
-}
 
 
 
@@ -1,6 +1,14 @@
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use serialport::SerialPort;
+use tracing::{debug, warn};
 
+/// How long the link may stay quiet before a `Ping` is sent to check on the board
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(1000);
+/// How long to wait between attempts to reopen a lost connection, doubled after every failed attempt
+pub const RECONNECT_INTERVAL: Duration = Duration::from_millis(2000);
+/// Longest wait between attempts to reopen a lost connection
+pub const MAX_RECONNECT_INTERVAL: Duration = Duration::from_secs(60);
 
 
 pub enum FPGACommand {
@@ -13,29 +21,153 @@ pub enum FPGACommand {
     LoadROM(u32,u8), 
     DebugLED,
     FailAck,             
+    GetTicks,
+    Step(u32),
 }
 
+/// Rate sent with `SetRTPS` to make the tick clock free run
+pub const RTPS_UNLIMITED: u32 = u32::MAX;
+
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ConnectionStatus {
+    #[default]
+    Disconnected,
+    Connected,
+    Reconnecting,
+}
+
+impl ConnectionStatus {
+    pub fn to_str(&self) -> String {
+        match self {
+            ConnectionStatus::Disconnected => "&cDisconnected".to_string(),
+            ConnectionStatus::Connected =>    "&aConnected".to_string(),
+            ConnectionStatus::Reconnecting => "&eReconnecting".to_string(),
+        }
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct Interface {
     pub serial_conn: SerialConnection,
     pub outputs: Vec<u8>,
+    /// Hardware tick counter, as of the last `GetTicks`
+    pub ticks: u32,
+    pub status: ConnectionStatus,
+    last_contact: Option<Instant>,
+    last_attempt: Option<Instant>,
+    /// Attempts to reopen the connection that failed in a row
+    failed_attempts: u32,
 }
 
 impl Interface {
     pub fn new(name: &str, baud: u32, timeout: u32, outputs: usize) -> Interface{
         Interface{
             serial_conn: SerialConnection::new(name, baud, timeout), 
-            outputs: Vec::with_capacity(outputs)
+            outputs: Vec::with_capacity(outputs),
+            ..Default::default()
         }
     }
 
-    pub fn serial_start(&mut self, name: &str, baud: u32) {
+    pub fn serial_start(&mut self, name: &str, baud: u32) -> bool {
         self.serial_conn = SerialConnection::new(name, baud, 20);
-        self.serial_conn.start();
+        self.serial_conn.start()
+    }
+
+    /// Opens `name` and checks that a RoC board answers a `Ping` on it
+    pub fn connect(&mut self, name: &str, baud: u32) -> bool {
+        self.last_attempt = Some(Instant::now());
+        if !self.serial_start(name, baud) || !self.send_command(FPGACommand::Ping) {
+            self.serial_conn.close();
+            self.status = ConnectionStatus::Disconnected;
+            return false;
+        }
+        debug!("FPGA connected on {}", name);
+        true
+    }
+
+    /// Enumerates the serial ports of this machine and returns the first one
+    /// with a RoC board behind it. This writes a `Ping` to every port, so it is
+    /// only done when asked for.
+    pub fn detect(baud: u32) -> Option<String> {
+        let ports = match serialport::available_ports() {
+            Ok(ports) => ports,
+            Err(err) => {
+                warn!("Could not enumerate serial ports: {}", err);
+                return None;
+            }
+        };
+        for port in ports {
+            let mut probe = Interface::default();
+            if probe.connect(&port.port_name, baud) {
+                return Some(port.port_name);
+            }
+        }
+        None
+    }
+
+    /// Tries to open `ports` in order on a background thread, so their timeouts don't hold up
+    /// the plot. The thread returns the first port a RoC board answers on, see [`Interface::resume`].
+    pub fn probe(ports: Vec<String>, baud: u32) -> JoinHandle<Option<(String, SerialConnection)>> {
+        thread::spawn(move || {
+            ports.into_iter().find_map(|port| {
+                let mut probe = Interface::default();
+                probe.connect(&port, baud).then(|| (port, std::mem::take(&mut probe.serial_conn)))
+            })
+        })
+    }
+
+    /// Takes over a connection opened by [`Interface::probe`]
+    pub fn resume(&mut self, conn: SerialConnection) {
+        self.serial_conn = conn;
+        self.status = ConnectionStatus::Connected;
+        self.last_contact = Some(Instant::now());
+    }
+
+    /// Returns true if enough time has passed since the last attempt to reconnect
+    pub fn should_reconnect(&self) -> bool {
+        self.last_attempt.map_or(true, |t| t.elapsed() >= self.reconnect_delay())
     }
 
+    /// How long to wait after the last attempt to reconnect before trying again
+    pub fn reconnect_delay(&self) -> Duration {
+        RECONNECT_INTERVAL
+            .saturating_mul(1 << self.failed_attempts.min(16))
+            .min(MAX_RECONNECT_INTERVAL)
+    }
+
+    /// Records the outcome of an attempt to reopen the connection, failures back off
+    /// further attempts, see [`Interface::reconnect_delay`]
+    pub fn reconnected(&mut self, ok: bool) {
+        self.last_attempt = Some(Instant::now());
+        self.failed_attempts = if ok { 0 } else { self.failed_attempts + 1 };
+    }
+
+    /// Pings the board if nothing was heard from it for `HEARTBEAT_INTERVAL`.
+    /// Returns false if the board did not answer.
+    pub fn heartbeat(&mut self) -> bool {
+        if self.status != ConnectionStatus::Connected {
+            return false;
+        }
+        if self.last_contact.is_some_and(|t| t.elapsed() < HEARTBEAT_INTERVAL) {
+            return true;
+        }
+        self.send_command(FPGACommand::Ping)
+    }
+
+    pub fn close(&mut self) {
+        self.serial_conn.close();
+        self.status = ConnectionStatus::Disconnected;
+    }
+
+    /// Sends a command and waits for the board to echo it back.
+    /// Returns false if the echo (or the requested data) never arrived,
+    /// in which case the connection is considered lost.
     pub fn send_command(&mut self, cmd: FPGACommand) -> bool {
+        if !self.serial_conn.is_open() {
+            self.status = ConnectionStatus::Disconnected;
+            return false;
+        }
 
         self.serial_conn.clear_buffer();
 
@@ -73,25 +205,54 @@ impl Interface {
                 vec![0xC7,0,0,0,0,0xA5],
             FPGACommand::FailAck =>                              
                 vec![0xC8,0,0,0,0,0xA5],
+            FPGACommand::GetTicks =>                             
+                vec![0xC9,0,0,0,0,0xA5],
+            FPGACommand::Step(ticks) =>                   
+                vec![0xCA,
+                    (ticks>>24 & 0xFF) as u8,
+                    (ticks>>16 & 0xFF) as u8,
+                    (ticks>>8 & 0xFF) as u8,
+                    (ticks & 0xFF) as u8,
+                    0xA5],
         };
-        self.serial_conn.write(&bytes);
-
         let mut response: Vec<u8> = vec![0; 6];
-        self.serial_conn.read(&mut response); 
+        if !self.serial_conn.write(&bytes) || !self.serial_conn.read(&mut response) {
+            warn!("FPGA did not respond to command {:02X}", bytes[0]);
+            self.status = ConnectionStatus::Disconnected;
+            return false;
+        }
 
         if response != bytes {
-            println!("{response:?} != {bytes:?} CMD doesnt match");
+            warn!("FPGA echoed {:02X?} for command {:02X?}", response, bytes);
+            self.status = ConnectionStatus::Disconnected;
             return false;
         }
 
-        match cmd {
+        let ok = match cmd {
             FPGACommand::GetOutupts => {
                 let ret = self.serial_conn.read(&mut self.outputs);
                 self.serial_conn.clear_buffer();
                 ret
             }                      
-            _ => false
-        }        
+            FPGACommand::GetTicks => {
+                let mut ticks: Vec<u8> = vec![0; 4];
+                let ret = self.serial_conn.read(&mut ticks);
+                if ret {
+                    self.ticks = u32::from_le_bytes([ticks[0], ticks[1], ticks[2], ticks[3]]);
+                }
+                ret
+            }
+            _ => true
+        };
+
+        if ok {
+            self.status = ConnectionStatus::Connected;
+            self.last_contact = Some(Instant::now());
+        }
+        else {
+            self.status = ConnectionStatus::Disconnected;
+        }
+        ok
     }
 
 }
@@ -151,19 +312,31 @@ impl SerialConnection {
         !self.conn.is_none()
     }
 
+    pub fn is_open (&self) -> bool {
+        self.conn.is_some()
+    }
+
+    pub fn close (&mut self) {
+        self.conn = None;
+    }
+
     pub fn read (&mut self, buffer: &mut Vec<u8>) -> bool {
-        self.conn.as_mut().unwrap().read_exact(buffer).is_ok()
+        let Some(conn) = self.conn.as_mut() else { return false };
+        conn.read_exact(buffer).is_ok()
     }
 
     pub fn clear_buffer (&mut self) -> bool {
-        self.conn.as_mut().unwrap().clear(serialport::ClearBuffer::Input).is_ok()
+        let Some(conn) = self.conn.as_mut() else { return false };
+        conn.clear(serialport::ClearBuffer::Input).is_ok()
     }
 
     pub fn write (&mut self, data: &Vec<u8>) -> bool {
-        self.conn.as_mut().unwrap().write(data).is_ok()
+        let Some(conn) = self.conn.as_mut() else { return false };
+        conn.write_all(data).is_ok()
     }
 
     pub fn write_byte (&mut self, data: u8) -> bool {
-        self.conn.as_mut().unwrap().write(&vec![data]).is_ok()
+        let Some(conn) = self.conn.as_mut() else { return false };
+        conn.write_all(&[data]).is_ok()
     }
 }
\ No newline at end of file
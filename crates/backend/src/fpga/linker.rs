@@ -1,9 +1,15 @@
 use std::{fs::File, io::Write, path::Path};
 
 use crate::fpga::{compiler::DeviceConfig, interface::BinaryIterator, FPGABackend};
+use mchprs_redpiler::block_powered_mut;
+use mchprs_redpiler::compile_graph::{Annotations, CompileGraph, NodeType};
 use mchprs_blocks::{blocks::{Block, ButtonFace, Lever, LeverFace, RedstoneWire, RedstoneWireSide, StoneButton, TrapdoorHalf}, BlockDirection, BlockPos};
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::to_string_pretty;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tracing::warn;
 
 impl FPGABackend {
     pub fn from_link_file(link: Linker, path: String, config: DeviceConfig) -> FPGABackend {
@@ -11,7 +17,8 @@ impl FPGABackend {
             fpga: Default::default(),
             path: path,
             config: config,
-            link: link
+            link: link,
+            ..Default::default()
         }
     }
 }
@@ -24,10 +31,179 @@ pub struct Linker {
     pub output_bits: u32,
     pub inputs: Vec<IntfBlock>,
     pub input_bits: u32,
+    /// Interface blocks grouped by `[bus]` signs or a bus file
+    #[serde(default)]
+    pub buses: Vec<Bus>,
+}
+
+/// Interface blocks that are read or written together as one number
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bus {
+    pub name: String,
+    /// Whether the blocks are inputs, otherwise they are outputs
+    pub is_input: bool,
+    /// Index in `inputs` or `outputs` of every block, least significant first
+    pub blocks: Vec<usize>,
+}
+
+/// Bus names mapped to the positions of their bits, least significant first:
+/// `{ "data": ["1,2,1", "1,2,3"] }`
+pub type BusFile = BTreeMap<String, Vec<String>>;
+
+/// Makes the blocks of a bus file bits of their bus, like `[bus]` signs do.
+/// Positions without a node are an error.
+pub fn apply_bus_file(graph: &mut CompileGraph, path: &Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let file: BusFile = serde_json::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+
+    let nodes: FxHashMap<BlockPos, _> =
+        graph.node_indices().filter_map(|idx| Some((graph[idx].block?.0, idx))).collect();
+    for (name, bits) in file {
+        for (bit, pos) in bits.iter().enumerate() {
+            let pos = BlockPos::from_str(pos).map_err(|_| format!("invalid block position \"{}\" in bus {}", pos, name))?;
+            let Some(&idx) = nodes.get(&pos) else {
+                return Err(format!("bit {} of bus {} at {} is not part of the build", bit, name, pos));
+            };
+            graph[idx].annotations.bus = Some((name.clone(), bit as u32));
+        }
+    }
+    Ok(())
 }
 
 impl Linker {
 
+    /// The inputs and outputs of a compiled graph, in the order the verilog uses them
+    pub fn from_graph(graph: &CompileGraph) -> Linker {
+        let mut link = Linker::default();
+        for nodeid in graph.node_indices() {
+            let node = &graph[nodeid];
+            match node.block {
+                // Annotated wires are only added to be named, not as outputs
+                Some(_) if node.ty == NodeType::Wire && !node.is_output => (),
+                Some((pos, blockid)) if node.is_forced_output() => {
+                    link.output_bits += 1;
+                    link.outputs.push(IntfBlock {
+                        ty: IntfType::Component { block: blockid },
+                        pos,
+                        state: node.state.powered as u8,
+                        name: None,
+                        bus: None,
+                    });
+                }
+                Some((pos, blockid)) => link.add_block(Block::from_id(blockid), pos),
+                // Synthetic nodes of a hybrid build, see `crate::hybrid`
+                None => match node.ty {
+                    NodeType::Lever => link.add_boundary(nodeid.index() as u32, true),
+                    NodeType::Trapdoor => link.add_boundary(nodeid.index() as u32, false),
+                    _ => (),
+                }
+            }
+            if let Some((pos, _)) = node.block {
+                link.annotate(pos, &node.annotations);
+            }
+        }
+        link.group_buses();
+        link
+    }
+
+    /// Groups the interface blocks by their bus. Buses with gaps or that mix
+    /// inputs and outputs are left out with a warning.
+    fn group_buses(&mut self) {
+        let mut bits: BTreeMap<&str, Vec<(u32, bool, usize)>> = BTreeMap::new();
+        for (is_input, blocks) in [(true, &self.inputs), (false, &self.outputs)] {
+            for (i, intf) in blocks.iter().enumerate() {
+                if let Some((bus, bit)) = &intf.bus {
+                    bits.entry(bus).or_default().push((*bit, is_input, i));
+                }
+            }
+        }
+
+        let mut buses = Vec::new();
+        for (name, mut bits) in bits {
+            bits.sort_unstable();
+            let is_input = bits[0].1;
+            if bits.iter().any(|b| b.1 != is_input) {
+                warn!("Bus {} has both inputs and outputs, it is left out", name);
+                continue;
+            }
+            if bits.iter().enumerate().any(|(i, b)| b.0 != i as u32) {
+                warn!("Bus {} has missing or repeated bits, it is left out", name);
+                continue;
+            }
+            buses.push(Bus { name: name.to_string(), is_input, blocks: bits.iter().map(|b| b.2).collect() });
+        }
+        self.buses = buses;
+    }
+
+    pub fn find_bus(&self, name: &str) -> Option<&Bus> {
+        self.buses.iter().find(|bus| bus.name == name)
+    }
+
+    /// The current value of `name`, from the last outputs read for output buses
+    pub fn bus_value(&self, name: &str) -> Result<u64, String> {
+        let bus = self.find_bus(name).ok_or_else(|| format!("There is no bus {}", name))?;
+        let list = if bus.is_input { &self.inputs } else { &self.outputs };
+        let mut value = 0;
+        let mut shift = 0;
+        for intf in bus.blocks.iter().map(|&i| &list[i]) {
+            if shift >= 64 {
+                return Err(format!("Bus {} is wider than 64 bits", name));
+            }
+            value |= (intf.state as u64) << shift;
+            shift += intf.bit_count() as u32;
+        }
+        Ok(value)
+    }
+
+    /// Sets the input bus `name` to `value` and returns the (id, state) pair of
+    /// every input that changed, as sent by `SetInputs`
+    pub fn set_bus(&mut self, name: &str, value: u64) -> Result<Vec<(u32, u8)>, String> {
+        let bus = self.find_bus(name).ok_or_else(|| format!("There is no bus {}", name))?;
+        if !bus.is_input {
+            return Err(format!("Bus {} is made of outputs and can't be set", name));
+        }
+        let width: u32 = bus.blocks.iter().map(|&i| self.inputs[i].bit_count() as u32).sum();
+        if width < 64 && value >> width != 0 {
+            return Err(format!("{} does not fit in the {} bits of bus {}", value, width, name));
+        }
+
+        let blocks = bus.blocks.clone();
+        let ids = first_ids(&self.inputs);
+        let mut changed = Vec::new();
+        let mut shift = 0;
+        for i in blocks {
+            let id = ids[i];
+            let input = &mut self.inputs[i];
+            let state = if shift < 64 { (value >> shift) as u8 } else { 0 };
+            shift += input.bit_count() as u32;
+            let old = input.state;
+            input.set_state(state);
+            if input.state != old {
+                changed.push((id, input.state));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Copies the name and bus bit of a node to its interface block, if it has one
+    fn annotate(&mut self, pos: BlockPos, annotations: &Annotations) {
+        let intf = self.inputs.iter_mut().chain(&mut self.outputs).find(|intf| intf.pos == pos && !intf.is_boundary());
+        if let Some(intf) = intf {
+            intf.name = annotations.name.clone();
+            intf.bus = annotations.bus.clone();
+        }
+    }
+
+    /// The interface block placed at `pos`
+    pub fn find(&self, pos: BlockPos) -> Option<&IntfBlock> {
+        self.inputs.iter().chain(&self.outputs).find(|intf| intf.pos == pos && !intf.is_boundary())
+    }
+
+    /// The interface block named `name`
+    pub fn find_name(&self, name: &str) -> Option<&IntfBlock> {
+        self.inputs.iter().chain(&self.outputs).find(|intf| intf.name.as_deref() == Some(name))
+    }
+
     pub fn generate_link_file(&self, path: &Path) {
         let prefix = path.parent().unwrap();
         std::fs::create_dir_all(prefix).unwrap();
@@ -65,7 +241,7 @@ impl Linker {
     pub fn toggle_input(&mut self, pos: BlockPos) -> (u32, u8, u8) {
         let mut id = 0;
         for input in &mut self.inputs {
-            if input.pos == pos {
+            if input.pos == pos && !matches!(input.ty, IntfType::Boundary { .. }) {
                 input.set_state(!input.state);
                 return (id, 0, input.state);
             }
@@ -74,8 +250,63 @@ impl Linker {
         (0,0,0)
     }
 
-    pub fn get_blocks_to_change(&mut self, data: &mut BinaryIterator) -> Vec<(BlockPos, Block)> {
-        let mut res: Vec<(BlockPos, Block)> = Vec::new();
+    /// Returns the (id, state) pair of every input, as sent by `SetInputs`
+    pub fn input_states(&self) -> Vec<(u32, u8)> {
+        let mut res = Vec::with_capacity(self.inputs.len());
+        let mut id = 0;
+        for input in &self.inputs {
+            res.push((id, input.state));
+            id += input.bit_count() as u32;
+        }
+        res
+    }
+
+    /// Adds a signal crossing between the FPGA and the CPU in a hybrid build.
+    /// `node` is the index of the synthetic node in the FPGA graph.
+    pub fn add_boundary(&mut self, node: u32, is_input: bool) {
+        let intf = IntfBlock { ty: IntfType::Boundary { node }, pos: BlockPos::new(0, 0, 0), state: 0, name: None, bus: None };
+        if is_input {
+            self.input_bits += 1;
+            self.inputs.push(intf);
+        }
+        else {
+            self.output_bits += 1;
+            self.outputs.push(intf);
+        }
+    }
+
+    pub fn has_boundary(&self) -> bool {
+        self.inputs.iter().chain(&self.outputs).any(|intf| matches!(intf.ty, IntfType::Boundary { .. }))
+    }
+
+    /// Sets the boundary input of `node` and returns its input id if the state changed
+    pub fn set_boundary_input(&mut self, node: u32, state: bool) -> Option<u32> {
+        let mut id = 0;
+        for input in &mut self.inputs {
+            if matches!(input.ty, IntfType::Boundary { node: n } if n == node) {
+                if (input.state == 1) == state {
+                    return None;
+                }
+                input.set_state(state as u8);
+                return Some(id);
+            }
+            id += input.bit_count() as u32;
+        }
+        None
+    }
+
+    pub fn boundary_output(&self, node: u32) -> bool {
+        self.outputs.iter()
+            .find(|output| matches!(output.ty, IntfType::Boundary { node: n } if n == node))
+            .is_some_and(|output| output.state == 1)
+    }
+
+    pub fn has_input(&self, pos: BlockPos) -> bool {
+        self.inputs.iter().any(|input| input.pos == pos && !matches!(input.ty, IntfType::Boundary { .. }))
+    }
+
+    /// Stores the output bits read from the board
+    pub fn update_outputs(&mut self, data: &mut BinaryIterator) {
         for output in &mut self.outputs {
             let state = match data.next(output.bit_count()) {
                 Some(data) => {
@@ -84,20 +315,46 @@ impl Linker {
                 None => 0,
             };
             output.set_state(state);
-            res.push((output.pos, output.get_block()));
-        }
-        for input in &self.inputs {
-            res.push((input.pos, input.get_block()));
         }
-        res
+    }
+
+    /// All interface blocks placed in the world, with their current state
+    pub fn blocks(&self) -> Vec<(BlockPos, Block)> {
+        self.outputs.iter()
+            .chain(&self.inputs)
+            .filter(|intf| !matches!(intf.ty, IntfType::Boundary { .. }))
+            .map(|intf| (intf.pos, intf.get_block()))
+            .collect()
+    }
+
+    pub fn get_blocks_to_change(&mut self, data: &mut BinaryIterator) -> Vec<(BlockPos, Block)> {
+        self.update_outputs(data);
+        self.blocks()
     }
 }
 
+/// The first id of every block of `list`, blocks can span several bits
+fn first_ids(list: &[IntfBlock]) -> Vec<u32> {
+    let mut ids = Vec::with_capacity(list.len());
+    let mut id = 0;
+    for intf in list {
+        ids.push(id);
+        id += intf.bit_count() as u32;
+    }
+    ids
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IntfBlock {
     ty: IntfType,
     pub pos: BlockPos,
     state: u8,
+    /// From a `[name]` sign
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// From a `[bus]` sign, the bus and bit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bus: Option<(String, u32)>,
 }
 
 impl IntfBlock {
@@ -107,42 +364,67 @@ impl IntfBlock {
                 Some(IntfBlock{ 
                     ty: IntfType::Lamp, 
                     pos: pos, 
-                    state: if l {1} else {0}
+                    state: if l {1} else {0},
+                    name: None,
+                    bus: None,
                 }),
             Block::IronTrapdoor { facing:f, half:h, powered:p } =>
                 Some(IntfBlock{ 
                     ty: IntfType::Trapdoor { facing: f, half: h }, 
                     pos: pos, 
-                    state: if p {1} else {0} 
+                    state: if p {1} else {0},
+                    name: None,
+                    bus: None,
                 }),
             Block::RedstoneWire { wire: RedstoneWire{north:RedstoneWireSide::None, east:RedstoneWireSide::None, south:RedstoneWireSide::None, west:RedstoneWireSide::None, power: p} } =>
                 Some(IntfBlock{ 
                     ty: IntfType::HexLamp, 
                     pos: pos, 
-                    state: p 
+                    state: p,
+                    name: None,
+                    bus: None,
                 }),
             Block::Lever { lever: Lever { face:f, facing:fa, powered:p } } =>
                 Some(IntfBlock{ 
                     ty: IntfType::Lever{face:f, facing:fa}, 
                     pos: pos, 
-                    state: if p {1} else {0} 
+                    state: if p {1} else {0},
+                    name: None,
+                    bus: None,
                 }),
             Block::StoneButton { button: StoneButton { face:f, facing:fa, powered:p } } =>
                 Some(IntfBlock{ 
                     ty: IntfType::Button{face:f, facing:fa}, 
                     pos: pos, 
-                    state: if p {1} else {0} 
+                    state: if p {1} else {0},
+                    name: None,
+                    bus: None,
                 }),
             Block::StonePressurePlate { powered:p } =>
                 Some(IntfBlock{ 
                     ty: IntfType::PressurePlate, 
                     pos: pos, 
-                    state: if p {1} else {0} 
+                    state: if p {1} else {0},
+                    name: None,
+                    bus: None,
                 }),
             _ => None
         }
     }
 
+    /// Synthetic entries of a hybrid build that have no block in the world
+    pub fn is_boundary(&self) -> bool {
+        matches!(self.ty, IntfType::Boundary { .. })
+    }
+
+    /// The node of a hybrid build that a boundary stands for
+    pub fn boundary_node(&self) -> Option<u32> {
+        match self.ty {
+            IntfType::Boundary { node } => Some(node),
+            _ => None,
+        }
+    }
+
     pub fn bit_count(&self) -> u8 {
         match self.ty {
             IntfType::Lamp | 
@@ -150,6 +432,8 @@ impl IntfBlock {
             IntfType::Lever {..} | 
             IntfType::PressurePlate | 
             IntfType::Button {..} |
+            IntfType::Boundary {..} |
+            IntfType::Component {..} |
             IntfType::BinROM => 
                 1,
             IntfType::HexLamp |
@@ -165,6 +449,8 @@ impl IntfBlock {
             IntfType::Lever {..} | 
             IntfType::PressurePlate | 
             IntfType::Button {..} | 
+            IntfType::Boundary {..} |
+            IntfType::Component {..} |
             IntfType::BinROM => 
                 state & 0x01,
             IntfType::HexROM | 
@@ -190,6 +476,15 @@ impl IntfBlock {
                 Block::StoneButton { button: StoneButton { face:f, facing:fa, powered:self.state == 1 } },
             IntfType::PressurePlate =>
                 Block::StonePressurePlate { powered:self.state == 1 },
+            IntfType::Boundary {..} =>
+                unreachable!("boundary signals have no block"),
+            IntfType::Component { block } => {
+                let mut block = Block::from_id(block);
+                if let Some(powered) = block_powered_mut(&mut block) {
+                    *powered = self.state == 1;
+                }
+                block
+            }
             IntfType::BinROM =>
                 todo!("BinROM"),
             IntfType::HexROM =>
@@ -220,6 +515,10 @@ enum IntfType {
     Lever {face: LeverFace, facing: BlockDirection},
     Button {face: ButtonFace, facing: BlockDirection},
     PressurePlate,
+    /// Signal shared with the CPU side of a hybrid build
+    Boundary {node: u32},
+    /// Repeater, torch or comparator made an output by an `[output]` sign
+    Component {block: u32},
     BinROM, //TODO
     HexROM, //TODO
 }
\ No newline at end of file
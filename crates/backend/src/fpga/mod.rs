@@ -1,21 +1,33 @@
 mod assembler;
 pub mod linker;
+pub mod netlist;
 pub mod interface;
 pub mod compiler;
+pub mod programmer;
+pub mod worker;
+
+pub use assembler::generate_verilog;
 
 use super::JITBackend;
+use itertools::Itertools;
 use mchprs_redpiler::compile_graph::CompileGraph;
+use mchprs_redpiler::passes::same_tick_cycles;
 use crate::fpga::linker::Linker;
 use crate::CompilerOptions;
 use compiler::DeviceConfig;
-use mchprs_blocks::blocks::Block;
 use mchprs_blocks::BlockPos;
 use mchprs_world::World;
 use mchprs_world::TickEntry;
 use std::path::Path;
+use std::thread::JoinHandle;
+use tracing::{debug, info, warn};
+
 
+use worker::{BuildBundle, WorkerPool};
+use interface::{Interface, FPGACommand, BinaryIterator, ConnectionStatus, SerialConnection, RTPS_UNLIMITED};
 
-use interface::{Interface, FPGACommand, BinaryIterator};
+/// Baud rate of the command UART on the board
+const COMMAND_BAUD: u32 = 2500000;
 
 use std::fs::{remove_dir_all, copy};
 
@@ -25,10 +37,191 @@ pub struct FPGABackend {
     path: String,
     config: DeviceConfig,
     pub link: Linker,
+    port: Option<String>,
+    /// Rate last set by the plot, sent again after reconnecting
+    rtps: Option<u32>,
+    /// Whether the host steps the board instead of it running at `rtps`
+    stepped: bool,
+    /// Total hardware ticks, extended past the 32 bit counter on the board
+    ticks: u64,
+    /// Why the graph was not built, returned by `run`
+    refused: Option<String>,
+    /// Attempt to reopen a lost connection, running in the background
+    reconnecting: Option<JoinHandle<Option<(String, SerialConnection)>>>,
+}
+
+impl FPGABackend {
+    /// The ports a lost connection is looked for on: the configured `command_com`, unless it
+    /// is `auto` (or empty), and the port the board was last found on
+    fn known_ports(&self) -> Vec<String> {
+        let configured = self.config.command_com.trim();
+        let configured = (!configured.is_empty() && !configured.eq_ignore_ascii_case("auto")).then(|| configured.to_string());
+        configured.into_iter().chain(self.port.clone()).unique().collect()
+    }
+
+    /// Opens the command port on one of the [known ports](Self::known_ports). With a
+    /// `command_com` of `auto` and no board found yet, every serial port is scanned.
+    fn connect(&mut self) -> bool {
+        let ports = self.known_ports();
+        if ports.is_empty() {
+            return self.scan();
+        }
+        for port in ports {
+            if self.fpga.connect(&port, COMMAND_BAUD) {
+                self.port = Some(port);
+                return true;
+            }
+            warn!("No FPGA answered on {}", port);
+        }
+        false
+    }
+
+    /// Looks for the board on every serial port of this machine and opens the first one it
+    /// answers on. This writes to unrelated devices too, so it is only done when asked for.
+    pub fn scan(&mut self) -> bool {
+        self.reconnecting = None;
+        let Some(port) = Interface::detect(COMMAND_BAUD) else {
+            return false;
+        };
+        info!("Found FPGA on {}", port);
+        let connected = self.fpga.connect(&port, COMMAND_BAUD);
+        self.port = Some(port);
+        self.fpga.reconnected(connected);
+        if connected {
+            self.restore_state();
+        }
+        connected
+    }
+
+    /// Brings a freshly (re)opened board back to the state the plot expects
+    fn restore_state(&mut self) {
+        for (id, state) in self.link.input_states() {
+            self.fpga.send_command(FPGACommand::SetInputs(id, 0, state));
+        }
+        if self.stepped {
+            self.fpga.send_command(FPGACommand::Step(0));
+        } else if let Some(rtps) = self.rtps {
+            self.fpga.send_command(FPGACommand::SetRTPS(rtps));
+        }
+    }
+
+    /// Synthesizes the project on a build worker, leaving the results in `prj`
+    fn remote_build(&self, pool: &WorkerPool) -> Result<(), String> {
+        let prj = format!("bin/{}/prj", self.path);
+        let root = Path::new("FPGA");
+        if !self.config.write_project(&root.join(&prj).join("prj.tcl"), self.link.output_bits, self.link.input_bits) {
+            return Err("Could not write project".to_string());
+        }
+
+        let mut bundle = BuildBundle::new(&self.link.name, &prj);
+        bundle.add_dir(root, "src")
+            .and_then(|_| bundle.add_dir(root, "ip"))
+            .and_then(|_| bundle.add_file(root, &format!("bin/{}/redstone.sv", self.path)))
+            .and_then(|_| bundle.add_file(root, &format!("{}/prj.tcl", prj)))
+            .map_err(|err| format!("Could not bundle build: {}", err))?;
+
+        pool.build(&bundle, &root.join(&prj), &mut |line| println!("{}", line))
+    }
+
+    /// Latches the outputs of the board and stores them in the linker.
+    /// Returns false if the board could not be reached.
+    pub(crate) fn read_outputs(&mut self) -> bool {
+        if !self.check_connection() {
+            return false;
+        }
+        self.fpga.send_command(FPGACommand::Capture);
+        if !self.fpga.send_command(FPGACommand::GetOutupts) {
+            return false;
+        }
+        let mut output_iter: BinaryIterator = BinaryIterator::new(self.fpga.outputs.clone());
+        self.link.update_outputs(&mut output_iter);
+        true
+    }
+
+    pub(crate) fn write_blocks<W: World>(&self, world: &mut W) {
+        for (pos, block) in self.link.blocks() {
+            world.set_block(pos, block);
+        }
+    }
+
+    /// Advances the redstone on the board by `ticks`, leaving it stopped afterwards
+    pub(crate) fn step(&mut self, ticks: u32) -> bool {
+        self.stepped = true;
+        // A stepped board runs exactly what it is told, so the counter follows without a read
+        let stepped = self.fpga.send_command(FPGACommand::Step(ticks));
+        if stepped {
+            self.ticks += ticks as u64;
+        }
+        stepped
+    }
+
+    /// Writes `value` to the input bus `name`, least significant bit first, and
+    /// flips the levers of the bus in `world` like using them would
+    pub fn set_bus<W: World>(&mut self, world: &mut W, name: &str, value: u64) -> Result<(), String> {
+        for (id, state) in self.link.set_bus(name, value)? {
+            self.fpga.send_command(FPGACommand::SetInputs(id, 0, state));
+        }
+        self.read_outputs();
+        self.write_blocks(world);
+        Ok(())
+    }
+
+    /// Reads the bus `name`. Output buses are read from the board if it can be reached.
+    pub fn get_bus(&mut self, name: &str) -> Result<u64, String> {
+        if self.link.find_bus(name).is_some_and(|bus| !bus.is_input) {
+            self.read_outputs();
+        }
+        self.link.bus_value(name)
+    }
+
+    pub(crate) fn set_boundary_input(&mut self, node: u32, state: bool) {
+        if let Some(id) = self.link.set_boundary_input(node, state) {
+            self.fpga.send_command(FPGACommand::SetInputs(id, 0, state as u8));
+        }
+    }
+
+    /// Sends heartbeats while the link is up and periodically tries to reopen
+    /// it once it has been lost. Returns true if the board is reachable.
+    fn check_connection(&mut self) -> bool {
+        if self.fpga.status == ConnectionStatus::Connected && self.fpga.heartbeat() {
+            return true;
+        }
+
+        // The known ports are probed in the background, the plot keeps running meanwhile
+        if let Some(probe) = &self.reconnecting {
+            if !probe.is_finished() {
+                return false;
+            }
+            let found = self.reconnecting.take().unwrap().join().ok().flatten();
+            self.fpga.reconnected(found.is_some());
+            let Some((port, conn)) = found else {
+                debug!("FPGA still unreachable, next attempt in {:?}", self.fpga.reconnect_delay());
+                return false;
+            };
+            info!("FPGA reconnected on {}, restoring inputs", port);
+            self.fpga.resume(conn);
+            self.port = Some(port);
+            self.restore_state();
+            return true;
+        }
+
+        if self.port.is_none() || !self.fpga.should_reconnect() {
+            return false;
+        }
+        self.fpga.close();
+        self.fpga.status = ConnectionStatus::Reconnecting;
+        self.reconnecting = Some(Interface::probe(self.known_ports(), COMMAND_BAUD));
+        false
+    }
 }
 
 impl JITBackend for FPGABackend {
-    fn inspect(&mut self, _pos: BlockPos) {}
+    fn inspect(&mut self, pos: BlockPos) {
+        match self.link.find(pos) {
+            Some(intf) => debug!("Interface block at {}: {:#?}", pos, intf),
+            None => debug!("no interface block at pos {}", pos),
+        }
+    }
 
     fn reset<W: World>(&mut self, _world: &mut W, _io_only: bool) {}
 
@@ -42,17 +235,15 @@ impl JITBackend for FPGABackend {
     fn tick(&mut self) {}
 
     fn flush<W: World>(&mut self, world: &mut W, _io_only: bool) { 
-        self.fpga.send_command(FPGACommand::Capture);
-        self.fpga.send_command(FPGACommand::GetOutupts);
-        let mut output_iter: BinaryIterator = BinaryIterator::new(self.fpga.outputs.clone());
-        for (pos, block) in self.link.get_blocks_to_change(&mut output_iter) {
-            world.set_block(pos, block);
+        if !self.read_outputs() {
+            return;
         }
+        self.write_blocks(world);
     }
 
     fn compile(
         &mut self,
-        graph: CompileGraph,
+        mut graph: CompileGraph,
         _ticks: Vec<TickEntry>,
         plot: String,
         name: String,
@@ -61,13 +252,23 @@ impl JITBackend for FPGABackend {
     ) {
         //println!("{:?}", &graph);
 
-        for nodeid in graph.node_indices() {
-            let node = &graph[nodeid];
-            if let Some((pos, blockid)) = node.block {
-                let block = Block::from_id(blockid);
-                self.link.add_block(block, pos);
+        // Buses can also be given by a file next to the link file
+        let buses = format!("FPGA/bin/{}/{}/buses.json", plot, name);
+        if !options.sim_test && Path::new(&buses).exists() {
+            if let Err(err) = linker::apply_bus_file(&mut graph, Path::new(&buses)) {
+                warn!("Ignoring the bus file: {}", err);
             }
         }
+        self.link = Linker::from_graph(&graph);
+
+        // Quartus would reject these loops or turn them into latches
+        let cycles = same_tick_cycles::find(&graph);
+        if !cycles.is_empty() {
+            let message = cycles.iter().map(|cycle| same_tick_cycles::describe(&graph, cycle)).join("; ");
+            warn!("Not building for the FPGA: {}", message);
+            self.refused = Some(message);
+            return;
+        }
 
         if !options.sim_test {
 
@@ -80,10 +281,21 @@ impl JITBackend for FPGABackend {
             self.link.generate_link_file(Path::new(&format!("FPGA/bin/{}/link.json", self.path)));
             println!("generating veruilog");
             assembler::generate_verilog(&graph, Path::new(&format!("FPGA/bin/{}/redstone.sv", self.path)));
-            println!("create_project");
-            self.config.create_project(Path::new(&format!("FPGA/bin/{}/prj/prj.tcl",self.path)), self.link.output_bits as u32, self.link.input_bits);
-            println!("compile");
-            self.config.compile(Path::new(&format!("FPGA/bin/{}/prj", self.path)));
+            if options.export_netlist {
+                if let Err(err) = netlist::export(&graph, Path::new(&format!("FPGA/bin/{}", self.path))) {
+                    warn!("Could not write the netlist: {}", err);
+                }
+            }
+            let remote = WorkerPool::global().map(|pool| self.remote_build(pool));
+            if !matches!(remote, Some(Ok(()))) {
+                if let Some(Err(err)) = remote {
+                    warn!("Remote build failed, building locally: {}", err);
+                }
+                println!("create_project");
+                self.config.create_project(Path::new(&format!("FPGA/bin/{}/prj/prj.tcl",self.path)), self.link.output_bits as u32, self.link.input_bits);
+                println!("compile");
+                self.config.compile(Path::new(&format!("FPGA/bin/{}/prj", self.path)));
+            }
             println!("done");
             _ = copy(Path::new(&format!("FPGA/bin/{}/prj/RoC.sof", self.path)), Path::new(&format!("FPGA/bin/{}/RoC.sof", self.path)));
             if !options.compile_verilog {
@@ -95,22 +307,63 @@ impl JITBackend for FPGABackend {
         }
     }
 
-    fn run(&mut self) {
+    fn run(&mut self) -> Result<(), String> {
+        if let Some(message) = &self.refused {
+            return Err(message.clone());
+        }
         println!("programming");
-        self.config.program(Path::new(&format!("FPGA/bin/{}", self.path)));
+        let results = self.config.program(Path::new(&format!("FPGA/bin/{}", self.path)));
+        if !results.success {
+            return Err(format!("Programming failed: {}", results.message));
+        }
         println!("serial start");
         println!("{}", self.link.get_output_bytes());
         self.fpga.outputs = vec![0; self.link.get_output_bytes()];
-        self.fpga.serial_start(&self.config.command_com, 2500000);
-        self.set_rtps(10);
+        if !self.connect() {
+            return Err(match self.known_ports()[..] {
+                [] => "Could not find a responding FPGA on any serial port".to_string(),
+                ref ports => format!("No FPGA answered on {}, set command_com to auto to search every port", ports.join(", ")),
+            });
+        }
+        self.ticks = 0;
+        if self.fpga.send_command(FPGACommand::GetTicks) {
+            self.ticks = self.fpga.ticks as u64;
+        }
+        Ok(())
     }
 
     fn stop(&mut self) {
+        self.fpga.close();
         self.fpga = Default::default();
+        self.port = None;
+        self.reconnecting = None;
+    }
+
+    /// Stepped boards only remember the rate, `SetRTPS` would make them run freely
+    fn set_rtps(&mut self, rtps: Option<u32>) {
+        let rtps = rtps.unwrap_or(RTPS_UNLIMITED);
+        self.rtps = Some(rtps);
+        if !self.stepped {
+            self.fpga.send_command(FPGACommand::SetRTPS(rtps));
+        }
+    }
+
+    fn hardware_ticks(&self) -> Option<u64> {
+        Some(self.ticks)
+    }
+
+    fn read_hardware_ticks(&mut self) -> Option<u64> {
+        let last = self.ticks as u32;
+        // Steps are counted when they are sent, the board may still be running them
+        if self.fpga.send_command(FPGACommand::GetTicks) {
+            let ran = self.fpga.ticks.wrapping_sub(last) as i32;
+            self.ticks += ran.max(0) as u64;
+        }
+        Some(self.ticks)
     }
 
-    fn set_rtps(&mut self, rtps: u32) {
-        self.fpga.send_command(FPGACommand::SetRTPS(rtps));
+    fn connection_status(&self) -> Option<ConnectionStatus> {
+        Some(self.fpga.status)
     }
 
     fn has_pending_ticks(&self) -> bool {false}
@@ -0,0 +1,494 @@
+//! Netlist export of a compile graph as Yosys JSON or BLIF.
+//!
+//! Every node drives a 4 bit signal strength net. Links become `RS_SUB` cells
+//! for their distance and are merged with `RS_MAX`, so every cell of
+//! [`CELL_LIBRARY`] has a fixed width. The top module has the same `tick`,
+//! `inputs` and `outputs` ports as the `RoC` module from `assembler.rs`, with
+//! inputs and outputs in [`Linker`](super::linker::Linker) order, and can be
+//! used in its place.
+
+use super::assembler::Identifiers;
+use super::linker::{IntfBlock, Linker};
+use mchprs_blocks::blocks::ComparatorMode;
+use mchprs_blocks::BlockPos;
+use mchprs_redpiler::compile_graph::{delay_segments, logic_sources, observed_node, CompileGraph, LinkType, NodeIdx, NodeType};
+use mchprs_redpiler::logic;
+use mchprs_redpiler::passes::same_tick_cycles::is_locker;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use rustc_hash::FxHashMap;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::warn;
+
+/// Behavioural models of the cells. `RS_REPEATER` and `RS_DELAY` wrap `repeater` and
+/// `delay` from `FPGA/src/redstone/components.sv`, which has to be read as well.
+/// `RS_LOGIC` has a bit per input and looks its function up in `TABLE`, see
+/// [`mchprs_redpiler::logic`]. `RS_OBSERVER` behaves like `observer`, with `SEEN` and `LATE` from
+/// [`observed_node`].
+pub const CELL_LIBRARY: &str = r#"// Cells of the redpiler netlist export. Strengths are 4 bit, 15 is fully powered.
+
+module RS_INPUT (input A, output [3:0] Y);
+    assign Y = {4{A}};
+endmodule
+
+module RS_OUTPUT (input [3:0] A, output Y);
+    assign Y = |A;
+endmodule
+
+module RS_CONST #(parameter SS = 0) (output [3:0] Y);
+    assign Y = SS;
+endmodule
+
+// Strength left after DIST blocks of wire
+module RS_SUB #(parameter DIST = 0) (input [3:0] A, output [3:0] Y);
+    assign Y = A > DIST ? A - DIST : 4'd0;
+endmodule
+
+module RS_MAX (input [3:0] A, input [3:0] B, output [3:0] Y);
+    assign Y = A > B ? A : B;
+endmodule
+
+module RS_TORCH #(parameter INIT = 1) (input CLK, input [3:0] A, output [3:0] Y);
+    reg lit = INIT;
+    assign Y = {4{lit}};
+    always @(posedge CLK) lit <= ~|A;
+endmodule
+
+module RS_REPEATER #(parameter DELAY = 1, INIT = 0, LOCK_OUT = 0, LOCKABLE = 0) (
+    input CLK, input [3:0] A, input [3:0] L, output [3:0] Y
+);
+    wire out;
+    repeater #(.t(DELAY), .state(INIT), .lock_out(LOCK_OUT), .lockable(LOCKABLE)) r (
+        .i_clk(CLK), .i_in(|A), .i_lock(|L), .o_out(out)
+    );
+    assign Y = {4{out}};
+endmodule
+
+module RS_DELAY #(parameter PULSE = 1, DELAY = 2, INIT = 0) (input CLK, input [3:0] A, output [3:0] Y);
+    wire out;
+    delay #(.pulse(PULSE), .t(DELAY), .state(INIT)) d (.i_clk(CLK), .i_in(|A), .o_out(out));
+    assign Y = {4{out}};
+endmodule
+
+module RS_LOGIC #(parameter TABLE = 0, DEPTH = 2, INIT = 0) (input CLK, input [4:0] A, output [3:0] Y);
+    wire [31:0] table_bits = TABLE;
+    reg [DEPTH-1:0] stages = INIT;
+    always @(posedge CLK) stages <= {stages[DEPTH-2:0], table_bits[A]};
+    assign Y = {4{stages[DEPTH-1]}};
+endmodule
+
+// `phase` counts the ticks of a pulse, the input is ignored while it runs
+module RS_OBSERVER #(parameter INIT = 0, SEEN = 0, LATE = 0) (input CLK, input [3:0] A, output [3:0] Y);
+    reg last = SEEN;
+    reg [1:0] phase = INIT ? 2'd3 : 2'd0;
+    reg blind = 0;
+    assign Y = {4{phase[1]}};
+    always @(posedge CLK) begin
+        last <= |A;
+        blind <= !LATE && phase == 2'd3;
+        if (phase != 2'd0) phase <= phase + 2'd1;
+        else if ((|A) != last && !blind) phase <= 2'd1;
+    end
+endmodule
+
+// A is the back input, B the strongest side. FAR is the strength read through
+// a block, used while A is below 15; 16 means there is none.
+module RS_COMPARATOR #(parameter SUBTRACT = 0, INIT = 0, FAR = 16) (
+    input CLK, input [3:0] A, input [3:0] B, output [3:0] Y
+);
+    reg [3:0] ss = INIT;
+    wire [3:0] back = FAR < 16 && A < 15 ? FAR : A;
+    assign Y = ss;
+    always @(posedge CLK)
+        if (back < B) ss <= 0;
+        else ss <= SUBTRACT ? back - B : back;
+endmodule
+"#;
+
+/// Ports of every cell type: name, whether it is an input, width
+const CELLS: &[(&str, &[(&str, bool, usize)])] = &[
+    ("RS_INPUT", &[("A", true, 1), ("Y", false, 4)]),
+    ("RS_OUTPUT", &[("A", true, 4), ("Y", false, 1)]),
+    ("RS_CONST", &[("Y", false, 4)]),
+    ("RS_SUB", &[("A", true, 4), ("Y", false, 4)]),
+    ("RS_MAX", &[("A", true, 4), ("B", true, 4), ("Y", false, 4)]),
+    ("RS_TORCH", &[("CLK", true, 1), ("A", true, 4), ("Y", false, 4)]),
+    ("RS_REPEATER", &[("CLK", true, 1), ("A", true, 4), ("L", true, 4), ("Y", false, 4)]),
+    ("RS_COMPARATOR", &[("CLK", true, 1), ("A", true, 4), ("B", true, 4), ("Y", false, 4)]),
+    ("RS_DELAY", &[("CLK", true, 1), ("A", true, 4), ("Y", false, 4)]),
+    ("RS_LOGIC", &[("CLK", true, 1), ("A", true, logic::MAX_INPUTS), ("Y", false, 4)]),
+    ("RS_OBSERVER", &[("CLK", true, 1), ("A", true, 4), ("Y", false, 4)]),
+];
+
+pub(crate) fn cell_ports(ty: &str) -> &'static [(&'static str, bool, usize)] {
+    CELLS.iter().find(|(name, _)| *name == ty).unwrap().1
+}
+
+pub(crate) struct Cell {
+    pub(crate) name: String,
+    pub(crate) ty: &'static str,
+    pub(crate) params: Vec<(&'static str, u32)>,
+    /// Bits of every port, in the order of `CELLS`
+    pub(crate) connections: Vec<Vec<usize>>,
+    pub(crate) pos: Option<BlockPos>,
+}
+
+impl Cell {
+    pub(crate) fn param(&self, name: &str) -> u32 {
+        self.params.iter().find(|(n, _)| *n == name).map_or(0, |(_, v)| *v)
+    }
+}
+
+/// A flat netlist of library cells. Bits are numbered from 2 like in Yosys,
+/// 0 and 1 are the constants.
+pub struct Netlist {
+    pub(crate) cells: Vec<Cell>,
+    /// Name of every bit, indexed by bit
+    bit_names: Vec<String>,
+    nets: Vec<(String, Vec<usize>)>,
+    tick: usize,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+}
+
+impl Netlist {
+    pub fn new(graph: &CompileGraph) -> Netlist {
+        let mut netlist = Netlist {
+            cells: Vec::new(),
+            bit_names: vec!["0".to_string(), "1".to_string()],
+            nets: Vec::new(),
+            tick: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+        netlist.tick = netlist.net("tick", 1)[0];
+        netlist.bit_names[netlist.tick] = "tick".to_string();
+
+        // The ports have the widths and bit order of the link file
+        let link = Linker::from_graph(graph);
+        netlist.inputs = netlist.net("inputs", link.input_bits as usize);
+        netlist.outputs = netlist.net("outputs", link.output_bits as usize);
+        let input_bits = port_bits(&link.inputs);
+        let output_bits = port_bits(&link.outputs);
+
+        // Nets first, links can point backwards in the graph
+        let mut strengths = FxHashMap::default();
+        for idx in graph.node_indices() {
+            if !matches!(graph[idx].ty, NodeType::Lamp | NodeType::Trapdoor | NodeType::NoteBlock { .. }) {
+                strengths.insert(idx, netlist.net(&format!("n{}", idx.index()), 4));
+            }
+        }
+
+        let mut identifiers = Identifiers::default();
+        for idx in graph.node_indices() {
+            let node = &graph[idx];
+            let id = idx.index();
+            let pos = node.block.map(|(pos, _)| pos);
+            let key = port_key(pos, id as u32);
+            let out = strengths.get(&idx).cloned();
+            match node.ty {
+                NodeType::Lever | NodeType::Button | NodeType::PressurePlate => {
+                    // Inputs the linker has no bit for stay off
+                    let a = vec![input_bits.get(&key).map_or(0, |&(bit, _)| netlist.inputs[bit])];
+                    netlist.cell(format!("c{}", id), "RS_INPUT", vec![], vec![a, out.unwrap()], pos);
+                }
+                NodeType::Lamp | NodeType::Trapdoor => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    if let Some(&(bit, _)) = output_bits.get(&key) {
+                        let y = vec![netlist.outputs[bit]];
+                        netlist.cell(format!("c{}", id), "RS_OUTPUT", vec![], vec![a, y], pos);
+                    }
+                }
+                NodeType::Constant => {
+                    let params = vec![("SS", node.state.output_strength as u32)];
+                    netlist.cell(format!("c{}", id), "RS_CONST", params, vec![out.unwrap()], pos);
+                }
+                NodeType::Wire => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    netlist.cell(format!("c{}", id), "RS_MAX", vec![], vec![a.clone(), a, out.unwrap()], pos);
+                }
+                NodeType::Torch => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    let params = vec![("INIT", node.state.powered as u32)];
+                    let clk = vec![netlist.tick];
+                    netlist.cell(format!("c{}", id), "RS_TORCH", params, vec![clk, a, out.unwrap()], pos);
+                }
+                NodeType::Observer => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    let (seen, late) = observed_node(graph, idx);
+                    let params = vec![
+                        ("INIT", node.state.powered as u32),
+                        ("SEEN", seen as u32),
+                        ("LATE", late as u32),
+                    ];
+                    let clk = vec![netlist.tick];
+                    netlist.cell(format!("c{}", id), "RS_OBSERVER", params, vec![clk, a, out.unwrap()], pos);
+                }
+                NodeType::Repeater { delay, .. } => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    let l = netlist.merge(graph, &strengths, idx, LinkType::Side);
+                    let params = vec![
+                        ("DELAY", delay as u32),
+                        ("INIT", node.state.powered as u32),
+                        ("LOCK_OUT", is_locker(graph, idx) as u32),
+                        ("LOCKABLE", has_side_inputs(graph, idx) as u32),
+                    ];
+                    let clk = vec![netlist.tick];
+                    netlist.cell(format!("c{}", id), "RS_REPEATER", params, vec![clk, a, l, out.unwrap()], pos);
+                }
+                NodeType::Delay { ref stages } => {
+                    // One cell per part of the chain, the last one drives the node's net
+                    let segments = delay_segments(stages);
+                    let mut a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    for (k, &(pulse, delay)) in segments.iter().enumerate() {
+                        let params = vec![
+                            ("PULSE", pulse as u32),
+                            ("DELAY", delay),
+                            ("INIT", node.state.powered as u32),
+                        ];
+                        let (name, y) = if k + 1 == segments.len() {
+                            (format!("c{}", id), out.clone().unwrap())
+                        } else {
+                            (format!("c{}_{}", id, k), netlist.net(&format!("n{}_{}", id, k), 4))
+                        };
+                        let clk = vec![netlist.tick];
+                        netlist.cell(name, "RS_DELAY", params, vec![clk, a, y.clone()], pos);
+                        a = y;
+                    }
+                }
+                NodeType::Logic { ref gates } => {
+                    // The sources are either off or at 15, any bit tells whether they are on
+                    let mut a = vec![0; logic::MAX_INPUTS];
+                    for (input, source) in logic_sources(graph, idx) {
+                        a[input] = strengths[&source][0];
+                    }
+                    let init = logic::pending_outputs(gates)
+                        .iter()
+                        .fold(0, |init, &on| init << 1 | on as u32);
+                    let params = vec![
+                        ("TABLE", logic::truth_table(gates)),
+                        ("DEPTH", logic::depth(gates) as u32),
+                        ("INIT", init),
+                    ];
+                    let clk = vec![netlist.tick];
+                    netlist.cell(format!("c{}", id), "RS_LOGIC", params, vec![clk, a, out.unwrap()], pos);
+                }
+                NodeType::Comparator { mode, far_input, .. } => {
+                    let a = netlist.merge(graph, &strengths, idx, LinkType::Default);
+                    let b = netlist.merge(graph, &strengths, idx, LinkType::Side);
+                    let params = vec![
+                        ("SUBTRACT", (mode == ComparatorMode::Subtract) as u32),
+                        ("INIT", node.state.output_strength as u32),
+                        ("FAR", far_input.map_or(16, |ss| ss as u32)),
+                    ];
+                    let clk = vec![netlist.tick];
+                    netlist.cell(format!("c{}", id), "RS_COMPARATOR", params, vec![clk, a, b, out.unwrap()], pos);
+                }
+                NodeType::NoteBlock { .. } => {}
+            }
+
+            // Forced outputs are one bit, dot wires are hex lamps with the whole strength
+            match (output_bits.get(&key), strengths.get(&idx)) {
+                (Some(&(bit, 1)), Some(out)) => {
+                    let y = vec![netlist.outputs[bit]];
+                    netlist.cell(format!("c{}_out", id), "RS_OUTPUT", vec![], vec![out.clone(), y], None);
+                }
+                (Some(&(bit, width)), Some(out)) => {
+                    let y = netlist.outputs[bit..bit + width].to_vec();
+                    netlist.cell(format!("c{}_out", id), "RS_MAX", vec![], vec![out.clone(), out.clone(), y], None);
+                }
+                _ => {}
+            }
+            // Named nets share the bits of the node, like the wires in the verilog.
+            // Outputs have no net but take their name all the same, so suffixes match.
+            if let Some(name) = &node.annotations.name {
+                let name = identifiers.unique("n_", name);
+                if let Some(bits) = strengths.get(&idx) {
+                    netlist.nets.push((name, bits.clone()));
+                }
+            }
+        }
+        netlist
+    }
+
+    fn net(&mut self, name: &str, width: usize) -> Vec<usize> {
+        let start = self.bit_names.len();
+        for i in 0..width {
+            self.bit_names.push(format!("{}[{}]", name, i));
+        }
+        let bits: Vec<usize> = (start..start + width).collect();
+        self.nets.push((name.to_string(), bits.clone()));
+        bits
+    }
+
+    fn cell(&mut self, name: String, ty: &'static str, params: Vec<(&'static str, u32)>, connections: Vec<Vec<usize>>, pos: Option<BlockPos>) {
+        debug_assert!(cell_ports(ty).iter().zip(&connections).all(|(port, bits)| port.2 == bits.len()));
+        self.cells.push(Cell { name, ty, params, connections, pos });
+    }
+
+    /// The strongest input of `node` over links of type `ty`
+    fn merge(&mut self, graph: &CompileGraph, strengths: &FxHashMap<NodeIdx, Vec<usize>>, node: NodeIdx, ty: LinkType) -> Vec<usize> {
+        let id = node.index();
+        let side = if ty == LinkType::Side { "s" } else { "a" };
+        let mut merged: Option<Vec<usize>> = None;
+        for (i, edge) in graph.edges_directed(node, Direction::Incoming).filter(|e| e.weight().ty == ty).enumerate() {
+            let Some(source) = strengths.get(&edge.source()) else {
+                warn!("Netlist: {:?} can't drive other nodes", graph[edge.source()].ty);
+                continue;
+            };
+            let dist = edge.weight().ss;
+            let input = if dist == 0 {
+                source.clone()
+            } else {
+                let y = self.net(&format!("n{}_{}{}", id, side, i), 4);
+                self.cell(format!("c{}_{}sub{}", id, side, i), "RS_SUB", vec![("DIST", dist as u32)], vec![source.clone(), y.clone()], None);
+                y
+            };
+            merged = Some(match merged {
+                None => input,
+                Some(prev) => {
+                    let y = self.net(&format!("n{}_{}max{}", id, side, i), 4);
+                    self.cell(format!("c{}_{}max{}", id, side, i), "RS_MAX", vec![], vec![prev, input, y.clone()], None);
+                    y
+                }
+            });
+        }
+        merged.unwrap_or_else(|| {
+            let y = self.net(&format!("n{}_{}", id, side), 4);
+            self.cell(format!("c{}_{}const", id, side), "RS_CONST", vec![("SS", 0)], vec![y.clone()], None);
+            y
+        })
+    }
+
+    pub fn to_yosys_json(&self) -> Value {
+        let mut ports = Map::new();
+        ports.insert("tick".to_string(), json!({ "direction": "input", "bits": [self.tick] }));
+        if !self.inputs.is_empty() {
+            ports.insert("inputs".to_string(), json!({ "direction": "input", "bits": self.inputs }));
+        }
+        if !self.outputs.is_empty() {
+            ports.insert("outputs".to_string(), json!({ "direction": "output", "bits": self.outputs }));
+        }
+
+        let mut cells = Map::new();
+        for cell in &self.cells {
+            let ports = cell_ports(cell.ty);
+            let params: Map<String, Value> = cell.params.iter().map(|(name, v)| (name.to_string(), json!(format!("{:032b}", v)))).collect();
+            let mut attributes = Map::new();
+            if let Some(pos) = cell.pos {
+                attributes.insert("src".to_string(), json!(format!("{},{},{}", pos.x, pos.y, pos.z)));
+            }
+            let directions: Map<String, Value> =
+                ports.iter().map(|(name, input, _)| (name.to_string(), json!(if *input { "input" } else { "output" }))).collect();
+            let connections: Map<String, Value> =
+                ports.iter().zip(&cell.connections).map(|((name, _, _), bits)| (name.to_string(), json!(bits))).collect();
+            cells.insert(
+                cell.name.clone(),
+                json!({
+                    "hide_name": 0,
+                    "type": cell.ty,
+                    "parameters": params,
+                    "attributes": attributes,
+                    "port_directions": directions,
+                    "connections": connections,
+                }),
+            );
+        }
+
+        let netnames: Map<String, Value> = self
+            .nets
+            .iter()
+            .map(|(name, bits)| (name.clone(), json!({ "hide_name": 0, "bits": bits, "attributes": {} })))
+            .collect();
+
+        json!({
+            "creator": "redpiler",
+            "modules": {
+                "RoC": {
+                    "attributes": { "top": "00000000000000000000000000000001" },
+                    "ports": ports,
+                    "cells": cells,
+                    "netnames": netnames,
+                }
+            }
+        })
+    }
+
+    /// Yosys flavoured BLIF with `.param` and `.cname`, followed by a blackbox model per cell type
+    pub fn to_blif(&self) -> String {
+        let names = |bits: &[usize]| bits.iter().map(|b| self.bit_names[*b].as_str()).collect::<Vec<_>>().join(" ");
+        let mut blif = String::from("# redpiler netlist, see the RS_* cells in rs_cells.v\n.model RoC\n");
+        let inputs: Vec<usize> = std::iter::once(self.tick).chain(self.inputs.iter().copied()).collect();
+        blif.push_str(&format!(".inputs {}\n", names(&inputs)));
+        if !self.outputs.is_empty() {
+            blif.push_str(&format!(".outputs {}\n", names(&self.outputs)));
+        }
+
+        for cell in &self.cells {
+            blif.push_str(&format!(".subckt {}", cell.ty));
+            for ((port, _, _), bits) in cell_ports(cell.ty).iter().zip(&cell.connections) {
+                for (i, bit) in bits.iter().enumerate() {
+                    blif.push_str(&format!(" {}[{}]={}", port, i, self.bit_names[*bit]));
+                }
+            }
+            blif.push_str(&format!("\n.cname {}\n", cell.name));
+            for (name, value) in &cell.params {
+                blif.push_str(&format!(".param {} {:032b}\n", name, value));
+            }
+        }
+        blif.push_str(".end\n");
+
+        for (ty, ports) in CELLS {
+            let bits = |input: bool| {
+                ports
+                    .iter()
+                    .filter(|p| p.1 == input)
+                    .flat_map(|(name, _, width)| (0..*width).map(move |i| format!("{}[{}]", name, i)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            blif.push_str(&format!("\n.model {}\n.inputs {}\n.outputs {}\n.blackbox\n.end\n", ty, bits(true), bits(false)));
+        }
+        blif
+    }
+}
+
+/// Writes `roc.json`, `roc.blif` and the cell library `rs_cells.v` to `dir`
+pub fn export(graph: &CompileGraph, dir: &Path) -> io::Result<()> {
+    let netlist = Netlist::new(graph);
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("roc.json"), serde_json::to_string_pretty(&netlist.to_yosys_json())?)?;
+    fs::write(dir.join("roc.blif"), netlist.to_blif())?;
+    fs::write(dir.join("rs_cells.v"), CELL_LIBRARY)
+}
+
+/// Identifies the interface block of a node: its position, or its node id for
+/// the boundaries of a hybrid build, which have none
+fn port_key(pos: Option<BlockPos>, node: u32) -> (Option<BlockPos>, Option<u32>) {
+    match pos {
+        Some(pos) => (Some(pos), None),
+        None => (None, Some(node)),
+    }
+}
+
+/// The first bit and width of every interface block in a port of the linker
+fn port_bits(entries: &[IntfBlock]) -> FxHashMap<(Option<BlockPos>, Option<u32>), (usize, usize)> {
+    let mut bits = FxHashMap::default();
+    let mut bit = 0;
+    for entry in entries {
+        let key = match entry.boundary_node() {
+            Some(node) => (None, Some(node)),
+            None => (Some(entry.pos), None),
+        };
+        bits.insert(key, (bit, entry.bit_count() as usize));
+        bit += entry.bit_count() as usize;
+    }
+    bits
+}
+
+fn has_side_inputs(graph: &CompileGraph, node: NodeIdx) -> bool {
+    graph.edges_directed(node, Direction::Incoming).any(|e| e.weight().ty == LinkType::Side)
+}
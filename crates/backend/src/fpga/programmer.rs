@@ -0,0 +1,112 @@
+//! Tools that load a compiled bitstream onto a board.
+//!
+//! The tool is picked with the `programmer` field of the device config. The
+//! cable comes from `cable`, or `program_com` when there is none, and the
+//! position of the FPGA in the JTAG chain from `chain_index`. Without them
+//! quartus programs the second device on the `DE-SoC [USB-1]` cable, as it
+//! always did, and openFPGALoader picks the cable itself. Both tools are run
+//! from the `PATH`, quartus from `quartus_dir` if the config has one.
+
+use std::path::Path;
+use std::process::Command;
+
+use super::compiler::DeviceConfig;
+
+/// Cable quartus uses when the config has none
+const DEFAULT_QUARTUS_CABLE: &str = "DE-SoC [USB-1]";
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgrammerTool {
+    #[default]
+    Quartus,
+    OpenFPGALoader,
+    /// Does nothing and always succeeds, for running without a board attached
+    Mock,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProgramResults {
+    pub success: bool,
+    pub message: String,
+}
+
+impl ProgramResults {
+    fn failed(message: String) -> ProgramResults {
+        ProgramResults { success: false, message }
+    }
+}
+
+pub trait Programmer {
+    /// Programs `RoC.sof` in the directory `path`
+    fn program(&self, config: &DeviceConfig, path: &Path) -> ProgramResults;
+}
+
+pub fn programmer_for(tool: ProgrammerTool) -> Box<dyn Programmer> {
+    match tool {
+        ProgrammerTool::Quartus => Box::new(QuartusProgrammer),
+        ProgrammerTool::OpenFPGALoader => Box::new(OpenFPGALoader),
+        ProgrammerTool::Mock => Box::new(MockProgrammer),
+    }
+}
+
+/// Runs `command` and turns its exit status into `ProgramResults`
+fn run_tool(mut command: Command, tool: &str) -> ProgramResults {
+    let out = match command.output() {
+        Ok(out) => out,
+        Err(err) => return ProgramResults::failed(format!("Could not start {}: {}", tool, err)),
+    };
+    println!("{:?}", String::from_utf8_lossy(&out.stdout));
+
+    if out.status.success() {
+        ProgramResults { success: true, message: format!("{} finished", tool) }
+    }
+    else {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let reason = stderr.lines().chain(stdout.lines())
+            .find(|line| line.contains("Error"))
+            .unwrap_or("no error message")
+            .trim()
+            .to_string();
+        ProgramResults::failed(format!("{} failed ({}): {}", tool, out.status, reason))
+    }
+}
+
+pub struct QuartusProgrammer;
+
+impl Programmer for QuartusProgrammer {
+    fn program(&self, config: &DeviceConfig, path: &Path) -> ProgramResults {
+        let cable = config.cable().unwrap_or(DEFAULT_QUARTUS_CABLE);
+        let mut command = Command::new(config.quartus_tool("quartus_pgm"));
+        command
+            .current_dir(path)
+            .args(["-c", cable, "-m", "jtag", "-o"])
+            .arg(format!("p;RoC.sof@{}", config.chain_index()));
+        run_tool(command, "quartus_pgm")
+    }
+}
+
+pub struct OpenFPGALoader;
+
+impl Programmer for OpenFPGALoader {
+    fn program(&self, config: &DeviceConfig, path: &Path) -> ProgramResults {
+        let mut command = Command::new("openFPGALoader");
+        command.current_dir(path);
+        if let Some(cable) = config.cable() {
+            command.args(["-c", cable]);
+        }
+        // openFPGALoader counts the chain from 0, quartus from 1
+        command.args(["--index-chain", &config.chain_index().saturating_sub(1).to_string()]);
+        command.arg("RoC.sof");
+        run_tool(command, "openFPGALoader")
+    }
+}
+
+pub struct MockProgrammer;
+
+impl Programmer for MockProgrammer {
+    fn program(&self, _config: &DeviceConfig, path: &Path) -> ProgramResults {
+        ProgramResults { success: true, message: format!("mock programmed {}", path.display()) }
+    }
+}
@@ -0,0 +1,368 @@
+//! Remote synthesis for FPGA builds.
+//!
+//! Instead of running Quartus on the game server, `FPGABackend::compile` can
+//! send a [`BuildBundle`] to a `roc-worker` process. The worker runs the
+//! toolchain, streams its output back line by line and finally returns the
+//! bitstream and reports.
+//!
+//! Every message is a little endian `u32` length followed by a bincode encoded
+//! [`WorkerMsg`]. A connection carries exactly one build: the client sends a
+//! `Bundle`, the worker answers with any number of `Log` and `File` messages
+//! and ends with `Done`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+
+/// Where the shared worker pool is configured
+pub const WORKERS_CONFIG: &str = "FPGA/config/workers.json";
+/// Largest message accepted, bitstreams of big devices are a few tens of MB
+const MAX_MESSAGE_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundleFile {
+    /// Path relative to the bundle root, using `/` as separator
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// Everything needed to synthesize a build, laid out like the `FPGA` directory
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BuildBundle {
+    pub name: String,
+    /// Directory of the Quartus project, relative to the bundle root
+    pub project_dir: String,
+    pub files: Vec<BundleFile>,
+}
+
+impl BuildBundle {
+    pub fn new(name: &str, project_dir: &str) -> BuildBundle {
+        BuildBundle {
+            name: name.to_string(),
+            project_dir: project_dir.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Adds the file at `root/path` under the name `path`
+    pub fn add_file(&mut self, root: &Path, path: &str) -> io::Result<()> {
+        let data = fs::read(root.join(path))?;
+        self.files.push(BundleFile { path: path.to_string(), data });
+        Ok(())
+    }
+
+    /// Adds every file below `root/dir`
+    pub fn add_dir(&mut self, root: &Path, dir: &str) -> io::Result<()> {
+        for entry in fs::read_dir(root.join(dir))? {
+            let entry = entry?;
+            let name = format!("{}/{}", dir, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.add_dir(root, &name)?;
+            } else {
+                self.add_file(root, &name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum WorkerMsg {
+    Bundle(BuildBundle),
+    Log(String),
+    File { name: String, data: Vec<u8> },
+    Done { success: bool, message: String },
+}
+
+pub fn send_msg(stream: &mut impl Write, msg: &WorkerMsg) -> io::Result<()> {
+    let data = bincode::serialize(msg).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(&data)?;
+    stream.flush()
+}
+
+pub fn recv_msg(stream: &mut impl Read) -> io::Result<WorkerMsg> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("message of {} bytes is too large", len)));
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+    bincode::deserialize(&data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Returns `path` if it stays inside the directory it is joined to
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(path);
+    if path.components().all(|c| matches!(c, Component::Normal(_))) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+pub enum Toolchain {
+    /// Runs `quartus_sh` from the given bin directory
+    Quartus { bin_dir: PathBuf },
+    /// Pretends to synthesize, producing a placeholder bitstream
+    Mock,
+}
+
+impl Toolchain {
+    fn run_logged(mut command: Command, log: &mut dyn FnMut(&str)) -> Result<(), String> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| format!("Could not start toolchain: {}", err))?;
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                log(&line);
+            }
+        }
+        let status = child.wait().map_err(|err| err.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("toolchain exited with {}", status))
+        }
+    }
+
+    /// Builds the project in `prj`, leaving `RoC.sof` and the reports there
+    pub fn build(&self, prj: &Path, log: &mut dyn FnMut(&str)) -> Result<(), String> {
+        match self {
+            Toolchain::Quartus { bin_dir } => {
+                let quartus_sh = bin_dir.join("quartus_sh");
+                let mut setup = Command::new(&quartus_sh);
+                setup.current_dir(prj).args(["-t", "prj.tcl"]);
+                Self::run_logged(setup, log)?;
+
+                let mut compile = Command::new(&quartus_sh);
+                compile.current_dir(prj).args(["--flow", "compile", "RoC"]);
+                Self::run_logged(compile, log)
+            }
+            Toolchain::Mock => {
+                let tcl = fs::read(prj.join("prj.tcl")).map_err(|err| format!("Missing project: {}", err))?;
+                log("mock: synthesizing");
+                let mut sof = b"MOCK-SOF\n".to_vec();
+                sof.extend_from_slice(&tcl);
+                fs::write(prj.join("RoC.sof"), sof).map_err(|err| err.to_string())?;
+                fs::write(prj.join("RoC.fit.summary"), "Fitter Status : Successful\n").map_err(|err| err.to_string())?;
+                log("mock: done");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Files sent back after a build: the bitstream and any reports
+fn is_result_file(name: &str) -> bool {
+    name == "RoC.sof" || name.ends_with(".rpt") || name.ends_with(".summary")
+}
+
+pub struct Worker {
+    toolchain: Toolchain,
+    workdir: PathBuf,
+    /// Builds use all cores anyway, so they are run one after another
+    build_lock: Mutex<()>,
+    jobs: AtomicU64,
+}
+
+impl Worker {
+    pub fn new(toolchain: Toolchain, workdir: PathBuf) -> Worker {
+        Worker {
+            toolchain,
+            workdir,
+            build_lock: Mutex::new(()),
+            jobs: AtomicU64::new(0),
+        }
+    }
+
+    /// Accepts builds on `listener` forever
+    pub fn serve(self: Arc<Self>, listener: TcpListener) {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let worker = self.clone();
+            thread::spawn(move || {
+                if let Err(err) = worker.handle(stream) {
+                    println!("roc-worker: connection failed: {}", err);
+                }
+            });
+        }
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let WorkerMsg::Bundle(bundle) = recv_msg(&mut stream)? else {
+            return send_msg(&mut stream, &WorkerMsg::Done { success: false, message: "expected a build bundle".to_string() });
+        };
+
+        let _build = self.build_lock.lock().unwrap();
+        let job = self.jobs.fetch_add(1, Ordering::Relaxed);
+        let job_dir = self.workdir.join(format!("job{}", job));
+        let result = self.build(&mut stream, &bundle, &job_dir);
+        _ = fs::remove_dir_all(&job_dir);
+
+        let done = match result? {
+            Ok(()) => WorkerMsg::Done { success: true, message: format!("{} built", bundle.name) },
+            Err(message) => WorkerMsg::Done { success: false, message },
+        };
+        send_msg(&mut stream, &done)
+    }
+
+    /// The outer error is a broken connection, the inner one a failed build
+    fn build(&self, stream: &mut TcpStream, bundle: &BuildBundle, job_dir: &Path) -> io::Result<Result<(), String>> {
+        for file in &bundle.files {
+            let Some(path) = safe_relative_path(&file.path) else {
+                return Ok(Err(format!("Invalid path in bundle: {}", file.path)));
+            };
+            let path = job_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, &file.data)?;
+        }
+        let Some(prj) = safe_relative_path(&bundle.project_dir) else {
+            return Ok(Err(format!("Invalid project dir: {}", bundle.project_dir)));
+        };
+        let prj = job_dir.join(prj);
+
+        let mut log_err = None;
+        let built = self.toolchain.build(&prj, &mut |line| {
+            if log_err.is_none() {
+                log_err = send_msg(stream, &WorkerMsg::Log(line.to_string())).err();
+            }
+        });
+        if let Some(err) = log_err {
+            return Err(err);
+        }
+        if let Err(err) = built {
+            return Ok(Err(err));
+        }
+
+        for dir in [prj.clone(), prj.join("output_files")] {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if is_result_file(&name) {
+                    let data = fs::read(entry.path())?;
+                    send_msg(stream, &WorkerMsg::File { name, data })?;
+                }
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// Sends `bundle` to the worker at `address` and stores the returned files in `out_dir`
+pub fn remote_build(address: &str, bundle: &BuildBundle, out_dir: &Path, on_log: &mut dyn FnMut(&str)) -> Result<(), String> {
+    let mut stream = TcpStream::connect(address).map_err(|err| format!("Could not reach worker {}: {}", address, err))?;
+    send_msg(&mut stream, &WorkerMsg::Bundle(bundle.clone())).map_err(|err| err.to_string())?;
+    fs::create_dir_all(out_dir).map_err(|err| err.to_string())?;
+
+    loop {
+        match recv_msg(&mut stream).map_err(|err| format!("Lost worker {}: {}", address, err))? {
+            WorkerMsg::Log(line) => on_log(&line),
+            WorkerMsg::File { name, data } => {
+                // Only plain file names are accepted back
+                let Some(file_name) = Path::new(&name).file_name() else {
+                    continue;
+                };
+                fs::write(out_dir.join(file_name), data).map_err(|err| err.to_string())?;
+            }
+            WorkerMsg::Done { success: true, .. } => return Ok(()),
+            WorkerMsg::Done { success: false, message } => return Err(message),
+            WorkerMsg::Bundle(_) => return Err("Worker sent an unexpected bundle".to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WorkerConfig {
+    pub name: String,
+    pub address: String,
+}
+
+/// Workers shared by all plots. A build waits until one of them is free.
+pub struct WorkerPool {
+    workers: Vec<WorkerConfig>,
+    busy: Mutex<Vec<bool>>,
+    available: Condvar,
+}
+
+pub struct PooledWorker<'a> {
+    pool: &'a WorkerPool,
+    idx: usize,
+}
+
+impl PooledWorker<'_> {
+    pub fn config(&self) -> &WorkerConfig {
+        &self.pool.workers[self.idx]
+    }
+}
+
+impl Drop for PooledWorker<'_> {
+    fn drop(&mut self) {
+        self.pool.busy.lock().unwrap()[self.idx] = false;
+        self.pool.available.notify_one();
+    }
+}
+
+impl WorkerPool {
+    pub fn new(workers: Vec<WorkerConfig>) -> WorkerPool {
+        WorkerPool {
+            busy: Mutex::new(vec![false; workers.len()]),
+            workers,
+            available: Condvar::new(),
+        }
+    }
+
+    /// Returns `None` if there is no config or it lists no workers
+    pub fn load_from_config(path: &str) -> Option<WorkerPool> {
+        let config_str = fs::read_to_string(path).ok()?;
+        let workers: Vec<WorkerConfig> = match serde_json::from_str(&config_str) {
+            Ok(workers) => workers,
+            Err(err) => {
+                println!("Invalid worker config {}: {}", path, err);
+                return None;
+            }
+        };
+        if workers.is_empty() {
+            return None;
+        }
+        Some(WorkerPool::new(workers))
+    }
+
+    /// The pool from [`WORKERS_CONFIG`], loaded on first use
+    pub fn global() -> Option<&'static WorkerPool> {
+        static POOL: OnceLock<Option<WorkerPool>> = OnceLock::new();
+        POOL.get_or_init(|| WorkerPool::load_from_config(WORKERS_CONFIG)).as_ref()
+    }
+
+    /// Blocks until a worker is free and reserves it
+    pub fn acquire(&self) -> PooledWorker<'_> {
+        let mut busy = self.busy.lock().unwrap();
+        loop {
+            if let Some(idx) = busy.iter().position(|b| !*b) {
+                busy[idx] = true;
+                return PooledWorker { pool: self, idx };
+            }
+            busy = self.available.wait(busy).unwrap();
+        }
+    }
+
+    pub fn build(&self, bundle: &BuildBundle, out_dir: &Path, on_log: &mut dyn FnMut(&str)) -> Result<(), String> {
+        let worker = self.acquire();
+        println!("building {} on worker {}", bundle.name, worker.config().name);
+        remote_build(&worker.config().address, bundle, out_dir, on_log)
+    }
+}
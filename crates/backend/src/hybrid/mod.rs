@@ -0,0 +1,140 @@
+//! The hybrid backend places everything the verilog assembler supports on an
+//! FPGA and runs the rest of the graph in a [`DirectBackend`]. Signals crossing
+//! between the two are exchanged through the linker once per tick, with the
+//! board stepped in lockstep with the CPU.
+
+mod partition;
+
+use crate::direct::DirectBackend;
+use crate::fpga::compiler::DeviceConfig;
+use crate::fpga::interface::ConnectionStatus;
+use crate::fpga::FPGABackend;
+use crate::JITBackend;
+use mchprs_blocks::BlockPos;
+use mchprs_redpiler::compile_graph::CompileGraph;
+use mchprs_redpiler::CompilerOptions;
+use mchprs_world::{TickEntry, World};
+use tracing::debug;
+
+#[derive(Default)]
+pub struct HybridBackend {
+    fpga: FPGABackend,
+    direct: DirectBackend,
+    /// (FPGA trapdoor, CPU lever) pairs
+    to_cpu: Vec<(u32, usize)>,
+    /// (CPU trapdoor, FPGA lever) pairs
+    to_fpga: Vec<(usize, u32)>,
+}
+
+impl HybridBackend {
+    /// The part of the build on the board
+    pub fn fpga(&mut self) -> &mut FPGABackend {
+        &mut self.fpga
+    }
+
+    fn sync_to_fpga(&mut self) {
+        for &(cpu_node, fpga_node) in &self.to_fpga {
+            let powered = self.direct.is_powered(cpu_node);
+            self.fpga.set_boundary_input(fpga_node, powered);
+        }
+    }
+
+    fn sync_to_cpu(&mut self) {
+        for &(fpga_node, cpu_node) in &self.to_cpu {
+            let powered = self.fpga.link.boundary_output(fpga_node);
+            self.direct.set_boundary_input(cpu_node, powered);
+        }
+    }
+}
+
+impl JITBackend for HybridBackend {
+    fn compile(
+        &mut self,
+        graph: CompileGraph,
+        ticks: Vec<TickEntry>,
+        plot: String,
+        name: String,
+        config: Option<DeviceConfig>,
+        options: &CompilerOptions,
+    ) {
+        let part = partition::partition(&graph);
+        debug!(
+            "Hybrid partition: {} FPGA nodes, {} CPU nodes, {} boundary signals",
+            part.fpga.node_count(),
+            part.cpu.node_count(),
+            part.to_cpu.len() + part.to_fpga.len()
+        );
+        self.to_cpu = part.to_cpu;
+        self.to_fpga = part.to_fpga;
+
+        // Scheduled ticks of nodes on the FPGA are lost, same as for a plain FPGA build
+        self.direct
+            .compile(part.cpu, ticks, plot.clone(), name.clone(), None, options);
+        self.fpga
+            .compile(part.fpga, Vec::new(), plot, name, config, options);
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        self.fpga.run()?;
+        // Stop the free running clock, ticks come from `tick` from now on
+        if !self.fpga.step(0) {
+            return Err("FPGA did not accept the step command".to_string());
+        }
+        self.sync_to_fpga();
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.fpga.stop();
+    }
+
+    fn tick(&mut self) {
+        if self.fpga.read_outputs() {
+            self.sync_to_cpu();
+        }
+        self.direct.tick();
+        self.sync_to_fpga();
+        self.fpga.step(1);
+    }
+
+    fn on_use_block(&mut self, pos: BlockPos) {
+        if self.fpga.link.has_input(pos) {
+            self.fpga.on_use_block(pos);
+        } else {
+            self.direct.on_use_block(pos);
+        }
+    }
+
+    fn set_pressure_plate(&mut self, pos: BlockPos, powered: bool) {
+        if self.direct.has_node_at(pos) {
+            self.direct.set_pressure_plate(pos, powered);
+        }
+    }
+
+    fn flush<W: World>(&mut self, world: &mut W, io_only: bool) {
+        self.direct.flush(world, io_only);
+        self.fpga.write_blocks(world);
+    }
+
+    fn reset<W: World>(&mut self, world: &mut W, io_only: bool) {
+        self.direct.reset(world, io_only);
+        self.fpga.reset(world, io_only);
+    }
+
+    fn has_pending_ticks(&self) -> bool {
+        self.direct.has_pending_ticks()
+    }
+
+    fn inspect(&mut self, pos: BlockPos) {
+        self.direct.inspect(pos);
+    }
+
+    /// The plot paces hybrid builds, the board only ever runs stepped
+    fn set_rtps(&mut self, rtps: Option<u32>) {
+        self.fpga.set_rtps(rtps);
+    }
+
+    fn connection_status(&self) -> Option<ConnectionStatus> {
+        self.fpga.connection_status()
+    }
+}
@@ -0,0 +1,316 @@
+use mchprs_redpiler::compile_graph::{
+    Annotations, CompileGraph, CompileLink, CompileNode, NodeIdx, NodeState, NodeType,
+};
+use mchprs_redpiler::passes::same_tick_cycles;
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    Fpga,
+    Cpu,
+}
+
+/// The graph of a hybrid build, split between the two backends.
+///
+/// Every edge crossing the split is replaced by a synthetic trapdoor on the
+/// source side and a synthetic lever on the target side. Both have no block,
+/// so the backends only know them by their index in the partition graph.
+#[derive(Default)]
+pub struct Partition {
+    pub fpga: CompileGraph,
+    pub cpu: CompileGraph,
+    /// (FPGA trapdoor, CPU lever) pairs
+    pub to_cpu: Vec<(u32, usize)>,
+    /// (CPU trapdoor, FPGA lever) pairs
+    pub to_fpga: Vec<(usize, u32)>,
+}
+
+impl Partition {
+    fn graph_mut(&mut self, side: Side) -> &mut CompileGraph {
+        match side {
+            Side::Fpga => &mut self.fpga,
+            Side::Cpu => &mut self.cpu,
+        }
+    }
+}
+
+/// Nodes the verilog assembler knows how to generate
+fn fpga_capable(ty: &NodeType) -> bool {
+    matches!(
+        ty,
+        NodeType::Repeater { .. }
+            | NodeType::Delay { .. }
+            | NodeType::Logic { .. }
+            | NodeType::Observer
+            | NodeType::Torch
+            | NodeType::Comparator { states: Some(_), .. }
+            | NodeType::Lever
+            | NodeType::Lamp
+            | NodeType::Trapdoor
+    )
+}
+
+/// Nodes whose output strength is always either 0 or 15
+fn digital_output(ty: &NodeType) -> bool {
+    matches!(
+        ty,
+        NodeType::Repeater { .. }
+            | NodeType::Delay { .. }
+            | NodeType::Logic { .. }
+            | NodeType::Observer
+            | NodeType::Torch
+            | NodeType::Lever
+            | NodeType::Button
+            | NodeType::PressurePlate
+    )
+}
+
+/// Nodes that only care whether any input is powered
+fn digital_input(ty: &NodeType) -> bool {
+    matches!(
+        ty,
+        NodeType::Repeater { .. }
+            | NodeType::Delay { .. }
+            | NodeType::Observer
+            | NodeType::Torch
+            | NodeType::Lamp
+            | NodeType::Trapdoor
+            | NodeType::NoteBlock { .. }
+    )
+}
+
+fn synthetic_node(ty: NodeType, powered: bool) -> CompileNode {
+    CompileNode {
+        is_input: ty == NodeType::Lever,
+        is_output: ty == NodeType::Trapdoor,
+        ty,
+        block: None,
+        state: NodeState::simple(powered),
+        annotations: Annotations::default(),
+    }
+}
+
+fn assign_sides(graph: &CompileGraph) -> FxHashMap<NodeIdx, Side> {
+    // Loops of locking repeaters would have no register in hardware
+    let same_tick: FxHashSet<NodeIdx> = same_tick_cycles::find(graph).into_iter().flatten().collect();
+    let mut sides: FxHashMap<NodeIdx, Side> = graph
+        .node_indices()
+        .map(|idx| {
+            let side = if fpga_capable(&graph[idx].ty) && !same_tick.contains(&idx) {
+                Side::Fpga
+            } else {
+                Side::Cpu
+            };
+            (idx, side)
+        })
+        .collect();
+
+    // The boundary only carries single bits, so an analog signal into an
+    // analog input has to stay on one side. Pull such pairs onto the CPU
+    // until nothing changes anymore.
+    loop {
+        let mut changed = false;
+        for edge in graph.edge_references() {
+            let (source, target) = (edge.source(), edge.target());
+            if graph[source].ty == NodeType::Constant || sides[&source] == sides[&target] {
+                continue;
+            }
+            if !digital_output(&graph[source].ty) && !digital_input(&graph[target].ty) {
+                let moved = if sides[&source] == Side::Fpga { source } else { target };
+                sides.insert(moved, Side::Cpu);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // A lever only driving CPU nodes would just waste a boundary bit
+    for idx in graph.node_indices() {
+        if graph[idx].ty == NodeType::Lever
+            && graph
+                .neighbors_directed(idx, petgraph::Direction::Outgoing)
+                .all(|target| sides[&target] == Side::Cpu)
+        {
+            sides.insert(idx, Side::Cpu);
+        }
+    }
+
+    sides
+}
+
+pub fn partition(graph: &CompileGraph) -> Partition {
+    let sides = assign_sides(graph);
+    let mut part = Partition::default();
+
+    let mut nodes_map: FxHashMap<(Side, NodeIdx), NodeIdx> = FxHashMap::default();
+    for idx in graph.node_indices() {
+        // Constants are copied to whichever side uses them
+        if graph[idx].ty == NodeType::Constant {
+            continue;
+        }
+        let side = sides[&idx];
+        let new_idx = part.graph_mut(side).add_node(graph[idx].clone());
+        nodes_map.insert((side, idx), new_idx);
+    }
+
+    let mut boundaries: FxHashMap<(NodeIdx, u8), NodeIdx> = FxHashMap::default();
+    for edge in graph.edge_references() {
+        let (source, target) = (edge.source(), edge.target());
+        let link = edge.weight();
+        let target_side = sides[&target];
+        let new_target = nodes_map[&(target_side, target)];
+
+        if graph[source].ty == NodeType::Constant {
+            let new_source = *nodes_map
+                .entry((target_side, source))
+                .or_insert_with(|| part.graph_mut(target_side).add_node(graph[source].clone()));
+            part.graph_mut(target_side)
+                .add_edge(new_source, new_target, link.clone());
+            continue;
+        }
+
+        let source_side = sides[&source];
+        if source_side == target_side {
+            let new_source = nodes_map[&(source_side, source)];
+            part.graph_mut(source_side)
+                .add_edge(new_source, new_target, link.clone());
+            continue;
+        }
+
+        // Digital sources cross as is and keep their distance on the target
+        // side, analog ones are compared against the distance before crossing
+        let digital = digital_output(&graph[source].ty);
+        let threshold = if digital { 0 } else { link.ss };
+        let lever = match boundaries.get(&(source, threshold)) {
+            Some(lever) => *lever,
+            None => {
+                let powered = graph[source].state.output_strength > threshold;
+                let new_source = nodes_map[&(source_side, source)];
+
+                let sink = part
+                    .graph_mut(source_side)
+                    .add_node(synthetic_node(NodeType::Trapdoor, powered));
+                part.graph_mut(source_side)
+                    .add_edge(new_source, sink, CompileLink::default(threshold));
+                let lever = part
+                    .graph_mut(target_side)
+                    .add_node(synthetic_node(NodeType::Lever, powered));
+
+                match source_side {
+                    Side::Fpga => part.to_cpu.push((sink.index() as u32, lever.index())),
+                    Side::Cpu => part.to_fpga.push((sink.index(), lever.index() as u32)),
+                }
+                boundaries.insert((source, threshold), lever);
+                lever
+            }
+        };
+        let ss = if digital { link.ss } else { 0 };
+        part.graph_mut(target_side)
+            .add_edge(lever, new_target, CompileLink { ss, ..link.clone() });
+    }
+
+    part
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mchprs_blocks::blocks::{ComparatorMode, Instrument};
+    use mchprs_redpiler::compile_graph::LinkType;
+
+    fn node(ty: NodeType) -> CompileNode {
+        CompileNode {
+            is_input: ty == NodeType::Lever || ty == NodeType::Button,
+            is_output: ty == NodeType::Lamp,
+            ty,
+            block: None,
+            state: NodeState::default(),
+            annotations: Annotations::default(),
+        }
+    }
+
+    fn repeater_ty() -> NodeType {
+        NodeType::Repeater { delay: 1, facing_diode: false }
+    }
+
+    fn comparator(states: Option<u16>) -> NodeType {
+        NodeType::Comparator { mode: ComparatorMode::Compare, far_input: None, facing_diode: false, states }
+    }
+
+    #[test]
+    fn sides() {
+        let mut graph = CompileGraph::new();
+        let lever = graph.add_node(node(NodeType::Lever));
+        let repeater = graph.add_node(node(repeater_ty()));
+        let note = graph.add_node(node(NodeType::NoteBlock { instrument: Instrument::Harp, note: 0 }));
+        let cpu_lever = graph.add_node(node(NodeType::Lever));
+        graph.add_edge(lever, repeater, CompileLink::default(0));
+        graph.add_edge(repeater, note, CompileLink::default(0));
+        graph.add_edge(cpu_lever, note, CompileLink::default(0));
+
+        // An analog comparator into one the FPGA can't build has to stay on the CPU
+        let discrete = graph.add_node(node(comparator(Some(0b11))));
+        let analog = graph.add_node(node(comparator(None)));
+        graph.add_edge(lever, discrete, CompileLink::default(0));
+        graph.add_edge(discrete, analog, CompileLink::default(2));
+
+        // Repeaters locking each other in a loop have no register on the FPGA
+        let a = graph.add_node(node(repeater_ty()));
+        let b = graph.add_node(node(repeater_ty()));
+        let locked = graph.add_node(node(repeater_ty()));
+        graph.add_edge(a, b, CompileLink::default(0));
+        graph.add_edge(b, a, CompileLink::default(0));
+        graph.add_edge(a, locked, CompileLink::new(LinkType::Side, 0));
+        graph.add_edge(b, locked, CompileLink::new(LinkType::Side, 0));
+
+        let sides = assign_sides(&graph);
+        assert_eq!(sides[&lever], Side::Fpga);
+        assert_eq!(sides[&repeater], Side::Fpga);
+        assert_eq!(sides[&note], Side::Cpu);
+        assert_eq!(sides[&cpu_lever], Side::Cpu);
+        assert_eq!(sides[&discrete], Side::Cpu);
+        assert_eq!(sides[&analog], Side::Cpu);
+        assert_eq!((sides[&a], sides[&b], sides[&locked]), (Side::Cpu, Side::Cpu, Side::Fpga));
+    }
+
+    #[test]
+    fn boundaries() {
+        let mut graph = CompileGraph::new();
+        let lever = graph.add_node(node(NodeType::Lever));
+        let torch = graph.add_node(node(NodeType::Torch));
+        let note = graph.add_node(node(NodeType::NoteBlock { instrument: Instrument::Harp, note: 0 }));
+        let wire = graph.add_node(node(NodeType::Wire));
+        let lamp = graph.add_node(node(NodeType::Lamp));
+        let constant = graph.add_node(node(NodeType::Constant));
+        graph[torch].state = NodeState::simple(true);
+        graph.add_edge(lever, torch, CompileLink::default(0));
+        graph.add_edge(torch, note, CompileLink::default(1));
+        graph.add_edge(torch, wire, CompileLink::default(3));
+        graph.add_edge(wire, lamp, CompileLink::default(0));
+        graph.add_edge(constant, torch, CompileLink::new(LinkType::Side, 0));
+        graph.add_edge(constant, note, CompileLink::default(0));
+
+        let part = partition(&graph);
+        // Both sides get a boundary trapdoor and lever and their own copy of the constant
+        assert_eq!(part.fpga.node_count(), 3 + 3);
+        assert_eq!(part.cpu.node_count(), 2 + 3);
+        assert!(part.to_fpga.len() == 1 && part.to_cpu.len() == 1);
+
+        // Both uses of the torch on the CPU share one boundary, keeping their distance
+        let (trapdoor, cpu_lever) = part.to_cpu[0];
+        let trapdoor = NodeIdx::new(trapdoor as usize);
+        assert_eq!(part.fpga[trapdoor].ty, NodeType::Trapdoor);
+        assert!(part.fpga[trapdoor].state.powered && part.fpga[trapdoor].block.is_none());
+        let mut distances: Vec<u8> =
+            part.cpu.edges(NodeIdx::new(cpu_lever)).map(|edge| edge.weight().ss).collect();
+        distances.sort();
+        assert_eq!(distances, vec![1, 3]);
+
+        let (wire_sink, fpga_lever) = part.to_fpga[0];
+        assert_eq!(part.cpu[NodeIdx::new(wire_sink)].ty, NodeType::Trapdoor);
+        assert_eq!(part.fpga[NodeIdx::new(fpga_lever as usize)].ty, NodeType::Lever);
+    }
+}
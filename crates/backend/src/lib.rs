@@ -1,5 +1,8 @@
 pub mod direct;
+pub mod formal;
 pub mod fpga;
+pub mod hybrid;
+pub mod sim_test;
 
 use mchprs_blocks::BlockPos;
 use mchprs_world::TickEntry;
@@ -8,23 +11,27 @@ use std::fs;
 use std::path::Path;
 use std::sync::mpsc::Sender;
 use std::sync::Mutex;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::debug;
 use fpga::linker::Linker;
+use fpga::interface::ConnectionStatus;
 
 
 use mchprs_redpiler::{
     compile_graph::CompileGraph, 
     CompilerOptions, 
     passes::make_default_pass_manager,
+    report::CompileReport,
     CompilerInput,
     BackendVariant,
 };
 use enum_dispatch::enum_dispatch;
 use direct::DirectBackend;
 use fpga::FPGABackend;
+use hybrid::HybridBackend;
 
 use crate::fpga::compiler::DeviceConfig;
+use crate::sim_test::Recorder;
 
 
 #[enum_dispatch]
@@ -38,7 +45,7 @@ pub trait JITBackend {
         config: Option<DeviceConfig>,
         options: &CompilerOptions,  
     );
-    fn run(&mut self);
+    fn run(&mut self) -> Result<(), String>;
     fn stop(&mut self);
     fn tick(&mut self);
     fn tickn(&mut self, ticks: u64) {
@@ -52,13 +59,28 @@ pub trait JITBackend {
     fn reset<W: World>(&mut self, world: &mut W, io_only: bool);
     fn has_pending_ticks(&self) -> bool;
     fn inspect(&mut self, pos: BlockPos);
-    fn set_rtps(&mut self, rtps: u32);
+    /// Sets the tick rate of hardware backends, `None` runs as fast as possible
+    fn set_rtps(&mut self, rtps: Option<u32>);
+    /// Number of ticks executed by the hardware since `run` as of the last
+    /// read, if it counts them. Does not talk to the hardware.
+    fn hardware_ticks(&self) -> Option<u64> {
+        None
+    }
+    /// Reads the tick counter from the hardware and returns the new count
+    fn read_hardware_ticks(&mut self) -> Option<u64> {
+        self.hardware_ticks()
+    }
+    /// Health of the link to the hardware running this backend, if there is one
+    fn connection_status(&self) -> Option<ConnectionStatus> {
+        None
+    }
 }
 
 #[enum_dispatch(JITBackend)] 
 pub enum BackendDispatcher {
     DirectBackend,
     FPGABackend,
+    HybridBackend,
 }
 
 impl Default for BackendDispatcher {
@@ -91,7 +113,12 @@ impl BackendStatus {
 pub enum BackendMsg {
     BackendStatus{backend: String, status: BackendStatus},
     New{backend: String, options: CompilerOptions},
-    Delete{backend: String}
+    Delete{backend: String},
+    Connection{backend: String, status: ConnectionStatus},
+    /// Sent once a build made by [`Backend::new`] is ready
+    Compiled{backend: String, report: CompileReport},
+    /// A `/redpiler timing` report as chat lines for the player with the uuid `player`
+    Timing{player: u128, lines: Result<Vec<String>, String>},
 }
 
 #[derive(Default)]
@@ -101,6 +128,17 @@ pub struct Backend {
     pub name: String,
     jit: BackendDispatcher,
     options: CompilerOptions,
+    connection: Option<ConnectionStatus>,
+    /// Hardware tick count as of the last read, `None` if the backend has no tick counter
+    hardware_ticks: Option<u64>,
+    last_hardware_read: Option<Instant>,
+    /// Region the graph was compiled from, unknown for builds loaded from disk
+    bounds: Option<(BlockPos, BlockPos)>,
+    /// Ticks run by the plot, for backends without a hardware tick counter
+    ticks: u64,
+    recorder: Option<Recorder>,
+    /// What the passes and the backend did, only known for builds compiled from a world
+    report: Option<CompileReport>,
 }
 
 impl Backend {
@@ -114,6 +152,10 @@ impl Backend {
                 let link_path = entry.unwrap().path().join("link.json");
                 let links_str = fs::read_to_string(link_path).unwrap();
                 let link: Linker = serde_json::from_str(&links_str).unwrap();
+                // Hybrid builds also need their CPU part, which is not saved
+                if link.has_boundary() {
+                    continue;
+                }
 
                 let name = link.name.clone();
 
@@ -126,7 +168,14 @@ impl Backend {
                     sender: Some(sender.clone()),
                     name: name.clone(),
                     jit: BackendDispatcher::FPGABackend(backend),
-                    options: CompilerOptions::fpga()  
+                    options: CompilerOptions::fpga(),
+                    connection: None,
+                    hardware_ticks: None,
+                    last_hardware_read: None,
+                    bounds: None,
+                    ticks: 0,
+                    recorder: None,
+                    report: None,
                 });
             }
         }
@@ -152,11 +201,45 @@ impl Backend {
 
         let input = CompilerInput { world: world, bounds };
         let pass_manager = make_default_pass_manager::<W>();
-        let graph = pass_manager.run_passes(&options, &input);
+        let (graph, mut report) = pass_manager.run_passes_with_report(&options, &input);
+
+        let backend_start = Instant::now();
+        let mut backend = Backend::compile(sender.clone(), name.clone(), plot, config, graph, options, ticks);
+        report.backend = backend_start.elapsed();
+        backend.bounds = Some(bounds);
+        debug!("Compile completed in {:?}", start.elapsed());
+        _ = sender.send(BackendMsg::Compiled { backend: name, report: report.clone() });
+        backend.report = Some(report);
+        backend
+    }
+
+    /// Compiles an already built graph, e.g. one loaded with [`mchprs_redpiler::graph_file::load`].
+    /// Without a world the bounds of the build and its scheduled ticks are unknown.
+    pub fn from_graph(
+        sender: Sender<BackendMsg>,
+        name: String,
+        plot: String,
+        config: Option<DeviceConfig>,
+        graph: CompileGraph,
+        options: CompilerOptions,
+    ) -> Backend {
+        _ = sender.send(BackendMsg::New { backend: name.clone(), options: options.clone() });
+        Backend::compile(sender, name, plot, config, graph, options, Vec::new())
+    }
 
+    fn compile(
+        sender: Sender<BackendMsg>,
+        name: String,
+        plot: String,
+        config: Option<DeviceConfig>,
+        graph: CompileGraph,
+        options: CompilerOptions,
+        ticks: Vec<TickEntry>,
+    ) -> Backend {
         let mut jit = match options.backend_variant {
             BackendVariant::Direct => BackendDispatcher::DirectBackend(Default::default()),
-            BackendVariant::FPGA => BackendDispatcher::FPGABackend(Default::default())
+            BackendVariant::FPGA => BackendDispatcher::FPGABackend(Default::default()),
+            BackendVariant::Hybrid => BackendDispatcher::HybridBackend(Default::default()),
         };
 
         _ = sender.send(BackendMsg::BackendStatus { backend: name.clone(), status: BackendStatus::Compiling });
@@ -170,7 +253,6 @@ impl Backend {
             &options);
 
         _ = sender.send(BackendMsg::BackendStatus { backend: name.clone(), status: BackendStatus::Ready });
-        debug!("Compile completed in {:?}", start.elapsed());
 
         Backend{ 
             is_active: false,
@@ -178,6 +260,13 @@ impl Backend {
             name: name,
             jit: jit,
             options: options,
+            connection: None,
+            hardware_ticks: None,
+            last_hardware_read: None,
+            bounds: None,
+            ticks: 0,
+            recorder: None,
+            report: None,
         }
     }
 
@@ -185,6 +274,10 @@ impl Backend {
         &self.options
     }
 
+    pub fn report(&self) -> Option<&CompileReport> {
+        self.report.as_ref()
+    }
+
     pub fn reset<W: World>(&mut self, world: &mut W, bounds: (BlockPos, BlockPos)) {
         let io_only = self.options.io_only;
         self.backend().reset(world, io_only);
@@ -203,47 +296,169 @@ impl Backend {
         &mut self.jit
     }
 
-    pub fn run(&mut self) {
-        self.backend().run();
+    pub fn run(&mut self) -> Result<(), String> {
+        self.backend().run()?;
+        self.hardware_ticks = self.backend().read_hardware_ticks();
+        self.last_hardware_read = Some(Instant::now());
         _ = self.sender.as_mut().unwrap().send(BackendMsg::BackendStatus { backend: self.name.clone(), status: BackendStatus::Active });
+        self.report_connection();
+        Ok(())
+    }
+
+    /// Forwards changes of the hardware link status to the plot
+    fn report_connection(&mut self) {
+        let status = self.backend().connection_status();
+        if status == self.connection {
+            return;
+        }
+        self.connection = status;
+        if let (Some(status), Some(sender)) = (status, self.sender.as_mut()) {
+            _ = sender.send(BackendMsg::Connection { backend: self.name.clone(), status });
+        }
     }
 
     pub fn stop(&mut self) {
         self.backend().stop();
+        self.report_connection();
         _ = self.sender.as_mut().unwrap().send(BackendMsg::BackendStatus { backend: self.name.clone(), status: BackendStatus::Ready });
     }
 
     pub fn tick(&mut self) {
+        self.ticks += 1;
         self.backend().tick();
     }
 
     pub fn tickn(&mut self, ticks: u64) {
+        self.ticks += ticks;
         self.backend().tickn(ticks);
     }
 
     pub fn on_use_block(&mut self, pos: BlockPos) {
+        if self.recorder.is_some() {
+            let tick = self.current_tick();
+            self.recorder.as_mut().unwrap().use_block(pos, tick);
+        }
         self.backend().on_use_block(pos);
     }
 
     pub fn set_pressure_plate(&mut self, pos: BlockPos, powered: bool) {
+        if self.recorder.is_some() {
+            let tick = self.current_tick();
+            self.recorder.as_mut().unwrap().set_pressure_plate(pos, powered, tick);
+        }
         self.backend().set_pressure_plate(pos, powered);
     }
 
     pub fn flush<W: World>(&mut self, world: &mut W) {
         let io_only = self.options.io_only;
         self.backend().flush(world, io_only);
+        self.report_connection();
+        if self.recorder.is_some() {
+            self.record_outputs(world);
+        }
+    }
+
+    /// The tick inputs and outputs are recorded at. Hardware counters are only
+    /// as fresh as their last read, so this never waits for the board.
+    fn current_tick(&mut self) -> u64 {
+        self.backend().hardware_ticks().unwrap_or(self.ticks)
+    }
+
+    fn record_outputs<W: World>(&mut self, world: &W) {
+        let tick = self.current_tick();
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        for pos in recorder.output_positions() {
+            // Lamps in the world turn off late, the direct backend knows what they receive
+            let powered = match &self.jit {
+                BackendDispatcher::DirectBackend(direct) => direct.output_driven(pos),
+                _ => None,
+            };
+            if let Some(powered) = powered.or_else(|| sim_test::output_state(world.get_block(pos))) {
+                recorder.output(pos, powered, tick);
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> Option<(BlockPos, BlockPos)> {
+        self.bounds
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording inputs and output changes within the compiled bounds
+    pub fn start_recording<W: World>(&mut self, name: String, world: &W) -> Result<(), String> {
+        let Some(bounds) = self.bounds else {
+            return Err("The bounds of this build are unknown, recompile it to record".to_string());
+        };
+        self.backend().read_hardware_ticks();
+        let tick = self.current_tick();
+        self.recorder = Some(Recorder::new(name, world, bounds, tick));
+        Ok(())
+    }
+
+    /// Ends the recording with the outputs as they are in `world` now
+    pub fn stop_recording<W: World>(&mut self, world: &W) -> Option<Recorder> {
+        self.recorder.as_ref()?;
+        self.backend().read_hardware_ticks();
+        self.record_outputs(world);
+        self.recorder.take()
     }
 
     pub fn inspect(&mut self, pos: BlockPos) {
         self.backend().inspect(pos);
     }
 
+    fn fpga(&mut self) -> Result<&mut FPGABackend, String> {
+        match &mut self.jit {
+            BackendDispatcher::FPGABackend(fpga) => Ok(fpga),
+            BackendDispatcher::HybridBackend(hybrid) => Ok(hybrid.fpga()),
+            BackendDispatcher::DirectBackend(_) => Err("Only FPGA builds have a board".to_string()),
+        }
+    }
+
+    /// Writes `value` to the input bus `name` of an FPGA build and updates its levers in `world`
+    pub fn set_bus<W: World>(&mut self, world: &mut W, name: &str, value: u64) -> Result<(), String> {
+        self.fpga()?.set_bus(world, name, value)
+    }
+
+    /// Reads the bus `name` of an FPGA build
+    pub fn get_bus(&mut self, name: &str) -> Result<u64, String> {
+        self.fpga()?.get_bus(name)
+    }
+
+    /// Searches every serial port for the board of an FPGA build and reconnects to it
+    pub fn scan_ports(&mut self) -> Result<(), String> {
+        if !self.fpga()?.scan() {
+            return Err("No FPGA answered on any serial port".to_string());
+        }
+        self.report_connection();
+        Ok(())
+    }
+
     pub fn has_pending_ticks(&mut self) -> bool {
         self.backend().has_pending_ticks()
     }
 
-    pub fn set_rtps(&mut self, rtps: u32) {
+    pub fn set_rtps(&mut self, rtps: Option<u32>) {
         self.backend().set_rtps(rtps);
     }
+
+    /// Returns the ticks the hardware executed since the last call, or `None`
+    /// if the backend is ticked by the plot. The counter is read over the link
+    /// at most once per `interval`, calls in between report no ticks.
+    pub fn take_hardware_ticks(&mut self, interval: Duration) -> Option<u64> {
+        let last = self.hardware_ticks?;
+        if self.last_hardware_read.is_some_and(|t| t.elapsed() < interval) {
+            return Some(0);
+        }
+        self.last_hardware_read = Some(Instant::now());
+        let ticks = self.backend().read_hardware_ticks()?;
+        self.hardware_ticks = Some(ticks);
+        Some(ticks.saturating_sub(last))
+    }
 }
 
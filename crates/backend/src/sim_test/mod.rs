@@ -0,0 +1,488 @@
+//! Declarative tests for schematics, run against a [`DirectBackend`], the
+//! generated verilog in a simulator or the formal [`Model`] of a build.
+//!
+//! A version 2 `test.json` names its signals and lists steps per case:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "name": "latch",
+//!   "signals": { "set": "1,2,1", "btn": "1,2,3", "q": "5,2,1", "level": { "pos": "4,2,2", "analog": true } },
+//!   "tests": [{
+//!     "description": "latch holds",
+//!     "steps": [
+//!       { "set": { "set": 1 }, "expect": { "q": "x" } },
+//!       { "wait": 3 },
+//!       { "press": ["btn"], "expect": { "q": 1, "level": 13 } }
+//!     ]
+//!   }]
+//! }
+//! ```
+//!
+//! Every step applies its inputs, checks its expectations and then waits
+//! `wait` ticks (1 by default). Pressed buttons release after [`BUTTON_TICKS`].
+//! Files without a `version` use the original positional format.
+
+mod record;
+mod verilog;
+
+pub use record::{output_state, Recorder};
+pub use verilog::{simulate, Simulator};
+
+use crate::direct::DirectBackend;
+use crate::formal::{Model, Simulation};
+use crate::{Backend, BackendDispatcher, JITBackend};
+use mchprs_blocks::block_entities::BlockEntity;
+use mchprs_blocks::blocks::{Block, Lever};
+use mchprs_blocks::BlockPos;
+use mchprs_world::World;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const SCHEMA_VERSION: u32 = 2;
+/// How long a stone button stays pressed
+pub const BUTTON_TICKS: usize = 10;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SignalDef {
+    Pos(String),
+    Full {
+        pos: String,
+        #[serde(default)]
+        analog: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Signal {
+    pub pos: BlockPos,
+    /// Compared by signal strength instead of on/off
+    pub analog: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+pub enum Expect {
+    Value(u8),
+    /// Written as `"x"`
+    DontCare,
+}
+
+impl TryFrom<serde_json::Value> for Expect {
+    type Error = String;
+
+    fn try_from(value: serde_json::Value) -> Result<Expect, String> {
+        match &value {
+            serde_json::Value::Number(n) => n
+                .as_u64()
+                .filter(|&v| v <= 15)
+                .map(|v| Expect::Value(v as u8))
+                .ok_or_else(|| format!("expected value {} is not a signal strength", n)),
+            serde_json::Value::String(s) if s == "x" => Ok(Expect::DontCare),
+            _ => Err(format!("expected a signal strength or \"x\", found {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expect::Value(v) => write!(f, "{}", v),
+            Expect::DontCare => write!(f, "x"),
+        }
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    #[serde(default)]
+    pub set: BTreeMap<String, u8>,
+    #[serde(default)]
+    pub press: Vec<String>,
+    #[serde(default)]
+    pub expect: BTreeMap<String, Expect>,
+    /// Ticks to run after the expectations are checked
+    #[serde(default = "one")]
+    pub wait: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCase {
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSuite {
+    version: u32,
+    name: String,
+    #[serde(default)]
+    description: String,
+    signals: BTreeMap<String, SignalDef>,
+    tests: Vec<TestCase>,
+}
+
+/// The original format: positional bit strings like `"3: 1 0"`
+#[derive(Debug, Deserialize)]
+struct LegacySuite {
+    name: String,
+    #[serde(default)]
+    description: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    tests: Vec<LegacyProcedure>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LegacyProcedure {
+    description: String,
+    procedure: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct TestSuite {
+    pub name: String,
+    pub description: String,
+    pub signals: BTreeMap<String, Signal>,
+    pub tests: Vec<TestCase>,
+}
+
+/// An expectation together with the step it came from
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub step: usize,
+    pub signal: String,
+    pub expect: Expect,
+}
+
+/// Input changes and checks that happen before a tick
+#[derive(Debug, Default, Clone)]
+pub struct Tick {
+    pub inputs: Vec<(String, bool)>,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub step: usize,
+    pub tick: usize,
+    pub signal: String,
+    pub pos: BlockPos,
+    pub expected: Expect,
+    pub actual: Option<u8>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let actual = self.actual.map_or("missing".to_string(), |a| a.to_string());
+        write!(
+            f,
+            "step {} (tick {}): {} at {} expected {} got {}",
+            self.step, self.tick, self.signal, self.pos, self.expected, actual
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CaseReport {
+    pub description: String,
+    pub failures: Vec<Failure>,
+    /// Expectations the backend could not observe, e.g. analog signals on the FPGA
+    pub skipped: usize,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Signal strength of the block at `pos` as the world shows it
+pub fn world_strength<W: World>(world: &W, pos: BlockPos) -> Option<u8> {
+    let on = |powered: bool| if powered { 15 } else { 0 };
+    match world.get_block(pos) {
+        Block::RedstoneWire { wire } => Some(wire.power),
+        Block::RedstoneComparator { .. } => match world.get_block_entity(pos) {
+            Some(BlockEntity::Comparator { output_strength }) => Some(*output_strength),
+            _ => Some(0),
+        },
+        Block::RedstoneRepeater { repeater } => Some(on(repeater.powered)),
+        Block::RedstoneTorch { lit } | Block::RedstoneWallTorch { lit, .. } => Some(on(lit)),
+        Block::Lever { lever: Lever { powered, .. } } => Some(on(powered)),
+        Block::StoneButton { button } => Some(on(button.powered)),
+        Block::StonePressurePlate { powered } => Some(on(powered)),
+        block => output_state(block).map(on),
+    }
+}
+
+fn parse_pos(s: &str) -> Result<BlockPos, String> {
+    BlockPos::from_str(s).map_err(|_| format!("invalid block position \"{}\"", s))
+}
+
+impl TestSuite {
+    pub fn load(path: &Path) -> Result<TestSuite, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        TestSuite::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<TestSuite, String> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let suite = match value.get("version").map(|v| v.as_u64()) {
+            None => {
+                let legacy: LegacySuite = serde_json::from_value(value).map_err(|e| e.to_string())?;
+                TestSuite::from_legacy(legacy)?
+            }
+            Some(Some(v)) if v == SCHEMA_VERSION as u64 => {
+                let raw: RawSuite = serde_json::from_value(value).map_err(|e| e.to_string())?;
+                TestSuite::from_raw(raw)?
+            }
+            Some(_) => return Err(format!("unsupported test schema version {}", value["version"])),
+        };
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    fn from_raw(raw: RawSuite) -> Result<TestSuite, String> {
+        debug_assert_eq!(raw.version, SCHEMA_VERSION);
+        let mut signals = BTreeMap::new();
+        for (name, def) in raw.signals {
+            let signal = match def {
+                SignalDef::Pos(pos) => Signal { pos: parse_pos(&pos)?, analog: false },
+                SignalDef::Full { pos, analog } => Signal { pos: parse_pos(&pos)?, analog },
+            };
+            signals.insert(name, signal);
+        }
+        Ok(TestSuite {
+            name: raw.name,
+            description: raw.description,
+            signals,
+            tests: raw.tests,
+        })
+    }
+
+    /// Signals are named after their position, every step sets all inputs and checks all outputs
+    fn from_legacy(legacy: LegacySuite) -> Result<TestSuite, String> {
+        let mut signals = BTreeMap::new();
+        for pos in legacy.inputs.iter().chain(&legacy.outputs) {
+            signals.insert(pos.clone(), Signal { pos: parse_pos(pos)?, analog: false });
+        }
+
+        let mut tests = Vec::new();
+        for proc in legacy.tests {
+            let mut steps = Vec::new();
+            for line in &proc.procedure {
+                // "<n>: <inputs> <outputs>", the inputs are empty if there are none
+                let args: Vec<&str> = line.split(' ').collect();
+                let [_, inputs, outputs] = args[..] else {
+                    return Err(format!("malformed step \"{}\"", line));
+                };
+                let (inputs, outputs) = (inputs.as_bytes(), outputs.as_bytes());
+                if inputs.len() < legacy.inputs.len() || outputs.len() < legacy.outputs.len() {
+                    return Err(format!("step \"{}\" does not cover every signal", line));
+                }
+                let set = legacy.inputs.iter().zip(inputs).map(|(p, &b)| (p.clone(), (b == b'1') as u8)).collect();
+                let expect = legacy
+                    .outputs
+                    .iter()
+                    .zip(outputs)
+                    .map(|(p, &b)| (p.clone(), Expect::Value((b == b'1') as u8)))
+                    .collect();
+                steps.push(Step { set, press: Vec::new(), expect, wait: 1 });
+            }
+            tests.push(TestCase { description: proc.description, steps });
+        }
+
+        Ok(TestSuite {
+            name: legacy.name,
+            description: legacy.description,
+            signals,
+            tests,
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for case in &self.tests {
+            for (i, step) in case.steps.iter().enumerate() {
+                let names = step.set.keys().chain(&step.press).chain(step.expect.keys());
+                for name in names {
+                    if !self.signals.contains_key(name) {
+                        return Err(format!("\"{}\" step {}: unknown signal \"{}\"", case.description, i, name));
+                    }
+                }
+                for (name, value) in &step.set {
+                    if *value > 1 {
+                        return Err(format!("\"{}\" step {}: input {} can only be set to 0 or 1", case.description, i, name));
+                    }
+                }
+                for (name, expect) in &step.expect {
+                    let max = if self.signals[name].analog { 15 } else { 1 };
+                    if matches!(expect, Expect::Value(v) if *v > max) {
+                        return Err(format!("\"{}\" step {}: {} expects {} but the maximum is {}", case.description, i, name, expect, max));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flattens a case into the input changes and checks before each tick
+    pub fn schedule(&self, case: &TestCase) -> Vec<Tick> {
+        let mut ticks: Vec<Tick> = Vec::new();
+        let mut at = 0;
+        let mut len = 0;
+        for (i, step) in case.steps.iter().enumerate() {
+            let end = at + step.wait.max(1);
+            let button_end = at + BUTTON_TICKS + 1;
+            if ticks.len() < end.max(button_end) {
+                ticks.resize(end.max(button_end), Tick::default());
+            }
+            len = len.max(end);
+
+            let tick = &mut ticks[at];
+            tick.inputs.extend(step.set.iter().map(|(name, &v)| (name.clone(), v == 1)));
+            tick.inputs.extend(step.press.iter().map(|name| (name.clone(), true)));
+            tick.checks.extend(step.expect.iter().map(|(name, &expect)| Check {
+                step: i,
+                signal: name.clone(),
+                expect,
+            }));
+            for name in &step.press {
+                ticks[at + BUTTON_TICKS].inputs.push((name.clone(), false));
+            }
+            // `wait: 0` checks again before the same tick
+            if step.wait > 0 {
+                at = end;
+            }
+        }
+        // Trailing ticks only exist for button releases nobody looks at
+        ticks.truncate(len);
+        ticks
+    }
+
+    fn check(&self, report: &mut CaseReport, tick: usize, check: &Check, strength: Option<u8>) {
+        let Expect::Value(expected) = check.expect else {
+            return;
+        };
+        let signal = self.signals[&check.signal];
+        let actual = strength.map(|ss| if signal.analog { ss } else { (ss > 0) as u8 });
+        if actual != Some(expected) {
+            report.failures.push(Failure {
+                step: check.step,
+                tick,
+                signal: check.signal.clone(),
+                pos: signal.pos,
+                expected: check.expect,
+                actual,
+            });
+        }
+    }
+
+    /// Runs a case on a freshly compiled backend
+    pub fn run_direct(&self, case: &TestCase, direct: &mut DirectBackend) -> CaseReport {
+        let mut report = CaseReport {
+            description: case.description.clone(),
+            ..Default::default()
+        };
+        for (index, tick) in self.schedule(case).iter().enumerate() {
+            for (name, on) in &tick.inputs {
+                direct.set_input(self.signals[name].pos, *on);
+            }
+            for check in &tick.checks {
+                let strength = direct.signal_strength(self.signals[&check.signal].pos);
+                self.check(&mut report, index, check, strength);
+            }
+            direct.tick();
+        }
+        report
+    }
+
+    /// Steps the formal model of a build through a case, one step per tick
+    pub fn run_model(&self, case: &TestCase, model: &Model) -> CaseReport {
+        let mut report = CaseReport {
+            description: case.description.clone(),
+            ..Default::default()
+        };
+        let mut sim = Simulation::new(&model.btor);
+        for (index, tick) in self.schedule(case).iter().enumerate() {
+            for (name, on) in &tick.inputs {
+                if let Some(input) = model.input(self.signals[name].pos) {
+                    sim.set(input, *on as u64);
+                }
+            }
+            for check in &tick.checks {
+                let strength = model.probe(self.signals[&check.signal].pos).map(|nid| {
+                    let value = sim.get(nid) as u8;
+                    if model.btor.width(nid) == 1 { value * 15 } else { value }
+                });
+                self.check(&mut report, index, check, strength);
+            }
+            sim.step();
+        }
+        report
+    }
+
+    /// Runs a case through a compiled [`Backend`] the way a player would: levers
+    /// are flipped and buttons pressed with `on_use_block`, pressure plates set,
+    /// and outputs read back from `world` after every flush. Signal positions are
+    /// relative to `origin`.
+    pub fn run_backend<W: World>(&self, case: &TestCase, backend: &mut Backend, world: &mut W, origin: BlockPos) -> CaseReport {
+        let mut report = CaseReport {
+            description: case.description.clone(),
+            ..Default::default()
+        };
+        let mut levers = BTreeMap::new();
+        let mut pending = 0;
+        for (index, tick) in self.schedule(case).iter().enumerate() {
+            if tick.inputs.is_empty() && tick.checks.is_empty() {
+                pending += 1;
+                continue;
+            }
+            if pending > 0 {
+                backend.tickn(pending);
+                pending = 0;
+            }
+
+            for (name, on) in &tick.inputs {
+                let pos = origin + self.signals[name].pos;
+                match world.get_block(pos) {
+                    Block::Lever { lever } => {
+                        let state = levers.entry(name.as_str()).or_insert(lever.powered);
+                        if *state != *on {
+                            *state = *on;
+                            backend.on_use_block(pos);
+                        }
+                    }
+                    // Buttons release on their own after `BUTTON_TICKS`
+                    Block::StoneButton { .. } if *on => backend.on_use_block(pos),
+                    Block::StonePressurePlate { .. } => backend.set_pressure_plate(pos, *on),
+                    _ => {}
+                }
+            }
+
+            backend.flush(world);
+            for check in &tick.checks {
+                let pos = origin + self.signals[&check.signal].pos;
+                // Lamps in the world turn off late, the direct backend knows what they receive
+                let strength = match backend.backend() {
+                    BackendDispatcher::DirectBackend(direct) if output_state(world.get_block(pos)).is_some() => {
+                        direct.signal_strength(pos)
+                    }
+                    _ => world_strength(world, pos),
+                };
+                self.check(&mut report, index, check, strength);
+            }
+            pending += 1;
+        }
+        report
+    }
+}
@@ -0,0 +1,239 @@
+//! Captures what happens to a running backend so it can be replayed as a test case
+
+use super::SCHEMA_VERSION;
+use mchprs_blocks::blocks::{Block, Lever, StoneButton};
+use mchprs_blocks::BlockPos;
+use mchprs_world::World;
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputKind {
+    Lever,
+    Button,
+    PressurePlate,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Input { pos: BlockPos, powered: bool },
+    Output { pos: BlockPos, powered: bool },
+}
+
+/// Whether the lamp or trapdoor `block` is on
+pub fn output_state(block: Block) -> Option<bool> {
+    match block {
+        Block::RedstoneLamp { lit } => Some(lit),
+        Block::IronTrapdoor { powered, .. } => Some(powered),
+        _ => None,
+    }
+}
+
+fn input_state(block: Block) -> Option<(InputKind, bool)> {
+    match block {
+        Block::Lever { lever: Lever { powered, .. } } => Some((InputKind::Lever, powered)),
+        Block::StoneButton { button: StoneButton { powered, .. } } => Some((InputKind::Button, powered)),
+        Block::StonePressurePlate { powered } => Some((InputKind::PressurePlate, powered)),
+        _ => None,
+    }
+}
+
+struct Input {
+    pos: BlockPos,
+    kind: InputKind,
+    initial: bool,
+    state: bool,
+}
+
+struct Output {
+    pos: BlockPos,
+    initial: bool,
+    state: bool,
+}
+
+/// Records input events and output changes of a region with their tick.
+/// Positions in the resulting test are relative to the lowest corner of the region.
+pub struct Recorder {
+    pub name: String,
+    origin: BlockPos,
+    start_tick: u64,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    events: Vec<(u64, Event)>,
+}
+
+impl Recorder {
+    pub fn new<W: World>(name: String, world: &W, bounds: (BlockPos, BlockPos), tick: u64) -> Recorder {
+        let start = bounds.0.min(bounds.1);
+        let end = bounds.0.max(bounds.1);
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for y in start.y..=end.y {
+            for z in start.z..=end.z {
+                for x in start.x..=end.x {
+                    let pos = BlockPos::new(x, y, z);
+                    let block = world.get_block(pos);
+                    if let Some((kind, on)) = input_state(block) {
+                        inputs.push(Input { pos, kind, initial: on, state: on });
+                    } else if let Some(on) = output_state(block) {
+                        outputs.push(Output { pos, initial: on, state: on });
+                    }
+                }
+            }
+        }
+
+        Recorder {
+            name,
+            origin: start,
+            start_tick: tick,
+            inputs,
+            outputs,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn output_positions(&self) -> Vec<BlockPos> {
+        self.outputs.iter().map(|o| o.pos).collect()
+    }
+
+    fn push(&mut self, tick: u64, event: Event) {
+        self.events.push((tick.saturating_sub(self.start_tick), event));
+    }
+
+    fn input(&self, pos: BlockPos) -> Option<&Input> {
+        self.inputs.iter().find(|i| i.pos == pos)
+    }
+
+    pub fn use_block(&mut self, pos: BlockPos, tick: u64) {
+        let Some(input) = self.inputs.iter_mut().find(|i| i.pos == pos) else {
+            return;
+        };
+        let powered = match input.kind {
+            InputKind::Lever => {
+                input.state = !input.state;
+                input.state
+            }
+            // Buttons release on their own, so every press is recorded
+            InputKind::Button => true,
+            InputKind::PressurePlate => return,
+        };
+        self.push(tick, Event::Input { pos, powered });
+    }
+
+    pub fn set_pressure_plate(&mut self, pos: BlockPos, powered: bool, tick: u64) {
+        let Some(input) = self.inputs.iter_mut().find(|i| i.pos == pos && i.kind == InputKind::PressurePlate) else {
+            return;
+        };
+        input.state = powered;
+        self.push(tick, Event::Input { pos, powered });
+    }
+
+    pub fn output(&mut self, pos: BlockPos, powered: bool, tick: u64) {
+        let Some(output) = self.outputs.iter_mut().find(|o| o.pos == pos) else {
+            return;
+        };
+        if output.state == powered {
+            return;
+        }
+        output.state = powered;
+        self.push(tick, Event::Output { pos, powered });
+    }
+
+    fn signal_name(&self, pos: BlockPos) -> String {
+        let rel = pos - self.origin;
+        let kind = match self.input(pos).map(|i| i.kind) {
+            Some(InputKind::Lever) => "lever",
+            Some(InputKind::Button) => "button",
+            Some(InputKind::PressurePlate) => "plate",
+            None => "out",
+        };
+        format!("{}_{}_{}_{}", kind, rel.x, rel.y, rel.z)
+    }
+
+    /// The recording as a version 2 `test.json`. Inputs that never change are
+    /// left out unless they start powered, every output is checked whenever
+    /// anything happened.
+    pub fn to_test(&self) -> Value {
+        let used = |input: &&Input| {
+            (input.initial && input.kind != InputKind::Button)
+                || self.events.iter().any(|(_, e)| matches!(e, Event::Input { pos, .. } if *pos == input.pos))
+        };
+        let mut signals = Map::new();
+        let positions = self.inputs.iter().filter(used).map(|i| i.pos).chain(self.outputs.iter().map(|o| o.pos));
+        for pos in positions {
+            let rel = pos - self.origin;
+            signals.insert(self.signal_name(pos), json!(format!("{},{},{}", rel.x, rel.y, rel.z)));
+        }
+
+        let mut by_tick: Vec<(u64, Vec<Event>)> = Vec::new();
+        for &(tick, event) in &self.events {
+            match by_tick.last_mut() {
+                Some((t, events)) if *t == tick => events.push(event),
+                _ => by_tick.push((tick, vec![event])),
+            }
+        }
+
+        let mut outputs: Vec<bool> = self.outputs.iter().map(|o| o.initial).collect();
+        let expect_all = |outputs: &[bool]| -> Map<String, Value> {
+            self.outputs
+                .iter()
+                .zip(outputs)
+                .map(|(o, &on)| (self.signal_name(o.pos), json!(on as u8)))
+                .collect()
+        };
+
+        // Simulations start with every input off, so levers that start on are switched on first
+        let initial: Map<String, Value> = self
+            .inputs
+            .iter()
+            .filter(|i| i.initial && i.kind != InputKind::Button)
+            .map(|i| (self.signal_name(i.pos), json!(1)))
+            .collect();
+        let first = by_tick.first().map_or(1, |(t, _)| *t);
+        let mut steps = Vec::new();
+        if first > 0 {
+            steps.push(json!({ "set": initial, "expect": expect_all(&outputs), "wait": first }));
+        }
+
+        for (i, (tick, events)) in by_tick.iter().enumerate() {
+            let mut set = if *tick == 0 { initial.clone() } else { Map::new() };
+            let mut press = Vec::new();
+            for event in events {
+                match *event {
+                    Event::Input { pos, powered } => {
+                        if self.input(pos).is_some_and(|i| i.kind == InputKind::Button) {
+                            press.push(json!(self.signal_name(pos)));
+                        } else {
+                            set.insert(self.signal_name(pos), json!(powered as u8));
+                        }
+                    }
+                    Event::Output { pos, powered } => {
+                        let idx = self.outputs.iter().position(|o| o.pos == pos).unwrap();
+                        outputs[idx] = powered;
+                    }
+                }
+            }
+            let wait = by_tick.get(i + 1).map_or(1, |(next, _)| next - tick);
+            let mut step = Map::new();
+            if !set.is_empty() {
+                step.insert("set".to_string(), Value::Object(set));
+            }
+            if !press.is_empty() {
+                step.insert("press".to_string(), Value::Array(press));
+            }
+            step.insert("expect".to_string(), Value::Object(expect_all(&outputs)));
+            step.insert("wait".to_string(), json!(wait));
+            steps.push(Value::Object(step));
+        }
+
+        json!({
+            "version": SCHEMA_VERSION,
+            "name": self.name,
+            "description": format!("recorded in game, {} events", self.events.len()),
+            "signals": signals,
+            "tests": [{
+                "description": "replay of the recording",
+                "steps": steps,
+            }],
+        })
+    }
+}
@@ -0,0 +1,200 @@
+//! Runs test cases against the `roc.sv` of a sim test build in iverilog or verilator
+
+use super::{CaseReport, TestCase, TestSuite};
+use crate::fpga::linker::{IntfBlock, Linker};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const COMPONENTS: &str = "FPGA/src/redstone/components.sv";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Simulator {
+    Iverilog,
+    Verilator,
+}
+
+impl Simulator {
+    /// Picks the simulator from `ROC_SIM`, or else the first one installed
+    pub fn detect() -> Option<Simulator> {
+        let installed = |cmd: &str, arg: &str| Command::new(cmd).arg(arg).output().is_ok();
+        match std::env::var("ROC_SIM").as_deref() {
+            Ok("iverilog") => return Some(Simulator::Iverilog),
+            Ok("verilator") => return Some(Simulator::Verilator),
+            _ => {}
+        }
+        if installed("iverilog", "-V") && installed("vvp", "-V") {
+            Some(Simulator::Iverilog)
+        } else if installed("verilator", "--version") {
+            Some(Simulator::Verilator)
+        } else {
+            None
+        }
+    }
+
+    /// Builds and runs `tb.sv` and `roc.sv` in `dir`, returning the simulation output
+    pub fn run(self, dir: &Path) -> Result<String, String> {
+        let components = fs::canonicalize(COMPONENTS).map_err(|e| format!("could not find {}: {}", COMPONENTS, e))?;
+        let (mut build, mut exe) = match self {
+            Simulator::Iverilog => {
+                let mut cmd = Command::new("iverilog");
+                cmd.args(["-g2005-sv", "-o", "sim", "tb.sv", "roc.sv"]).arg(&components);
+                let mut exe = Command::new("vvp");
+                exe.arg("sim");
+                (cmd, exe)
+            }
+            Simulator::Verilator => {
+                let mut cmd = Command::new("verilator");
+                cmd.args(["--binary", "--timing", "-Wno-fatal", "--top-module", "tb", "-Mdir", "obj", "tb.sv", "roc.sv"])
+                    .arg(&components);
+                (cmd, Command::new(dir.join("obj/Vtb")))
+            }
+        };
+
+        let out = build.current_dir(dir).output().map_err(|e| format!("could not start {:?}: {}", self, e))?;
+        if !out.status.success() {
+            return Err(format!("{:?} could not build {}:\n{}", self, dir.display(), String::from_utf8_lossy(&out.stderr)));
+        }
+        let out = exe.current_dir(dir).output().map_err(|e| format!("could not run the simulation: {}", e))?;
+        Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    }
+}
+
+/// First bit and width of the link entry at the signal's position, entries like
+/// hex lamps take several bits of the port
+fn link_bits(entries: &[IntfBlock], suite: &TestSuite, name: &str) -> Option<(usize, usize)> {
+    let pos = suite.signals[name].pos;
+    let mut bit = 0;
+    for entry in entries {
+        let width = entry.bit_count() as usize;
+        if entry.pos == pos && !entry.is_boundary() {
+            return Some((bit, width));
+        }
+        bit += width;
+    }
+    None
+}
+
+fn testbench(suite: &TestSuite, case: &TestCase, link: &Linker) -> Result<String, String> {
+    let mut steps = String::new();
+    for (i, tick) in suite.schedule(case).iter().enumerate() {
+        steps.push_str(&format!("\n        // tick {}", i));
+        for (name, on) in &tick.inputs {
+            let Some((bit, width)) = link_bits(&link.inputs, suite, name) else {
+                return Err(format!("{} at {} is not an input of the build", name, suite.signals[name].pos));
+            };
+            steps.push_str(&format!("\n        inputs[{}:{}] = {}'d{};", bit + width - 1, bit, width, *on as u8));
+        }
+        steps.push_str("
+        #1
+        $display(\"OUT %b\", outputs);
+        tick = ~tick;
+        #1
+        tick = ~tick;
+        #1");
+    }
+
+    Ok(format!("module tb;
+
+    reg tick = 0;
+    wire[{out_cnt}-1:0] outputs;
+    reg[{in_cnt}-1:0]   inputs = 0;
+
+    RoC #(
+        .OUTPUTS({out_cnt}),
+        .INPUTS({in_cnt})
+    ) redstone (
+        .tick(tick),
+        .outputs(outputs),
+        .inputs(inputs)
+    );
+
+    initial begin
+        #2{steps}
+        $display(\"DONE\");
+        $finish;
+    end
+
+endmodule: tb",
+    out_cnt = link.output_bits.max(1),
+    in_cnt = link.input_bits.max(1)))
+}
+
+/// Writes a testbench for `case` next to `roc.sv` in `dir`, simulates it and
+/// checks every expectation on an FPGA output. Analog signals and blocks that
+/// are not outputs can't be observed and are counted as skipped.
+pub fn simulate(sim: Simulator, dir: &Path, suite: &TestSuite, case: &TestCase, link: &Linker) -> Result<CaseReport, String> {
+    let tb = testbench(suite, case, link)?;
+    fs::write(dir.join("tb.sv"), tb).map_err(|e| e.to_string())?;
+    let log = sim.run(dir)?;
+
+    let ticks = suite.schedule(case);
+    let trace: Vec<&[u8]> = log
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("OUT "))
+        .map(|bits| bits.trim().as_bytes())
+        .collect();
+    if trace.len() != ticks.len() || !log.contains("DONE") {
+        return Err(format!("simulation stopped after {} of {} ticks:\n{}", trace.len(), ticks.len(), log));
+    }
+
+    let mut report = CaseReport {
+        description: case.description.clone(),
+        ..Default::default()
+    };
+    for (index, (tick, bits)) in ticks.iter().zip(trace).enumerate() {
+        for check in &tick.checks {
+            // Single bits only tell whether an analog signal is on, hex lamps show its strength
+            let entry = link_bits(&link.outputs, suite, &check.signal);
+            let Some((bit, width)) = entry.filter(|&(_, width)| width > 1 || !suite.signals[&check.signal].analog) else {
+                report.skipped += 1;
+                continue;
+            };
+            let strength = output_value(bits, bit, width).map(|value| if width == 1 { value * 15 } else { value });
+            suite.check(&mut report, index, check, strength);
+        }
+    }
+    Ok(report)
+}
+
+/// The `width` bits from `bit` up of a line printed with `%b`, which starts with the highest bit
+fn output_value(bits: &[u8], bit: usize, width: usize) -> Option<u8> {
+    let mut value = 0;
+    for i in (bit..bit + width).rev() {
+        let &b = bits.get(bits.len().checked_sub(i + 1)?)?;
+        value = value << 1 | (b == b'1') as u8;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mchprs_blocks::blocks::{Block, Lever, RedstoneWire};
+    use mchprs_blocks::BlockPos;
+
+    #[test]
+    fn hex_lamp_bits() {
+        let mut link = Linker::default();
+        link.add_block(Block::RedstoneLamp { lit: false }, BlockPos::new(1, 1, 1));
+        link.add_block(Block::RedstoneWire { wire: RedstoneWire::default() }, BlockPos::new(2, 1, 1));
+        link.add_block(Block::RedstoneLamp { lit: false }, BlockPos::new(3, 1, 1));
+        link.add_block(Block::Lever { lever: Lever::default() }, BlockPos::new(4, 1, 1));
+        let suite = TestSuite::parse(r#"{
+            "version": 2, "name": "hex", "description": "",
+            "signals": { "hex": { "pos": "2,1,1", "analog": true }, "lamp": "3,1,1", "lever": "4,1,1" },
+            "tests": [{ "description": "", "steps": [{ "set": { "lever": 1 }, "expect": { "hex": 9, "lamp": 1 } }] }]
+        }"#).unwrap();
+
+        let tb = testbench(&suite, &suite.tests[0], &link).unwrap();
+        assert!(tb.contains("wire[6-1:0] outputs;"), "{}", tb);
+        assert!(tb.contains("inputs[0:0] = 1'd1;"), "{}", tb);
+
+        // The lamp is bit 5, the hex lamp bits 1 to 4
+        let bits = b"110010";
+        assert_eq!(output_value(bits, 5, 1), Some(1));
+        assert_eq!(output_value(bits, 1, 4), Some(9));
+        assert_eq!(output_value(bits, 0, 1), Some(0));
+        assert_eq!(output_value(bits, 4, 4), None);
+    }
+}
@@ -130,6 +130,18 @@ fn repeater_id_test() {
     assert_eq!(new, original);
 }
 
+#[test]
+fn observer_id_test() {
+    let original = Block::Observer {
+        facing: BlockFacing::West,
+        powered: true,
+    };
+    let id = original.get_id();
+    assert_eq!(id, 12556);
+    let new = Block::from_id(id);
+    assert_eq!(new, original);
+}
+
 #[test]
 fn comparator_id_test() {
     let original = Block::RedstoneComparator {
@@ -745,20 +757,22 @@ blocks! {
     },
     Observer {
         props: {
-            facing: BlockFacing
+            facing: BlockFacing,
+            powered: bool
         },
-        get_id: (facing.get_id() << 1) + 12551,
-        from_id_offset: 12551,
-        from_id(id): 12551..=12561 => {
-            facing: BlockFacing::from_id(id >> 1)
+        get_id: (facing.get_id() << 1) + !powered as u32 + 12550,
+        from_id_offset: 12550,
+        from_id(id): 12550..=12561 => {
+            facing: BlockFacing::from_id(id >> 1),
+            powered: (id & 1) == 0
         },
         from_names(_name): {
             "observer" => {
-                facing: Default::default()
+                facing: Default::default(),
+                powered: false
             }
         },
         get_name: "observer",
-        solid: true,
         cube: true,
     },
     SeaPickle {
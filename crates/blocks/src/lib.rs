@@ -98,7 +98,10 @@ impl FromStr for BlockPos {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut cords: Vec<i32> = Vec::new();
         for cord in s.split(',') {
-            cords.push(i32::from_str(cord).unwrap());
+            cords.push(i32::from_str(cord).map_err(|_| false)?);
+        }
+        if cords.len() != 3 {
+            return Err(false);
         }
 
         Ok(BlockPos{x:cords[0],y:cords[1],z:cords[2]})
@@ -378,6 +381,29 @@ impl BlockFacing {
         }
     }
 
+    pub fn opposite(self) -> BlockFacing {
+        use BlockFacing::*;
+        match self {
+            North => South,
+            South => North,
+            East => West,
+            West => East,
+            Up => Down,
+            Down => Up,
+        }
+    }
+
+    pub fn block_face(self) -> BlockFace {
+        match self {
+            BlockFacing::North => BlockFace::North,
+            BlockFacing::South => BlockFace::South,
+            BlockFacing::East => BlockFace::East,
+            BlockFacing::West => BlockFace::West,
+            BlockFacing::Up => BlockFace::Top,
+            BlockFacing::Down => BlockFace::Bottom,
+        }
+    }
+
     pub fn offset_pos(self, mut pos: BlockPos, n: i32) -> BlockPos {
         match self {
             BlockFacing::North => pos.z -= n,
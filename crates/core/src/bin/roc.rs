@@ -0,0 +1,162 @@
+//! Compiles a schematic, or a graph exported with `--export`, to RoC verilog and
+//! a link file without starting the server.
+//!
+//! Usage: roc <SCHEMATIC|GRAPH.bc> [--out DIR] [--name NAME] [--bounds X,Y,Z X,Y,Z]
+//!            [--project] [--devices FILE] [--buses FILE] [--formal PROPERTIES]
+//!            [--equiv OTHER] [compiler options]
+//!
+//! Compiler options are the ones of `/redpiler compile`, e.g. `--optimize`.
+//! They and `--bounds` don't apply to graphs, which are already compiled,
+//! except for `--export-netlist`, which also writes a Yosys JSON and BLIF netlist,
+//! and `--export-html`, which writes `graph.html` to browse the compiled graph.
+//! `--project` also writes the Quartus project for the first device of
+//! `--devices`. Its source paths are relative, so the output directory should
+//! stay three levels below `FPGA/` like the default `FPGA/bin/offline/<name>`.
+//!
+//! `--buses` groups interface blocks into buses like `[bus]` signs, from a JSON
+//! file of bus names and bit positions, see `mchprs_backend::fpga::linker::BusFile`.
+//! Each bus becomes a `bus_<name>` port of the verilog and an entry of the link file.
+//!
+//! `--formal` writes `model.btor2` with the properties of a JSON file, see
+//! `mchprs_backend::formal`. `--equiv` writes `equiv.btor2`, which is bad when
+//! the build and OTHER, compiled without compiler options, differ in an output.
+//! Both are checked with a BTOR2 model checker, e.g. `btormc -kmax 50 model.btor2`.
+
+use mchprs_backend::formal;
+use mchprs_backend::fpga::compiler::DeviceConfig;
+use mchprs_backend::fpga::generate_verilog;
+use mchprs_backend::fpga::linker::{self, Linker};
+use mchprs_backend::fpga::netlist;
+use mchprs_blocks::BlockPos;
+use mchprs_core::plot::worldedit::schematic::{load_schematic, SchematicWorld};
+use mchprs_redpiler::{graph_file, graph_html};
+use mchprs_redpiler::passes::make_default_pass_manager;
+use mchprs_redpiler::compile_graph::CompileGraph;
+use mchprs_redpiler::{BackendVariant, CompilerInput, CompilerOptions};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::{fs, process};
+
+fn fail(msg: &str) -> ! {
+    eprintln!("roc: {}", msg);
+    process::exit(1);
+}
+
+fn parse_pos(s: Option<String>) -> BlockPos {
+    let s = s.unwrap_or_else(|| fail("--bounds needs two positions"));
+    BlockPos::from_str(&s).unwrap_or_else(|_| fail(&format!("invalid block position \"{}\"", s)))
+}
+
+/// Loads an exported graph, or compiles a schematic within `bounds` and prints the compile report
+fn load_graph(path: &Path, options: &CompilerOptions, bounds: Option<(BlockPos, BlockPos)>) -> CompileGraph {
+    if path.extension().is_some_and(|ext| ext == "bc") {
+        return graph_file::load(path).unwrap_or_else(|err| fail(&err));
+    }
+    let cb = load_schematic(path.to_str().unwrap(), true)
+        .unwrap_or_else(|err| fail(&format!("could not load {}: {}", path.display(), err)));
+    let (world, schematic_bounds) = SchematicWorld::from_schematic(&cb);
+    let bounds = bounds.unwrap_or(schematic_bounds);
+    let world = Mutex::new(world);
+
+    let input = CompilerInput { world: &world, bounds };
+    let (graph, report) = make_default_pass_manager::<SchematicWorld>().run_passes_with_report(options, &input);
+    print!("{}", report);
+    graph
+}
+
+fn main() {
+    let mut schematic = None;
+    let mut out = None;
+    let mut name = None;
+    let mut bounds = None;
+    let mut project = false;
+    let mut devices = PathBuf::from("FPGA/config/devices.json");
+    let mut properties: Option<PathBuf> = None;
+    let mut equiv: Option<PathBuf> = None;
+    let mut buses: Option<PathBuf> = None;
+    let mut compiler_args = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" | "-o" => out = Some(PathBuf::from(args.next().unwrap_or_else(|| fail("--out needs a directory")))),
+            "--name" => name = Some(args.next().unwrap_or_else(|| fail("--name needs a name"))),
+            "--bounds" => bounds = Some((parse_pos(args.next()), parse_pos(args.next()))),
+            "--project" => project = true,
+            "--devices" => devices = args.next().unwrap_or_else(|| fail("--devices needs a file")).into(),
+            "--buses" => buses = Some(args.next().unwrap_or_else(|| fail("--buses needs a bus file")).into()),
+            "--formal" => properties = Some(args.next().unwrap_or_else(|| fail("--formal needs a property file")).into()),
+            "--equiv" => equiv = Some(args.next().unwrap_or_else(|| fail("--equiv needs a schematic or graph")).into()),
+            _ if arg.starts_with('-') => compiler_args.push(arg),
+            _ if schematic.is_none() => schematic = Some(PathBuf::from(arg)),
+            _ => fail(&format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+    let Some(schematic) = schematic else {
+        fail("usage: roc <SCHEMATIC|GRAPH.bc> [--out DIR] [--name NAME] [--bounds X,Y,Z X,Y,Z] [--project] [--devices FILE] [--buses FILE] [--formal PROPERTIES] [--equiv OTHER] [compiler options]");
+    };
+
+    let mut options = CompilerOptions::parse(&compiler_args.join(" "));
+    match options.backend_variant {
+        BackendVariant::Hybrid => fail("hybrid builds need the server for their CPU part"),
+        _ => options.backend_variant = BackendVariant::FPGA,
+    }
+
+    let name = name.unwrap_or_else(|| schematic.file_stem().unwrap().to_string_lossy().to_string());
+    let out = out.unwrap_or_else(|| Path::new("FPGA/bin/offline").join(&name));
+
+    // Written to the output directory below instead of by the export pass
+    let export_html = std::mem::take(&mut options.export_html);
+    let mut graph = load_graph(&schematic, &options, bounds);
+    if let Some(buses) = &buses {
+        linker::apply_bus_file(&mut graph, buses).unwrap_or_else(|err| fail(&err));
+    }
+
+    let mut link = Linker::from_graph(&graph);
+    link.name = name;
+    link.generate_link_file(&out.join("link.json"));
+    generate_verilog(&graph, &out.join("redstone.sv"));
+    if options.export_netlist {
+        netlist::export(&graph, &out).unwrap_or_else(|err| fail(&format!("could not write the netlist: {}", err)));
+    }
+    if export_html {
+        graph_html::save(&graph, &link.name, &out.join("graph.html")).unwrap_or_else(|err| fail(&format!("could not write the graph viewer: {}", err)));
+    }
+    println!(
+        "{} nodes, {} input bits, {} output bits written to {}",
+        graph.node_count(),
+        link.input_bits,
+        link.output_bits,
+        out.display()
+    );
+    for bus in &link.buses {
+        let kind = if bus.is_input { "input" } else { "output" };
+        println!("  {} bus {}: {} blocks", kind, bus.name, bus.blocks.len());
+    }
+
+    if let Some(properties) = properties {
+        formal::export(&graph, &properties, &out.join("model.btor2")).unwrap_or_else(|err| fail(&err));
+        println!("Model checking properties of {} written to {}", properties.display(), out.join("model.btor2").display());
+    }
+    if let Some(other) = equiv {
+        let plain = CompilerOptions { backend_variant: BackendVariant::FPGA, ..Default::default() };
+        let other_graph = load_graph(&other, &plain, bounds);
+        formal::export_miter(&graph, &other_graph, &out.join("equiv.btor2")).unwrap_or_else(|err| fail(&err));
+        println!("Equivalence check against {} written to {}", other.display(), out.join("equiv.btor2").display());
+    }
+
+    if project {
+        let text = fs::read_to_string(&devices)
+            .unwrap_or_else(|err| fail(&format!("could not read {}: {}", devices.display(), err)));
+        let configs: Vec<DeviceConfig> =
+            serde_json::from_str(&text).unwrap_or_else(|err| fail(&format!("invalid {}: {}", devices.display(), err)));
+        let Some(config) = configs.first() else {
+            fail(&format!("{} lists no devices", devices.display()));
+        };
+        if !config.write_project(&out.join("prj/prj.tcl"), link.output_bits, link.input_bits) {
+            fail("could not write the project");
+        }
+        println!("Quartus project for {} written to {}", config.name, out.join("prj").display());
+    }
+}
@@ -218,7 +218,7 @@ pub fn place_in_world(
             }
         };
     }
-    world.set_block(pos, block);
+    redstone::set_block(world, pos, block);
     change_surrounding_blocks(world, pos);
     if let Block::RedstoneWire { .. } = block {
         redstone::update_wire_neighbors(world, pos);
@@ -234,12 +234,12 @@ pub fn destroy(block: Block, world: &mut impl World, pos: BlockPos) {
 
     match block {
         Block::RedstoneWire { .. } => {
-            world.set_block(pos, Block::Air {});
+            redstone::set_block(world, pos, Block::Air {});
             change_surrounding_blocks(world, pos);
             redstone::update_wire_neighbors(world, pos);
         }
         Block::Lever { lever } => {
-            world.set_block(pos, Block::Air {});
+            redstone::set_block(world, pos, Block::Air {});
             // This is a horrible idea, don't do this.
             // One day this will be fixed, but for now... too bad!
             match lever.face {
@@ -264,7 +264,7 @@ pub fn destroy(block: Block, world: &mut impl World, pos: BlockPos) {
             }
         }
         _ => {
-            world.set_block(pos, Block::Air {});
+            redstone::set_block(world, pos, Block::Air {});
             change_surrounding_blocks(world, pos);
             redstone::update_surrounding_blocks(world, pos);
         }
@@ -334,7 +334,7 @@ pub fn change(block: Block, world: &mut impl World, pos: BlockPos, direction: Bl
     }
     if let Block::RedstoneWire { wire } = block {
         let new_state = redstone::wire::on_neighbor_changed(wire, world, pos, direction);
-        if world.set_block(pos, Block::RedstoneWire { wire: new_state }) {
+        if redstone::set_block(world, pos, Block::RedstoneWire { wire: new_state }) {
             redstone::update_wire_neighbors(world, pos);
         }
     }
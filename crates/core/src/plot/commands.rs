@@ -1,23 +1,91 @@
+use super::worldedit::schematic;
 use super::{database, worldedit, Plot, PlotWorld};
 use crate::player::{Gamemode, PacketSender, PlayerPos};
-use crate::plot::data::sleep_time_for_tps;
+use crate::plot::data::{backend_rtps, sleep_time_for_tps};
 use crate::profile::PlayerProfile;
 use crate::server::Message;
+use mchprs_backend::sim_test::TestSuite;
+use mchprs_backend::{Backend, BackendMsg};
 use mchprs_blocks::items::ItemStack;
+use mchprs_blocks::BlockPos;
 use mchprs_network::packets::clientbound::{
     CCommands, CCommandsNode as Node, CDeclareCommandsNodeParser as Parser, ClientBoundPacket,
 };
 use mchprs_network::packets::PacketEncoder;
 use mchprs_network::PlayerPacketSender;
-use mchprs_redpiler::{BackendVariant, CompilerOptions};
+use mchprs_redpiler::compile_graph::CompileGraph;
+use mchprs_redpiler::{timing, BackendVariant, CompilerInput, CompilerOptions};
 use mchprs_save_data::plot_data::{Tps, WorldSendRate};
 use mchprs_text::TextComponent;
 use once_cell::sync::Lazy;
+use std::fs;
 use std::ops::Add;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::thread;
 use std::time::Instant;
 use tracing::{debug, info, warn};
 
+/// Where `/roc record` saves its schematics and tests, one folder per recording.
+/// `/test run` looks up its tests here as well.
+const RECORD_DIR: &str = "tests/roc/sim_tests";
+
+/// Paths listed by `/redpiler timing`, the others are only counted
+const TIMING_LINES: usize = 5;
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary bus value
+fn parse_bus_value(value: &str) -> Option<u64> {
+    let value = value.replace('_', "");
+    if let Some(hex) = value.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = value.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+/// The `/redpiler timing` report of `graph` as chat lines, for the paths from the first of
+/// `nodes` to the second or for every path when there are none
+fn timing_report(graph: &CompileGraph, nodes: &[String]) -> Result<Vec<String>, String> {
+    let find = |arg: &str| {
+        let pos = arg.split(',').map(|c| c.parse::<i32>()).collect::<Result<Vec<_>, _>>();
+        graph
+            .node_indices()
+            .find(|&idx| match (&pos, graph[idx].block) {
+                (Ok(pos), Some((block, _))) if pos.len() == 3 => block == BlockPos::new(pos[0], pos[1], pos[2]),
+                _ => graph[idx].annotations.name.as_deref() == Some(arg),
+            })
+            .ok_or_else(|| format!("No component at {}", arg))
+    };
+    let latencies = match nodes {
+        [from, to] => {
+            let (from, to) = (find(from)?, find(to)?);
+            let timing = timing::latency(graph, from, to).ok_or("The first component never reaches the second")?;
+            vec![(from, to, timing)]
+        }
+        _ => timing::all_latencies(graph),
+    };
+    let Some((_, _, critical)) = latencies.first() else {
+        return Err("No input reaches an output".to_string());
+    };
+
+    let node = |idx| timing::describe_path(graph, &[idx]);
+    let mut lines = vec![format!("Timing of {} paths, slowest first:", latencies.len())];
+    for (from, to, timing) in latencies.iter().take(TIMING_LINES) {
+        lines.push(format!("&6{} &7to &6{}&7: &a{}&7-&a{} &7ticks", node(*from), node(*to), timing.min, timing.max));
+    }
+    lines.push(format!("&7Critical path: {}", timing::describe_path(graph, &critical.critical_path)));
+    if critical.loops {
+        lines.push("&7Loops on the way are counted once".to_string());
+    }
+    if critical.lock_gated {
+        lines.push("&7It goes through a repeater's lock, which only passes on a change of that repeater's input".to_string());
+    }
+    Ok(lines)
+}
+
 // Parses a relative or absolute coordinate relative to a reference coordinate
 fn parse_relative_coord<F: FromStr + Add + Add<Output = F>>(
     coord: &str,
@@ -212,10 +280,64 @@ impl Plot {
             "reset" | "r" => {
                 self.reset_backend();
             }
+            "passes" | "p" => {
+                let options = CompilerOptions::parse(&args.join(" "));
+                let passes = mchprs_redpiler::passes::make_default_pass_manager::<PlotWorld>()
+                    .describe(&options);
+                let player = &self.players[player];
+                player.send_chat_message(TextComponent::from_legacy_text(&format!(
+                    "&6Pass pipeline for {:?} ({} passes):",
+                    options.backend_variant,
+                    passes.len()
+                )));
+                for pass in passes {
+                    let color = if pass.runs { "&a" } else { "&c" };
+                    let required = if pass.required { " &e(required)" } else { "" };
+                    player.send_chat_message(TextComponent::from_legacy_text(&format!(
+                        "{}{}{} &7- {}",
+                        color, pass.id, required, pass.description
+                    )));
+                }
+            }
+            "timing" | "t" => {
+                if let Err(err) = self.redpiler_timing(player, args) {
+                    self.players[player].send_error_message(&err);
+                }
+            }
             _ => self.players[player].send_error_message("Invalid argument for /redpiler"),
         }
     }
 
+    /// Compiles the selection, or the whole plot, on another thread and reports how many ticks a
+    /// change takes from `<from>` to `<to>`, or from every input to every output. Nodes are given
+    /// by block position (`x,y,z`) or by the name of their `[name]` sign. The report comes back
+    /// as a [`BackendMsg::Timing`].
+    fn redpiler_timing(&mut self, player: usize, args: &[&str]) -> Result<(), String> {
+        let (nodes, flags): (Vec<&str>, Vec<&str>) = args.iter().copied().partition(|arg| !arg.starts_with("--"));
+        if !matches!(nodes.len(), 0 | 2) {
+            return Err("Usage: /redpiler timing [<from> <to>] [compiler options]".to_string());
+        }
+        let nodes: Vec<String> = nodes.into_iter().map(str::to_string).collect();
+        let options = CompilerOptions::parse(&flags.join(" "));
+        let plr = &self.players[player];
+        let bounds = match (plr.first_position, plr.second_position) {
+            (Some(first), Some(second)) => (first.min(second), first.max(second)),
+            _ => self.world.lock().unwrap().get_corners(),
+        };
+        plr.send_system_message("Compiling for the timing report...");
+
+        let world = Arc::clone(&self.world);
+        let sender = self.backend_tx.clone();
+        let uuid = plr.uuid;
+        thread::spawn(move || {
+            let input = CompilerInput { world: &*world, bounds };
+            let graph = mchprs_redpiler::passes::make_default_pass_manager::<PlotWorld>().run_passes(&options, &input);
+            let lines = timing_report(&graph, &nodes);
+            _ = sender.send(BackendMsg::Timing { player: uuid, lines });
+        });
+        Ok(())
+    }
+
     /// Handles a command that starts with `/fpga`
     fn handle_fpga_command(&mut self, player: usize, command: &str, args: &[&str]) {
         match command {
@@ -224,6 +346,17 @@ impl Plot {
             }
             "start" => {
 
+            }
+            // Writes to every serial port, so it is never done on its own
+            "scan" => {
+                let Some(active) = self.active_backend else {
+                    self.players[player].send_error_message("No backend is running");
+                    return;
+                };
+                match self.backends.lock().unwrap()[active].scan_ports() {
+                    Ok(()) => self.players[player].send_system_message("Reconnected to the FPGA"),
+                    Err(err) => self.players[player].send_error_message(&err),
+                }
             }
             _ => self.players[player].send_error_message("Invalid argument for /fpga"),
         }
@@ -233,11 +366,29 @@ impl Plot {
     fn handle_roc_command(&mut self, player: usize, command: &str, args: &[&str]) {
         match command {
             "compile" | "c" => {
-                let options = CompilerOptions::fpga();
+                let Some((name, flags)) = args.split_first().filter(|(name, _)| !name.starts_with("--")) else {
+                    self.players[player].send_error_message(
+                        "Usage: /roc compile <name> [--hybrid] [--export-netlist] [--export-html] [compiler options]",
+                    );
+                    return;
+                };
+                let mut options = CompilerOptions::fpga();
+                let parsed = CompilerOptions::parse(&flags.join(" "));
+                options.pipeline = parsed.pipeline;
+                options.passes = parsed.passes;
+                if flags.contains(&"--hybrid") {
+                    options.backend_variant = BackendVariant::Hybrid;
+                }
+                options.export_netlist = flags.contains(&"--export-netlist");
+                options.export_html = flags.contains(&"--export-html");
                 self.reset_backend();
-                self.start_backend(options, args[0].to_string(), player);
+                self.start_backend(options, name.to_string(), player);
             }
             "run" | "r" => {
+                let Some(&name) = args.first() else {
+                    self.players[player].send_error_message("Usage: /roc run <name>");
+                    return;
+                };
                 let mut backends = self.backends.lock().unwrap();
                 let mut i = 0;
 
@@ -249,14 +400,22 @@ impl Plot {
 
                 if self.scheduler.lock().unwrap().lock(self.world.lock().unwrap().get_plot()) {
                     for backend in &mut *backends {
-                        if backend.name == args[0] {
+                        if backend.name == name {
                             break;
                         }
                         i += 1;
                     }
                     if i < backends.len() {
-                        backends[i].run();
-                        self.active_backend = Some(i);
+                        match backends[i].run() {
+                            Ok(()) => {
+                                backends[i].set_rtps(backend_rtps(self.tps));
+                                self.active_backend = Some(i);
+                            }
+                            Err(err) => {
+                                self.players[player].send_error_message(&err);
+                                self.scheduler.lock().unwrap().free(self.world.lock().unwrap().get_plot());
+                            }
+                        }
                     }
                     else {
                         self.players[player].send_error_message("Invalid Build Name");
@@ -277,10 +436,228 @@ impl Plot {
                     self.scheduler.lock().unwrap().free(self.world.lock().unwrap().get_plot());
                 }
             }
+            "record" => self.handle_roc_record(player, args),
+            "set" | "get" => self.handle_roc_bus(player, command, args),
             _ => self.players[player].send_error_message("Invalid argument for /fpga"),
         }
     }
 
+    /// Handles `/roc set <bus> <value>` and `/roc get <bus>` on the running build
+    fn handle_roc_bus(&mut self, player: usize, command: &str, args: &[&str]) {
+        let Some(active) = self.active_backend else {
+            self.players[player].send_error_message("No backend is running");
+            return;
+        };
+        let mut backends = self.backends.lock().unwrap();
+        let backend = &mut backends[active];
+        let result = match (command, args) {
+            ("set", [bus, value]) => match parse_bus_value(value) {
+                Some(value) => {
+                    let mut world = self.world.lock().unwrap();
+                    let result = backend.set_bus(&mut *world, bus, value);
+                    world.flush_block_changes();
+                    result.map(|_| format!("Set {} to {:#x} ({})", bus, value, value))
+                }
+                None => Err(format!("Invalid value \"{}\", use decimal, 0x hex or 0b binary", value)),
+            },
+            ("get", [bus]) => backend.get_bus(bus).map(|value| format!("{} = {:#x} ({})", bus, value, value)),
+            _ => Err("Usage: /roc set <bus> <value> | /roc get <bus>".to_string()),
+        };
+        match result {
+            Ok(msg) => self.players[player].send_system_message(&msg),
+            Err(err) => self.players[player].send_error_message(&err),
+        }
+    }
+
+    /// Handles `/roc record start <name> [--force]` and `/roc record stop`
+    fn handle_roc_record(&mut self, player: usize, args: &[&str]) {
+        match args {
+            ["start", name, flags @ ..] if flags.iter().all(|flag| *flag == "--force") => {
+                let valid_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid_name {
+                    self.players[player].send_error_message("Test names may only contain letters, digits and '_'");
+                    return;
+                }
+                let dir = Path::new(RECORD_DIR).join(name);
+                if dir.exists() && flags.is_empty() {
+                    self.players[player].send_error_message(&format!(
+                        "{} already exists, use --force to overwrite it",
+                        dir.display()
+                    ));
+                    return;
+                }
+                let Some(active) = self.active_backend else {
+                    self.players[player].send_error_message("No backend is running");
+                    return;
+                };
+                let mut world = self.world.lock().unwrap();
+                let mut backends = self.backends.lock().unwrap();
+                if backends.iter().any(|b| b.is_recording()) {
+                    self.players[player].send_error_message("Already recording, use /roc record stop first");
+                    return;
+                }
+                let backend = &mut backends[active];
+                if let Err(err) = backend.start_recording(name.to_string(), &*world) {
+                    self.players[player].send_error_message(&err);
+                    return;
+                }
+                let (first, second) = backend.bounds().unwrap();
+                self.recording = Some(worldedit::create_clipboard(&mut world, first.min(second), first, second));
+                self.players[player].send_system_message(&format!("Recording {}, use /roc record stop to save it", name));
+            }
+            ["stop"] => {
+                let world = self.world.lock().unwrap();
+                let recorder = self.backends.lock().unwrap().iter_mut().find_map(|b| b.stop_recording(&*world));
+                drop(world);
+                let (Some(recorder), Some(clipboard)) = (recorder, self.recording.take()) else {
+                    self.players[player].send_error_message("Nothing is being recorded");
+                    return;
+                };
+                let dir = Path::new(RECORD_DIR).join(&recorder.name);
+                let test = serde_json::to_string_pretty(&recorder.to_test()).unwrap();
+                let saved = schematic::save_schematic_to(&dir.join("build.schem"), &clipboard)
+                    .map_err(|err| err.to_string())
+                    .and_then(|_| fs::write(dir.join("test.json"), test).map_err(|err| err.to_string()));
+                match saved {
+                    Ok(()) => self.players[player].send_system_message(&format!(
+                        "Saved the recording to {}, the sim tests run it from now on",
+                        dir.display()
+                    )),
+                    Err(err) => self.players[player].send_error_message(&format!("Could not save the recording: {}", err)),
+                }
+            }
+            _ => self.players[player].send_error_message("Usage: /roc record start <name> [--force] | /roc record stop"),
+        }
+    }
+
+    /// Handles `/test run <file> [--paste] [compiler options]`
+    fn handle_test_command(&mut self, player: usize, command: &str, args: &[&str]) {
+        let ("run", [file, rest @ ..]) = (command, args) else {
+            self.players[player].send_error_message("Usage: /test run <file> [--paste] [compiler options]");
+            return;
+        };
+        let paste = rest.contains(&"--paste");
+        let options: Vec<&str> = rest.iter().copied().filter(|arg| *arg != "--paste").collect();
+        let options = CompilerOptions::parse(&options.join(" "));
+
+        if let Err(err) = self.run_test(player, file, paste, options) {
+            self.players[player].send_error_message(&err);
+        }
+    }
+
+    /// Compiles the selection, or `build.schem` pasted at the first position, once
+    /// per case of the test and reports every step in chat. The build and its
+    /// pending ticks are put back as they were after each case.
+    fn run_test(&mut self, player: usize, file: &str, paste: bool, options: CompilerOptions) -> Result<(), String> {
+        if file.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err("Invalid test file".to_string());
+        }
+        let mut path = Path::new(RECORD_DIR).join(file);
+        if path.is_dir() {
+            path.push("test.json");
+        }
+        let suite = TestSuite::load(&path)?;
+
+        // Plain FPGA builds run on their own clock, only backends that tick with the plot can be stepped
+        if options.backend_variant == BackendVariant::FPGA {
+            return Err("FPGA builds can't be stepped, use --hybrid or the simulation tests".to_string());
+        }
+        if self.active_backend.is_some() {
+            return Err("Stop the running backend before running a test".to_string());
+        }
+
+        let plr = &self.players[player];
+        let bounds = if paste {
+            let schem = path.with_file_name("build.schem");
+            let cb = schematic::load_schematic(schem.to_str().unwrap(), true)
+                .map_err(|err| format!("Could not load {}: {}", schem.display(), err))?;
+            let origin = plr.first_position.unwrap_or_else(|| plr.pos.block_pos());
+            let end = origin + BlockPos::new(cb.size_x as i32 - 1, cb.size_y as i32 - 1, cb.size_z as i32 - 1);
+            let pos = origin + BlockPos::new(cb.offset_x, cb.offset_y, cb.offset_z);
+            worldedit::paste_clipboard(&mut self.world.lock().unwrap(), &cb, pos, false);
+            (origin, end)
+        } else {
+            match (plr.first_position, plr.second_position) {
+                (Some(first), Some(second)) => (first.min(second), first.max(second)),
+                _ => return Err("Select the build or use --paste".to_string()),
+            }
+        };
+        let origin = bounds.0;
+
+        let uses_fpga = options.backend_variant.uses_fpga();
+        let config = if uses_fpga {
+            if !self.scheduler.lock().unwrap().lock(self.world.lock().unwrap().get_plot()) {
+                return Err("No Active FPGAs".to_string());
+            }
+            self.players[player].send_system_message("Compiling for the FPGA, the plot will pause until it is done");
+            Some(self.scheduler.lock().unwrap().get_config())
+        } else {
+            None
+        };
+
+        let (x, z, snapshot, initial_ticks) = {
+            let mut world = self.world.lock().unwrap();
+            let snapshot = worldedit::create_clipboard(&mut world, bounds.0, bounds.0, bounds.1);
+            (world.x, world.z, snapshot, world.to_be_ticked.clone())
+        };
+        let mut passed = 0;
+        for case in &suite.tests {
+            // The scoreboard only lists backends of the plot, not test builds
+            let (sender, _receiver) = mpsc::channel();
+            let ticks = self.world.lock().unwrap().to_be_ticked.drain(..).collect();
+            let mut backend = Backend::new(
+                sender,
+                format!("test_{}", suite.name),
+                format!("{}-{}", x, z),
+                config.clone(),
+                &self.world,
+                bounds,
+                options.clone(),
+                ticks,
+            );
+            if let Err(err) = backend.run() {
+                self.players[player].send_error_message(&err);
+                break;
+            }
+
+            let mut world = self.world.lock().unwrap();
+            let report = suite.run_backend(case, &mut backend, &mut *world, origin);
+            if uses_fpga {
+                backend.stop();
+            }
+            backend.reset(&mut *world, bounds);
+            // Resetting only restores the interface blocks, the rest of the build keeps its last state
+            worldedit::paste_clipboard(&mut world, &snapshot, bounds.0, false);
+            world.to_be_ticked = initial_ticks.clone();
+            drop(world);
+
+            let steps = case.steps.len();
+            let plr = &self.players[player];
+            if report.passed() {
+                passed += 1;
+                plr.send_system_message(&format!("{}: passed {} steps", case.description, steps));
+                continue;
+            }
+            plr.send_error_message(&format!("{}: failed", case.description));
+            for step in 0..steps {
+                let failures: Vec<_> = report.failures.iter().filter(|f| f.step == step).collect();
+                if failures.is_empty() {
+                    plr.send_system_message(&format!("  step {} passed", step));
+                }
+                for failure in failures {
+                    plr.send_error_message(&format!("  {}", failure));
+                }
+            }
+        }
+
+        if uses_fpga {
+            self.scheduler.lock().unwrap().free(self.world.lock().unwrap().get_plot());
+        }
+        self.reset_timings();
+        self.players[player].send_system_message(&format!("{}: {} of {} cases passed", suite.name, passed, suite.tests.len()));
+        Ok(())
+    }
+
     // Returns true if packets should stop being handled
     pub(super) fn handle_command(
         &mut self,
@@ -376,13 +753,8 @@ impl Plot {
                 self.tps = tps;
                 self.reset_timings();
                 self.players[player].send_system_message("The rtps was successfully set.");
-                match tps {
-                    Tps::Limited(rtps) => {
-                        if !self.active_backend.is_none() {
-                            self.backends.lock().unwrap()[self.active_backend.unwrap()].set_rtps(rtps);
-                        }
-                    }
-                    _ => ()
+                if let Some(active) = self.active_backend {
+                    self.backends.lock().unwrap()[active].set_rtps(backend_rtps(tps));
                 }
             }
             "radv" | "radvance" => {
@@ -495,6 +867,14 @@ impl Plot {
                 let command = args.remove(0);
                 self.handle_roc_command(player, command, &args);
             }
+            "test" => {
+                if args.is_empty() {
+                    self.players[player].send_error_message("Invalid number of arguments!");
+                    return false;
+                }
+                let command = args.remove(0);
+                self.handle_test_command(player, command, &args);
+            }
             "speed" => {
                 if args.len() != 1 {
                     self.players[player].send_error_message("/speed <0-10>");
@@ -1033,7 +1413,7 @@ pub static DECLARE_COMMANDS: Lazy<PacketEncoder> = Lazy::new(|| {
             // 44: /redpiler
             Node {
                 flags: CommandFlags::LITERAL.bits() as i8,
-                children: vec![46, 47, 48], // Children are compile, inspect, reset
+                children: vec![46, 47, 48, 52, 53], // Children are compile, inspect, reset, passes, timing
                 redirect_node: None,
                 name: Some("redpiler"),
                 parser: None,
@@ -1102,6 +1482,24 @@ pub static DECLARE_COMMANDS: Lazy<PacketEncoder> = Lazy::new(|| {
                 parser: None,
                 suggestions_type: None,
             },
+            // 52: /redpiler passes
+            Node {
+                flags: (CommandFlags::LITERAL | CommandFlags::EXECUTABLE).bits() as i8,
+                children: vec![],
+                redirect_node: None,
+                name: Some("passes"),
+                parser: None,
+                suggestions_type: None,
+            },
+            // 53: /redpiler timing
+            Node {
+                flags: (CommandFlags::LITERAL | CommandFlags::EXECUTABLE).bits() as i8,
+                children: vec![],
+                redirect_node: None,
+                name: Some("timing"),
+                parser: None,
+                suggestions_type: None,
+            },
         ],
         root_index: 0,
     };
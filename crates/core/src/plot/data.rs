@@ -19,6 +19,14 @@ pub fn sleep_time_for_tps(tps: Tps) -> Duration {
     }
 }
 
+/// The rate handed to hardware backends, `None` meaning free running
+pub fn backend_rtps(tps: Tps) -> Option<u32> {
+    match tps {
+        Tps::Limited(tps) => Some(tps),
+        Tps::Unlimited => None,
+    }
+}
+
 pub fn load_plot(path: impl AsRef<Path>) -> Result<PlotData> {
     let path = path.as_ref();
     if path.exists() {
@@ -12,6 +12,7 @@ use crate::interaction::UseOnBlockContext;
 use crate::player::{EntityId, Gamemode, PacketSender, Player, PlayerPos};
 use crate::server::{BroadcastMessage, Message, PrivMessage};
 use crate::utils::HyphenatedUUID;
+use worldedit::WorldEditClipboard;
 use anyhow::Error;
 use bus::BusReader;
 use fpga::scheduler::FPGAScheduler;
@@ -23,7 +24,8 @@ use mchprs_network::packets::clientbound::*;
 use mchprs_network::packets::serverbound::SUseItemOn;
 use mchprs_network::PlayerPacketSender;
 use mchprs_backend::{Backend, BackendMsg};
-use mchprs_redpiler::{BackendVariant, CompilerOptions};
+use mchprs_redpiler::report::CompileReport;
+use mchprs_redpiler::CompilerOptions;
 use mchprs_save_data::plot_data::{ChunkData, PlotData, Tps, WorldSendRate};
 use mchprs_text::TextComponent;
 use mchprs_world::storage::Chunk;
@@ -41,7 +43,7 @@ use tokio::runtime::Runtime;
 use tracing::{debug, error, warn};
 
 use self::data::sleep_time_for_tps;
-use self::scoreboard::Scoreboard;
+use self::scoreboard::{Scoreboard, RTPS_REFRESH_INTERVAL};
 
 /// The width of a plot (2^n)
 pub const PLOT_SCALE: u32 = 5;
@@ -102,6 +104,8 @@ pub struct Plot {
 
     //fpga
     scheduler: Arc<Mutex<FPGAScheduler>>,
+    /// The compiled region as it was when `/roc record start` ran
+    recording: Option<WorldEditClipboard>,
 }
 
 pub struct PlotWorld {
@@ -285,8 +289,15 @@ impl World for PlotWorld {
 impl Plot {
     fn tickn(&mut self, ticks: u64) {
         if !self.active_backend.is_none() {
-            self.timings.tickn(ticks);
-            self.backends.lock().unwrap()[self.active_backend.unwrap()].tickn(ticks);
+            let mut backends = self.backends.lock().unwrap();
+            let backend = &mut backends[self.active_backend.unwrap()];
+            backend.tickn(ticks);
+            // Hardware backends tick on their own clock, so count what they actually ran.
+            // Their counter is only read as often as the measured rtps is shown.
+            match backend.take_hardware_ticks(RTPS_REFRESH_INTERVAL) {
+                Some(hw_ticks) => self.timings.tickn(hw_ticks),
+                None => self.timings.tickn(ticks),
+            }
             return;
         }
 
@@ -345,6 +356,32 @@ impl Plot {
         }
     }
 
+    /// Sums up a finished compile for everyone on the plot
+    fn send_compile_report(&mut self, backend: &str, report: &CompileReport) {
+        let mut lines = report.summary().into_iter();
+        if let Some(total) = lines.next() {
+            self.broadcast_plot_chat_message(&format!("&6{}: &a{}", backend, total));
+        }
+        for line in lines {
+            self.broadcast_plot_chat_message(&format!("&7{}", line));
+        }
+    }
+
+    /// Sends a `/redpiler timing` report to the player who asked for it, if they are still here
+    fn send_timing_report(&mut self, uuid: u128, lines: Result<Vec<String>, String>) {
+        let Some(player) = self.players.iter().find(|p| p.uuid == uuid) else {
+            return;
+        };
+        match lines {
+            Ok(lines) => {
+                for line in lines {
+                    player.send_chat_message(TextComponent::from_legacy_text(&line));
+                }
+            }
+            Err(err) => player.send_error_message(&err),
+        }
+    }
+
     fn change_player_gamemode(&mut self, player_idx: usize, gamemode: Gamemode) {
         self.players[player_idx].set_gamemode(gamemode);
         let _ = self.message_sender.send(Message::PlayerUpdateGamemode(
@@ -726,7 +763,7 @@ impl Plot {
             } else {
                 self.world.lock().unwrap().get_corners()
             }.clone(); 
-        let config = if options.backend_variant == BackendVariant::FPGA {
+        let config = if options.backend_variant.uses_fpga() {
                 Some(self.scheduler.lock().unwrap().get_config())
             }
             else {
@@ -1035,9 +1072,24 @@ impl Plot {
 
         let mut new_sb = false;
         while let Ok(message) = self.backend_rx.try_recv() {
+            if let BackendMsg::Timing { player, lines } = message {
+                self.send_timing_report(player, lines);
+                continue;
+            }
+            if let BackendMsg::Connection { status, .. } = &message {
+                let plot = self.world.lock().unwrap().get_plot();
+                self.scheduler.lock().unwrap().set_status(plot, *status);
+            }
+            if let BackendMsg::Compiled { backend, report } = &message {
+                self.send_compile_report(backend, report);
+            }
             self.scoreboard.parse_scoreboard_msg(message);
             new_sb = true;
         }
+        if self.scoreboard.rtps_refresh_due() {
+            let measured = self.timings.generate_report().map(|report| report.ten_s);
+            new_sb |= self.scoreboard.set_rtps(self.tps, measured);
+        }
         if new_sb {
             self.scoreboard.update(&self.players, &self.scheduler);
         }
@@ -1221,6 +1273,7 @@ impl Plot {
             scoreboard: Scoreboard::new(),
             world:Arc::new(Mutex::new(world)),
             scheduler: fpga_scheduler, 
+            recording: None,
         }
 
     }
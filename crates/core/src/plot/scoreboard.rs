@@ -6,13 +6,19 @@ use mchprs_network::packets::clientbound::{
 };
 use mchprs_backend::{BackendStatus, BackendMsg};
 use mchprs_redpiler::CompilerOptions;
+use mchprs_save_data::plot_data::Tps;
 use mchprs_text::{ColorCode, TextComponent, TextComponentBuilder};
-use std::{collections::HashMap, sync::{Arc, Mutex}};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+/// How often the measured rtps on the scoreboard is refreshed
+pub const RTPS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Default)]
 pub struct Scoreboard {
     backend_list: HashMap<String, (CompilerOptions, BackendStatus)>,
     lines: Vec<ScoreboardLine>,
+    rtps: String,
+    last_rtps_refresh: Option<Instant>,
 }
 
 impl Scoreboard {
@@ -24,7 +30,7 @@ impl Scoreboard {
     fn to_str_vec(&self, scheduler: &Arc<Mutex<FPGAScheduler>>) -> Vec<ScoreboardLine> {
         let mut sb: Vec<ScoreboardLine> = Vec::new();
 
-        sb.push(ScoreboardLine::from_str("&fRTPS", Some("10")));
+        sb.push(ScoreboardLine::from_str("&fRTPS", Some(&self.rtps)));
         sb.push(ScoreboardLine::from_str("&fWSR", Some("10")));
         sb.push(ScoreboardLine::from_str("", None));
 
@@ -46,6 +52,7 @@ impl Scoreboard {
             if let Some((x,z)) = fpga.get_owner() {
                 sb.push(ScoreboardLine::from_str(&format!("&f{}", fpga.config.name), Some("&cLocked")));
                 sb.push(ScoreboardLine::from_str("  &fOwner", Some(&format!("&f{x},{z}"))));
+                sb.push(ScoreboardLine::from_str("  &fLink", Some(&fpga.get_status().to_str())));
             }
             else {
                 sb.push(ScoreboardLine::from_str(&format!("&f{}", fpga.config.name), Some("&aOpen")));
@@ -56,6 +63,25 @@ impl Scoreboard {
         sb
     }
 
+    pub fn rtps_refresh_due(&self) -> bool {
+        self.last_rtps_refresh.map_or(true, |t| t.elapsed() >= RTPS_REFRESH_INTERVAL)
+    }
+
+    /// Sets the rtps line to the measured rate next to the target.
+    /// Returns true if the text changed and the scoreboard needs resending.
+    pub fn set_rtps(&mut self, target: Tps, measured: Option<f32>) -> bool {
+        self.last_rtps_refresh = Some(Instant::now());
+        let rtps = match measured {
+            Some(measured) => format!("&a{:.1}&7/&f{}", measured, target),
+            None => format!("&f{}", target),
+        };
+        if rtps == self.rtps {
+            return false;
+        }
+        self.rtps = rtps;
+        true
+    }
+
     pub fn add_backend(&mut self, name: String, options: CompilerOptions) {
         self.backend_list.insert(name, (options, BackendStatus::Redpiling));
     }
@@ -77,6 +103,10 @@ impl Scoreboard {
             BackendMsg::BackendStatus { backend, status } => {
                 self.backend_list.get_mut(&backend).unwrap().1 = status;
             }
+            // Shown through the FPGA scheduler, see `Plot::update`
+            BackendMsg::Connection { .. } => {}
+            // Sent to chat, see `Plot::update`
+            BackendMsg::Compiled { .. } | BackendMsg::Timing { .. } => {}
         }
     }
 
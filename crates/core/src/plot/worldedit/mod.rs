@@ -975,7 +975,7 @@ fn worldedit_start_operation(player: &mut Player) -> WorldEditOperation {
     WorldEditOperation::new(first_pos, second_pos)
 }
 
-fn create_clipboard(
+pub(super) fn create_clipboard(
     plot: &mut PlotWorld,
     origin: BlockPos,
     first_pos: BlockPos,
@@ -1041,7 +1041,7 @@ fn clear_area(plot: &mut PlotWorld, first_pos: BlockPos, second_pos: BlockPos) {
     }
 }
 
-fn paste_clipboard(plot: &mut PlotWorld, cb: &WorldEditClipboard, pos: BlockPos, ignore_air: bool) {
+pub(super) fn paste_clipboard(plot: &mut PlotWorld, cb: &WorldEditClipboard, pos: BlockPos, ignore_air: bool) {
     let offset_x = pos.x - cb.offset_x;
     let offset_y = pos.y - cb.offset_y;
     let offset_z = pos.z - cb.offset_z;
@@ -8,13 +8,14 @@ use itertools::Itertools;
 use mchprs_blocks::block_entities::BlockEntity;
 use mchprs_blocks::blocks::Block;
 use mchprs_blocks::BlockPos;
-use mchprs_world::storage::PalettedBitBuffer;
+use mchprs_world::storage::{Chunk, PalettedBitBuffer};
+use mchprs_world::{TickEntry, TickPriority, World};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use rustc_hash::FxHashMap;
 use serde::Serialize;
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 macro_rules! nbt_as {
     // I'm not sure if path is the right type here.
@@ -215,9 +216,14 @@ struct Schematic {
 pub fn save_schematic(file_name: &str, clipboard: &WorldEditClipboard) -> Result<()> {
     let mut path = PathBuf::from("./schems");
     path.push(file_name);
+    save_schematic_to(&path, clipboard)
+}
+
+/// Saves a schematic to `path` instead of the `schems` folder
+pub fn save_schematic_to(path: &Path, clipboard: &WorldEditClipboard) -> Result<()> {
     fs::create_dir_all(path.parent().unwrap())?;
 
-    let mut file = File::create("./schems/".to_owned() + file_name)?;
+    let mut file = File::create(path)?;
     let size_x = clipboard.size_x;
     let size_y = clipboard.size_y;
     let size_z = clipboard.size_z;
@@ -301,3 +307,138 @@ pub fn save_schematic(file_name: &str, clipboard: &WorldEditClipboard) -> Result
 
     Ok(())
 }
+
+/// A world of a few chunks at the origin, to compile and run builds without a
+/// plot, see [`SchematicWorld::from_schematic`]
+#[derive(Clone, Debug)]
+pub struct SchematicWorld {
+    chunks: Vec<Chunk>,
+    pub to_be_ticked: Vec<TickEntry>,
+    size: i32,
+}
+
+impl SchematicWorld {
+    /// An empty world of `size` by `size` chunks, `size` sections high
+    pub fn new(size: i32) -> SchematicWorld {
+        let mut chunks = Vec::new();
+        for x in 0..size {
+            for z in 0..size {
+                chunks.push(Chunk::empty(x, z, size as usize));
+            }
+        }
+        SchematicWorld {
+            chunks,
+            to_be_ticked: Vec::new(),
+            size,
+        }
+    }
+
+    /// A world just large enough for `cb` with it pasted at the origin, and the
+    /// corners of the schematic in it
+    pub fn from_schematic(cb: &WorldEditClipboard) -> (SchematicWorld, (BlockPos, BlockPos)) {
+        let largest = cb.size_x.max(cb.size_y).max(cb.size_z);
+        let mut world = SchematicWorld::new(((largest + 15) >> 4).max(1) as i32);
+        world.paste(cb);
+        let end = BlockPos::new(cb.size_x as i32 - 1, cb.size_y as i32 - 1, cb.size_z as i32 - 1);
+        (world, (BlockPos::new(0, 0, 0), end))
+    }
+
+    /// Pastes `cb` with its lowest corner at the origin, leaving out air
+    pub fn paste(&mut self, cb: &WorldEditClipboard) {
+        let entries = cb.data.entries();
+        let mut i = 0;
+        'top_loop: for y in 0..cb.size_y as i32 {
+            for z in 0..cb.size_z as i32 {
+                for x in 0..cb.size_x as i32 {
+                    if i >= entries {
+                        break 'top_loop;
+                    }
+                    let entry = cb.data.get_entry(i);
+                    i += 1;
+                    if entry != 0 {
+                        self.set_block_raw(BlockPos::new(x, y, z), entry);
+                    }
+                }
+            }
+        }
+
+        for (pos, block_entity) in &cb.block_entities {
+            self.set_block_entity(*pos, block_entity.clone());
+        }
+    }
+
+    /// Runs the scheduled ticks that are due, like a plot does every game tick
+    pub fn tick(&mut self) {
+        self.to_be_ticked.sort_by_key(|e| (e.ticks_left, e.tick_priority));
+        for pending in &mut self.to_be_ticked {
+            pending.ticks_left = pending.ticks_left.saturating_sub(1);
+        }
+        while self.to_be_ticked.first().map_or(1, |e| e.ticks_left) == 0 {
+            let entry = self.to_be_ticked.remove(0);
+            mchprs_redstone::tick(self.get_block(entry.pos), self, entry.pos);
+        }
+    }
+
+    fn chunk_index(&self, pos: BlockPos) -> Option<usize> {
+        let (chunk_x, chunk_z) = (pos.x >> 4, pos.z >> 4);
+        let inside = (0..self.size).contains(&chunk_x) && (0..self.size).contains(&chunk_z);
+        inside.then(|| (chunk_x * self.size + chunk_z) as usize)
+    }
+}
+
+impl World for SchematicWorld {
+    fn get_block_raw(&self, pos: BlockPos) -> u32 {
+        match self.chunk_index(pos) {
+            Some(idx) if (0..self.size * 16).contains(&pos.y) => {
+                self.chunks[idx].get_block((pos.x & 0xF) as u32, pos.y as u32, (pos.z & 0xF) as u32)
+            }
+            _ => 0,
+        }
+    }
+
+    fn set_block_raw(&mut self, pos: BlockPos, block: u32) -> bool {
+        match self.chunk_index(pos) {
+            Some(idx) if (0..self.size * 16).contains(&pos.y) => {
+                self.chunks[idx].set_block((pos.x & 0xF) as u32, pos.y as u32, (pos.z & 0xF) as u32, block)
+            }
+            _ => false,
+        }
+    }
+
+    fn delete_block_entity(&mut self, pos: BlockPos) {
+        if let Some(idx) = self.chunk_index(pos) {
+            self.chunks[idx].delete_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF));
+        }
+    }
+
+    fn get_block_entity(&self, pos: BlockPos) -> Option<&BlockEntity> {
+        let idx = self.chunk_index(pos)?;
+        self.chunks[idx].get_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF))
+    }
+
+    fn set_block_entity(&mut self, pos: BlockPos, block_entity: BlockEntity) {
+        if let Some(idx) = self.chunk_index(pos) {
+            self.chunks[idx].set_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF), block_entity);
+        }
+    }
+
+    fn get_chunk(&self, x: i32, z: i32) -> Option<&Chunk> {
+        self.chunks.iter().find(|c| c.x == x && c.z == z)
+    }
+
+    fn get_chunk_mut(&mut self, x: i32, z: i32) -> Option<&mut Chunk> {
+        self.chunks.iter_mut().find(|c| c.x == x && c.z == z)
+    }
+
+    fn schedule_tick(&mut self, pos: BlockPos, delay: u32, priority: TickPriority) {
+        self.to_be_ticked.push(TickEntry {
+            pos,
+            ticks_left: delay,
+            tick_priority: priority,
+        });
+    }
+
+    fn pending_tick_at(&mut self, pos: BlockPos) -> bool {
+        self.to_be_ticked.iter().any(|e| e.pos == pos)
+    }
+}
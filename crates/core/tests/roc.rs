@@ -0,0 +1,31 @@
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+
+fn roc(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_roc")).args(args).output().unwrap()
+}
+
+#[test]
+fn compiles_schematic() {
+    let schem = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/roc/sim_tests/in_out/build.schem");
+    let out = env::temp_dir().join(format!("roc_test_{}", std::process::id()));
+    let output = roc(&[schem.to_str().unwrap(), "--out", out.to_str().unwrap(), "--name", "in_out"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let link: Value = serde_json::from_str(&fs::read_to_string(out.join("link.json")).unwrap()).unwrap();
+    assert_eq!(link["name"], "in_out");
+    assert_eq!(link["input_bits"], 1);
+    assert_eq!(link["output_bits"], 1);
+    let verilog = fs::read_to_string(out.join("redstone.sv")).unwrap();
+    assert!(verilog.contains("module"), "{}", verilog);
+    fs::remove_dir_all(&out).unwrap();
+}
+
+#[test]
+fn rejects_bad_invocations() {
+    assert!(!roc(&[]).status.success());
+    assert!(!roc(&["missing.schem", "--out"]).status.success());
+    assert!(!roc(&["missing.schem", "--out", "unused"]).status.success());
+}
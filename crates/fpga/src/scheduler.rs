@@ -1,4 +1,5 @@
 use mchprs_backend::fpga::compiler::DeviceConfig;
+use mchprs_backend::fpga::interface::ConnectionStatus;
 use crate::scheduler;
 use std::{fs, path::Path};
 
@@ -21,6 +22,7 @@ impl FPGAScheduler {
             fpgas.push(FPGA { 
                 config: cfg,
                 owner: None, 
+                status: ConnectionStatus::Disconnected,
             });
         }
 
@@ -48,20 +50,35 @@ impl FPGAScheduler {
         for fpga in &self.fpgas {
             if fpga.owner == Some(plot) {
                 self.fpgas[i].owner = None;
+                self.fpgas[i].status = ConnectionStatus::Disconnected;
                 break;
             }
             i += 1;
         }
     }
+
+    pub fn set_status(&mut self, plot: (i32, i32), status: ConnectionStatus) {
+        for fpga in &mut self.fpgas {
+            if fpga.owner == Some(plot) {
+                fpga.status = status;
+                break;
+            }
+        }
+    }
 }
 
 pub struct FPGA {
     pub config: DeviceConfig,
     owner: Option<(i32,i32)>,
+    status: ConnectionStatus,
 }
 
 impl FPGA {
     pub fn get_owner(&self) -> Option<(i32,i32)> {
         self.owner
     }
+
+    pub fn get_status(&self) -> ConnectionStatus {
+        self.status
+    }
 }
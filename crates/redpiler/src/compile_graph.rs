@@ -3,6 +3,8 @@ use std::collections::HashSet;
 use mchprs_blocks::blocks::{ComparatorMode, Instrument};
 use mchprs_blocks::BlockPos;
 use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
 
 pub type NodeIdx = NodeIndex;
 
@@ -30,7 +32,70 @@ pub enum NodeType {
         instrument: Instrument,
         note: u32,
     },
+    /// A chain of repeaters collapsed by the `collapse-repeaters` pass, see [`delay_segments`]
+    Delay {
+        stages: Vec<DelayStage>,
+    },
+    /// Torches merged by the `minimise-logic` pass, see [`crate::logic`]. Input `i` is the
+    /// incoming link with `logic_input` `i`, see [`logic_sources`]. The last gate is the output.
+    Logic {
+        gates: Vec<LogicGate>,
+    },
+    /// Pulses for two ticks, two ticks after the node it faces turned on or off. Its default
+    /// input is that node.
+    Observer,
+}
+
+/// A repeater of a chain collapsed into a [`NodeType::Delay`], in signal order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DelayStage {
+    pub delay: u8,
+    pub facing_diode: bool,
+    /// Position and protocol id of the repeater, so backends can keep its block up to date
+    pub block: Option<(BlockPos, u32)>,
+}
+
+/// Splits the stages of a delay into parts that each behave exactly like a repeater shaping
+/// the pulses followed by a shift register, as `(pulse, delay)` with the total delay of the part.
+///
+/// 1 tick repeaters don't shape the signal, and a repeater doesn't change pulses at least as long
+/// as its delay. So a part is shaped by its first repeater longer than 1 tick, and a repeater
+/// longer than that starts the next part.
+pub fn delay_segments(stages: &[DelayStage]) -> Vec<(u8, u32)> {
+    let mut segments: Vec<(u8, u32)> = Vec::new();
+    let mut shaper = None;
+    for stage in stages {
+        match segments.last_mut() {
+            Some((pulse, delay)) if shaper.map_or(true, |shaper| stage.delay <= shaper) => {
+                *pulse = (*pulse).max(stage.delay);
+                *delay += stage.delay as u32;
+            }
+            _ => {
+                segments.push((stage.delay, stage.delay as u32));
+                shaper = None;
+            }
+        }
+        if shaper.is_none() && stage.delay > 1 {
+            shaper = Some(stage.delay);
+        }
+    }
+    segments
 }
+
+/// A torch of a [`NodeType::Logic`], it is on while none of its sources are
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogicGate {
+    pub sources: Vec<LogicSource>,
+    pub powered: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicSource {
+    Input(u8),
+    /// A gate earlier in the list
+    Gate(u8),
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct NodeState {
     pub powered: bool,
@@ -71,8 +136,29 @@ impl NodeState {
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct Annotations {}
+/// Set by `[...]` lines on signs, see `NodeAnnotation` in `identify_nodes`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Annotations {
+    /// `[name cpu.pc0]`
+    pub name: Option<String>,
+    /// `[bus data 3]` makes the node bit 3 of the bus `data`
+    pub bus: Option<(String, u32)>,
+    /// `[probe]` keeps the node observable, e.g. as a kept wire on the FPGA
+    pub probe: bool,
+    /// `[keep]` stops optimizations from removing or folding the node
+    pub keep: bool,
+}
+
+impl Annotations {
+    /// The name, or else the bus bit, as written on the sign
+    pub fn label(&self) -> Option<String> {
+        match (&self.name, &self.bus) {
+            (Some(name), _) => Some(name.clone()),
+            (None, Some((bus, bit))) => Some(format!("{}[{}]", bus, bit)),
+            (None, None) => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct CompileNode {
@@ -87,31 +173,42 @@ pub struct CompileNode {
 
 impl CompileNode {
     pub fn is_removable(&self) -> bool {
-        !self.is_input && !self.is_output
+        !self.is_input && !self.is_output && !self.annotations.keep && !self.annotations.probe
+    }
+
+    /// Components that only are outputs because of an `[output]` sign
+    pub fn is_forced_output(&self) -> bool {
+        self.is_output && matches!(
+            self.ty,
+            NodeType::Repeater { .. } | NodeType::Torch | NodeType::Comparator { .. } | NodeType::Logic { .. } | NodeType::Observer
+        )
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LinkType {
     Default,
     Side,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CompileLink {
     pub ty: LinkType,
     pub ss: u8,
+    /// The input of the [`NodeType::Logic`] this link goes into
+    pub logic_input: Option<u8>,
 }
 
 impl CompileLink {
     pub fn new(ty: LinkType, ss: u8) -> CompileLink {
-        CompileLink { ty, ss }
+        CompileLink { ty, ss, logic_input: None }
     }
 
     pub fn default(ss: u8) -> CompileLink {
         CompileLink {
             ty: LinkType::Default,
             ss,
+            logic_input: None,
         }
     }
 
@@ -119,8 +216,62 @@ impl CompileLink {
         CompileLink {
             ty: LinkType::Side,
             ss,
+            logic_input: None,
+        }
+    }
+
+    /// A link into input `input` of a [`NodeType::Logic`]
+    pub fn logic(input: u8) -> CompileLink {
+        CompileLink {
+            ty: LinkType::Default,
+            ss: 0,
+            logic_input: Some(input),
         }
     }
 }
 
-pub type CompileGraph = StableGraph<CompileNode, CompileLink>;
\ No newline at end of file
+pub type CompileGraph = StableGraph<CompileNode, CompileLink>;
+
+/// Whether the node an [`NodeType::Observer`] faces is on, and whether the observer sees it
+/// change in the tick its pulse ends.
+///
+/// Both tick then and the observer only sees the change if it ticks first. Its tick was
+/// scheduled two ticks earlier with normal priority, so it goes before torches and comparators
+/// scheduled one tick earlier, but after repeaters, which have a higher priority. Inputs change
+/// between ticks, after it. Other observers tick in the order of the changes they saw.
+pub fn observed_node(graph: &CompileGraph, observer: NodeIdx) -> (bool, bool) {
+    let mut on = false;
+    let mut late = false;
+    for edge in graph.edges_directed(observer, Direction::Incoming) {
+        on |= graph[edge.source()].state.output_strength > edge.weight().ss;
+        late |= changes_late(graph, edge.source());
+    }
+    (on, late)
+}
+
+/// The sources of the [`NodeType::Logic`] `node` as `(input, source)`
+pub fn logic_sources(graph: &CompileGraph, node: NodeIdx) -> impl Iterator<Item = (usize, NodeIdx)> + '_ {
+    graph
+        .edges_directed(node, Direction::Incoming)
+        .map(|edge| (edge.weight().logic_input.expect("links into logic nodes have an input") as usize, edge.source()))
+}
+
+/// Follows a chain of observers to the node that starts it, a cycle of them is never late
+fn changes_late(graph: &CompileGraph, mut node: NodeIdx) -> bool {
+    for _ in 0..graph.node_count() {
+        match graph[node].ty {
+            NodeType::Torch
+            | NodeType::Logic { .. }
+            | NodeType::Comparator { facing_diode: false, .. }
+            | NodeType::Lever
+            | NodeType::Button
+            | NodeType::PressurePlate => return true,
+            NodeType::Observer => match graph.neighbors_directed(node, Direction::Incoming).next() {
+                Some(source) => node = source,
+                None => return false,
+            },
+            _ => return false,
+        }
+    }
+    false
+}
@@ -0,0 +1,274 @@
+//! Conversion between a [`CompileGraph`] and the standalone [`redpiler_graph`](crate::redpiler_graph)
+//! format, so a graph can be compiled by a backend without the world it came from.
+
+use crate::logic;
+use crate::compile_graph::{
+    Annotations, CompileGraph, CompileLink, CompileNode, DelayStage, LinkType as CLinkType, LogicGate as CLogicGate,
+    LogicSource as CLogicSource, NodeIdx, NodeState as CNodeState, NodeType as CNodeType,
+};
+use crate::redpiler_graph::{
+    deserialize, serialize, Annotations as GAnnotations, BlockPos, ComparatorMode, Link, LinkType, LogicGate, LogicSource, Node,
+    NodeState, NodeType,
+};
+use itertools::Itertools;
+use mchprs_blocks::blocks::{ComparatorMode as CComparatorMode, Instrument};
+use mchprs_blocks::BlockPos as CBlockPos;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use rustc_hash::FxHashMap;
+use std::fs;
+use std::path::Path;
+
+fn export_pos(pos: CBlockPos) -> BlockPos {
+    BlockPos { x: pos.x, y: pos.y, z: pos.z }
+}
+
+fn import_pos(pos: BlockPos) -> CBlockPos {
+    CBlockPos::new(pos.x, pos.y, pos.z)
+}
+
+fn convert_node(
+    graph: &CompileGraph,
+    node_idx: NodeIdx,
+    nodes_map: &FxHashMap<NodeIdx, usize>,
+) -> Node {
+    let node = &graph[node_idx];
+
+    let mut inputs = Vec::new();
+    for edge in graph.edges_directed(node_idx, Direction::Incoming) {
+        let idx = nodes_map[&edge.source()];
+        let weight = edge.weight();
+        inputs.push(Link {
+            ty: match weight.ty {
+                CLinkType::Default => LinkType::Default,
+                CLinkType::Side => LinkType::Side,
+            },
+            weight: weight.ss,
+            to: idx,
+            logic_input: weight.logic_input,
+        });
+    }
+
+    let updates = graph
+        .neighbors_directed(node_idx, Direction::Outgoing)
+        .map(|idx| nodes_map[&idx])
+        .collect();
+
+    let facing_diode = match node.ty {
+        CNodeType::Repeater { facing_diode, .. } | CNodeType::Comparator { facing_diode, .. } => {
+            facing_diode
+        }
+        CNodeType::Delay { ref stages } => stages.last().unwrap().facing_diode,
+        _ => false,
+    };
+
+    let (comparator_far_input, comparator_states) = match node.ty {
+        CNodeType::Comparator { far_input, states, .. } => (far_input, states),
+        _ => (None, None),
+    };
+
+    Node {
+        ty: match node.ty {
+            CNodeType::Repeater { delay, .. } => NodeType::Repeater(delay),
+            CNodeType::Torch => NodeType::Torch,
+            CNodeType::Comparator { mode, .. } => NodeType::Comparator(match mode {
+                CComparatorMode::Compare => ComparatorMode::Compare,
+                CComparatorMode::Subtract => ComparatorMode::Subtract,
+            }),
+            CNodeType::Lamp => NodeType::Lamp,
+            CNodeType::Button => NodeType::Button,
+            CNodeType::Lever => NodeType::Lever,
+            CNodeType::PressurePlate => NodeType::PressurePlate,
+            CNodeType::Trapdoor => NodeType::Trapdoor,
+            CNodeType::Wire => NodeType::Wire,
+            CNodeType::Constant => NodeType::Constant,
+            CNodeType::NoteBlock { instrument, note } => NodeType::NoteBlock {
+                instrument: instrument.get_id(),
+                note,
+            },
+            CNodeType::Delay { ref stages } => NodeType::Delay {
+                stages: stages
+                    .iter()
+                    .map(|stage| (stage.delay, stage.facing_diode, stage.block.map(|(pos, id)| (export_pos(pos), id))))
+                    .collect(),
+            },
+            CNodeType::Observer => NodeType::Observer,
+            CNodeType::Logic { ref gates } => NodeType::Logic {
+                gates: gates
+                    .iter()
+                    .map(|gate| LogicGate {
+                        sources: gate
+                            .sources
+                            .iter()
+                            .map(|source| match *source {
+                                CLogicSource::Input(i) => LogicSource::Input(i),
+                                CLogicSource::Gate(gate) => LogicSource::Gate(gate),
+                            })
+                            .collect(),
+                        powered: gate.powered,
+                    })
+                    .collect(),
+            },
+        },
+        block: node.block.map(|(pos, id)| (export_pos(pos), id)),
+        state: NodeState {
+            output_strength: node.state.output_strength,
+            powered: node.state.powered,
+            repeater_locked: node.state.repeater_locked,
+        },
+        comparator_far_input,
+        comparator_states,
+        facing_diode,
+        is_input: node.is_input,
+        is_output: node.is_output,
+        annotations: GAnnotations {
+            name: node.annotations.name.clone(),
+            bus: node.annotations.bus.clone(),
+            probe: node.annotations.probe,
+            keep: node.annotations.keep,
+        },
+        inputs,
+        updates,
+    }
+}
+
+/// The nodes of `graph`, numbered in index order
+pub fn to_nodes(graph: &CompileGraph) -> Vec<Node> {
+    let mut nodes_map = FxHashMap::with_capacity_and_hasher(graph.node_count(), Default::default());
+    for node in graph.node_indices() {
+        nodes_map.insert(node, nodes_map.len());
+    }
+
+    graph
+        .node_indices()
+        .map(|idx| convert_node(graph, idx, &nodes_map))
+        .collect_vec()
+}
+
+/// Rebuilds a graph from its nodes
+pub fn from_nodes(nodes: &[Node]) -> CompileGraph {
+    let mut graph = CompileGraph::default();
+    let indices = nodes
+        .iter()
+        .map(|node| {
+            let ty = match node.ty {
+                NodeType::Repeater(delay) => CNodeType::Repeater {
+                    delay,
+                    facing_diode: node.facing_diode,
+                },
+                NodeType::Torch => CNodeType::Torch,
+                NodeType::Comparator(mode) => CNodeType::Comparator {
+                    mode: match mode {
+                        ComparatorMode::Compare => CComparatorMode::Compare,
+                        ComparatorMode::Subtract => CComparatorMode::Subtract,
+                    },
+                    far_input: node.comparator_far_input,
+                    facing_diode: node.facing_diode,
+                    states: node.comparator_states,
+                },
+                NodeType::Lamp => CNodeType::Lamp,
+                NodeType::Button => CNodeType::Button,
+                NodeType::Lever => CNodeType::Lever,
+                NodeType::PressurePlate => CNodeType::PressurePlate,
+                NodeType::Trapdoor => CNodeType::Trapdoor,
+                NodeType::Wire => CNodeType::Wire,
+                NodeType::Constant => CNodeType::Constant,
+                NodeType::NoteBlock { instrument, note } => CNodeType::NoteBlock {
+                    instrument: Instrument::from_id(instrument),
+                    note,
+                },
+                NodeType::Delay { ref stages } => CNodeType::Delay {
+                    stages: stages
+                        .iter()
+                        .map(|&(delay, facing_diode, block)| DelayStage {
+                            delay,
+                            facing_diode,
+                            block: block.map(|(pos, id)| (import_pos(pos), id)),
+                        })
+                        .collect(),
+                },
+                NodeType::Observer => CNodeType::Observer,
+                NodeType::Logic { ref gates } => CNodeType::Logic {
+                    gates: gates
+                        .iter()
+                        .map(|gate| CLogicGate {
+                            sources: gate
+                                .sources
+                                .iter()
+                                .map(|source| match *source {
+                                    LogicSource::Input(i) => CLogicSource::Input(i),
+                                    LogicSource::Gate(gate) => CLogicSource::Gate(gate),
+                                })
+                                .collect(),
+                            powered: gate.powered,
+                        })
+                        .collect(),
+                },
+            };
+            graph.add_node(CompileNode {
+                ty,
+                block: node.block.map(|(pos, id)| (import_pos(pos), id)),
+                state: CNodeState {
+                    powered: node.state.powered,
+                    repeater_locked: node.state.repeater_locked,
+                    output_strength: node.state.output_strength,
+                },
+                is_input: node.is_input,
+                is_output: node.is_output,
+                annotations: Annotations {
+                    name: node.annotations.name.clone(),
+                    bus: node.annotations.bus.clone(),
+                    probe: node.annotations.probe,
+                    keep: node.annotations.keep,
+                },
+            })
+        })
+        .collect_vec();
+
+    for (node, &idx) in nodes.iter().zip(&indices) {
+        for link in &node.inputs {
+            let ty = match link.ty {
+                LinkType::Default => CLinkType::Default,
+                LinkType::Side => CLinkType::Side,
+            };
+            let weight = CompileLink { ty, ss: link.weight, logic_input: link.logic_input };
+            graph.add_edge(indices[link.to], idx, weight);
+        }
+    }
+    graph
+}
+
+pub fn save(graph: &CompileGraph, path: &Path) -> Result<(), String> {
+    let bytes = serialize(&to_nodes(graph)).map_err(|e| e.to_string())?;
+    fs::write(path, bytes).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+pub fn load(path: &Path) -> Result<CompileGraph, String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let nodes = deserialize(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+    check_nodes(&nodes).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(from_nodes(&nodes))
+}
+
+/// Checks that [`from_nodes`] can rebuild a graph from `nodes`, which may come
+/// from a file written by something else
+pub fn check_nodes(nodes: &[Node]) -> Result<(), String> {
+    for (idx, node) in nodes.iter().enumerate() {
+        if let Some(link) = node.inputs.iter().find(|l| l.to >= nodes.len()) {
+            return Err(format!("node {} links to missing node {}", idx, link.to));
+        }
+        if let Some(update) = node.updates.iter().find(|&&u| u >= nodes.len()) {
+            return Err(format!("node {} updates missing node {}", idx, update));
+        }
+        if let NodeType::NoteBlock { instrument, .. } = node.ty {
+            if instrument > Instrument::Piglin.get_id() {
+                return Err(format!("node {} has unknown instrument {}", idx, instrument));
+            }
+        }
+        let is_logic_input = |input: Option<u8>| input.is_some_and(|i| (i as usize) < logic::MAX_INPUTS);
+        if matches!(node.ty, NodeType::Logic { .. }) && !node.inputs.iter().all(|l| is_logic_input(l.logic_input)) {
+            return Err(format!("node {} has a link that is not one of its logic inputs", idx));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,390 @@
+//! A self-contained HTML page to inspect a [`CompileGraph`] in the browser.
+//!
+//! Nodes are drawn at their block position on the x/z plane, one y layer at a
+//! time, and coloured by type. Clicking a node shows its state and links, and
+//! nodes can be found by position (`x,y,z`) or index (`#12`). Nodes without a
+//! block are placed in a row below the build.
+
+use crate::compile_graph::{delay_segments, CompileGraph, LinkType, NodeType};
+use crate::logic;
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>redpiler graph</title>
+<style>
+  body { margin: 0; font: 13px sans-serif; display: flex; height: 100vh; background: #1e1e1e; color: #ddd; }
+  #side { width: 320px; padding: 8px; overflow-y: auto; background: #262626; box-sizing: border-box; }
+  #main { flex: 1; position: relative; }
+  canvas { position: absolute; width: 100%; height: 100%; cursor: grab; }
+  input, select { width: 100%; box-sizing: border-box; margin: 2px 0 8px; background: #333; color: #ddd; border: 1px solid #555; padding: 3px; }
+  h3 { margin: 8px 0 4px; }
+  .legend span { display: inline-block; margin: 2px 4px 2px 0; padding: 1px 6px; border-radius: 3px; cursor: pointer; color: #111; }
+  .legend span.off { opacity: 0.3; }
+  table { border-collapse: collapse; width: 100%; }
+  td { padding: 1px 4px; vertical-align: top; }
+  td:first-child { color: #999; }
+  a { color: #8cf; cursor: pointer; }
+  #error { color: #f88; }
+</style>
+</head>
+<body>
+<div id="side">
+  <b id="title"></b><div id="stats"></div>
+  <h3>Find</h3>
+  <input id="search" placeholder="x,y,z, #index or name">
+  <div id="error"></div>
+  <h3>Layer</h3>
+  <select id="layer"></select>
+  <h3>Types</h3>
+  <div class="legend" id="legend"></div>
+  <div id="details"></div>
+</div>
+<div id="main"><canvas id="canvas"></canvas></div>
+<script>
+const graph = /*GRAPH*/;
+const colors = {
+  Repeater: "#e0a030", Torch: "#e05040", Comparator: "#d070d0", Lamp: "#f0e070", Button: "#90c0f0",
+  Lever: "#60a0ff", PressurePlate: "#80d0d0", Trapdoor: "#c0a080", Wire: "#c03030", Constant: "#909090",
+  NoteBlock: "#70d070", Delay: "#e07020", Logic: "#b04060",
+};
+const canvas = document.getElementById("canvas");
+const ctx = canvas.getContext("2d");
+const byId = new Map(graph.nodes.map(n => [n.id, n]));
+const incoming = new Map(), outgoing = new Map();
+for (const l of graph.links) {
+  if (!incoming.has(l.to)) incoming.set(l.to, []);
+  if (!outgoing.has(l.from)) outgoing.set(l.from, []);
+  incoming.get(l.to).push(l);
+  outgoing.get(l.from).push(l);
+}
+
+// Nodes without a block go in a row below the build
+const placed = graph.nodes.filter(n => n.pos);
+const bound = (i, f) => placed.length ? placed.reduce((m, n) => f(m, n.pos[i]), placed[0].pos[i]) : 0;
+const minX = bound(0, Math.min), minZ = bound(2, Math.min), maxZ = bound(2, Math.max);
+let spare = 0;
+for (const n of graph.nodes) {
+  n.at = n.pos ? [n.pos[0], n.pos[2]] : [minX + spare++, maxZ + 3];
+}
+
+const hidden = new Set();
+let layer = "all", selected = null;
+let scale = 24, offX = 0, offY = 0;
+
+document.getElementById("title").textContent = graph.name;
+document.getElementById("stats").textContent = `${graph.nodes.length} nodes, ${graph.links.length} links`;
+
+const layers = [...new Set(placed.map(n => n.pos[1]))].sort((a, b) => a - b);
+const layerSelect = document.getElementById("layer");
+for (const y of ["all", ...layers]) {
+  const o = document.createElement("option");
+  o.value = y;
+  o.textContent = y === "all" ? "all layers" : "y = " + y;
+  layerSelect.appendChild(o);
+}
+layerSelect.onchange = () => { layer = layerSelect.value; draw(); };
+
+const legend = document.getElementById("legend");
+for (const ty of [...new Set(graph.nodes.map(n => n.ty))].sort()) {
+  const s = document.createElement("span");
+  s.textContent = ty + " (" + graph.nodes.filter(n => n.ty === ty).length + ")";
+  s.style.background = colors[ty] || "#fff";
+  s.onclick = () => { hidden.has(ty) ? hidden.delete(ty) : hidden.add(ty); s.classList.toggle("off"); draw(); };
+  legend.appendChild(s);
+}
+
+function inLayer(n) {
+  return layer === "all" || !n.pos || String(n.pos[1]) === layer;
+}
+function visible(n) {
+  return !hidden.has(n.ty);
+}
+function screen(n) {
+  return [n.at[0] * scale + offX, n.at[1] * scale + offY];
+}
+
+function resize() {
+  canvas.width = canvas.clientWidth * devicePixelRatio;
+  canvas.height = canvas.clientHeight * devicePixelRatio;
+  ctx.setTransform(devicePixelRatio, 0, 0, devicePixelRatio, 0, 0);
+  draw();
+}
+
+function draw() {
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  const size = scale * 0.7;
+  for (const l of graph.links) {
+    const a = byId.get(l.from), b = byId.get(l.to);
+    if (!visible(a) || !visible(b) || (!inLayer(a) && !inLayer(b))) continue;
+    const mine = selected && (l.from === selected.id || l.to === selected.id);
+    const [x1, y1] = screen(a), [x2, y2] = screen(b);
+    ctx.globalAlpha = inLayer(a) && inLayer(b) ? (selected && !mine ? 0.25 : 0.8) : 0.15;
+    ctx.strokeStyle = mine ? "#fff" : "#888";
+    ctx.lineWidth = mine ? 2 : 1;
+    ctx.setLineDash(l.ty === "side" ? [4, 3] : []);
+    ctx.beginPath();
+    ctx.moveTo(x1, y1);
+    ctx.lineTo(x2, y2);
+    ctx.stroke();
+    // Arrow head at the edge of the target
+    const angle = Math.atan2(y2 - y1, x2 - x1), len = Math.hypot(x2 - x1, y2 - y1);
+    if (len > size) {
+      const tx = x2 - Math.cos(angle) * size / 2, ty = y2 - Math.sin(angle) * size / 2;
+      ctx.setLineDash([]);
+      ctx.beginPath();
+      ctx.moveTo(tx, ty);
+      ctx.lineTo(tx - 7 * Math.cos(angle - 0.4), ty - 7 * Math.sin(angle - 0.4));
+      ctx.lineTo(tx - 7 * Math.cos(angle + 0.4), ty - 7 * Math.sin(angle + 0.4));
+      ctx.closePath();
+      ctx.fillStyle = ctx.strokeStyle;
+      ctx.fill();
+    }
+    if (l.ss > 0 && scale >= 20) {
+      ctx.fillStyle = "#ddd";
+      ctx.fillText(l.ss, (x1 + x2) / 2 + 3, (y1 + y2) / 2 - 3);
+    }
+  }
+  ctx.setLineDash([]);
+  ctx.globalAlpha = 1;
+  const near = selected ? neighbours(selected) : null;
+  for (const n of graph.nodes) {
+    if (!visible(n) || !inLayer(n)) continue;
+    const [x, y] = screen(n);
+    ctx.globalAlpha = selected && selected !== n && !near.has(n.id) ? 0.4 : 1;
+    ctx.fillStyle = colors[n.ty] || "#fff";
+    ctx.fillRect(x - size / 2, y - size / 2, size, size);
+    if (n.state.output_strength > 0 || n.state.powered) {
+      ctx.strokeStyle = "#fff";
+      ctx.lineWidth = 2;
+      ctx.strokeRect(x - size / 2, y - size / 2, size, size);
+    }
+    if (n === selected) {
+      ctx.strokeStyle = "#0ff";
+      ctx.lineWidth = 3;
+      ctx.strokeRect(x - size / 2 - 3, y - size / 2 - 3, size + 6, size + 6);
+    }
+    if (scale >= 28) {
+      ctx.fillStyle = "#111";
+      ctx.fillText(n.state.output_strength, x - 3, y + 4);
+    }
+  }
+  ctx.globalAlpha = 1;
+}
+
+function neighbours(n) {
+  const ids = new Set();
+  for (const l of incoming.get(n.id) || []) ids.add(l.from);
+  for (const l of outgoing.get(n.id) || []) ids.add(l.to);
+  return ids;
+}
+
+function describe(n) {
+  return n.ty + (n.name ? " " + n.name : "") + (n.pos ? " at " + n.pos.join(",") : " #" + n.id);
+}
+
+function linkRow(l, other) {
+  const tr = document.createElement("tr");
+  const a = document.createElement("a");
+  a.textContent = describe(byId.get(other));
+  a.onclick = () => select(byId.get(other), true);
+  tr.insertCell().textContent = (l.ty === "side" ? "side " : "") + "-" + l.ss;
+  tr.insertCell().appendChild(a);
+  return tr;
+}
+
+function table(title, rows) {
+  const h = document.createElement("h3");
+  h.textContent = title;
+  const t = document.createElement("table");
+  for (const r of rows) t.appendChild(r);
+  return [h, t];
+}
+
+function row(key, value) {
+  const tr = document.createElement("tr");
+  tr.insertCell().textContent = key;
+  tr.insertCell().textContent = value;
+  return tr;
+}
+
+function select(n, center) {
+  selected = n;
+  const details = document.getElementById("details");
+  details.replaceChildren();
+  if (n) {
+    const info = [row("index", n.id), row("type", n.ty), row("position", n.pos ? n.pos.join(",") : "none")];
+    for (const [k, v] of Object.entries(n.props)) info.push(row(k, v));
+    if (n.input) info.push(row("input", "yes"));
+    if (n.output) info.push(row("output", "yes"));
+    if (n.name) info.push(row("name", n.name));
+    if (n.bus) info.push(row("bus", n.bus[0] + "[" + n.bus[1] + "]"));
+    if (n.probe) info.push(row("probe", "yes"));
+    if (n.keep) info.push(row("keep", "yes"));
+    details.append(...table("Node", info));
+    details.append(...table("State", Object.entries(n.state).map(([k, v]) => row(k, v))));
+    details.append(...table("Inputs", (incoming.get(n.id) || []).map(l => linkRow(l, l.from))));
+    details.append(...table("Outputs", (outgoing.get(n.id) || []).map(l => linkRow(l, l.to))));
+    if (center) {
+      if (n.pos && layer !== "all") {
+        layer = String(n.pos[1]);
+        layerSelect.value = layer;
+      }
+      offX = canvas.clientWidth / 2 - n.at[0] * scale;
+      offY = canvas.clientHeight / 2 - n.at[1] * scale;
+    }
+  }
+  draw();
+}
+
+document.getElementById("search").onkeydown = e => {
+  if (e.key !== "Enter") return;
+  const q = e.target.value.trim();
+  let n;
+  if (q.startsWith("#")) {
+    n = byId.get(Number(q.slice(1)));
+  } else if (!/^[-\d ,]+$/.test(q)) {
+    n = graph.nodes.find(n => n.name === q || (n.bus && n.bus[0] + "[" + n.bus[1] + "]" === q));
+  } else {
+    const p = q.split(/[ ,]+/).map(Number);
+    n = graph.nodes.find(n => n.pos && n.pos.every((v, i) => v === p[i]));
+  }
+  document.getElementById("error").textContent = n ? "" : "No node " + q;
+  if (n) select(n, true);
+};
+
+let drag = null;
+canvas.onmousedown = e => { drag = { x: e.offsetX, y: e.offsetY, moved: false }; };
+canvas.onmousemove = e => {
+  if (!drag) return;
+  offX += e.offsetX - drag.x;
+  offY += e.offsetY - drag.y;
+  drag.moved ||= Math.abs(e.offsetX - drag.x) + Math.abs(e.offsetY - drag.y) > 0;
+  drag.x = e.offsetX;
+  drag.y = e.offsetY;
+  draw();
+};
+canvas.onmouseup = e => {
+  if (drag && !drag.moved) {
+    const hit = graph.nodes.filter(n => visible(n) && inLayer(n)).find(n => {
+      const [x, y] = screen(n);
+      return Math.abs(e.offsetX - x) <= scale * 0.35 && Math.abs(e.offsetY - y) <= scale * 0.35;
+    });
+    select(hit || null, false);
+  }
+  drag = null;
+};
+canvas.onwheel = e => {
+  e.preventDefault();
+  const factor = e.deltaY < 0 ? 1.2 : 1 / 1.2;
+  offX = e.offsetX - (e.offsetX - offX) * factor;
+  offY = e.offsetY - (e.offsetY - offY) * factor;
+  scale *= factor;
+  draw();
+};
+
+window.onresize = resize;
+offX = 40 - minX * scale;
+offY = 40 - minZ * scale;
+resize();
+</script>
+</body>
+</html>
+"##;
+
+fn node_props(ty: &NodeType) -> Value {
+    match ty {
+        NodeType::Repeater { delay, facing_diode } => json!({ "delay": delay, "facing_diode": facing_diode }),
+        NodeType::Comparator { mode, far_input, facing_diode, states } => json!({
+            "mode": format!("{:?}", mode),
+            "far_input": far_input,
+            "facing_diode": facing_diode,
+            "states": states.map(|s| format!("{:016b}", s)),
+        }),
+        NodeType::NoteBlock { instrument, note } => json!({ "instrument": format!("{:?}", instrument), "note": note }),
+        NodeType::Delay { stages } => json!({
+            "delays": stages.iter().map(|stage| stage.delay).collect::<Vec<_>>(),
+            "segments": delay_segments(stages),
+        }),
+        NodeType::Logic { gates } => json!({
+            "gates": gates.len(),
+            "depth": logic::depth(gates),
+            "truth_table": format!("{:08x}", logic::truth_table(gates)),
+        }),
+        _ => json!({}),
+    }
+}
+
+fn type_name(ty: &NodeType) -> &'static str {
+    match ty {
+        NodeType::Repeater { .. } => "Repeater",
+        NodeType::Torch => "Torch",
+        NodeType::Comparator { .. } => "Comparator",
+        NodeType::Lamp => "Lamp",
+        NodeType::Button => "Button",
+        NodeType::Lever => "Lever",
+        NodeType::PressurePlate => "PressurePlate",
+        NodeType::Trapdoor => "Trapdoor",
+        NodeType::Wire => "Wire",
+        NodeType::Constant => "Constant",
+        NodeType::NoteBlock { .. } => "NoteBlock",
+        NodeType::Delay { .. } => "Delay",
+        NodeType::Logic { .. } => "Logic",
+        NodeType::Observer => "Observer",
+    }
+}
+
+/// The nodes and links of `graph` as read by the page
+pub fn to_json(graph: &CompileGraph, name: &str) -> Value {
+    let nodes: Vec<Value> = graph
+        .node_indices()
+        .map(|idx| {
+            let node = &graph[idx];
+            json!({
+                "id": idx.index(),
+                "ty": type_name(&node.ty),
+                "pos": node.block.map(|(pos, _)| [pos.x, pos.y, pos.z]),
+                "props": node_props(&node.ty),
+                "state": {
+                    "powered": node.state.powered,
+                    "repeater_locked": node.state.repeater_locked,
+                    "output_strength": node.state.output_strength,
+                },
+                "input": node.is_input,
+                "output": node.is_output,
+                "name": node.annotations.name,
+                "bus": node.annotations.bus,
+                "probe": node.annotations.probe,
+                "keep": node.annotations.keep,
+            })
+        })
+        .collect();
+    let links: Vec<Value> = graph
+        .edge_references()
+        .map(|edge| {
+            json!({
+                "from": edge.source().index(),
+                "to": edge.target().index(),
+                "ty": if edge.weight().ty == LinkType::Side { "side" } else { "default" },
+                "ss": edge.weight().ss,
+            })
+        })
+        .collect();
+    json!({ "name": name, "nodes": nodes, "links": links })
+}
+
+pub fn render(graph: &CompileGraph, name: &str) -> String {
+    // `</script>` in a name must not end the script early
+    let data = to_json(graph, name).to_string().replace("</", "<\\/");
+    TEMPLATE.replace("/*GRAPH*/", &data)
+}
+
+pub fn save(graph: &CompileGraph, name: &str, path: &Path) -> io::Result<()> {
+    fs::write(path, render(graph, name))
+}
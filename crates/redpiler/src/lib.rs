@@ -1,6 +1,11 @@
 pub mod compile_graph;
+pub mod graph_file;
+pub mod graph_html;
+pub mod logic;
 pub mod redpiler_graph;
 pub mod passes;
+pub mod report;
+pub mod timing;
 
 use mchprs_blocks::blocks::Block;
 use mchprs_blocks::BlockPos;
@@ -21,6 +26,7 @@ pub fn block_powered_mut(block: &mut Block) -> Option<&mut bool> {
         Block::RedstoneLamp { lit } => lit,
         Block::IronTrapdoor { powered, .. } => powered,
         Block::NoteBlock { powered, .. } => powered,
+        Block::Observer { powered, .. } => powered,
         _ => return None,
     })
 }
@@ -47,6 +53,43 @@ pub struct CompilerOptions {
     pub backend_variant: BackendVariant,
     /// Is this backend used for simulation testing
     pub sim_test: bool,
+    /// Write a Yosys JSON and BLIF netlist next to the verilog (FPGA backends)
+    pub export_netlist: bool,
+    /// Write an HTML page to browse the compiled graph. See [`graph_html`].
+    pub export_html: bool,
+    /// Which passes run and in what order, from `--pipeline`
+    pub pipeline: PassPipeline,
+    /// Passes turned on (`+id`) or off (`-id`) by `--pass`, on top of the pipeline
+    pub passes: Vec<(String, bool)>,
+    /// Check the graph invariants after every pass, which debug builds always do
+    pub validate: bool,
+}
+
+/// Presets for the passes that run, see [`passes::required_passes`] for the
+/// passes that always run
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub enum PassPipeline {
+    /// Every pass decides based on the other options
+    #[default]
+    Default,
+    /// Only the passes required by the backend
+    Minimal,
+    /// Every pass that would run with `--optimize`
+    Full,
+    /// These passes, in this order
+    Custom(Vec<String>),
+}
+
+impl PassPipeline {
+    /// `default`, `minimal`, `full` or a comma separated list of pass ids
+    pub fn parse(s: &str) -> PassPipeline {
+        match s {
+            "default" => PassPipeline::Default,
+            "minimal" => PassPipeline::Minimal,
+            "full" => PassPipeline::Full,
+            ids => PassPipeline::Custom(ids.split(',').filter(|id| !id.is_empty()).map(str::to_string).collect()),
+        }
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
@@ -55,6 +98,15 @@ pub enum BackendVariant {
     #[default]
     Direct,
     FPGA,
+    /// FPGA for everything the assembler supports, `Direct` for the rest
+    Hybrid,
+}
+
+impl BackendVariant {
+    /// Returns true if (part of) the graph will be placed on an FPGA
+    pub fn uses_fpga(self) -> bool {
+        matches!(self, BackendVariant::FPGA | BackendVariant::Hybrid)
+    }
 }
 
 impl CompilerOptions {
@@ -72,7 +124,22 @@ impl CompilerOptions {
                     "--wire-dot-out" => co.wire_dot_out = true,
                     "--selection" => co.selection = true,
                     "--fpga" => co.backend_variant = BackendVariant::FPGA,
+                    "--hybrid" => co.backend_variant = BackendVariant::Hybrid,
                     "--compile" => co.compile_verilog = true,
+                    "--export-netlist" => co.export_netlist = true,
+                    "--export-html" => co.export_html = true,
+                    "--validate" => co.validate = true,
+                    _ if option.starts_with("--pass=") => {
+                        for pass in option["--pass=".len()..].split(',').filter(|p| !p.is_empty()) {
+                            match pass.strip_prefix('-') {
+                                Some(id) => co.passes.push((id.to_string(), false)),
+                                None => co.passes.push((pass.trim_start_matches('+').to_string(), true)),
+                            }
+                        }
+                    }
+                    _ if option.starts_with("--pipeline=") => {
+                        co.pipeline = PassPipeline::parse(&option["--pipeline=".len()..]);
+                    }
                     // FIXME: use actual error handling
                     _ => warn!("Unrecognized option: {}", option),
                 }
@@ -121,6 +188,26 @@ impl CompilerOptions {
         if self.selection && backend == BackendVariant::Direct{
             flags.push("    &3- selection only".to_string());
         }
+        if backend == BackendVariant::Hybrid {
+            flags.push("    &3- hybrid".to_string());
+        }
+        if self.export_netlist && backend.uses_fpga() {
+            flags.push("    &3- netlist".to_string());
+        }
+        if self.export_html {
+            flags.push("    &3- export html".to_string());
+        }
+        match &self.pipeline {
+            PassPipeline::Default => {}
+            PassPipeline::Custom(ids) => flags.push(format!("    &3- pipeline {}", ids.join(","))),
+            preset => flags.push(format!("    &3- pipeline {:?}", preset).to_lowercase()),
+        }
+        if self.validate {
+            flags.push("    &3- validate".to_string());
+        }
+        for (id, on) in &self.passes {
+            flags.push(format!("    &3- pass {}{}", if *on { '+' } else { '-' }, id));
+        }
         flags
     }
 
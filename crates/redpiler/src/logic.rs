@@ -0,0 +1,206 @@
+//! Boolean functions of the [`NodeType::Logic`] nodes built by the `minimise-logic` pass.
+//!
+//! The gates are torches, each one tick behind its sources. Every path from an input to the
+//! output gate is equally long, so the output follows a single function of the inputs, the
+//! [`truth_table`], [`depth`] ticks later. Until then it shows what is already on its way
+//! through the gates, see [`pending_outputs`].
+//!
+//! [`NodeType::Logic`]: crate::compile_graph::NodeType::Logic
+
+use crate::compile_graph::{LogicGate, LogicSource};
+use std::collections::BTreeSet;
+
+/// Most inputs of a logic node, so that its truth table fits into a `u32`
+pub const MAX_INPUTS: usize = 5;
+/// Most ticks from the inputs to the output, so that the [`pending_outputs`] fit into a `u32`
+pub const MAX_DEPTH: usize = 32;
+
+/// A product of inputs: the inputs in `mask` must have the bits of `value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Implicant {
+    pub mask: u8,
+    pub value: u8,
+}
+
+impl Implicant {
+    pub fn covers(self, inputs: u32) -> bool {
+        inputs as u8 & self.mask == self.value
+    }
+
+    /// The inputs of the product and whether they must be on
+    pub fn literals(self) -> impl Iterator<Item = (usize, bool)> {
+        (0..MAX_INPUTS)
+            .filter(move |i| self.mask & 1 << i != 0)
+            .map(move |i| (i, self.value & 1 << i != 0))
+    }
+}
+
+/// Ticks from the inputs to the output
+pub fn depth(gates: &[LogicGate]) -> usize {
+    let mut depths: Vec<usize> = Vec::with_capacity(gates.len());
+    for gate in gates {
+        let sources = gate.sources.iter().map(|source| match *source {
+            LogicSource::Input(_) => 0,
+            LogicSource::Gate(gate) => depths[gate as usize],
+        });
+        depths.push(sources.max().unwrap_or(0) + 1);
+    }
+    *depths.last().unwrap()
+}
+
+/// The output for every state of the inputs, bit `i` of the index being input `i`
+pub fn truth_table(gates: &[LogicGate]) -> u32 {
+    let mut table = 0;
+    for inputs in 0..1u32 << MAX_INPUTS {
+        let mut values: Vec<bool> = Vec::with_capacity(gates.len());
+        for gate in gates {
+            let on = gate.sources.iter().any(|source| match *source {
+                LogicSource::Input(i) => inputs & 1 << i != 0,
+                LogicSource::Gate(gate) => values[gate as usize],
+            });
+            values.push(!on);
+        }
+        if *values.last().unwrap() {
+            table |= 1 << inputs;
+        }
+    }
+    table
+}
+
+/// The output in the next [`depth`] ticks, starting with the current one. These only depend
+/// on the states of the gates.
+pub fn pending_outputs(gates: &[LogicGate]) -> Vec<bool> {
+    let mut states: Vec<bool> = gates.iter().map(|gate| gate.powered).collect();
+    let mut outputs = vec![*states.last().unwrap()];
+    for _ in 1..depth(gates) {
+        states = gates
+            .iter()
+            .map(|gate| {
+                !gate.sources.iter().any(|source| match *source {
+                    LogicSource::Input(_) => false,
+                    LogicSource::Gate(gate) => states[gate as usize],
+                })
+            })
+            .collect();
+        outputs.push(*states.last().unwrap());
+    }
+    outputs
+}
+
+/// A small sum of products for `table`, found with Quine-McCluskey and a greedy cover.
+/// An empty sum is always off.
+pub fn minimise(table: u32) -> Vec<Implicant> {
+    let full = (1u8 << MAX_INPUTS) - 1;
+    let minterms: Vec<u32> = (0..1 << MAX_INPUTS).filter(|i| table & 1 << i != 0).collect();
+
+    // Merge implicants differing in a single input until none can be merged
+    let mut primes = BTreeSet::new();
+    let mut terms: BTreeSet<Implicant> = minterms
+        .iter()
+        .map(|&i| Implicant { mask: full, value: i as u8 })
+        .collect();
+    while !terms.is_empty() {
+        let mut merged = BTreeSet::new();
+        let mut used = BTreeSet::new();
+        for a in &terms {
+            for b in &terms {
+                let diff = a.value ^ b.value;
+                if a.mask == b.mask && diff.count_ones() == 1 && a.value < b.value {
+                    merged.insert(Implicant { mask: a.mask & !diff, value: a.value });
+                    used.insert(*a);
+                    used.insert(*b);
+                }
+            }
+        }
+        primes.extend(terms.difference(&used).copied());
+        terms = merged;
+    }
+
+    // Essential primes first, then the ones covering the most minterms left
+    let mut cover = Vec::new();
+    let mut left = minterms;
+    for &minterm in &left.clone() {
+        let mut covering = primes.iter().filter(|prime| prime.covers(minterm));
+        if let (Some(&prime), None) = (covering.next(), covering.next()) {
+            if !cover.contains(&prime) {
+                cover.push(prime);
+            }
+        }
+    }
+    left.retain(|&minterm| !cover.iter().any(|prime| prime.covers(minterm)));
+    while !left.is_empty() {
+        let best = *primes
+            .iter()
+            .max_by_key(|prime| (left.iter().filter(|&&minterm| prime.covers(minterm)).count(), std::cmp::Reverse(**prime)))
+            .unwrap();
+        cover.push(best);
+        left.retain(|&minterm| !best.covers(minterm));
+    }
+    cover
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The table of a sum of products, to check it against the one it was minimised from
+    fn table(cover: &[Implicant]) -> u32 {
+        (0..1 << MAX_INPUTS)
+            .filter(|&inputs| cover.iter().any(|implicant| implicant.covers(inputs)))
+            .fold(0, |table, inputs| table | 1 << inputs)
+    }
+
+    #[test]
+    fn minimise_constants() {
+        assert_eq!(minimise(0), vec![]);
+        assert_eq!(minimise(u32::MAX), vec![Implicant { mask: 0, value: 0 }]);
+    }
+
+    #[test]
+    fn minimise_gates() {
+        // Input 0 and 1 both on, the other inputs don't matter
+        let and = (0..32).filter(|i| i & 3 == 3).fold(0, |table, i| table | 1 << i);
+        assert_eq!(minimise(and), vec![Implicant { mask: 3, value: 3 }]);
+
+        let xor = (0..32).filter(|i| (i ^ i >> 1) & 1 != 0).fold(0, |table, i| table | 1 << i);
+        let cover = minimise(xor);
+        assert_eq!(cover.len(), 2);
+        assert_eq!(table(&cover), xor);
+
+        // A or not B needs one literal per product
+        let or_not = (0..32).filter(|i| i & 1 != 0 || i & 2 == 0).fold(0, |table, i| table | 1 << i);
+        let mut cover = minimise(or_not);
+        cover.sort();
+        assert_eq!(cover, vec![Implicant { mask: 1, value: 1 }, Implicant { mask: 2, value: 0 }]);
+    }
+
+    #[test]
+    fn minimise_keeps_tables() {
+        let mut table_bits = 0x2545_f491u32;
+        for _ in 0..200 {
+            table_bits ^= table_bits << 13;
+            table_bits ^= table_bits >> 17;
+            table_bits ^= table_bits << 5;
+            assert_eq!(table(&minimise(table_bits)), table_bits, "{:#010x}", table_bits);
+        }
+    }
+
+    #[test]
+    fn and_tower() {
+        // Two torches on the inputs and one on both of them
+        let gate = |sources: &[LogicSource], powered| LogicGate { sources: sources.to_vec(), powered };
+        let gates = [
+            gate(&[LogicSource::Input(0)], true),
+            gate(&[LogicSource::Input(1)], true),
+            gate(&[LogicSource::Gate(0), LogicSource::Gate(1)], false),
+        ];
+        assert_eq!(depth(&gates), 2);
+        assert_eq!(table(&minimise(truth_table(&gates))), truth_table(&gates));
+        assert_eq!(minimise(truth_table(&gates)), vec![Implicant { mask: 3, value: 3 }]);
+        assert_eq!(pending_outputs(&gates), vec![false, false]);
+
+        // With both input torches off, the output turns on next tick whatever the inputs
+        let gates = [gate(&[LogicSource::Input(0)], false), gate(&[LogicSource::Input(1)], false), gates[2].clone()];
+        assert_eq!(pending_outputs(&gates), vec![false, true]);
+    }
+}
@@ -9,6 +9,7 @@ use crate::compile_graph::{
     Annotations, CompileGraph, CompileLink, CompileNode, LinkType, NodeIdx, NodeType,
 };
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use itertools::Itertools;
 use mchprs_blocks::blocks::ComparatorMode;
 use mchprs_world::World;
@@ -18,7 +19,13 @@ use petgraph::Direction;
 pub struct AnalogRepeaters;
 
 impl<W: World> Pass<W> for AnalogRepeaters {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         'next: for i in 0..graph.node_bound() {
             let start_idx = NodeIdx::new(i);
             if !graph.contains_node(start_idx) {
@@ -107,9 +114,14 @@ impl<W: World> Pass<W> for AnalogRepeaters {
 
             graph.add_edge(start_idx, new_comparator, CompileLink::default(0));
             graph.add_edge(new_comparator, end_idx, CompileLink::default(0));
+            stats.add("repeater ladders merged", 1);
         }
     }
 
+    fn id(&self) -> &'static str {
+        "analog-repeaters"
+    }
+
     fn status_message(&self) -> &'static str {
         "Combining analog repeaters"
     }
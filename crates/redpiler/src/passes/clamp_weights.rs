@@ -1,13 +1,22 @@
 use super::Pass;
 use crate::compile_graph::CompileGraph;
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_world::World;
 
 pub struct ClampWeights;
 
 impl<W: World> Pass<W> for ClampWeights {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
+        let edges = graph.edge_count();
         graph.retain_edges(|g, edge| g[edge].ss < 15);
+        stats.add("links clamped", edges - graph.edge_count());
     }
 
     fn should_run(&self, _: &CompilerOptions) -> bool {
@@ -15,6 +24,10 @@ impl<W: World> Pass<W> for ClampWeights {
         true
     }
 
+    fn id(&self) -> &'static str {
+        "clamp-weights"
+    }
+
     fn status_message(&self) -> &'static str {
         "Clamping weights"
     }
@@ -1,6 +1,7 @@
 use super::Pass;
 use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use itertools::Itertools;
 use mchprs_world::World;
 use petgraph::visit::{EdgeRef, NodeIndexable};
@@ -10,16 +11,27 @@ use tracing::trace;
 pub struct Coalesce;
 
 impl<W: World> Pass<W> for Coalesce {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         loop {
             let num_coalesced = run_iteration(graph);
             trace!("Iteration combined {} nodes", num_coalesced);
+            stats.add("nodes coalesced", num_coalesced);
             if num_coalesced == 0 {
                 break;
             }
         }
     }
 
+    fn id(&self) -> &'static str {
+        "coalesce"
+    }
+
     fn status_message(&self) -> &'static str {
         "Combining duplicate logic"
     }
@@ -0,0 +1,121 @@
+//! # [`CollapseRepeaters`]
+//!
+//! This pass replaces chains of repeaters, such as delay lines and long bus wiring, with a
+//! single [`NodeType::Delay`] node.
+//!
+//! The node keeps the delay of every repeater so the direct backend can replay the chain tick
+//! for tick, the FPGA backends build it from the parts given by
+//! [`delay_segments`](crate::compile_graph::delay_segments).
+//!
+//! Only unlocked repeaters with a single input and a single output are part of a chain, and the
+//! repeaters must all be in the same state without pending ticks.
+
+use super::same_tick_cycles::is_locker;
+use super::Pass;
+use crate::compile_graph::{CompileGraph, DelayStage, LinkType, NodeIdx, NodeType};
+use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
+use itertools::Itertools;
+use mchprs_world::World;
+use petgraph::visit::{EdgeRef, NodeIndexable};
+use petgraph::Direction;
+use rustc_hash::FxHashSet;
+
+pub struct CollapseRepeaters;
+
+impl<W: World> Pass<W> for CollapseRepeaters {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        input: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
+        let mut world = input.world.lock().unwrap();
+        let members: FxHashSet<NodeIdx> = graph
+            .node_indices()
+            .filter(|&idx| is_member(graph, idx))
+            .filter(|&idx| !graph[idx].block.is_some_and(|(pos, _)| world.pending_tick_at(pos)))
+            .collect();
+        drop(world);
+
+        let mut chains = Vec::new();
+        for i in 0..graph.node_bound() {
+            let idx = NodeIdx::new(i);
+            if !members.contains(&idx) {
+                continue;
+            }
+            // Chains are walked from their first repeater, so rings are never collapsed
+            let source = graph.neighbors_directed(idx, Direction::Incoming).next().unwrap();
+            if next(graph, &members, source) == Some(idx) {
+                continue;
+            }
+
+            let mut chain = vec![idx];
+            while let Some(next) = next(graph, &members, *chain.last().unwrap()) {
+                chain.push(next);
+            }
+            chains.push(chain);
+        }
+
+        for chain in chains {
+            if chain.len() > 1 {
+                stats.add("repeaters collapsed", chain.len());
+                collapse(graph, &chain);
+            }
+        }
+    }
+
+    fn id(&self) -> &'static str {
+        "collapse-repeaters"
+    }
+
+    fn status_message(&self) -> &'static str {
+        "Collapsing repeater chains"
+    }
+}
+
+/// Whether the node can be part of a chain: an unlocked repeater with a single default input
+/// that doesn't lock other repeaters
+fn is_member(graph: &CompileGraph, idx: NodeIdx) -> bool {
+    let node = &graph[idx];
+    if !matches!(node.ty, NodeType::Repeater { .. }) || !node.is_removable() || node.state.repeater_locked {
+        return false;
+    }
+    let Ok(input) = graph.edges_directed(idx, Direction::Incoming).exactly_one() else {
+        return false;
+    };
+    input.weight().ty == LinkType::Default && input.source() != idx && !is_locker(graph, idx)
+}
+
+/// The repeater following `idx` in a chain, if its only output is that repeater
+fn next(graph: &CompileGraph, members: &FxHashSet<NodeIdx>, idx: NodeIdx) -> Option<NodeIdx> {
+    if !members.contains(&idx) {
+        return None;
+    }
+    let output = graph.edges_directed(idx, Direction::Outgoing).exactly_one().ok()?;
+    let target = output.target();
+    let linked = output.weight().ty == LinkType::Default && output.weight().ss < 15;
+    (linked && members.contains(&target) && graph[target].state.powered == graph[idx].state.powered)
+        .then_some(target)
+}
+
+/// Turns the last repeater of `chain` into the delay node and removes the others
+fn collapse(graph: &mut CompileGraph, chain: &[NodeIdx]) {
+    let stages = chain
+        .iter()
+        .map(|&idx| match graph[idx].ty {
+            NodeType::Repeater { delay, facing_diode } => DelayStage { delay, facing_diode, block: graph[idx].block },
+            _ => unreachable!(),
+        })
+        .collect();
+
+    let (first, last) = (chain[0], *chain.last().unwrap());
+    let input = graph.edges_directed(first, Direction::Incoming).next().unwrap();
+    let (source, link) = (input.source(), input.weight().clone());
+    for &idx in &chain[..chain.len() - 1] {
+        graph.remove_node(idx);
+    }
+    graph.add_edge(source, last, link);
+    graph[last].ty = NodeType::Delay { stages };
+}
@@ -1,6 +1,7 @@
 use super::Pass;
 use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_blocks::blocks::ComparatorMode;
 use mchprs_world::World;
 use petgraph::visit::{EdgeRef, NodeIndexable};
@@ -10,16 +11,27 @@ use tracing::trace;
 pub struct ConstantFold;
 
 impl<W: World> Pass<W> for ConstantFold {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         loop {
             let num_folded = fold(graph);
             if num_folded == 0 {
                 break;
             }
             trace!("Fold iteration: {} nodes", num_folded);
+            stats.add("constants folded", num_folded);
         }
     }
 
+    fn id(&self) -> &'static str {
+        "constant-fold"
+    }
+
     fn status_message(&self) -> &'static str {
         "Constant folding"
     }
@@ -30,7 +42,7 @@ fn fold(graph: &mut CompileGraph) -> usize {
 
     'nodes: for i in 0..graph.node_bound() {
         let idx = NodeIdx::new(i);
-        if !graph.contains_node(idx) {
+        if !graph.contains_node(idx) || !graph[idx].is_removable() {
             continue;
         }
 
@@ -8,6 +8,7 @@
 use super::Pass;
 use crate::compile_graph::{CompileGraph, NodeIdx};
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_world::World;
 use petgraph::visit::{EdgeRef, NodeIndexable};
 use petgraph::Direction;
@@ -15,7 +16,13 @@ use petgraph::Direction;
 pub struct DedupLinks;
 
 impl<W: World> Pass<W> for DedupLinks {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         for i in 0..graph.node_bound() {
             let idx = NodeIdx::new(i);
             if !graph.contains_node(idx) {
@@ -41,11 +48,16 @@ impl<W: World> Pass<W> for DedupLinks {
 
                 if should_remove {
                     graph.remove_edge(edge_idx);
+                    stats.add("links deduped", 1);
                 }
             }
         }
     }
 
+    fn id(&self) -> &'static str {
+        "dedup-links"
+    }
+
     fn status_message(&self) -> &'static str {
         "Deduplicating links"
     }
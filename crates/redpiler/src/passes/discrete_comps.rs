@@ -2,14 +2,21 @@ use std::u16::MAX;
 
 use super::Pass;
 use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
-use crate::{BackendVariant, CompilerInput, CompilerOptions};
+use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_blocks::blocks::ComparatorMode;
 use mchprs_world::World;
 use petgraph::visit::{EdgeRef, NodeIndexable};
 pub struct DiscreteComparators;
 
 impl<W: World> Pass<W> for DiscreteComparators {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         for i in 0..graph.node_bound() {
             let start_idx = NodeIdx::new(i);
 
@@ -17,43 +24,56 @@ impl<W: World> Pass<W> for DiscreteComparators {
                 continue;
             }
 
-            if let NodeType::Comparator { mode, far_input, facing_diode, states } = graph[start_idx].ty {
-                let mut states: u16 = 0x8000; 
-
-                for edge in graph.edges_directed(start_idx, petgraph::Direction::Outgoing) {
-                    let weight = edge.weight(); 
-                    let output = &graph[edge.target()].ty;
-
-                    match output {
-                        NodeType::Repeater {..} |
-                        NodeType::Torch |
-                        NodeType::Lamp | 
-                        NodeType::Trapdoor=> {
-                            states |= 0x1 << weight.ss;
-                        }
-                        NodeType::Comparator {..} => {
-                            states |= 0x7FFF >> weight.ss;
-                        }
-                        _ => {} 
-                    } 
-                }
-
+            if let NodeType::Comparator { mode, far_input, facing_diode, .. } = graph[start_idx].ty {
+                let states = required_states(graph, start_idx);
                 graph[start_idx].ty = NodeType::Comparator {
                     mode,
                     far_input,
                     facing_diode,
                     states: Some(states),
                 };
+                stats.add("comparators discretised", 1);
             }   
         }
     }
 
+    fn id(&self) -> &'static str {
+        "discrete-comps"
+    }
+
     fn status_message(&self) -> &'static str {
         "Optimizing Comparators for FPGA"
     }
 
     fn should_run(&self, options: &CompilerOptions) -> bool {
-        options.backend_variant == BackendVariant::FPGA
+        options.backend_variant.uses_fpga()
     }
 }
 
+/// The output strengths of a comparator its outputs can tell apart, as a bit per strength.
+/// Bit 15 is always set.
+pub(super) fn required_states(graph: &CompileGraph, idx: NodeIdx) -> u16 {
+    let mut states: u16 = 0x8000;
+
+    for edge in graph.edges_directed(idx, petgraph::Direction::Outgoing) {
+        let weight = edge.weight();
+        let output = &graph[edge.target()].ty;
+
+        match output {
+            NodeType::Repeater {..} |
+            NodeType::Delay {..} |
+            NodeType::Logic {..} |
+            NodeType::Observer |
+            NodeType::Torch |
+            NodeType::Lamp |
+            NodeType::Trapdoor=> {
+                states |= 0x1 << weight.ss;
+            }
+            NodeType::Comparator {..} => {
+                states |= 0x7FFF >> weight.ss;
+            }
+            _ => {}
+        }
+    }
+    states
+}
@@ -0,0 +1,36 @@
+use super::Pass;
+use crate::compile_graph::CompileGraph;
+use crate::graph_html;
+use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
+use mchprs_world::World;
+use std::path::Path;
+use tracing::warn;
+
+pub struct ExportHtml;
+
+impl<W: World> Pass<W> for ExportHtml {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        _: &mut PassStats,
+    ) {
+        if let Err(err) = graph_html::save(graph, "redpiler graph", Path::new("redpiler_graph.html")) {
+            warn!("Could not write redpiler_graph.html: {}", err);
+        }
+    }
+
+    fn should_run(&self, options: &CompilerOptions) -> bool {
+        options.export_html
+    }
+
+    fn id(&self) -> &'static str {
+        "export-html"
+    }
+
+    fn status_message(&self) -> &'static str {
+        "Exporting graph viewer"
+    }
+}
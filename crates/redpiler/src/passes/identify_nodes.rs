@@ -3,14 +3,19 @@
 //! This pass populates the graph with nodes using the input given in [`CompilerInput`].
 //! This pass is *mandatory*. Without it, the graph will never be populated.
 //!
-//! If `optimize` is set in [`CompilerOptions`], redstone wires will not be added to the graph.
+//! If `optimize` is set in [`CompilerOptions`], redstone wires will not be added to the graph,
+//! unless a sign annotates them.
+//!
+//! Signs annotate the component they are placed on with `[...]` lines:
+//! `[name cpu.pc0]`, `[input]`, `[output]`, `[probe]`, `[bus data 3]` and `[keep]`.
+//! See [`NodeAnnotation`].
 //!
 //! There are no requirements for this pass.
 
 use super::Pass;
 use crate::compile_graph::{Annotations, CompileGraph, CompileNode, NodeIdx, NodeState, NodeType};
 use crate::{CompilerInput, CompilerOptions};
-use crate::BackendVariant;
+use crate::report::PassStats;
 use itertools::Itertools;
 use mchprs_blocks::block_entities::BlockEntity;
 use mchprs_blocks::blocks::Block;
@@ -29,8 +34,9 @@ impl<W: World> Pass<W> for IdentifyNodes {
         graph: &mut CompileGraph,
         options: &CompilerOptions,
         input: &CompilerInput<W>,
+        _: &mut PassStats,
     ) {
-        let ignore_wires = options.optimize || (options.backend_variant == BackendVariant::FPGA);
+        let ignore_wires = options.optimize || options.backend_variant.uses_fpga();
         let plot = &*input.world.lock().unwrap();
 
         let mut first_pass = FxHashMap::default();
@@ -51,8 +57,9 @@ impl<W: World> Pass<W> for IdentifyNodes {
         });
 
         for pos in second_pass {
-            apply_annotations(graph, options, &first_pass, plot, pos);
+            apply_annotations(graph, options, &mut first_pass, plot, pos);
         }
+        check_labels(graph);
     }
 
     fn should_run(&self, _: &CompilerOptions) -> bool {
@@ -60,6 +67,10 @@ impl<W: World> Pass<W> for IdentifyNodes {
         true
     }
 
+    fn id(&self) -> &'static str {
+        "identify-nodes"
+    }
+
     fn status_message(&self) -> &'static str {
         "Identifying nodes"
     }
@@ -99,6 +110,20 @@ fn for_pos<W: World>(
         return;
     }
 
+    add_node(graph, first_pass, ty, state, pos, id, is_input, is_output);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_node(
+    graph: &mut CompileGraph,
+    first_pass: &mut FxHashMap<BlockPos, NodeIdx>,
+    ty: NodeType,
+    state: NodeState,
+    pos: BlockPos,
+    id: u32,
+    is_input: bool,
+    is_output: bool,
+) -> NodeIdx {
     let node_idx = graph.add_node(CompileNode {
         ty,
         block: Some((pos, id)),
@@ -109,6 +134,42 @@ fn for_pos<W: World>(
         annotations: Annotations::default(),
     });
     first_pass.insert(pos, node_idx);
+    node_idx
+}
+
+/// Adds a wire that was left out because wires are ignored, so it can be annotated
+fn add_ignored_wire<W: World>(
+    graph: &mut CompileGraph,
+    first_pass: &mut FxHashMap<BlockPos, NodeIdx>,
+    world: &W,
+    pos: BlockPos,
+) -> Option<NodeIdx> {
+    let id = world.get_block_raw(pos);
+    let block = Block::from_id(id);
+    if !matches!(block, Block::RedstoneWire { .. }) {
+        return None;
+    }
+    let (ty, state) = identify_block(block, pos, world)?;
+    Some(add_node(graph, first_pass, ty, state, pos, id, false, false))
+}
+
+/// Warns about names and bus bits given to more than one component
+fn check_labels(graph: &CompileGraph) {
+    let mut names = FxHashMap::default();
+    let mut bits = FxHashMap::default();
+    for node in graph.node_weights() {
+        let pos = node.block.map(|(pos, _)| pos);
+        if let Some(name) = &node.annotations.name {
+            if let Some(other) = names.insert(name.clone(), pos) {
+                warn!("The name {} is used at {:?} and {:?}", name, other, pos);
+            }
+        }
+        if let Some(bit) = &node.annotations.bus {
+            if let Some(other) = bits.insert(bit.clone(), pos) {
+                warn!("Bit {} of bus {} is used at {:?} and {:?}", bit.1, bit.0, other, pos);
+            }
+        }
+    }
 }
 
 fn identify_block<W: World>(
@@ -158,6 +219,7 @@ fn identify_block<W: World>(
         }
         Block::IronTrapdoor { powered, .. } => (NodeType::Trapdoor, NodeState::simple(powered)),
         Block::RedstoneBlock {} => (NodeType::Constant, NodeState::ss(15)),
+        Block::Observer { powered, .. } => (NodeType::Observer, NodeState::simple(powered)),
         Block::NoteBlock {
             instrument: _,
             note,
@@ -181,7 +243,7 @@ fn identify_block<W: World>(
 fn apply_annotations<W: World>(
     graph: &mut CompileGraph,
     options: &CompilerOptions,
-    first_pass: &FxHashMap<BlockPos, NodeIdx>,
+    first_pass: &mut FxHashMap<BlockPos, NodeIdx>,
     world: &W,
     pos: BlockPos,
 ) {
@@ -212,8 +274,11 @@ fn apply_annotations<W: World>(
         _ => panic!("Block unimplemented for second pass"),
     };
 
-    let target = targets.iter().flat_map(|pos| first_pass.get(pos)).next();
-    if let Some(&node_idx) = target {
+    let target = targets
+        .iter()
+        .find_map(|pos| first_pass.get(pos).copied())
+        .or_else(|| targets.iter().find_map(|&pos| add_ignored_wire(graph, first_pass, world, pos)));
+    if let Some(node_idx) = target {
         for annotation in annotations {
             let result = annotation.apply(graph, node_idx, options);
             if let Err(msg) = result {
@@ -237,26 +302,112 @@ fn parse_sign_annotations(entity: Option<&BlockEntity>) -> Vec<NodeAnnotation> {
     }
 }
 
-pub enum NodeAnnotation {}
+/// A `[...]` line on a sign. Keywords are case insensitive, names are not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeAnnotation {
+    /// `[name cpu.pc0]`
+    Name(String),
+    /// `[input]` makes the component an input, even if it isn't a lever, button or pressure plate
+    Input,
+    /// `[output]` makes the component an output, even if it isn't a lamp or trapdoor
+    Output,
+    /// `[probe]` keeps the component observable without making it an output
+    Probe,
+    /// `[bus data 3]` makes the component bit 3 of the bus `data`
+    Bus(String, u32),
+    /// `[keep]` stops optimizations from removing the component
+    Keep,
+}
 
 impl NodeAnnotation {
     fn parse(s: &str) -> Option<Self> {
-        let s = s.trim().to_ascii_lowercase();
+        let s = s.trim();
         if !(s.starts_with('[') && s.ends_with(']')) {
             return None;
         }
-        let parts = s[1..s.len() - 1].split(' ').collect_vec();
-        match parts.as_slice() {
+        let parts = s[1..s.len() - 1].split_whitespace().collect_vec();
+        let (keyword, args) = parts.split_first()?;
+        match (keyword.to_ascii_lowercase().as_str(), args) {
+            ("name", [name]) => Some(NodeAnnotation::Name(name.to_string())),
+            ("input", []) => Some(NodeAnnotation::Input),
+            ("output", []) => Some(NodeAnnotation::Output),
+            ("probe", []) => Some(NodeAnnotation::Probe),
+            ("bus", [name, bit]) => Some(NodeAnnotation::Bus(name.to_string(), bit.parse().ok()?)),
+            ("keep", []) => Some(NodeAnnotation::Keep),
             _ => None,
         }
     }
 
     fn apply(
         self,
-        _graph: &mut CompileGraph,
-        _node_idx: NodeIdx,
-        _options: &CompilerOptions,
+        graph: &mut CompileGraph,
+        node_idx: NodeIdx,
+        options: &CompilerOptions,
     ) -> Result<(), String> {
-        match self {}
+        let node = &mut graph[node_idx];
+        let annotations = &mut node.annotations;
+        match self {
+            NodeAnnotation::Name(name) => match &annotations.name {
+                Some(old) if *old != name => {
+                    return Err(format!("Component is named both {} and {}", old, name))
+                }
+                _ => annotations.name = Some(name),
+            },
+            NodeAnnotation::Bus(name, bit) => match &annotations.bus {
+                Some(old) if *old != (name.clone(), bit) => {
+                    return Err(format!(
+                        "Component is both bit {} of bus {} and bit {} of bus {}",
+                        old.1, old.0, bit, name
+                    ))
+                }
+                _ => annotations.bus = Some((name, bit)),
+            },
+            NodeAnnotation::Probe => annotations.probe = true,
+            NodeAnnotation::Keep => annotations.keep = true,
+            NodeAnnotation::Input => {
+                node.is_input = true;
+                let interface = matches!(
+                    node.ty,
+                    NodeType::Button | NodeType::Lever | NodeType::PressurePlate
+                );
+                if options.backend_variant.uses_fpga() && !interface {
+                    return Err(
+                        "FPGA builds only take inputs from levers, buttons and pressure plates, \
+                         the component is only kept"
+                            .to_string(),
+                    );
+                }
+            }
+            NodeAnnotation::Output => {
+                node.is_output = true;
+                let interface = node.is_forced_output()
+                    || matches!(node.ty, NodeType::Lamp | NodeType::Trapdoor);
+                if options.backend_variant.uses_fpga() && !interface {
+                    return Err(
+                        "FPGA builds only output lamps, trapdoors, repeaters, torches and comparators, \
+                         the component is only kept"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_annotations() {
+        assert_eq!(NodeAnnotation::parse("[name cpu.PC0]"), Some(NodeAnnotation::Name("cpu.PC0".to_string())));
+        assert_eq!(NodeAnnotation::parse(" [Bus data 3] "), Some(NodeAnnotation::Bus("data".to_string(), 3)));
+        assert_eq!(NodeAnnotation::parse("[OUTPUT]"), Some(NodeAnnotation::Output));
+        assert_eq!(NodeAnnotation::parse("[keep]"), Some(NodeAnnotation::Keep));
+        assert_eq!(NodeAnnotation::parse("[bus data x]"), None);
+        assert_eq!(NodeAnnotation::parse("[probe now]"), None);
+        assert_eq!(NodeAnnotation::parse("name cpu"), None);
+        assert_eq!(NodeAnnotation::parse("[]"), None);
     }
 }
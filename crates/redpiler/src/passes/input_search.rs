@@ -4,8 +4,9 @@
 //! This pass is *mandatory*. Without it, there would be no links between nodes.
 
 use super::Pass;
-use crate::compile_graph::{CompileGraph, CompileLink, LinkType, NodeIdx};
+use crate::compile_graph::{CompileGraph, CompileLink, LinkType, NodeIdx, NodeType};
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_blocks::blocks::{Block, ButtonFace, LeverFace};
 use mchprs_blocks::{BlockDirection, BlockFace, BlockPos};
 use mchprs_redstone::{self, comparator, wire};
@@ -13,6 +14,7 @@ use mchprs_world::World;
 use petgraph::visit::NodeIndexable;
 use rustc_hash::FxHashMap;
 use std::collections::VecDeque;
+use tracing::warn;
 
 pub struct InputSearch;
 
@@ -22,6 +24,7 @@ impl<W: World> Pass<W> for InputSearch {
         graph: &mut CompileGraph,
         _: &CompilerOptions,
         input: &CompilerInput<'_, W>,
+        _: &mut PassStats,
     ) {
         let plot = &*input.world.lock().unwrap();
         let mut state = InputSearchState::new(plot, graph);
@@ -33,6 +36,10 @@ impl<W: World> Pass<W> for InputSearch {
         true
     }
 
+    fn id(&self) -> &'static str {
+        "input-search"
+    }
+
     fn status_message(&self) -> &'static str {
         "Searching for links"
     }
@@ -71,6 +78,7 @@ impl<'a, W: World> InputSearchState<'a, W> {
             Block::RedstoneComparator { comparator } if comparator.facing.block_face() == side => {
                 true
             }
+            Block::Observer { facing, .. } if facing.block_face() == side => true,
             _ => false,
         }
     }
@@ -92,6 +100,7 @@ impl<'a, W: World> InputSearchState<'a, W> {
             },
             Block::RedstoneRepeater { .. } => self.provides_weak_power(block, side),
             Block::RedstoneComparator { .. } => self.provides_weak_power(block, side),
+            Block::Observer { .. } => self.provides_weak_power(block, side),
             _ => false,
         }
     }
@@ -274,6 +283,29 @@ impl<'a, W: World> InputSearchState<'a, W> {
         }
     }
 
+    /// Observers only see the nodes that turn on and off, blocks without a node never change
+    fn search_observed(&mut self, id: NodeIdx, observed_pos: BlockPos) {
+        let Some(&observed) = self.pos_map.get(&observed_pos) else {
+            return;
+        };
+        match self.graph[observed].ty {
+            NodeType::Repeater { .. }
+            | NodeType::Torch
+            | NodeType::Comparator { .. }
+            | NodeType::Button
+            | NodeType::Lever
+            | NodeType::PressurePlate
+            | NodeType::Observer => {
+                self.graph.add_edge(observed, id, CompileLink::default(0));
+            }
+            NodeType::Constant => {}
+            _ => warn!(
+                "The observer facing {} can't see the block changing, it will never pulse",
+                observed_pos
+            ),
+        }
+    }
+
     fn search_node(&mut self, id: NodeIdx, (pos, block_id): (BlockPos, u32)) {
         match Block::from_id(block_id) {
             Block::RedstoneTorch { .. } => {
@@ -281,7 +313,7 @@ impl<'a, W: World> InputSearchState<'a, W> {
                 let bottom_block = self.world.get_block(bottom_pos);
                 self.get_redstone_links(
                     bottom_block,
-                    BlockFace::Top,
+                    BlockFace::Bottom,
                     bottom_pos,
                     LinkType::Default,
                     0,
@@ -327,6 +359,9 @@ impl<'a, W: World> InputSearchState<'a, W> {
             Block::RedstoneWire { .. } => {
                 self.search_wire(id, pos, LinkType::Default, 0);
             }
+            Block::Observer { facing, .. } => {
+                self.search_observed(id, pos.offset(facing.block_face()));
+            }
             Block::RedstoneLamp { .. } | Block::IronTrapdoor { .. } | Block::NoteBlock { .. } => {
                 for face in &BlockFace::values() {
                     let neighbor_pos = pos.offset(*face);
@@ -0,0 +1,181 @@
+//! # [`MinimiseLogic`]
+//!
+//! This pass merges torch gates, such as NOR trees and the torch towers of AND and XOR gates,
+//! into a single [`NodeType::Logic`] node. Both backends build the node from its minimised
+//! function, see [`crate::logic`], shifted through a register as deep as the torches.
+//!
+//! A region is grown backwards from a torch, one layer of torches at a time. A torch joins the
+//! region only if all of its outputs go into the layer before it, so every path from the inputs
+//! to the output takes the same number of ticks and the output follows a single function of the
+//! inputs. The region ends at the deepest layer whose sources are at most [`MAX_INPUTS`]
+//! components with an on or off output, like torches, repeaters and levers, and at most
+//! [`MAX_DEPTH`] layers deep.
+//!
+//! Comparator based gates are left alone. Comparators pass on signal strengths rather than on
+//! or off, so they don't fit into a truth table.
+
+use super::Pass;
+use crate::compile_graph::{CompileGraph, CompileLink, LogicGate, LogicSource, NodeIdx, NodeType};
+use crate::logic::{MAX_DEPTH, MAX_INPUTS};
+use crate::report::PassStats;
+use crate::{CompilerInput, CompilerOptions};
+use mchprs_world::World;
+use petgraph::Direction;
+use rustc_hash::FxHashSet;
+
+pub struct MinimiseLogic;
+
+impl<W: World> Pass<W> for MinimiseLogic {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        input: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
+        let mut world = input.world.lock().unwrap();
+        let torches: FxHashSet<NodeIdx> = graph
+            .node_indices()
+            .filter(|&idx| graph[idx].ty == NodeType::Torch)
+            .filter(|&idx| !graph[idx].block.is_some_and(|(pos, _)| world.pending_tick_at(pos)))
+            .collect();
+        drop(world);
+
+        // Torches feeding something else than torches end regions, try them first
+        let (ends, rest): (Vec<NodeIdx>, Vec<NodeIdx>) = graph
+            .node_indices()
+            .filter(|idx| torches.contains(idx))
+            .partition(|&idx| graph.neighbors_directed(idx, Direction::Outgoing).any(|target| !torches.contains(&target)));
+
+        let mut merged = FxHashSet::default();
+        for root in ends.into_iter().chain(rest) {
+            if merged.contains(&root) {
+                continue;
+            }
+            let Some(layers) = find_region(graph, &torches, &merged, root) else {
+                continue;
+            };
+            merged.extend(layers.iter().flatten().copied());
+            stats.add("torches merged", layers.iter().map(Vec::len).sum());
+            stats.add("logic nodes", 1);
+            merge(graph, layers);
+        }
+    }
+
+    fn id(&self) -> &'static str {
+        "minimise-logic"
+    }
+
+    fn status_message(&self) -> &'static str {
+        "Minimising torch logic"
+    }
+}
+
+/// Distinct sources of `nodes`, in index order
+fn sources(graph: &CompileGraph, nodes: &[NodeIdx]) -> Vec<NodeIdx> {
+    let mut sources: Vec<NodeIdx> = nodes
+        .iter()
+        .flat_map(|&idx| graph.neighbors_directed(idx, Direction::Incoming))
+        .collect();
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
+/// Components whose output is either off or fully powered
+fn is_digital(ty: &NodeType) -> bool {
+    matches!(
+        ty,
+        NodeType::Torch
+            | NodeType::Repeater { .. }
+            | NodeType::Lever
+            | NodeType::Button
+            | NodeType::Delay { .. }
+            | NodeType::Logic { .. }
+            | NodeType::Observer
+    )
+}
+
+/// The layers of the region ending at `root`, starting with it. `None` if there is no region
+/// of at least two layers.
+fn find_region(
+    graph: &CompileGraph,
+    torches: &FxHashSet<NodeIdx>,
+    merged: &FxHashSet<NodeIdx>,
+    root: NodeIdx,
+) -> Option<Vec<Vec<NodeIdx>>> {
+    let mut layers = vec![vec![root]];
+    let mut members: FxHashSet<NodeIdx> = FxHashSet::from_iter([root]);
+    let mut best = None;
+    loop {
+        let layer = layers.last().unwrap();
+        let sources = sources(graph, layer);
+
+        let inputs_valid = !sources.is_empty()
+            && sources.len() <= MAX_INPUTS
+            && sources.iter().all(|source| !members.contains(source) && is_digital(&graph[*source].ty));
+        if inputs_valid && layers.len() > 1 {
+            best = Some(layers.len());
+        }
+
+        let joins = |source: &NodeIdx| {
+            torches.contains(source)
+                && !merged.contains(source)
+                && !members.contains(source)
+                && graph[*source].is_removable()
+                && graph
+                    .neighbors_directed(*source, Direction::Outgoing)
+                    .all(|target| layer.contains(&target))
+        };
+        // Gates are numbered with a `u8`
+        let full = members.len() + sources.len() > u8::MAX as usize || layers.len() == MAX_DEPTH;
+        if sources.is_empty() || full || !sources.iter().all(joins) {
+            break;
+        }
+        members.extend(sources.iter().copied());
+        layers.push(sources);
+    }
+    best.map(|len| {
+        layers.truncate(len);
+        layers
+    })
+}
+
+/// Turns the first layer's torch into the logic node and removes the other torches
+fn merge(graph: &mut CompileGraph, layers: Vec<Vec<NodeIdx>>) {
+    let root = layers[0][0];
+    let inputs = sources(graph, layers.last().unwrap());
+
+    // Gates go from the inputs to the output
+    let order: Vec<NodeIdx> = layers.iter().rev().flatten().copied().collect();
+    let gates = order
+        .iter()
+        .map(|&idx| {
+            let mut sources: Vec<LogicSource> = graph
+                .neighbors_directed(idx, Direction::Incoming)
+                .map(|source| match inputs.iter().position(|&input| input == source) {
+                    Some(i) => LogicSource::Input(i as u8),
+                    None => LogicSource::Gate(order.iter().position(|&gate| gate == source).unwrap() as u8),
+                })
+                .collect();
+            sources.sort_by_key(|source| match *source {
+                LogicSource::Input(i) => (0, i),
+                LogicSource::Gate(gate) => (1, gate),
+            });
+            sources.dedup();
+            LogicGate {
+                sources,
+                powered: graph[idx].state.powered,
+            }
+        })
+        .collect();
+
+    // The root only has inputs from the removed torches
+    for &idx in &order[..order.len() - 1] {
+        graph.remove_node(idx);
+    }
+    for (i, &input) in inputs.iter().enumerate() {
+        graph.add_edge(input, root, CompileLink::logic(i as u8));
+    }
+    graph[root].ty = NodeType::Logic { gates };
+}
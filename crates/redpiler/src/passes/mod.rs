@@ -1,23 +1,28 @@
 mod analog_repeaters;
 mod clamp_weights;
 mod coalesce;
+mod collapse_repeaters;
 mod constant_coalesce;
 mod constant_fold;
 mod dedup_links;
 mod export_graph;
+mod export_html;
 mod identify_nodes;
 mod input_search;
+mod minimise_logic;
 mod prune_orphans;
+pub mod same_tick_cycles;
 mod unreachable_output;
 mod discrete_comps;
+pub mod validate;
 
 use mchprs_world::World;
 
 use super::compile_graph::CompileGraph;
-use super::{CompilerInput, CompilerOptions};
+use super::report::{CompileReport, PassReport, PassStats};
+use super::{BackendVariant, CompilerInput, CompilerOptions, PassPipeline};
 use std::time::Instant;
-use tracing::trace;
-use crate::BackendVariant;
+use tracing::{trace, warn};
 
 pub const fn make_default_pass_manager<'w, W: World>() -> PassManager<'w, W> {
     PassManager::new(&[
@@ -30,12 +35,36 @@ pub const fn make_default_pass_manager<'w, W: World>() -> PassManager<'w, W> {
         &unreachable_output::UnreachableOutput,
         &constant_coalesce::ConstantCoalesce,
         &coalesce::Coalesce,
+        &collapse_repeaters::CollapseRepeaters,
+        &minimise_logic::MinimiseLogic,
         &prune_orphans::PruneOrphans,
         &discrete_comps::DiscreteComparators,
+        &same_tick_cycles::SameTickCycles,
         &export_graph::ExportGraph,
+        &export_html::ExportHtml,
     ])
 }
 
+/// Ids of the passes a backend can't work without. They run even when disabled.
+pub fn required_passes(backend: BackendVariant) -> &'static [&'static str] {
+    match backend {
+        BackendVariant::Direct => &["identify-nodes", "input-search", "clamp-weights"],
+        // The assembler reads the comparator output strengths found by `discrete-comps`
+        BackendVariant::FPGA | BackendVariant::Hybrid => {
+            &["identify-nodes", "input-search", "clamp-weights", "discrete-comps"]
+        }
+    }
+}
+
+/// A pass of the pipeline resolved for some [`CompilerOptions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassInfo {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub runs: bool,
+    pub required: bool,
+}
+
 pub struct PassManager<'p, W: World> {
     passes: &'p [&'p dyn Pass<W>],
 }
@@ -45,31 +74,131 @@ impl<'p, W: World> PassManager<'p, W> {
         Self { passes }
     }
 
+    fn find(&self, id: &str) -> Option<&'p dyn Pass<W>> {
+        self.passes.iter().copied().find(|pass| pass.id() == id)
+    }
+
+    /// The passes in the order they run with `options`, and whether they run.
+    /// Unknown pass ids are skipped with a warning.
+    fn pipeline(&self, options: &CompilerOptions) -> Vec<(&'p dyn Pass<W>, PassInfo)> {
+        let required = required_passes(options.backend_variant);
+        for (id, _) in &options.passes {
+            if self.find(id).is_none() {
+                warn!("Unknown pass: {}", id);
+            }
+        }
+
+        let order = match &options.pipeline {
+            PassPipeline::Custom(ids) => {
+                let mut order: Vec<&'p dyn Pass<W>> = Vec::new();
+                for id in ids {
+                    match self.find(id) {
+                        Some(pass) => order.push(pass),
+                        None => warn!("Unknown pass: {}", id),
+                    }
+                }
+                // Required passes left out run at their default place
+                let default_idx = |pass: &dyn Pass<W>| self.passes.iter().position(|p| p.id() == pass.id());
+                for (idx, &pass) in self.passes.iter().enumerate() {
+                    if required.contains(&pass.id()) && !order.iter().any(|p| p.id() == pass.id()) {
+                        let at = order.iter().position(|&p| default_idx(p) > Some(idx)).unwrap_or(order.len());
+                        order.insert(at, pass);
+                    }
+                }
+                order
+            }
+            _ => self.passes.to_vec(),
+        };
+
+        let optimized = CompilerOptions { optimize: true, ..options.clone() };
+        order
+            .into_iter()
+            .map(|pass| {
+                let is_required = required.contains(&pass.id());
+                let set = options.passes.iter().rev().find(|(id, _)| id == pass.id()).map(|(_, on)| *on);
+                if set == Some(false) && is_required {
+                    warn!("Pass {} is required by the {:?} backend and still runs", pass.id(), options.backend_variant);
+                }
+                let runs = is_required
+                    || set.unwrap_or_else(|| match options.pipeline {
+                        PassPipeline::Default => pass.should_run(options),
+                        PassPipeline::Minimal => false,
+                        PassPipeline::Full => pass.should_run(&optimized),
+                        PassPipeline::Custom(_) => true,
+                    });
+                let info = PassInfo {
+                    id: pass.id(),
+                    description: pass.status_message(),
+                    runs,
+                    required: is_required,
+                };
+                (pass, info)
+            })
+            .collect()
+    }
+
+    /// Every pass, in the order it would run with `options`
+    pub fn describe(&self, options: &CompilerOptions) -> Vec<PassInfo> {
+        self.pipeline(options).into_iter().map(|(_, info)| info).collect()
+    }
+
     pub fn run_passes(
         &self,
         options: &CompilerOptions,
         input: &CompilerInput<'_, W>,
     ) -> CompileGraph {
+        self.run_passes_with_report(options, input).0
+    }
+
+    /// Runs the passes like [`PassManager::run_passes`], recording what each of them did
+    pub fn run_passes_with_report(
+        &self,
+        options: &CompilerOptions,
+        input: &CompilerInput<'_, W>,
+    ) -> (CompileGraph, CompileReport) {
         let mut graph = CompileGraph::new();
-    
-        for &pass in self.passes {
-            if !pass.should_run(options) {
+        let mut report = CompileReport::default();
+        let mut validate = (options.validate || cfg!(debug_assertions)).then(validate::Validate::default);
+
+        for (pass, info) in self.pipeline(options) {
+            if !info.runs {
                 trace!("Skipping pass: {}", pass.name());
                 continue;
             }
 
-
             trace!("Running pass: {}", pass.name());
+            let (nodes_before, edges_before) = (graph.node_count(), graph.edge_count());
+            let mut stats = PassStats::default();
             let start = Instant::now();
 
-            pass.run_pass(&mut graph, options, input);
+            pass.run_pass(&mut graph, options, input, &mut stats);
 
-            trace!("Completed pass in {:?}", start.elapsed());
+            let duration = start.elapsed();
+            trace!("Completed pass in {:?}", duration);
             trace!("node_count: {}", graph.node_count());
             trace!("edge_count: {}", graph.edge_count());
+            let violations = match &mut validate {
+                Some(validate) => validate.after_pass(pass.id(), &graph),
+                None => Vec::new(),
+            };
+            for violation in &violations {
+                warn!("Pass {} broke {}", pass.id(), violation);
+            }
+            report.passes.push(PassReport {
+                id: pass.id(),
+                duration,
+                nodes_before,
+                nodes_after: graph.node_count(),
+                edges_before,
+                edges_after: graph.edge_count(),
+                stats,
+                violations: violations.iter().map(ToString::to_string).collect(),
+            });
         }
 
-        graph
+        report.node_count = graph.node_count();
+        report.edge_count = graph.edge_count();
+        (graph, report)
     }
 }
 
@@ -79,6 +208,7 @@ pub trait Pass<W: World> {
         graph: &mut CompileGraph,
         options: &CompilerOptions,
         input: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
     );
 
     /// This name should only be use for debugging purposes,
@@ -87,9 +217,12 @@ pub trait Pass<W: World> {
         std::any::type_name::<Self>()
     }
 
+    /// Identifies the pass in `--pass` and `--pipeline`, e.g. `constant-fold`
+    fn id(&self) -> &'static str;
+
     fn should_run(&self, options: &CompilerOptions) -> bool {
         // Run passes for optimized builds by default
-        options.optimize || (options.backend_variant == BackendVariant::FPGA)
+        options.optimize || options.backend_variant.uses_fpga()
     }
 
     fn status_message(&self) -> &'static str;
@@ -4,7 +4,8 @@
 
 use super::Pass;
 use crate::compile_graph::CompileGraph;
-use crate::{BackendVariant, CompilerInput, CompilerOptions};
+use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use itertools::Itertools;
 use mchprs_world::World;
 use petgraph::Direction;
@@ -13,7 +14,13 @@ use rustc_hash::FxHashSet;
 pub struct PruneOrphans;
 
 impl<W: World> Pass<W> for PruneOrphans {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         let mut to_visit = graph
             .node_indices()
             .filter(|&idx| !graph[idx].is_removable())
@@ -26,11 +33,16 @@ impl<W: World> Pass<W> for PruneOrphans {
             }
         }
 
+        stats.add("orphans pruned", graph.node_count() - visited.len());
         graph.retain_nodes(|_, idx| visited.contains(&idx));
     }
 
     fn should_run(&self, options: &CompilerOptions) -> bool {
-        (options.io_only && options.optimize) || options.backend_variant == BackendVariant::FPGA
+        (options.io_only && options.optimize) || options.backend_variant.uses_fpga()
+    }
+
+    fn id(&self) -> &'static str {
+        "prune-orphans"
     }
 
     fn status_message(&self) -> &'static str {
@@ -0,0 +1,89 @@
+//! # [`SameTickCycles`]
+//!
+//! Finds loops the FPGA backends would build without a register in them. Every component
+//! is a register in hardware, except for repeaters locking another repeater: locking is
+//! instant, so their output already follows their input in the tick it changes. A loop made
+//! only of such repeaters becomes a combinational loop, which synthesis rejects or turns into
+//! a latch. The backends refuse to build these loops onto the FPGA, this pass reports them
+//! with their blocks while compiling.
+//!
+//! Comparators never take part: `comp` in `components.sv` and the netlist's `RS_COMPARATOR`
+//! always hold their output in a register, so a loop of comparators keeps its delay.
+
+use super::Pass;
+use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
+use crate::report::PassStats;
+use crate::timing;
+use crate::{CompilerInput, CompilerOptions};
+use itertools::Itertools;
+use mchprs_world::World;
+use petgraph::algo::tarjan_scc;
+use petgraph::visit::{EdgeFiltered, EdgeRef, IntoNeighbors};
+use petgraph::Direction;
+use tracing::warn;
+
+pub struct SameTickCycles;
+
+impl<W: World> Pass<W> for SameTickCycles {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
+        let cycles = find(graph);
+        for cycle in &cycles {
+            warn!("{}", describe(graph, cycle));
+        }
+        stats.add("same-tick cycles", cycles.len());
+    }
+
+    fn should_run(&self, options: &CompilerOptions) -> bool {
+        options.backend_variant.uses_fpga()
+    }
+
+    fn id(&self) -> &'static str {
+        "same-tick-cycles"
+    }
+
+    fn status_message(&self) -> &'static str {
+        "Checking for same-tick cycles"
+    }
+}
+
+/// Whether `node` is a repeater whose output follows its input in the same tick on the FPGA,
+/// see `lock_out` of `repeater` in `components.sv`
+pub fn is_locker(graph: &CompileGraph, node: NodeIdx) -> bool {
+    matches!(graph[node].ty, NodeType::Repeater { .. })
+        && graph
+            .edges_directed(node, Direction::Outgoing)
+            .any(|e| timing::is_lock(graph, e.target(), e.weight()))
+}
+
+/// The loops of locking repeaters feeding each other's input, each as the set of its nodes
+pub fn find(graph: &CompileGraph) -> Vec<Vec<NodeIdx>> {
+    let same_tick = EdgeFiltered::from_fn(graph, |edge| {
+        edge.weight().ty == LinkType::Default && is_locker(graph, edge.target())
+    });
+    tarjan_scc(&same_tick)
+        .into_iter()
+        .filter(|nodes| nodes.len() > 1 || same_tick.neighbors(nodes[0]).any(|next| next == nodes[0]))
+        .map(|mut nodes| {
+            nodes.sort();
+            nodes
+        })
+        .collect()
+}
+
+/// A message naming the blocks of a cycle found by [`find`]
+pub fn describe(graph: &CompileGraph, cycle: &[NodeIdx]) -> String {
+    let blocks = cycle
+        .iter()
+        .map(|&idx| match graph[idx].block {
+            Some((pos, _)) => pos.to_string(),
+            None => format!("node {}", idx.index()),
+        })
+        .join(", ");
+    format!("The repeaters at {} lock other repeaters and form a loop without delay on the FPGA", blocks)
+}
@@ -11,6 +11,7 @@
 use super::Pass;
 use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
 use crate::{CompilerInput, CompilerOptions};
+use crate::report::PassStats;
 use mchprs_blocks::blocks::ComparatorMode;
 use mchprs_world::World;
 use petgraph::visit::{EdgeRef, NodeIndexable};
@@ -19,7 +20,13 @@ use petgraph::Direction;
 pub struct UnreachableOutput;
 
 impl<W: World> Pass<W> for UnreachableOutput {
-    fn run_pass(&self, graph: &mut CompileGraph, _: &CompilerOptions, _: &CompilerInput<'_, W>) {
+    fn run_pass(
+        &self,
+        graph: &mut CompileGraph,
+        _: &CompilerOptions,
+        _: &CompilerInput<'_, W>,
+        stats: &mut PassStats,
+    ) {
         for i in 0..graph.node_bound() {
             let idx = NodeIdx::new(i);
             if !graph.contains_node(idx) {
@@ -67,11 +74,16 @@ impl<W: World> Pass<W> for UnreachableOutput {
             while let Some((edge_idx, _)) = outgoing.next(graph) {
                 if graph[edge_idx].ss >= max_output {
                     graph.remove_edge(edge_idx);
+                    stats.add("outputs pruned", 1);
                 }
             }
         }
     }
 
+    fn id(&self) -> &'static str {
+        "unreachable-output"
+    }
+
     fn status_message(&self) -> &'static str {
         "Pruning unreachable comparator outputs"
     }
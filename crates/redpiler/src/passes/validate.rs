@@ -0,0 +1,128 @@
+//! # [`Validate`]
+//!
+//! Checks the invariants the backends rely on after every pass, so that a broken graph is
+//! reported with the pass that broke it instead of panicking somewhere in a backend.
+//! It runs with `--validate` and always in debug builds.
+//!
+//! Some invariants only hold once the pass establishing them ran: weights are below 15
+//! after `clamp-weights`, there are no duplicate links after `dedup-links` and comparators
+//! have a state set after `discrete-comps`.
+
+use super::discrete_comps::required_states;
+use crate::compile_graph::{CompileGraph, LinkType, NodeIdx, NodeType};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use rustc_hash::FxHashSet;
+use std::fmt;
+
+/// A broken invariant at a node
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Violation {
+    pub node: NodeIdx,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {}: {}", self.node.index(), self.message)
+    }
+}
+
+/// Remembers the passes that ran and the violations already reported
+#[derive(Default)]
+pub struct Validate {
+    ran: Vec<&'static str>,
+    seen: FxHashSet<Violation>,
+}
+
+impl Validate {
+    /// Checks the graph after `pass` ran and returns the violations it introduced
+    pub fn after_pass(&mut self, pass: &'static str, graph: &CompileGraph) -> Vec<Violation> {
+        self.ran.push(pass);
+        let violations = check(graph, &self.ran);
+        let new = violations
+            .iter()
+            .filter(|violation| !self.seen.contains(violation))
+            .cloned()
+            .collect();
+        self.seen = violations.into_iter().collect();
+        new
+    }
+}
+
+/// Checks `graph` for the invariants established by the passes in `ran`
+pub fn check(graph: &CompileGraph, ran: &[&str]) -> Vec<Violation> {
+    let clamped = ran.contains(&"clamp-weights");
+    let deduped = ran.contains(&"dedup-links");
+    let discrete = ran.contains(&"discrete-comps");
+
+    let mut violations = Vec::new();
+    for idx in graph.node_indices() {
+        let node = &graph[idx];
+        let mut report = |message: String| {
+            let message = match (node.block, node.annotations.label()) {
+                (Some((pos, _)), Some(label)) => format!("{} at {} \"{}\"", message, pos, label),
+                (Some((pos, _)), None) => format!("{} at {}", message, pos),
+                (None, _) => message,
+            };
+            violations.push(Violation { node: idx, message });
+        };
+
+        if (node.is_input || node.is_output) && node.block.is_none() {
+            report(format!("{} is an input or output without a block", type_name(&node.ty)));
+        }
+
+        let mut links = FxHashSet::default();
+        for edge in graph.edges_directed(idx, Direction::Incoming) {
+            let link = edge.weight();
+            let source = edge.source().index();
+            if clamped && link.ss >= 15 {
+                report(format!("link from node {} has weight {}", source, link.ss));
+            }
+            if link.ty == LinkType::Side
+                && !matches!(node.ty, NodeType::Repeater { .. } | NodeType::Comparator { .. })
+            {
+                report(format!("{} has a side link from node {}", type_name(&node.ty), source));
+            }
+            if deduped && !links.insert((source, link.ty)) {
+                report(format!("duplicate {:?} link from node {}", link.ty, source));
+            }
+        }
+
+        if let NodeType::Comparator { states, .. } = node.ty {
+            match states {
+                Some(_) if !discrete => {
+                    report("comparator has a state set before discrete-comps".to_string())
+                }
+                None if discrete => report("comparator has no state set".to_string()),
+                Some(states) => {
+                    let missing = required_states(graph, idx) & !states;
+                    if missing != 0 {
+                        report(format!("comparator state set is missing {:#06x}", missing));
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+    violations
+}
+
+fn type_name(ty: &NodeType) -> &'static str {
+    match ty {
+        NodeType::Repeater { .. } => "repeater",
+        NodeType::Torch => "torch",
+        NodeType::Comparator { .. } => "comparator",
+        NodeType::Lamp => "lamp",
+        NodeType::Button => "button",
+        NodeType::Lever => "lever",
+        NodeType::PressurePlate => "pressure plate",
+        NodeType::Trapdoor => "trapdoor",
+        NodeType::Wire => "wire",
+        NodeType::Constant => "constant",
+        NodeType::NoteBlock { .. } => "note block",
+        NodeType::Delay { .. } => "delay",
+        NodeType::Logic { .. } => "logic",
+        NodeType::Observer => "observer",
+    }
+}
@@ -1,8 +1,39 @@
-use bincode::{BincodeRead, Result};
+//! Standalone format of an exported graph: a [`Header`] followed by the nodes, both bincode.
+
+use bincode::{ErrorKind, Result};
 use serde::{Deserialize, Serialize};
 
 pub type NodeId = usize;
 
+pub const MAGIC: [u8; 4] = *b"RPGR";
+/// Bumped whenever the encoding of [`Node`] changes
+pub const VERSION: u32 = 6;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Header {
+    pub magic: [u8; 4],
+    pub version: u32,
+}
+
+impl Header {
+    pub fn current() -> Header {
+        Header { magic: MAGIC, version: VERSION }
+    }
+
+    fn check(self) -> Result<()> {
+        if self.magic != MAGIC {
+            return Err(Box::new(ErrorKind::Custom("not a redpiler graph, or exported before it was versioned".to_string())));
+        }
+        if self.version != VERSION {
+            return Err(Box::new(ErrorKind::Custom(format!(
+                "redpiler graph version {} is not supported, expected {}",
+                self.version, VERSION
+            ))));
+        }
+        Ok(())
+    }
+}
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize, Hash)]
 pub struct BlockPos {
     pub x: i32,
@@ -27,9 +58,11 @@ pub struct Link {
     pub ty: LinkType,
     pub weight: u8,
     pub to: NodeId,
+    /// Input of the logic node the link goes into
+    pub logic_input: Option<u8>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub enum NodeType {
     Repeater(u8),
     Torch,
@@ -41,7 +74,25 @@ pub enum NodeType {
     Trapdoor,
     Wire,
     Constant,
-    NoteBlock,
+    NoteBlock { instrument: u32, note: u32 },
+    /// Collapsed repeater chain as the delay, `facing_diode` and block of every repeater,
+    /// see `compile_graph::NodeType::Delay`
+    Delay { stages: Vec<(u8, bool, Option<(BlockPos, u32)>)> },
+    /// Merged torches, input `i` is the link with `logic_input` `i`. See `compile_graph::NodeType::Logic`.
+    Logic { gates: Vec<LogicGate> },
+    Observer,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct LogicGate {
+    pub sources: Vec<LogicSource>,
+    pub powered: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LogicSource {
+    Input(u8),
+    Gate(u8),
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -51,6 +102,15 @@ pub struct NodeState {
     pub output_strength: u8,
 }
 
+/// Set by signs, see `compile_graph::Annotations`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Annotations {
+    pub name: Option<String>,
+    pub bus: Option<(String, u32)>,
+    pub probe: bool,
+    pub keep: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Node {
     pub ty: NodeType,
@@ -60,30 +120,39 @@ pub struct Node {
 
     pub facing_diode: bool,
     pub comparator_far_input: Option<u8>,
+    /// Reachable output strengths of a comparator, see the discrete comparator pass
+    pub comparator_states: Option<u16>,
+    pub is_input: bool,
+    pub is_output: bool,
+    pub annotations: Annotations,
 
     pub inputs: Vec<Link>,
     pub updates: Vec<NodeId>,
 }
 
 pub fn serialize(nodes: &[Node]) -> Result<Vec<u8>> {
-    bincode::serialize(nodes)
+    let mut bytes = Vec::new();
+    serialize_into(&mut bytes, nodes)?;
+    Ok(bytes)
 }
 
-pub fn serialize_into<W>(writer: W, value: &[Node]) -> Result<()>
+pub fn serialize_into<W>(mut writer: W, value: &[Node]) -> Result<()>
 where
     W: std::io::Write,
 {
+    bincode::serialize_into(&mut writer, &Header::current())?;
     bincode::serialize_into(writer, value)
 }
 
 pub fn deserialize(bytes: &[u8]) -> Result<Vec<Node>> {
-    bincode::deserialize(bytes)
+    deserialize_from(bytes)
 }
 
-pub fn deserialize_from<'a, R>(reader: R) -> Result<Vec<Node>>
+pub fn deserialize_from<R>(mut reader: R) -> Result<Vec<Node>>
 where
-    R: BincodeRead<'a>,
+    R: std::io::Read,
 {
+    let header: Header = bincode::deserialize_from(&mut reader)?;
+    header.check()?;
     bincode::deserialize_from(reader)
 }
-
@@ -0,0 +1,149 @@
+//! Statistics collected while compiling a build, see [`CompileReport`]
+
+use std::fmt;
+use std::time::Duration;
+
+/// Violations listed by [`CompileReport::summary`], the rest is only counted
+const MAX_VIOLATION_LINES: usize = 8;
+
+/// Counters a pass records while running, e.g. the number of links it removed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PassStats {
+    pub counters: Vec<(&'static str, usize)>,
+}
+
+impl PassStats {
+    /// Adds `n` to the counter `name`, creating it if needed
+    pub fn add(&mut self, name: &'static str, n: usize) {
+        match self.counters.iter_mut().find(|(counter, _)| *counter == name) {
+            Some((_, value)) => *value += n,
+            None => self.counters.push((name, n)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.counters
+            .iter()
+            .find(|(counter, _)| *counter == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// What a single pass did to the graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassReport {
+    pub id: &'static str,
+    pub duration: Duration,
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub edges_before: usize,
+    pub edges_after: usize,
+    pub stats: PassStats,
+    /// Graph invariants the pass broke, see [`crate::passes::validate`]
+    pub violations: Vec<String>,
+}
+
+impl PassReport {
+    pub fn node_delta(&self) -> isize {
+        self.nodes_after as isize - self.nodes_before as isize
+    }
+
+    pub fn edge_delta(&self) -> isize {
+        self.edges_after as isize - self.edges_before as isize
+    }
+}
+
+impl fmt::Display for PassReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.2?}, nodes {:+}, edges {:+}",
+            self.id,
+            self.duration,
+            self.node_delta(),
+            self.edge_delta()
+        )?;
+        for (name, value) in &self.stats.counters {
+            write!(f, ", {} {}", value, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Timings and graph sizes of a compile, pass by pass
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompileReport {
+    /// The passes that ran, in order
+    pub passes: Vec<PassReport>,
+    /// Time spent by the backend after the passes, e.g. assembling Verilog
+    pub backend: Duration,
+    pub node_count: usize,
+    pub edge_count: usize,
+}
+
+impl CompileReport {
+    pub fn pass(&self, id: &str) -> Option<&PassReport> {
+        self.passes.iter().find(|pass| pass.id == id)
+    }
+
+    /// Time spent in the passes
+    pub fn passes_duration(&self) -> Duration {
+        self.passes.iter().map(|pass| pass.duration).sum()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.passes_duration() + self.backend
+    }
+
+    /// Invariants broken by any pass, with the pass that broke them
+    pub fn violations(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.passes
+            .iter()
+            .flat_map(|pass| pass.violations.iter().map(move |v| (pass.id, v.as_str())))
+    }
+
+    /// One line per pass that changed the graph or counted something, plus a total.
+    /// Passes that did nothing are left out to keep the chat short.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Compiled {} nodes and {} edges in {:.2?} (passes {:.2?}, backend {:.2?})",
+            self.node_count,
+            self.edge_count,
+            self.total(),
+            self.passes_duration(),
+            self.backend
+        )];
+        lines.extend(
+            self.passes
+                .iter()
+                .filter(|pass| {
+                    pass.node_delta() != 0
+                        || pass.edge_delta() != 0
+                        || pass.stats.counters.iter().any(|(_, value)| *value != 0)
+                })
+                .map(|pass| format!("  {}", pass)),
+        );
+        let violations = self.violations().count();
+        if violations > 0 {
+            lines.push(format!("{} broken graph invariants:", violations));
+            lines.extend(
+                self.violations()
+                    .take(MAX_VIOLATION_LINES)
+                    .map(|(pass, violation)| format!("  {} broke {}", pass, violation)),
+            );
+            if violations > MAX_VIOLATION_LINES {
+                lines.push(format!("  and {} more", violations - MAX_VIOLATION_LINES));
+            }
+        }
+        lines
+    }
+}
+
+impl fmt::Display for CompileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.summary() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
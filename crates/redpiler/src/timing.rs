@@ -0,0 +1,182 @@
+//! Static timing of a [`CompileGraph`]: how many ticks a change of one node takes to reach
+//! another, see [`latency`].
+//!
+//! Every node delays the changes of its inputs by a range of ticks, see [`node_delay`]. The
+//! fastest path follows the shortest delays. The slowest one is only bounded without loops,
+//! so loops are cut where a depth first search from the start runs into them and are gone
+//! around at most once. Paths through a repeater's lock are timed like through its input, but
+//! only change the end if the repeater's input changed while it was locked, see [`is_lock`].
+
+use crate::compile_graph::{CompileGraph, CompileLink, LinkType, NodeIdx, NodeType};
+use crate::logic;
+use itertools::Itertools;
+use petgraph::algo::dijkstra;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// How long a change of one node takes to reach another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// Fewest ticks until the end can change
+    pub min: u32,
+    /// Most ticks until the end can change
+    pub max: u32,
+    /// The slowest path, from the start to the end
+    pub critical_path: Vec<NodeIdx>,
+    /// Whether a loop between the two was cut to find the slowest path
+    pub loops: bool,
+    /// Whether the slowest path goes through a repeater's lock, so it only changes the end
+    /// if that repeater's input differs from its output when it unlocks
+    pub lock_gated: bool,
+}
+
+/// Ticks from a change at the inputs of `node` until its output changes, as `(min, max)`.
+/// For a change at a repeater's lock, this is the time it takes to follow its input once
+/// unlocked, see [`is_lock`].
+pub fn node_delay(graph: &CompileGraph, node: NodeIdx) -> (u32, u32) {
+    let delay = match &graph[node].ty {
+        NodeType::Repeater { delay, .. } => *delay as u32,
+        NodeType::Delay { stages } => stages.iter().map(|stage| stage.delay as u32).sum(),
+        NodeType::Logic { gates } => logic::depth(gates) as u32,
+        NodeType::Torch | NodeType::Comparator { .. } => 1,
+        // The pulse starts two ticks after the change and ends two ticks later
+        NodeType::Observer => return (2, 4),
+        // Lamps light up right away but take two ticks to turn off
+        NodeType::Lamp => return (0, 2),
+        NodeType::Lever
+        | NodeType::Button
+        | NodeType::PressurePlate
+        | NodeType::Trapdoor
+        | NodeType::Wire
+        | NodeType::Constant
+        | NodeType::NoteBlock { .. } => 0,
+    };
+    (delay, delay)
+}
+
+/// Whether `link` into `target` locks a repeater. Locking is instant and holds the output,
+/// so a change there never reaches the output by itself. Unlocking lets the repeater follow
+/// its input after its delay, which only changes the output if the input changed meanwhile.
+/// Side links of comparators are ordinary inputs.
+pub fn is_lock(graph: &CompileGraph, target: NodeIdx, link: &CompileLink) -> bool {
+    link.ty == LinkType::Side && matches!(graph[target].ty, NodeType::Repeater { .. })
+}
+
+/// The timing from `from` to `to`, if a change of `from` can reach `to` at all
+pub fn latency(graph: &CompileGraph, from: NodeIdx, to: NodeIdx) -> Option<Timing> {
+    Search::new(graph, from).timing(to)
+}
+
+/// The timing from every input to every output it reaches, slowest first
+pub fn all_latencies(graph: &CompileGraph) -> Vec<(NodeIdx, NodeIdx, Timing)> {
+    let outputs = graph.node_indices().filter(|&idx| graph[idx].is_output).collect_vec();
+    let mut latencies = Vec::new();
+    for from in graph.node_indices().filter(|&idx| graph[idx].is_input) {
+        let search = Search::new(graph, from);
+        for &to in &outputs {
+            if let Some(timing) = search.timing(to) {
+                latencies.push((from, to, timing));
+            }
+        }
+    }
+    latencies.sort_by_key(|(from, to, timing)| (std::cmp::Reverse(timing.max), *from, *to));
+    latencies
+}
+
+/// Block positions of `path`, nodes without a block by their index
+pub fn describe_path(graph: &CompileGraph, path: &[NodeIdx]) -> String {
+    path.iter()
+        .map(|&idx| match graph[idx].block {
+            Some((pos, _)) => pos.to_string(),
+            None => format!("node {}", idx.index()),
+        })
+        .join(" -> ")
+}
+
+/// The fastest and slowest paths from one node to every node it reaches
+struct Search {
+    min: FxHashMap<NodeIdx, u32>,
+    max: FxHashMap<NodeIdx, u32>,
+    /// The node before each one on its slowest path, and whether the link from it is a lock
+    slowest_from: FxHashMap<NodeIdx, (NodeIdx, bool)>,
+    /// Nodes reached from a loop, which was cut on their slowest path
+    after_loop: FxHashSet<NodeIdx>,
+}
+
+impl Search {
+    fn new(graph: &CompileGraph, from: NodeIdx) -> Search {
+        let min = dijkstra(graph, from, None, |edge| node_delay(graph, edge.target()).0)
+            .into_iter()
+            .collect();
+
+        // Links to a node still on the stack close a loop. Everything else forms a DAG, its
+        // topological order is the reverse of the order the nodes are finished in.
+        let mut back_edges = FxHashSet::default();
+        let mut finished = Vec::new();
+        let mut on_stack = FxHashSet::default();
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![(from, graph.neighbors_directed(from, Direction::Outgoing).collect_vec())];
+        visited.insert(from);
+        on_stack.insert(from);
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match next.pop() {
+                Some(target) if on_stack.contains(&target) => {
+                    back_edges.insert((node, target));
+                }
+                Some(target) if visited.insert(target) => {
+                    on_stack.insert(target);
+                    stack.push((target, graph.neighbors_directed(target, Direction::Outgoing).collect_vec()));
+                }
+                Some(_) => {}
+                None => {
+                    on_stack.remove(&node);
+                    finished.push(node);
+                    stack.pop();
+                }
+            }
+        }
+
+        let mut max = FxHashMap::default();
+        let mut slowest_from = FxHashMap::default();
+        max.insert(from, 0);
+        for &node in finished.iter().rev() {
+            let ticks = max[&node];
+            for edge in graph.edges_directed(node, Direction::Outgoing) {
+                let target = edge.target();
+                if back_edges.contains(&(node, target)) {
+                    continue;
+                }
+                let arrival = ticks + node_delay(graph, target).1;
+                if max.get(&target).map_or(true, |&best| arrival > best) {
+                    max.insert(target, arrival);
+                    slowest_from.insert(target, (node, is_lock(graph, target, edge.weight())));
+                }
+            }
+        }
+
+        // The start of a loop reaches its end, so everything it reaches is after the loop
+        let mut after_loop = FxHashSet::default();
+        let mut to_visit = back_edges.iter().map(|&(_, target)| target).collect_vec();
+        while let Some(node) = to_visit.pop() {
+            if after_loop.insert(node) {
+                to_visit.extend(graph.neighbors_directed(node, Direction::Outgoing));
+            }
+        }
+
+        Search { min, max, slowest_from, after_loop }
+    }
+
+    fn timing(&self, to: NodeIdx) -> Option<Timing> {
+        let (&min, &max) = (self.min.get(&to)?, self.max.get(&to)?);
+        let mut critical_path = vec![to];
+        let mut lock_gated = false;
+        while let Some(&(before, lock)) = self.slowest_from.get(critical_path.last().unwrap()) {
+            critical_path.push(before);
+            lock_gated |= lock;
+        }
+        critical_path.reverse();
+        Some(Timing { min, max, critical_path, loops: self.after_loop.contains(&to), lock_gated })
+    }
+}
@@ -4,6 +4,7 @@
 
 pub mod comparator;
 pub mod noteblock;
+pub mod observer;
 pub mod repeater;
 pub mod wire;
 
@@ -20,6 +21,16 @@ pub fn bool_to_ss(b: bool) -> u8 {
     }
 }
 
+/// Sets the block at `pos` and lets the observers facing it know if it changed.
+/// Returns true if the block was changed.
+pub fn set_block(world: &mut impl World, pos: BlockPos, block: Block) -> bool {
+    let changed = world.set_block(pos, block);
+    if changed {
+        observer::on_observed_change(world, pos);
+    }
+    changed
+}
+
 fn get_weak_power(
     block: Block,
     world: &impl World,
@@ -34,6 +45,10 @@ fn get_weak_power(
         Block::StonePressurePlate { powered: true } => 15,
         Block::Lever { lever } if lever.powered => 15,
         Block::StoneButton { button } if button.powered => 15,
+        Block::Observer {
+            facing,
+            powered: true,
+        } if facing.block_face() == side => 15,
         Block::RedstoneRepeater { repeater }
             if repeater.facing.block_face() == side && repeater.powered =>
         {
@@ -93,6 +108,7 @@ fn get_strong_power(
         ),
         Block::StonePressurePlate { powered: true } if side == BlockFace::Top => 15,
         Block::RedstoneWire { .. } => get_weak_power(block, world, pos, side, dust_power),
+        Block::Observer { .. } => get_weak_power(block, world, pos, side, dust_power),
         Block::RedstoneRepeater { .. } => get_weak_power(block, world, pos, side, dust_power),
         Block::RedstoneComparator { .. } => get_weak_power(block, world, pos, side, dust_power),
         _ => 0,
@@ -143,7 +159,7 @@ fn get_redstone_power_no_dust(
 pub fn torch_should_be_off(world: &impl World, pos: BlockPos) -> bool {
     let bottom_pos = pos.offset(BlockFace::Bottom);
     let bottom_block = world.get_block(bottom_pos);
-    get_redstone_power(bottom_block, world, bottom_pos, BlockFace::Top) > 0
+    get_redstone_power(bottom_block, world, bottom_pos, BlockFace::Bottom) > 0
 }
 
 pub fn wall_torch_should_be_off(
@@ -209,7 +225,7 @@ pub fn update(block: Block, world: &mut impl World, pos: BlockPos) {
             if lit && !should_be_lit {
                 world.schedule_tick(pos, 2, TickPriority::Normal);
             } else if !lit && should_be_lit {
-                world.set_block(pos, Block::RedstoneLamp { lit: true });
+                set_block(world, pos, Block::RedstoneLamp { lit: true });
             }
         }
         Block::IronTrapdoor {
@@ -224,7 +240,7 @@ pub fn update(block: Block, world: &mut impl World, pos: BlockPos) {
                     half,
                     powered: should_be_powered,
                 };
-                world.set_block(pos, new_block);
+                set_block(world, pos, new_block);
             }
         }
         Block::NoteBlock {
@@ -250,7 +266,7 @@ pub fn update(block: Block, world: &mut impl World, pos: BlockPos) {
                 if should_be_powered && noteblock::is_noteblock_unblocked(world, pos) {
                     noteblock::play_note(world, pos, instrument, note);
                 }
-                world.set_block(pos, new_block);
+                set_block(world, pos, new_block);
             }
         }
         _ => {}
@@ -268,33 +284,36 @@ pub fn tick(block: Block, world: &mut impl World, pos: BlockPos) {
         Block::RedstoneTorch { lit } => {
             let should_be_off = torch_should_be_off(world, pos);
             if lit && should_be_off {
-                world.set_block(pos, Block::RedstoneTorch { lit: false });
+                set_block(world, pos, Block::RedstoneTorch { lit: false });
                 update_surrounding_blocks(world, pos);
             } else if !lit && !should_be_off {
-                world.set_block(pos, Block::RedstoneTorch { lit: true });
+                set_block(world, pos, Block::RedstoneTorch { lit: true });
                 update_surrounding_blocks(world, pos);
             }
         }
         Block::RedstoneWallTorch { lit, facing } => {
             let should_be_off = wall_torch_should_be_off(world, pos, facing);
             if lit && should_be_off {
-                world.set_block(pos, Block::RedstoneWallTorch { lit: false, facing });
+                set_block(world, pos, Block::RedstoneWallTorch { lit: false, facing });
                 update_surrounding_blocks(world, pos);
             } else if !lit && !should_be_off {
-                world.set_block(pos, Block::RedstoneWallTorch { lit: true, facing });
+                set_block(world, pos, Block::RedstoneWallTorch { lit: true, facing });
                 update_surrounding_blocks(world, pos);
             }
         }
         Block::RedstoneLamp { lit } => {
             let should_be_lit = redstone_lamp_should_be_lit(world, pos);
             if lit && !should_be_lit {
-                world.set_block(pos, Block::RedstoneLamp { lit: false });
+                set_block(world, pos, Block::RedstoneLamp { lit: false });
             }
         }
+        Block::Observer { facing, powered } => {
+            observer::tick(facing, powered, world, pos);
+        }
         Block::StoneButton { mut button } => {
             if button.powered {
                 button.powered = false;
-                world.set_block(pos, Block::StoneButton { button });
+                set_block(world, pos, Block::StoneButton { button });
                 update_surrounding_blocks(world, pos);
                 match button.face {
                     ButtonFace::Ceiling => {
@@ -361,19 +380,19 @@ pub fn on_use(block: Block, world: &mut impl World, pos: BlockPos) -> bool {
             if repeater.delay > 4 {
                 repeater.delay -= 4;
             }
-            world.set_block(pos, Block::RedstoneRepeater { repeater });
+            set_block(world, pos, Block::RedstoneRepeater { repeater });
             true
         }
         Block::RedstoneComparator { comparator } => {
             let mut comparator = comparator;
             comparator.mode = comparator.mode.toggle();
             comparator::tick(comparator, world, pos);
-            world.set_block(pos, Block::RedstoneComparator { comparator });
+            set_block(world, pos, Block::RedstoneComparator { comparator });
             true
         }
         Block::Lever { mut lever } => {
             lever.powered = !lever.powered;
-            world.set_block(pos, Block::Lever { lever });
+            set_block(world, pos, Block::Lever { lever });
             update_surrounding_blocks(world, pos);
             match lever.face {
                 LeverFace::Ceiling => {
@@ -392,7 +411,7 @@ pub fn on_use(block: Block, world: &mut impl World, pos: BlockPos) -> bool {
         Block::StoneButton { mut button } => {
             if !button.powered {
                 button.powered = true;
-                world.set_block(pos, Block::StoneButton { button });
+                set_block(world, pos, Block::StoneButton { button });
                 world.schedule_tick(pos, 10, TickPriority::Normal);
                 update_surrounding_blocks(world, pos);
                 match button.face {
@@ -420,7 +439,7 @@ pub fn on_use(block: Block, world: &mut impl World, pos: BlockPos) -> bool {
                 new_wire.power = wire.power;
                 new_wire = wire::get_regulated_sides(new_wire, world, pos);
                 if wire != new_wire {
-                    world.set_block(pos, Block::RedstoneWire { wire: new_wire });
+                    set_block(world, pos, Block::RedstoneWire { wire: new_wire });
                     update_wire_neighbors(world, pos);
                     return true;
                 }
@@ -431,7 +450,8 @@ pub fn on_use(block: Block, world: &mut impl World, pos: BlockPos) -> bool {
             let note = (note + 1) % 25;
             let instrument = noteblock::get_noteblock_instrument(world, pos);
 
-            world.set_block(
+            set_block(
+                world,
                 pos,
                 Block::NoteBlock {
                     instrument,
@@ -0,0 +1,42 @@
+//! Observers send a two tick pulse out of their back when the block they face changes.
+
+use mchprs_blocks::blocks::Block;
+use mchprs_blocks::{BlockFace, BlockFacing, BlockPos};
+use mchprs_world::{TickPriority, World};
+
+pub fn tick(facing: BlockFacing, powered: bool, world: &mut impl World, pos: BlockPos) {
+    super::set_block(
+        world,
+        pos,
+        Block::Observer {
+            facing,
+            powered: !powered,
+        },
+    );
+    if !powered {
+        world.schedule_tick(pos, 2, TickPriority::Normal);
+    }
+
+    let back_pos = pos.offset(facing.opposite().block_face());
+    let back_block = world.get_block(back_pos);
+    super::update(back_block, world, back_pos);
+    super::update_surrounding_blocks(world, back_pos);
+}
+
+/// Starts the pulse of the observers facing `pos`, called when the block at `pos` changed
+pub fn on_observed_change(world: &mut impl World, pos: BlockPos) {
+    for face in &BlockFace::values() {
+        let observer_pos = pos.offset(*face);
+        if let Block::Observer {
+            facing,
+            powered: false,
+        } = world.get_block(observer_pos)
+        {
+            if observer_pos.offset(facing.block_face()) == pos
+                && !world.pending_tick_at(observer_pos)
+            {
+                world.schedule_tick(observer_pos, 2, TickPriority::Normal);
+            }
+        }
+    }
+}
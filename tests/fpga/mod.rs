@@ -0,0 +1,75 @@
+use mchprs_backend::fpga::compiler::{DeviceConfig, DEFAULT_CHAIN_INDEX};
+use mchprs_backend::fpga::interface::{Interface, MAX_RECONNECT_INTERVAL, RECONNECT_INTERVAL};
+use mchprs_backend::fpga::linker::Linker;
+use mchprs_blocks::blocks::{Block, Lever, LeverFace, StoneButton};
+use mchprs_blocks::{BlockDirection, BlockPos};
+use std::path::Path;
+
+fn lever(powered: bool) -> Block {
+    Block::Lever { lever: Lever { face: LeverFace::Floor, facing: BlockDirection::North, powered } }
+}
+
+pub fn input_states() {
+    let mut link = Linker::default();
+    link.add_block(lever(true), BlockPos::new(0, 1, 0));
+    link.add_block(Block::RedstoneLamp { lit: true }, BlockPos::new(1, 1, 0));
+    link.add_block(Block::StoneButton { button: StoneButton::default() }, BlockPos::new(2, 1, 0));
+    link.add_boundary(7, true);
+    link.add_block(lever(false), BlockPos::new(3, 1, 0));
+    assert_eq!(link.input_bits, 4);
+    assert_eq!(link.input_states(), vec![(0, 1), (1, 0), (2, 0), (3, 0)]);
+
+    assert_eq!(link.toggle_input(BlockPos::new(2, 1, 0)), (1, 0, 1));
+    assert_eq!(link.set_boundary_input(7, true), Some(2));
+    assert_eq!(link.set_boundary_input(7, true), None);
+    assert_eq!(link.toggle_input(BlockPos::new(0, 1, 0)), (0, 0, 0));
+    assert_eq!(link.input_states(), vec![(0, 0), (1, 1), (2, 1), (3, 0)]);
+}
+
+pub fn reconnect_backoff() {
+    let mut fpga = Interface::default();
+    assert!(fpga.should_reconnect());
+    assert_eq!(fpga.reconnect_delay(), RECONNECT_INTERVAL);
+
+    fpga.reconnected(false);
+    assert!(!fpga.should_reconnect());
+    assert_eq!(fpga.reconnect_delay(), RECONNECT_INTERVAL * 2);
+    fpga.reconnected(false);
+    assert_eq!(fpga.reconnect_delay(), RECONNECT_INTERVAL * 4);
+    for _ in 0..40 {
+        fpga.reconnected(false);
+    }
+    assert_eq!(fpga.reconnect_delay(), MAX_RECONNECT_INTERVAL);
+
+    fpga.reconnected(true);
+    assert_eq!(fpga.reconnect_delay(), RECONNECT_INTERVAL);
+}
+
+fn device_config(extra: &str) -> DeviceConfig {
+    let json = format!(
+        r#"{{"name": "test", "device": "5CSEMA5F31C6", "family": "Cyclone V", "command_com": "auto",
+        {} "pin_assignments": {{"i_clk": "AF14", "i_rx": "AJ17", "o_tx": "AJ16"}}}}"#,
+        extra
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+pub fn programmer_config() {
+    let config = device_config(r#""programmer": "mock","#);
+    assert_eq!(config.cable(), None);
+    assert_eq!(config.chain_index(), DEFAULT_CHAIN_INDEX);
+    let results = config.program(Path::new("FPGA/bin/test"));
+    assert!(results.success, "{}", results.message);
+
+    let config = device_config(r#""programmer": "openfpgaloader", "cable": "de0nano", "chain_index": 1,"#);
+    assert_eq!(config.cable(), Some("de0nano"));
+    assert_eq!(config.chain_index(), 1);
+    assert_eq!(config.quartus_tool("quartus_pgm"), Path::new("quartus_pgm"));
+
+    let config = device_config(r#""program_com": "USB1", "quartus_dir": "/opt/quartus/bin","#);
+    assert_eq!(config.cable(), Some("USB1"));
+    assert_eq!(config.quartus_tool("quartus_pgm"), Path::new("/opt/quartus/bin/quartus_pgm"));
+
+    let config = device_config(r#""program_com": "USB1", "cable": "DE-SoC [USB-1]","#);
+    assert_eq!(config.cable(), Some("DE-SoC [USB-1]"));
+}
@@ -0,0 +1,298 @@
+//! Differential testing of the generated verilog against `DirectBackend`.
+//!
+//! Both sides get the same `CompileGraph`. Every tick the FPGA outputs in the
+//! simulator are compared to what the matching lamps and trapdoors receive in
+//! the direct backend. A diverging input sequence is shrunk before it is reported.
+
+use std::{fs, path::{Path, PathBuf}, sync::Mutex};
+use mchprs_backend::{direct::DirectBackend, fpga::FPGABackend, sim_test::Simulator, JITBackend};
+use mchprs_blocks::BlockPos;
+use mchprs_redpiler::{compile_graph::CompileGraph, passes::make_default_pass_manager, CompilerInput, CompilerOptions};
+
+use mchprs_core::plot::worldedit::schematic::SchematicWorld;
+use super::{case_dir, load_suite, load_world, sim_options};
+
+/// Seed used when `ROC_DIFF_SEED` is not set, so failures are reproducible
+const DEFAULT_SEED: u64 = 0x2b99_2ddf_a232_49d6;
+/// Random sequences per case when `ROC_DIFF_RUNS` is not set
+const DEFAULT_RUNS: usize = 8;
+const RANDOM_TICKS: usize = 24;
+
+/// Input states for every tick of a run, indexed like `Linker::inputs`
+type Sequence = Vec<Vec<bool>>;
+/// Output states for every tick of a run, indexed like `Linker::outputs`
+type Trace = Vec<Vec<bool>>;
+
+/// Runs the procedures of `tests/roc/sim_tests/<case>` and a batch of random
+/// input sequences through both backends. Skips if no simulator is installed.
+pub fn diff_case(case: &str) {
+    let Some(sim) = Simulator::detect() else {
+        eprintln!("skipping diff test {case}: neither iverilog nor verilator was found");
+        return;
+    };
+
+    let path = case_dir(case);
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_diff").join(case);
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let options = sim_options();
+    let (world, bounds) = load_world(&path);
+    let world = Mutex::new(world);
+    let input = CompilerInput { world: &world, bounds };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&options, &input);
+
+    // In sim test mode the fpga backend writes `<name>/roc.sv`
+    let mut fpga = FPGABackend::default();
+    fpga.compile(graph.clone(), Vec::new(), "0,0".to_string(), dir.to_str().unwrap().to_string(), None, &options);
+    if fpga.link.outputs.is_empty() {
+        return;
+    }
+
+    let harness = Harness {
+        sim,
+        dir,
+        graph,
+        options,
+        inputs: fpga.link.inputs.iter().map(|i| i.pos).collect(),
+        outputs: fpga.link.outputs.iter().map(|o| o.pos).collect(),
+    };
+
+    let (seed, runs) = settings();
+    let mut rng = XorShift::new(seed ^ fnv1a(case));
+    let mut sequences = harness.procedures(&path);
+    for _ in 0..runs {
+        let seq = (0..RANDOM_TICKS)
+            .map(|_| harness.inputs.iter().map(|_| rng.next() & 1 == 1).collect())
+            .collect();
+        sequences.push(seq);
+    }
+
+    for seq in sequences {
+        if harness.diverges(&seq).is_some() {
+            let seq = harness.shrink(seq);
+            let tick = harness.diverges(&seq).unwrap();
+            panic!("diff test {} failed with {:?} (seed {:#x}):\n{}", case, sim, seed, harness.report(&seq, tick));
+        }
+    }
+}
+
+fn settings() -> (u64, usize) {
+    let seed = std::env::var("ROC_DIFF_SEED")
+        .ok()
+        .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+        .unwrap_or(DEFAULT_SEED);
+    let runs = std::env::var("ROC_DIFF_RUNS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RUNS);
+    (seed, runs)
+}
+
+struct Harness {
+    sim: Simulator,
+    dir: PathBuf,
+    graph: CompileGraph,
+    options: CompilerOptions,
+    inputs: Vec<BlockPos>,
+    outputs: Vec<BlockPos>,
+}
+
+impl Harness {
+    /// The input states of every procedure in `test.json`
+    fn procedures(&self, path: &Path) -> Vec<Sequence> {
+        let suite = load_suite(path);
+        suite
+            .tests
+            .iter()
+            .map(|test| {
+                let mut state = vec![false; self.inputs.len()];
+                suite
+                    .schedule(test)
+                    .iter()
+                    .map(|tick| {
+                        for (name, on) in &tick.inputs {
+                            let pos = suite.signals[name].pos;
+                            if let Some(i) = self.inputs.iter().position(|p| *p == pos) {
+                                state[i] = *on;
+                            }
+                        }
+                        state.clone()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn run_direct(&self, seq: &Sequence) -> Trace {
+        let mut direct = DirectBackend::default();
+        direct.compile(self.graph.clone(), Vec::new(), "0,0".to_string(), "diff".to_string(), None, &self.options);
+
+        let mut trace = Vec::with_capacity(seq.len());
+        for step in seq {
+            for (pos, &on) in self.inputs.iter().zip(step) {
+                direct.set_input(*pos, on);
+            }
+            trace.push(
+                self.outputs
+                    .iter()
+                    .map(|pos| {
+                        direct
+                            .output_driven(*pos)
+                            .unwrap_or_else(|| panic!("output {} is missing from the direct backend", pos))
+                    })
+                    .collect(),
+            );
+            direct.tick();
+        }
+        trace
+    }
+
+    fn run_sim(&self, seq: &Sequence) -> Trace {
+        write_trace_tb(&self.dir, seq, self.inputs.len(), self.outputs.len());
+        let log = self.sim.run(&self.dir).unwrap_or_else(|e| panic!("{}", e));
+
+        let trace: Trace = log
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("OUT "))
+            .map(|bits| bits.trim().chars().rev().map(|c| c == '1').collect())
+            .collect();
+        if trace.len() != seq.len() {
+            panic!("simulation in {} stopped after {} of {} ticks:\n{}", self.dir.display(), trace.len(), seq.len(), log);
+        }
+        trace
+    }
+
+    /// The first tick where the two backends disagree
+    fn diverges(&self, seq: &Sequence) -> Option<usize> {
+        let direct = self.run_direct(seq);
+        let sim = self.run_sim(seq);
+        direct.iter().zip(&sim).position(|(d, s)| d != s)
+    }
+
+    /// Greedily drops ticks and clears inputs while the backends still disagree
+    fn shrink(&self, mut seq: Sequence) -> Sequence {
+        if let Some(tick) = self.diverges(&seq) {
+            seq.truncate(tick + 1);
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for i in (0..seq.len()).rev() {
+                if seq.len() == 1 {
+                    break;
+                }
+                let mut candidate = seq.clone();
+                candidate.remove(i);
+                if let Some(tick) = self.diverges(&candidate) {
+                    candidate.truncate(tick + 1);
+                    seq = candidate;
+                    changed = true;
+                }
+            }
+
+            for i in 0..seq.len() {
+                for j in 0..self.inputs.len() {
+                    if i >= seq.len() || !seq[i][j] {
+                        continue;
+                    }
+                    let mut candidate = seq.clone();
+                    candidate[i][j] = false;
+                    if let Some(tick) = self.diverges(&candidate) {
+                        candidate.truncate(tick + 1);
+                        seq = candidate;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        seq
+    }
+
+    fn report(&self, seq: &Sequence, tick: usize) -> String {
+        let direct = self.run_direct(seq);
+        let sim = self.run_sim(seq);
+
+        let mut report = format!("  inputs {:?}\n", self.inputs.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+        for (i, step) in seq.iter().enumerate() {
+            let bits: String = step.iter().map(|&b| if b { '1' } else { '0' }).collect();
+            report.push_str(&format!("  {}: {}\n", i, bits));
+        }
+        report.push_str(&format!("  tick {} diverges:\n", tick));
+        for (i, pos) in self.outputs.iter().enumerate() {
+            if direct[tick][i] != sim[tick][i] {
+                report.push_str(&format!("    output {} direct {} verilog {}\n", pos, direct[tick][i] as u8, sim[tick][i] as u8));
+            }
+        }
+        report.push_str(&format!("  testbench left in {}\n", self.dir.display()));
+        report
+    }
+}
+
+/// Writes a testbench that applies `seq` and prints the outputs of every tick
+fn write_trace_tb(dir: &Path, seq: &Sequence, in_cnt: usize, out_cnt: usize) {
+    let mut steps = String::new();
+    for step in seq {
+        let mut bits: String = step.iter().rev().map(|&b| if b { '1' } else { '0' }).collect();
+        if bits.is_empty() {
+            bits.push('0');
+        }
+        steps.push_str(&format!("
+        inputs = {size}'b{bits};
+        #1
+        $display(\"OUT %b\", outputs);
+        tick = ~tick;
+        #1
+        tick = ~tick;
+        #1", size = bits.len()));
+    }
+
+    let tb = format!("module tb;
+
+    reg tick = 0;
+    wire[{out_cnt}-1:0] outputs;
+    reg[{in_cnt}-1:0]   inputs = 0;
+
+    RoC #(
+        .OUTPUTS({out_cnt}),
+        .INPUTS({in_cnt})
+    ) redstone (
+        .tick(tick),
+        .outputs(outputs),
+        .inputs(inputs)
+    );
+
+    initial begin
+        #2
+        {steps}
+        $finish;
+    end
+
+endmodule: tb", in_cnt = in_cnt.max(1));
+
+    fs::write(dir.join("tb.sv"), tb).unwrap();
+}
+
+/// Small deterministic generator, good enough for picking lever states
+pub(super) struct XorShift(u64);
+
+impl XorShift {
+    pub(super) fn new(seed: u64) -> XorShift {
+        XorShift(seed.max(1))
+    }
+
+    pub(super) fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
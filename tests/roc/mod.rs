@@ -1,380 +1,1026 @@
-use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}, process::{Command, ExitCode}, str::FromStr, sync::Mutex};
-use std::sync::mpsc::{self, Receiver, Sender};
-use mchprs_backend::{fpga::{linker::{IntfBlock, Linker}, FPGABackend}, Backend, BackendDispatcher};
-use mchprs_blocks::{block_entities::BlockEntity, BlockPos};
-use mchprs_redpiler::CompilerOptions;
-use mchprs_world::{storage::Chunk, TickEntry, TickPriority, World};
-use mchprs_core::plot::worldedit::{schematic, WorldEditClipboard};
-use serde::Deserialize;
-
-pub fn run_simulations() {
-    let paths = get_test_paths();
-
-    for path_buf in paths {
-        let path = path_buf.as_path();
-        let link = generate_rs(path);
-        let test = parse_test(path);
-        println!("Testing: {}", test.name);
-        for proc in &test.tests {
-            println!("test: {}", proc.description);
-            let in_tbl = test.get_translation_table(IntfType::Input, &link.inputs);
-            let out_tbl = test.get_translation_table(IntfType::Ouptut, &link.outputs);
-            generate_tb(path, proc, in_tbl, out_tbl);
-            assert!(run_sim(path));
-        }
-    }
+use std::{fs, path::{Path, PathBuf}, sync::Mutex};
+use std::process::Command;
+use std::sync::mpsc;
+use mchprs_backend::{formal::{Model, Simulation}, fpga::{generate_verilog, linker::{self, Linker}, netlist}, sim_test::{self, CaseReport, Recorder, Simulator, TestSuite}, Backend, BackendDispatcher, JITBackend};
+use mchprs_blocks::block_entities::{BlockEntity, SignBlockEntity};
+use mchprs_blocks::{BlockDirection, BlockFacing, BlockPos, SignType};
+use mchprs_blocks::blocks::{Block, ComparatorMode, Lever, LeverFace, RedstoneRepeater, RedstoneWire, RedstoneWireSide};
+use mchprs_redpiler::compile_graph::{CompileGraph, CompileLink, CompileNode, NodeState, NodeType};
+use mchprs_redpiler::passes::{same_tick_cycles, validate};
+use mchprs_redpiler::{graph_file, graph_html, passes::make_default_pass_manager, redpiler_graph, report::CompileReport, timing, CompilerInput, CompilerOptions, PassPipeline};
+use mchprs_world::{storage::PalettedBitBuffer, World};
+use mchprs_core::plot::worldedit::schematic::{self, SchematicWorld};
+use mchprs_core::plot::worldedit::WorldEditClipboard;
+use serde_json::json;
+
+pub mod diff;
+
+fn case_dir(case: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roc/sim_tests").join(case)
 }
 
-fn get_test_paths() -> Vec<PathBuf> {
-    let mut list = Vec::new();
+fn load_suite(path: &Path) -> TestSuite {
+    TestSuite::load(&path.join("test.json")).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
 
-    if let Ok(tests) = fs::read_dir("tests/roc/sim_tests") {
-        for dir in tests {
-            if let Ok(dir) = dir {
-                let path = dir.path();
-                if path.is_dir() {
-                    list.push(path);
-                }
-            }
-        }
+/// Panics with every failed procedure of a case
+fn check_reports(kind: &str, suite: &TestSuite, reports: Vec<CaseReport>) {
+    let failures: Vec<String> = reports
+        .iter()
+        .filter(|r| !r.passed())
+        .map(|r| {
+            let lines: Vec<String> = r.failures.iter().map(|f| format!("    {}", f)).collect();
+            format!("  procedure \"{}\"\n{}", r.description, lines.join("\n"))
+        })
+        .collect();
+    if !failures.is_empty() {
+        panic!("{} test {} failed:\n{}", kind, suite.name, failures.join("\n"));
     }
-
-    list
 }
 
-fn run_sim(path: &Path) -> bool {
+/// Simulates the case in `tests/roc/sim_tests/<case>` against every procedure
+/// of its `test.json`. Skips (and passes) if no simulator is installed.
+pub fn run_case(case: &str) {
+    let Some(sim) = Simulator::detect() else {
+        eprintln!("skipping sim test {case}: neither iverilog nor verilator was found");
+        return;
+    };
 
-    let out = Command::new("cmd")
-        .current_dir(path)
-        .args(&["/C", "iverilog -g2005-sv -o sim tb.sv roc.sv ../../../../FPGA/src/redstone/components.sv"])
-        .output()
-        .unwrap();
+    let path = case_dir(case);
+    let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_sim").join(case);
+    _ = fs::remove_dir_all(&out);
+    fs::create_dir_all(&out).unwrap();
 
-    if !out.status.success() {
-        println!("{:?}", String::from_utf8_lossy(&out.stderr));
-    }
+    let link = generate_rs(&path, &out);
+    let suite = load_suite(&path);
+    let reports = suite
+        .tests
+        .iter()
+        .map(|test| sim_test::simulate(sim, &out, &suite, test, &link).unwrap_or_else(|e| panic!("{}", e)))
+        .collect();
+    check_reports(&format!("sim ({:?})", sim), &suite, reports);
+}
+
+/// Runs the same `test.json` against a `DirectBackend` compiled from the schematic
+pub fn run_direct_case(case: &str) {
+    let path = case_dir(case);
+    let suite = load_suite(&path);
+    let reports = suite
+        .tests
+        .iter()
+        .map(|test| {
+            let (world, bounds) = load_world(&path);
+            let mut backend = compile_direct(&Mutex::new(world), bounds);
+            let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+                unreachable!();
+            };
+            suite.run_direct(test, direct)
+        })
+        .collect();
+    check_reports("direct", &suite, reports);
+}
+
+/// Runs `test.json` through `Backend` like `/test run`, reading outputs back from the world
+pub fn run_world_case(case: &str) {
+    let path = case_dir(case);
+    let suite = load_suite(&path);
+    let reports = suite
+        .tests
+        .iter()
+        .map(|test| {
+            let (world, bounds) = load_world(&path);
+            let world = Mutex::new(world);
+            let mut backend = compile_direct(&world, bounds);
+            backend.run().unwrap();
+            let report = suite.run_backend(test, &mut backend, &mut *world.lock().unwrap(), bounds.0);
+            report
+        })
+        .collect();
+    check_reports("world", &suite, reports);
+}
 
-    let out = Command::new("cmd")
-        .current_dir(path)
-        .args(&["/C", "vvp sim"])
-        .output()
-        .unwrap();
+/// Runs the direct tests on a graph that went through the `redpiler_graph` format and back
+pub fn run_graph_case(case: &str) {
+    let path = case_dir(case);
+    let suite = load_suite(&path);
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&CompilerOptions::default(), &input);
 
-    let output = String::from_utf8_lossy(&out.stdout);
-    let lines = output.split(r"\r\n");
-    for line in lines {
-        println!("{line}");
-    }
+    let bytes = redpiler_graph::serialize(&graph_file::to_nodes(&graph)).unwrap();
+    let nodes = redpiler_graph::deserialize(&bytes).unwrap();
+    assert_eq!(graph.node_count(), nodes.len());
+    assert_eq!(graph.edge_count(), nodes.iter().map(|n| n.inputs.len()).sum::<usize>());
 
-    out.status.success()
+    let reports = suite
+        .tests
+        .iter()
+        .map(|test| {
+            let (sender, _receive) = mpsc::channel();
+            let graph = graph_file::from_nodes(&nodes);
+            let mut backend = Backend::from_graph(sender, "graph".to_string(), "0,0".to_string(), None, graph, CompilerOptions::default());
+            let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+                unreachable!();
+            };
+            suite.run_direct(test, direct)
+        })
+        .collect();
+    check_reports("graph", &suite, reports);
 }
 
-fn generate_tb(path: &Path, proc: &Procedure, in_tbl: Vec<usize>, out_tbl: Vec<usize>) {
+/// Graph files with links, updates or instruments that cannot be rebuilt are rejected
+pub fn graph_file_checks() {
+    let path = case_dir("in_out");
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&CompilerOptions::default(), &input);
+    let nodes = graph_file::to_nodes(&graph);
+    graph_file::check_nodes(&nodes).unwrap();
 
-    let mut proc_str = "".to_string();
-    for step in &proc.procedure {
-        proc_str.push_str(&parse_step(step, &in_tbl, &out_tbl));
-    }
+    let mut bad = nodes.clone();
+    bad[0].updates.push(nodes.len());
+    assert!(graph_file::check_nodes(&bad).unwrap_err().contains("updates missing node"));
 
-    let in_cnt = if in_tbl.len() == 0 {1} else {in_tbl.len()};
+    let mut bad = nodes.clone();
+    bad[0].ty = redpiler_graph::NodeType::NoteBlock { instrument: 22, note: 0 };
+    let file = std::env::temp_dir().join(format!("graph_file_checks_{}.bc", std::process::id()));
+    fs::write(&file, redpiler_graph::serialize(&bad).unwrap()).unwrap();
+    let err = graph_file::load(&file).unwrap_err();
+    fs::remove_file(&file).unwrap();
+    assert!(err.contains("unknown instrument 22"), "{}", err);
+}
 
-    let tb = format!("module tb;\n
-    parameter OUTPUTS = {out_cnt};
-    parameter INPUTS  = {in_cnt};
+/// Exports the netlist of the FPGA build and checks its ports against the link file
+pub fn netlist_case(case: &str) {
+    let path = case_dir(case);
+    let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_netlist").join(case);
+    _ = fs::remove_dir_all(&out);
 
-    //test 2
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&sim_options(), &input);
+    let link = Linker::from_graph(&graph);
+    netlist::export(&graph, &out).unwrap();
 
-    reg tick = 0;
-    wire[OUTPUTS-1:0] outputs;
-    reg[INPUTS-1:0]   inputs = 0;
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(out.join("roc.json")).unwrap()).unwrap();
+    let ports = &json["modules"]["RoC"]["ports"];
+    let width = |port: &str| ports[port]["bits"].as_array().map_or(0, |bits| bits.len());
+    assert_eq!(width("inputs"), link.input_bits as usize, "inputs of {}", case);
+    assert_eq!(width("outputs"), link.output_bits as usize, "outputs of {}", case);
+}
 
-    RoC #(
-        .OUTPUTS(OUTPUTS),
-        .INPUTS(INPUTS)
-    ) redstone (
-        .tick(tick),
-        .outputs(outputs),
-        .inputs(inputs)
+/// Reads the netlist of the case back into yosys with the cell library. Ignored
+/// by default as it needs yosys, run with `--ignored`.
+pub fn yosys_case(case: &str) {
+    netlist_case(case);
+    let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_netlist").join(case);
+    let components = fs::canonicalize("FPGA/src/redstone/components.sv").unwrap();
+    let script = format!(
+        "read_verilog -sv {} rs_cells.v; read_json roc.json; hierarchy -top RoC; check -assert",
+        components.display()
     );
+    let result = Command::new("yosys").args(["-q", "-p", &script]).current_dir(&out).output().expect("yosys was not found");
+    assert!(result.status.success(), "yosys rejected the netlist of {}:\n{}", case, String::from_utf8_lossy(&result.stderr));
+}
 
-    initial begin
-        #2
-        {proc}
-    end
+/// Steps the formal model of the FPGA build through every procedure of the case
+pub fn formal_case(case: &str) {
+    let path = case_dir(case);
+    let suite = load_suite(&path);
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&sim_options(), &input);
+    let model = Model::new(&graph).unwrap_or_else(|e| panic!("{}", e));
 
-endmodule: tb",
-    out_cnt = out_tbl.len(),
-    in_cnt = in_cnt,
-    proc = proc_str);
+    let reports = suite.tests.iter().map(|test| suite.run_model(test, &model)).collect();
+    check_reports("formal", &suite, reports);
+}
 
-    let mut file = File::create(path.join("tb.sv")).unwrap();
-    match file.write(tb.as_bytes()) {
-        Err(..) => println!("    Error Writing to file"),
-        _ => ()
-    }
+/// Records a session on levers, a button and a lamp and checks the `test.json` it turns into
+pub fn record() {
+    let lever = |powered| Block::Lever { lever: Lever { face: LeverFace::Floor, facing: BlockDirection::North, powered } };
+    let mut world = SchematicWorld::new(2);
+    world.set_block(BlockPos::new(2, 2, 2), lever(true));
+    world.set_block(BlockPos::new(3, 2, 2), Block::RedstoneLamp { lit: true });
+    world.set_block(BlockPos::new(4, 2, 2), lever(false));
+    world.set_block(BlockPos::new(5, 2, 2), Block::StoneButton { button: Default::default() });
+
+    let mut recorder = Recorder::new("recorded".to_string(), &world, (BlockPos::new(6, 3, 3), BlockPos::new(1, 1, 1)), 100);
+    recorder.use_block(BlockPos::new(2, 2, 2), 103);
+    recorder.output(BlockPos::new(3, 2, 2), false, 103);
+    recorder.use_block(BlockPos::new(5, 2, 2), 110);
+    recorder.use_block(BlockPos::new(2, 2, 2), 110);
+    recorder.output(BlockPos::new(3, 2, 2), false, 110);
+    recorder.output(BlockPos::new(3, 2, 2), true, 111);
+
+    // The lever that is never used is left out, the one starting on is switched on first
+    let test = recorder.to_test();
+    assert_eq!(test["signals"], json!({ "lever_1_1_1": "1,1,1", "out_2_1_1": "2,1,1", "button_4_1_1": "4,1,1" }));
+    assert_eq!(test["tests"][0]["steps"], json!([
+        { "set": { "lever_1_1_1": 1 }, "expect": { "out_2_1_1": 1 }, "wait": 3 },
+        { "set": { "lever_1_1_1": 0 }, "expect": { "out_2_1_1": 0 }, "wait": 7 },
+        { "set": { "lever_1_1_1": 1 }, "press": ["button_4_1_1"], "expect": { "out_2_1_1": 0 }, "wait": 1 },
+        { "expect": { "out_2_1_1": 1 }, "wait": 1 },
+    ]));
+    TestSuite::parse(&test.to_string()).unwrap();
 }
 
-fn parse_step(step: &String, in_tbl: &Vec<usize>, out_tbl: &Vec<usize>) -> String {
-    let args: Vec<&str> = step.split(' ').collect();
-    let mut input = "".to_string();
-    for i in in_tbl {
-        input.push(args[1].as_bytes()[*i] as char);
-    }
-    let mut output = "".to_string();
-    for i in out_tbl {
-        output.push(args[2].as_bytes()[*i] as char);
-    }
-    if input == "" {input.push('0');}
-
-    let step = format!("
-        inputs = {i_size}'b{i};
-        #1
-        if (outputs !== {o_size}'b{o}) begin
-            $display(\"FAILURE {o_size}'b%b != {o_size}'b{o}\", outputs);
-            $fatal;
-		end 
-        else
-			$display(\"%0d OK\", $time);
-        #1
-        tick = ~tick;
-        #1
-        tick = ~tick;
-        ", o_size = output.len(), o = output, i_size = input.len(), i = input);
-
-    step
-}
-
-fn generate_rs(path: &Path) -> Linker {
-    let mut co = CompilerOptions::fpga();
-    co.sim_test = true;
-    let (sender, _receive) = mpsc::channel();
-    let schem = schematic::load_schematic(&format!("{}/build.schem", path.to_str().unwrap()), true).unwrap();
-    let mut world = TestWorld::new(10);
-    world.paste_schem(&schem);
-    let ticks = world.to_be_ticked.clone();
-    let m_world = Mutex::new(world);
-    let bounds = (BlockPos::new(0, 0, 0), BlockPos::new(32, 32, 32));
-    
-    let mut b = Backend::new(
-        sender,
-        path.to_str().unwrap().to_string(),
-        "0,0".to_string(),
-        None,
-        &m_world,
-        bounds,
-        co.clone(),
-        ticks,
-    );
+/// A clipboard with fewer entries than its size is pasted as far as it goes
+pub fn schematic_world() {
+    let lamp = Block::RedstoneLamp { lit: true }.get_id();
+    let mut data = PalettedBitBuffer::new(2, 9);
+    data.set_entry(0, lamp);
+    let cb = WorldEditClipboard {
+        offset_x: 0,
+        offset_y: 0,
+        offset_z: 0,
+        size_x: 20,
+        size_y: 1,
+        size_z: 1,
+        data,
+        block_entities: Default::default(),
+    };
+    let (world, bounds) = SchematicWorld::from_schematic(&cb);
+    assert_eq!(bounds, (BlockPos::new(0, 0, 0), BlockPos::new(19, 0, 0)));
+    assert_eq!(world.get_block_raw(BlockPos::new(0, 0, 0)), lamp);
+    assert_eq!(world.get_block_raw(BlockPos::new(19, 0, 0)), 0);
+    // Two chunks fit the schematic, anything outside of them is air
+    assert_eq!(world.get_block_raw(BlockPos::new(31, 31, 31)), 0);
+    assert_eq!(world.get_block_raw(BlockPos::new(32, 0, 0)), 0);
+    assert_eq!(world.get_block_raw(BlockPos::new(0, -1, 0)), 0);
+}
 
-    match b.backend() {
-        BackendDispatcher::FPGABackend(fpga) => {
-            fpga.link.clone()
-        }
-        _ => panic!("no linker in backend")
-    }
+/// A dot of wire compiled with `wire_dot_out` is a four bit hex lamp in the
+/// link file and in the netlist
+pub fn netlist_hex_lamp() {
+    let mut world = SchematicWorld::new(1);
+    world.set_block(BlockPos::new(0, 0, 0), Block::Stone {});
+    world.set_block(BlockPos::new(1, 0, 0), Block::Stone {});
+    world.set_block(BlockPos::new(0, 1, 0), Block::Lever { lever: Lever { face: LeverFace::Floor, facing: BlockDirection::North, powered: false } });
+    world.set_block(BlockPos::new(1, 1, 0), Block::RedstoneWire { wire: RedstoneWire::default() });
+
+    let options = CompilerOptions { wire_dot_out: true, ..CompilerOptions::fpga() };
+    let input = CompilerInput { world: &Mutex::new(world), bounds: (BlockPos::new(0, 0, 0), BlockPos::new(1, 1, 0)) };
+    let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&options, &input);
+    let link = Linker::from_graph(&graph);
+    assert_eq!((link.input_bits, link.output_bits), (1, 4));
+
+    let json = netlist::Netlist::new(&graph).to_yosys_json();
+    let ports = &json["modules"]["RoC"]["ports"];
+    assert_eq!(ports["inputs"]["bits"].as_array().unwrap().len(), 1);
+    assert_eq!(ports["outputs"]["bits"].as_array().unwrap().len(), 4);
 }
 
-#[derive(Default, Debug, Deserialize)]
-struct Test {
-    name: String,
-    description: String,
-    inputs: Vec<String>,
-    outputs: Vec<String>,
-    tests: Vec<Procedure>,
+/// The graph viewer has every node and link, and names can't close its script
+pub fn graph_html() {
+    let path = case_dir("basic_sub_comp");
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let mut graph = make_default_pass_manager::<SchematicWorld>().run_passes(&CompilerOptions::default(), &input);
+    let first = graph.node_indices().next().unwrap();
+    graph[first].annotations.name = Some("</script><b>".to_string());
+
+    let json = graph_html::to_json(&graph, "sub</title>");
+    assert_eq!(json["name"], "sub</title>");
+    assert_eq!(json["nodes"].as_array().unwrap().len(), graph.node_count());
+    assert_eq!(json["links"].as_array().unwrap().len(), graph.edge_count());
+    assert_eq!(json["nodes"][first.index()]["name"], "</script><b>");
+
+    let html = graph_html::render(&graph, "sub</title>");
+    assert_eq!(html.matches("</script>").count(), 1);
+    assert!(html.contains(r#""name":"<\/script><b>""#));
+    assert!(html.contains(r#""name":"sub<\/title>""#));
+    assert!(!html.contains("/*GRAPH*/"));
 }
 
-enum IntfType {
-    Input,
-    Ouptut
+/// Groups levers and lamps of `basic_sub_comp` into buses with a bus file and
+/// reads and writes them through the linker
+pub fn buses() {
+    let path = case_dir("basic_sub_comp");
+    let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_buses");
+    _ = fs::remove_dir_all(&out);
+    fs::create_dir_all(&out).unwrap();
+
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let mut graph = make_default_pass_manager::<SchematicWorld>().run_passes(&sim_options(), &input);
+
+    fs::write(out.join("missing.json"), r#"{ "a": ["0,0,0"] }"#).unwrap();
+    assert!(linker::apply_bus_file(&mut graph, &out.join("missing.json")).is_err());
+    fs::write(
+        out.join("buses.json"),
+        r#"{ "a": ["2,2,1", "3,2,1", "4,2,1", "5,2,1"], "q": ["15,1,6", "15,1,7"] }"#,
+    )
+    .unwrap();
+    linker::apply_bus_file(&mut graph, &out.join("buses.json")).unwrap();
+
+    let mut link = Linker::from_graph(&graph);
+    let a = link.find_bus("a").unwrap();
+    assert!(a.is_input);
+    assert_eq!(a.blocks.len(), 4);
+    assert!(!link.find_bus("q").unwrap().is_input);
+
+    let changed = link.set_bus("a", 0b1010).unwrap();
+    assert_eq!(changed.len(), 2);
+    assert_eq!(link.bus_value("a"), Ok(0b1010));
+    assert!(link.set_bus("a", 16).is_err());
+    assert!(link.set_bus("q", 1).is_err());
+    assert!(link.set_bus("b", 1).is_err());
+
+    generate_verilog(&graph, &out.join("redstone.sv"));
+    let verilog = fs::read_to_string(out.join("redstone.sv")).unwrap();
+    assert!(verilog.contains("\twire [3:0] bus_a;"));
+    assert!(!verilog.contains("output  [3:0] bus_a"));
+    assert!(verilog.contains("output  [1:0] bus_q"));
 }
 
-impl Test {
-    fn get_translation_table(&self, ty: IntfType, intf: &Vec<IntfBlock>) -> Vec<usize> {
-        let mut table = Vec::new();
-        for block in intf {
-            let intf_pos = block.pos;
-            let sim_blocks = match ty {
-                IntfType::Input => &self.inputs,
-                IntfType::Ouptut => &self.outputs,
-            };
-            for i in 0..sim_blocks.len() {
-                let sim_pos = BlockPos::from_str(&sim_blocks[i]).unwrap();
-                if sim_pos == intf_pos {
-                    table.push(i);
-                    break;
-                }
+/// Signs on a line of repeaters: `[keep]` survives `--optimize`, `[output]`
+/// adds an FPGA output and names that sanitize alike get their own wires
+pub fn annotations() {
+    let compile = |args: &str| {
+        let (mut world, (start, end)) = repeater_line(&[1, 1, 1], false);
+        for (x, rows) in [(2, ["[keep]", "[name a.b]"]), (3, ["[output]", "[name a_b]"])] {
+            let pos = BlockPos::new(x, 1, 1);
+            world.set_block(pos, Block::WallSign { sign_type: SignType(0), facing: BlockDirection::South });
+            let mut front_rows: [String; 4] = Default::default();
+            for (row, text) in front_rows.iter_mut().zip(rows) {
+                *row = json!({ "text": text }).to_string();
             }
+            world.set_block_entity(pos, BlockEntity::Sign(Box::new(SignBlockEntity { front_rows, back_rows: Default::default() })));
+        }
+        let input = CompilerInput { world: &Mutex::new(world), bounds: (start, BlockPos::new(end.x, end.y, 1)) };
+        make_default_pass_manager::<SchematicWorld>().run_passes(&CompilerOptions::parse(args), &input)
+    };
+    let at = |graph: &CompileGraph, x| graph.node_weights().find(|n| n.block.map(|(pos, _)| pos) == Some(BlockPos::new(x, 1, 0))).cloned();
+
+    let graph = compile("--optimize");
+    let kept = at(&graph, 2).expect("the kept repeater was optimized out");
+    assert!(matches!(kept.ty, NodeType::Repeater { .. }) && kept.annotations.keep);
+    assert_eq!(kept.annotations.name.as_deref(), Some("a.b"));
+    assert!(at(&graph, 3).unwrap().is_output);
+
+    let graph = compile("--fpga --optimize");
+    let link = Linker::from_graph(&graph);
+    assert_eq!(link.output_bits, 2);
+    let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_annotations");
+    generate_verilog(&graph, &out.join("redstone.sv"));
+    let verilog = fs::read_to_string(out.join("redstone.sv")).unwrap();
+    assert_eq!(verilog.matches("wire n_a_b =").count(), 1, "{}", verilog);
+    assert_eq!(verilog.matches("wire n_a_b_2 =").count(), 1, "{}", verilog);
+    let json = netlist::Netlist::new(&graph).to_yosys_json();
+    let nets = &json["modules"]["RoC"]["netnames"];
+    assert!(nets.get("n_a_b").is_some() && nets.get("n_a_b_2").is_some(), "{}", nets);
+}
+
+/// Resolves pass pipelines for the FPGA backend and checks that required
+/// passes can't be turned off
+pub fn pipelines() {
+    let manager = make_default_pass_manager::<SchematicWorld>();
+    let runs = |args: &str| -> Vec<&'static str> {
+        manager
+            .describe(&CompilerOptions::parse(args))
+            .into_iter()
+            .filter(|pass| pass.runs)
+            .map(|pass| pass.id)
+            .collect()
+    };
+
+    assert_eq!(
+        runs("--fpga --pipeline=minimal"),
+        ["identify-nodes", "input-search", "clamp-weights", "discrete-comps"]
+    );
+    assert_eq!(runs("--pipeline=minimal"), ["identify-nodes", "input-search", "clamp-weights"]);
+    assert!(runs("--fpga --pass=-discrete-comps").contains(&"discrete-comps"));
+    assert!(runs("--optimize").contains(&"coalesce"));
+    assert!(!runs("--optimize --pass=-coalesce").contains(&"coalesce"));
+    assert!(runs("--pass=-coalesce,+coalesce").contains(&"coalesce"));
+    assert_eq!(
+        runs("--pipeline=coalesce,dedup-links"),
+        ["identify-nodes", "input-search", "clamp-weights", "coalesce", "dedup-links"]
+    );
+
+    // The minimal pipeline must still give a graph the backends accept
+    let path = case_dir("basic_sub_comp");
+    let (world, bounds) = load_world(&path);
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let mut options = sim_options();
+    options.pipeline = PassPipeline::Minimal;
+    let graph = manager.run_passes(&options, &input);
+    assert!(Linker::from_graph(&graph).get_input_bytes() > 0);
+}
+
+/// Checks the per-pass statistics of an FPGA compile and that `Backend::new` keeps its report
+pub fn compile_report() {
+    let path = case_dir("basic_sub_comp");
+    let (world, bounds) = load_world(&path);
+    let world = Mutex::new(world);
+    let input = CompilerInput { world: &world, bounds };
+    let (graph, report) = make_default_pass_manager::<SchematicWorld>().run_passes_with_report(&sim_options(), &input);
+
+    assert_eq!(report.node_count, graph.node_count());
+    assert_eq!(report.edge_count, graph.edge_count());
+    let identify = report.pass("identify-nodes").unwrap();
+    assert_eq!(identify.nodes_before, 0);
+    assert!(identify.nodes_after > 0);
+    let comparators = graph.node_weights().filter(|node| matches!(node.ty, NodeType::Comparator { .. })).count();
+    assert!(comparators > 0);
+    assert_eq!(report.pass("discrete-comps").unwrap().stats.get("comparators discretised"), Some(comparators));
+    assert!(report.pass("coalesce").is_some());
+    assert!(report.summary()[0].contains(&format!("{} nodes", graph.node_count())));
+
+    let backend = compile_direct(&world, bounds);
+    let report = backend.report().unwrap();
+    assert!(report.pass("discrete-comps").is_none());
+    assert!(report.pass("clamp-weights").is_some());
+}
+
+/// Compiles every case with all optimizations for both backends without breaking a graph
+/// invariant, and checks that a broken graph is caught
+pub fn validate() {
+    for entry in fs::read_dir(case_dir("")).unwrap() {
+        let path = entry.unwrap().path();
+        for args in ["--optimize --validate", "--fpga --pipeline=full --validate"] {
+            let (world, bounds) = load_world(&path);
+            let input = CompilerInput { world: &Mutex::new(world), bounds };
+            let (_, report) = make_default_pass_manager::<SchematicWorld>()
+                .run_passes_with_report(&CompilerOptions::parse(args), &input);
+            let violations: Vec<String> = report.violations().map(|(pass, v)| format!("{} broke {}", pass, v)).collect();
+            assert!(violations.is_empty(), "{} {}: {:#?}", path.display(), args, violations);
         }
-        table.reverse();
-        table
     }
+
+    let mut graph = CompileGraph::new();
+    let lever = graph.add_node(CompileNode { is_input: true, block: None, ..node(NodeType::Lever, 0) });
+    let torch = graph.add_node(node(NodeType::Torch, 1));
+    let comparator = graph.add_node(node(
+        NodeType::Comparator { mode: ComparatorMode::Compare, far_input: None, facing_diode: false, states: Some(0x8000) },
+        2,
+    ));
+    graph.add_edge(lever, torch, CompileLink::side(0));
+    graph.add_edge(lever, comparator, CompileLink::default(15));
+    graph.add_edge(lever, comparator, CompileLink::default(14));
+    graph.add_edge(comparator, torch, CompileLink::default(3));
+
+    let messages = |ran: &[&str]| -> Vec<String> {
+        validate::check(&graph, ran).into_iter().map(|v| v.message).collect()
+    };
+    assert_eq!(
+        messages(&[]),
+        [
+            "lever is an input or output without a block",
+            "torch has a side link from node 0 at (1, 1, 0)",
+            "comparator has a state set before discrete-comps at (2, 1, 0)",
+        ]
+    );
+    let all = messages(&["clamp-weights", "dedup-links", "discrete-comps"]);
+    assert!(all.contains(&"link from node 0 has weight 15 at (2, 1, 0)".to_string()));
+    assert!(all.contains(&"duplicate Default link from node 0 at (2, 1, 0)".to_string()));
+    assert!(all.contains(&"comparator state set is missing 0x0008 at (2, 1, 0)".to_string()));
+
+    // Only the violations a pass introduced are attributed to it
+    let mut validator = validate::Validate::default();
+    assert_eq!(validator.after_pass("identify-nodes", &graph).len(), 3);
+    assert!(validator.after_pass("input-search", &graph).is_empty());
+    assert_eq!(validator.after_pass("clamp-weights", &graph).len(), 1);
 }
 
-#[derive(Default, Debug, Deserialize)]
-struct Procedure {
-    description: String,
-    procedure: Vec<String>,
+/// A node of a hand built graph, at `x` on a line of blocks
+fn node(ty: NodeType, x: i32) -> CompileNode {
+    CompileNode {
+        ty,
+        block: Some((BlockPos::new(x, 1, 0), 0)),
+        state: NodeState::default(),
+        is_input: false,
+        is_output: false,
+        annotations: Default::default(),
+    }
 }
 
-fn parse_test(path: &Path) -> Test {
-    let test_str = fs::read_to_string(path.join("test.json")).unwrap();
-    let test: Test = serde_json::from_str(&test_str).unwrap();
+/// Finds a loop of repeaters that lock another repeater, which would have no register on the
+/// FPGA, and checks that the FPGA backend refuses to run it
+pub fn same_tick_cycles() {
+    let repeater = |x| node(NodeType::Repeater { delay: 1, facing_diode: false }, x);
+    let mut graph = CompileGraph::new();
+    let lever = graph.add_node(CompileNode { is_input: true, ..node(NodeType::Lever, 0) });
+    let (a, b, locked) = (graph.add_node(repeater(1)), graph.add_node(repeater(2)), graph.add_node(repeater(3)));
+    let lamp = graph.add_node(CompileNode { is_output: true, ..node(NodeType::Lamp, 4) });
+    graph.add_edge(lever, a, CompileLink::default(0));
+    graph.add_edge(a, b, CompileLink::default(0));
+    graph.add_edge(b, a, CompileLink::default(0));
+    graph.add_edge(lever, locked, CompileLink::default(0));
+    graph.add_edge(locked, lamp, CompileLink::default(0));
+
+    // A clock of repeaters is fine as long as none of them locks another one
+    assert!(same_tick_cycles::find(&graph).is_empty());
+    graph.add_edge(b, locked, CompileLink::side(0));
+    assert!(same_tick_cycles::find(&graph).is_empty());
+    graph.add_edge(a, locked, CompileLink::side(0));
+    let cycles = same_tick_cycles::find(&graph);
+    assert_eq!(cycles, [vec![a, b]]);
+    let message = same_tick_cycles::describe(&graph, &cycles[0]);
+    assert!(message.contains("(1, 1, 0), (2, 1, 0)"), "{}", message);
+
+    let (sender, _receive) = mpsc::channel();
+    let out = std::env::temp_dir().join("roc_same_tick_cycles");
+    let mut backend = Backend::from_graph(sender, out.to_str().unwrap().to_string(), "0,0".to_string(), None, graph, sim_options());
+    assert_eq!(backend.run(), Err(message));
+    assert!(!out.join("roc.sv").exists());
+
+    // The assembler builds every comparator as a `comp` register, so their loops are fine
+    for case in ["comp_loop", "two_comp_loop"] {
+        let (world, bounds) = load_world(&case_dir(case));
+        let input = CompilerInput { world: &Mutex::new(world), bounds };
+        let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&sim_options(), &input);
+        let comparators = graph.node_weights().filter(|node| matches!(node.ty, NodeType::Comparator { .. })).count();
+        assert!(comparators > 0, "{}", case);
+        assert!(same_tick_cycles::find(&graph).is_empty(), "{}", case);
 
-    test
+        let out = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/roc_same_tick_cycles").join(case);
+        generate_verilog(&graph, &out.join("redstone.sv"));
+        let verilog = fs::read_to_string(out.join("redstone.sv")).unwrap();
+        assert_eq!(verilog.matches("\tcomp #(").count(), comparators, "{}", verilog);
+    }
 }
 
-#[derive(Clone, Debug)]
-pub struct TestWorld {
-    chunks: Vec<Chunk>,
-    to_be_ticked: Vec<TickEntry>,
-    size: i32,
+/// Checks the latencies of static timing on a line of repeaters, collapsed or not, and that
+/// loops are gone around once
+pub fn timing() {
+    for optimize in [false, true] {
+        let (world, bounds) = repeater_line(&[1, 3, 2, 4], false);
+        let input = CompilerInput { world: &Mutex::new(world), bounds };
+        let graph = make_default_pass_manager::<SchematicWorld>().run_passes(&CompilerOptions { optimize, ..Default::default() }, &input);
+        let latencies = timing::all_latencies(&graph);
+        assert_eq!(latencies.len(), 1, "optimize {}", optimize);
+        let (from, to, found) = &latencies[0];
+        assert_eq!((&graph[*from].ty, &graph[*to].ty), (&NodeType::Lever, &NodeType::Lamp));
+        // The lamp lights up right away but takes two ticks to turn off
+        assert_eq!((found.min, found.max, found.loops), (10, 12, false), "optimize {}", optimize);
+        let path = timing::describe_path(&graph, &found.critical_path);
+        // A collapsed chain is at the block of its last repeater
+        assert!(path.starts_with("(0, 1, 0) -> ") && path.ends_with("(4, 1, 0) -> (5, 1, 0)"), "{}", path);
+    }
+
+    // A lever into a lamp through a repeater and through a loop of two torches
+    let mut graph = CompileGraph::new();
+    let lever = graph.add_node(CompileNode { is_input: true, ..node(NodeType::Lever, 0) });
+    let repeater = graph.add_node(node(NodeType::Repeater { delay: 3, facing_diode: false }, 1));
+    let (first, second) = (graph.add_node(node(NodeType::Torch, 2)), graph.add_node(node(NodeType::Torch, 3)));
+    let lamp = graph.add_node(CompileNode { is_output: true, ..node(NodeType::Lamp, 4) });
+    graph.add_edge(lever, repeater, CompileLink::default(0));
+    graph.add_edge(repeater, lamp, CompileLink::default(0));
+    graph.add_edge(lever, first, CompileLink::default(0));
+    graph.add_edge(first, second, CompileLink::default(0));
+    graph.add_edge(second, first, CompileLink::default(0));
+    graph.add_edge(second, lamp, CompileLink::default(0));
+
+    let found = timing::latency(&graph, lever, lamp).unwrap();
+    assert_eq!((found.min, found.max, found.loops), (2, 5, true));
+    assert_eq!(found.critical_path, [lever, repeater, lamp]);
+    let found = timing::latency(&graph, lever, first).unwrap();
+    assert_eq!((found.min, found.max), (1, 1));
+    assert!(timing::latency(&graph, lamp, lever).is_none());
+
+    // A second lever into a lamp through a repeater that the first one locks
+    let mut graph = CompileGraph::new();
+    let lever = graph.add_node(CompileNode { is_input: true, ..node(NodeType::Lever, 0) });
+    let locker = graph.add_node(node(NodeType::Repeater { delay: 1, facing_diode: false }, 1));
+    let data = graph.add_node(CompileNode { is_input: true, ..node(NodeType::Lever, 2) });
+    let locked = graph.add_node(node(NodeType::Repeater { delay: 2, facing_diode: false }, 3));
+    let lamp = graph.add_node(CompileNode { is_output: true, ..node(NodeType::Lamp, 4) });
+    graph.add_edge(lever, locker, CompileLink::default(0));
+    graph.add_edge(locker, locked, CompileLink::side(0));
+    graph.add_edge(data, locked, CompileLink::default(0));
+    graph.add_edge(locked, lamp, CompileLink::default(0));
+
+    let found = timing::latency(&graph, lever, lamp).unwrap();
+    assert_eq!((found.min, found.max, found.lock_gated), (3, 5, true));
+    assert_eq!(found.critical_path, [lever, locker, locked, lamp]);
+    let found = timing::latency(&graph, data, lamp).unwrap();
+    assert_eq!((found.min, found.max, found.lock_gated), (2, 4, false));
 }
 
-impl TestWorld {
-    /// Create a new square world for testing with a size in chunks
-    pub fn new(size: i32) -> TestWorld {
-        let mut chunks = Vec::new();
-        for x in 0..size {
-            for z in 0..size {
-                chunks.push(Chunk::empty(x, z, size as usize));
-            }
-        }
-        TestWorld {
-            chunks,
-            to_be_ticked: Vec::new(),
-            size,
+/// Lays out levers feeding repeater chains into lamps and checks that collapsing the chains
+/// into delay nodes changes neither the direct backend nor the formal model of the FPGA build
+pub fn collapse_repeaters() {
+    let chains: [&[u8]; 10] = [
+        &[2, 2, 2],
+        &[1, 1, 1],
+        &[1, 3, 1, 2],
+        &[2, 4, 1, 4, 3],
+        &[3, 1],
+        &[4, 4, 4, 4],
+        &[3, 3, 1, 3],
+        &[1, 1, 1, 1, 1, 1],
+        &[2, 1, 2, 1, 2],
+        &[4, 1, 2, 3, 1, 1, 4, 4, 1, 2],
+    ];
+    for delays in chains {
+        for powered in [false, true] {
+            let world = || repeater_line(delays, powered);
+            let (collapsed, report) = compile_with(world, &CompilerOptions::default(), "collapse-repeaters", true);
+            let total: u32 = collapsed
+                .node_weights()
+                .map(|node| match node.ty {
+                    NodeType::Repeater { delay, .. } => delay as u32,
+                    NodeType::Delay { ref stages } => stages.iter().map(|stage| stage.delay as u32).sum(),
+                    _ => 0,
+                })
+                .sum();
+            assert_eq!(total, delays.iter().map(|&d| d as u32).sum::<u32>(), "{:?}", delays);
+            let removed = report.pass("collapse-repeaters").unwrap().node_delta();
+            assert!(removed < 0, "{:?} was not collapsed", delays);
+
+            let lamp = BlockPos::new(delays.len() as i32 + 1, 1, 0);
+            assert_equivalent(world, &[(BlockPos::new(0, 1, 0), powered)], lamp, "collapse-repeaters");
         }
     }
+}
 
-    pub fn paste_schem(&mut self, cb: &WorldEditClipboard) {
-        let offset_x = 0;
-        let offset_y = 0;
-        let offset_z = 0;
-        let mut i = 0;
-        // This can be made better, but right now it's not D:
-        let x_range = offset_x..offset_x + cb.size_x as i32;
-        let y_range = offset_y..offset_y + cb.size_y as i32;
-        let z_range = offset_z..offset_z + cb.size_z as i32;
-
-        let entries = cb.data.entries();
-        // I have no clue if these clones are going to cost anything noticeable.
-        'top_loop: for y in y_range {
-            for z in z_range.clone() {
-                for x in x_range.clone() {
-                    if i >= entries {
-                        break 'top_loop;
-                    }
-                    let entry = cb.data.get_entry(i);
-                    i += 1;
-                    if entry == 0 {
-                        continue;
-                    }
-                    self.set_block_raw(BlockPos::new(x, y, z), entry);
-                }
-            }
-        }
+/// Runs a collapsed repeater chain next to the world simulation and checks that every
+/// repeater of the chain is flushed with the state it has in the world
+pub fn collapsed_blocks() {
+    let delays = [2, 4, 1, 4, 3];
+    let lever = BlockPos::new(0, 1, 0);
+    let (graph, _) = compile_with(|| repeater_line(&delays, false), &CompilerOptions::default(), "collapse-repeaters", true);
+    assert!(graph.node_weights().any(|node| matches!(node.ty, NodeType::Delay { .. })));
+    let (sender, _receive) = mpsc::channel();
+    let mut backend = Backend::from_graph(sender, "collapsed".to_string(), "0,0".to_string(), None, graph, CompilerOptions::default());
 
-        for (pos, block_entity) in &cb.block_entities {
-            let new_pos = BlockPos {
-                x: pos.x + offset_x,
-                y: pos.y + offset_y,
-                z: pos.z + offset_z,
-            };
-            self.set_block_entity(new_pos, block_entity.clone());
+    let (mut world, _) = repeater_line(&delays, false);
+    let mut flushed = world.clone();
+    let mut rng = diff::XorShift::new(0x0de1_a7ed);
+    let mut on = false;
+    for tick in 0..300 {
+        if rng.next() % 7 == 0 {
+            on = !on;
+            mchprs_redstone::on_use(world.get_block(lever), &mut world, lever);
+        }
+        let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+            unreachable!();
+        };
+        direct.set_input(lever, on);
+        backend.flush(&mut flushed);
+        for x in 1..=delays.len() as i32 {
+            let pos = BlockPos::new(x, 1, 0);
+            assert_eq!(flushed.get_block(pos), world.get_block(pos), "repeater {} differs in tick {}", x, tick);
         }
+        world.tick();
+        backend.tick();
     }
+}
 
-    fn get_chunk_index_for_chunk(&self, chunk_x: i32, chunk_z: i32) -> usize {
-        (chunk_x * self.size + chunk_z).unsigned_abs() as usize
+/// A lever, repeaters with `delays` pointing east and a lamp in a row, all `powered` or off
+fn repeater_line(delays: &[u8], powered: bool) -> (SchematicWorld, (BlockPos, BlockPos)) {
+    let mut world = SchematicWorld::new(2);
+    let end = delays.len() as i32 + 1;
+    for x in 0..=end {
+        world.set_block(BlockPos::new(x, 0, 0), Block::Stone {});
+    }
+    let lever = Lever { face: LeverFace::Floor, facing: BlockDirection::East, powered };
+    world.set_block(BlockPos::new(0, 1, 0), Block::Lever { lever });
+    for (x, &delay) in delays.iter().enumerate() {
+        let repeater = RedstoneRepeater { delay, facing: BlockDirection::West, locked: false, powered };
+        world.set_block(BlockPos::new(x as i32 + 1, 1, 0), Block::RedstoneRepeater { repeater });
     }
+    world.set_block(BlockPos::new(end, 1, 0), Block::RedstoneLamp { lit: powered });
+    (world, (BlockPos::new(0, 0, 0), BlockPos::new(end, 1, 0)))
+}
+
+/// Merges torch towers feeding an AND torch with `minimise-logic` and checks that the direct
+/// backend and the FPGA model give the same outputs as with the torches kept
+pub fn minimise_logic() {
+    let mut rng = diff::XorShift::new(0x9e37_79b9);
+    for inputs in 1..=3 {
+        for height in 1..=4 {
+            let levers: Vec<bool> = (0..inputs).map(|_| rng.next() % 2 == 0).collect();
+            let world = || torch_towers(&levers, height);
+            // Wires only stay in the graph without `--optimize`, for the world to show them
+            let optimized = CompilerOptions { optimize: true, ..Default::default() };
+            let (minimised, report) = compile_with(world, &optimized, "minimise-logic", true);
+            let stats = &report.pass("minimise-logic").unwrap().stats;
+            assert_eq!(stats.get("logic nodes"), Some(1), "{} towers of {}", inputs, height);
+            assert_eq!(stats.get("torches merged"), Some(inputs * height + 1), "{} towers of {}", inputs, height);
+            assert!(!minimised.node_weights().any(|node| node.ty == NodeType::Torch));
 
-    fn get_chunk_index_for_block(&self, block_x: i32, block_z: i32) -> Option<usize> {
-        let chunk_x = block_x >> 4;
-        let chunk_z = block_z >> 4;
-        if chunk_x >= self.size || chunk_z >= self.size || chunk_x < 0 || chunk_z < 0 {
-            return None;
+            let lamp = BlockPos::new(inputs as i32 - 1, 2 * height as i32 - 1, 4);
+            let levers: Vec<_> = levers.iter().enumerate().map(|(x, &on)| (BlockPos::new(2 * x as i32, 1, 0), on)).collect();
+            assert_equivalent(world, &levers, lamp, "minimise-logic");
         }
-        Some(((chunk_x * self.size) + chunk_z).unsigned_abs() as usize)
     }
 }
 
-impl World for TestWorld {
-    /// Returns the block state id of the block at `pos`
-    fn get_block_raw(&self, pos: BlockPos) -> u32 {
-        let chunk_index = match self.get_chunk_index_for_block(pos.x, pos.z) {
-            Some(idx) => idx,
-            None => return 0,
-        };
-        let chunk = &self.chunks[chunk_index];
-        chunk.get_block((pos.x & 0xF) as u32, pos.y as u32, (pos.z & 0xF) as u32)
-    }
+/// Merges torches computing `a & (b | c)`, which tells its inputs apart, and checks that the
+/// direct backend and the FPGA model light the lamp like the world does while the levers flip
+pub fn minimise_logic_world() {
+    let mut rng = diff::XorShift::new(0x1091_c5ed);
+    let (world, lamp, levers) = logic_world();
+    let bounds = (BlockPos::new(0, 0, 0), BlockPos::new(4, 2, 4));
+    let compile = |options: &CompilerOptions| {
+        let input = CompilerInput { world: &Mutex::new(world.clone()), bounds };
+        let mut options = options.clone();
+        options.passes.push(("minimise-logic".to_string(), true));
+        make_default_pass_manager::<SchematicWorld>().run_passes(&options, &input)
+    };
+    let graph = compile(&CompilerOptions { optimize: true, ..Default::default() });
+    assert!(graph.node_weights().any(|node| matches!(node.ty, NodeType::Logic { .. })));
+    assert!(!graph.node_weights().any(|node| node.ty == NodeType::Torch));
 
-    /// Sets a block in storage. Returns true if a block was changed.
-    fn set_block_raw(&mut self, pos: BlockPos, block: u32) -> bool {
-        let chunk_index = match self.get_chunk_index_for_block(pos.x, pos.z) {
-            Some(idx) => idx,
-            None => return false,
-        };
+    let (sender, _receive) = mpsc::channel();
+    let mut backend = Backend::from_graph(sender, "logic".to_string(), "0,0".to_string(), None, graph, CompilerOptions::default());
+    let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+        unreachable!();
+    };
+    let model = Model::new(&compile(&sim_options())).unwrap();
+    let mut sim = Simulation::new(&model.btor);
+    let probe = model.probe(lamp).unwrap();
 
-        // Check to see if block is within height limit
-        if pos.y >= self.size * 16 || pos.y < 0 {
-            return false;
+    let (mut world, mut on) = (world, [false; 3]);
+    for tick in 0..400 {
+        for (&lever, on) in levers.iter().zip(&mut on) {
+            if rng.next() % 5 == 0 {
+                *on = !*on;
+                mchprs_redstone::on_use(world.get_block(lever), &mut world, lever);
+            }
+            direct.set_input(lever, *on);
+            sim.set(model.input(lever).unwrap(), *on as u64);
         }
+        let outputs = [
+            mchprs_redstone::redstone_lamp_should_be_lit(&world, lamp),
+            direct.output_driven(lamp).unwrap(),
+            sim.get(probe) == 1,
+        ];
+        assert!(outputs.iter().all(|&lit| lit == outputs[0]), "{:?} differ in tick {}: {:?}", on, tick, outputs);
+        world.tick();
+        direct.tick();
+        sim.step();
+    }
+}
 
-        let chunk = &mut self.chunks[chunk_index];
-        chunk.set_block(
-            (pos.x & 0xF) as u32,
-            pos.y as u32,
-            (pos.z & 0xF) as u32,
-            block,
-        )
+/// Levers `a`, `b` and `c`, all off. A torch inverts `a`, another is on the block both `b` and
+/// `c` power, and dust from both torches turns off a wall torch lighting a lamp. Returns the
+/// world, the lamp and the levers.
+fn logic_world() -> (SchematicWorld, BlockPos, [BlockPos; 3]) {
+    let mut world = SchematicWorld::new(2);
+    let wall_lever = |facing| Block::Lever { lever: Lever { face: LeverFace::Wall, facing, powered: false } };
+    let levers = [BlockPos::new(0, 1, 0), BlockPos::new(3, 1, 0), BlockPos::new(4, 1, 1)];
+    for x in [0, 3] {
+        world.set_block(BlockPos::new(x, 1, 1), Block::Stone {});
+        world.set_block(BlockPos::new(x, 2, 1), Block::RedstoneTorch { lit: true });
+    }
+    world.set_block(levers[0], wall_lever(BlockDirection::North));
+    world.set_block(levers[1], wall_lever(BlockDirection::North));
+    world.set_block(levers[2], wall_lever(BlockDirection::East));
+    for x in 0..=3 {
+        world.set_block(BlockPos::new(x, 1, 2), Block::Stone {});
+    }
+    // Twice, so the power of every wire counts its neighbours
+    for _ in 0..2 {
+        for x in 0..=3 {
+            let pos = BlockPos::new(x, 2, 2);
+            let wire = mchprs_redstone::wire::get_state_for_placement(&world, pos);
+            world.set_block(pos, Block::RedstoneWire { wire });
+        }
     }
+    world.set_block(BlockPos::new(3, 1, 3), Block::RedstoneWallTorch { lit: false, facing: BlockDirection::South });
+    let lamp = BlockPos::new(3, 1, 4);
+    world.set_block(lamp, Block::RedstoneLamp { lit: false });
+    (world, lamp, levers)
+}
 
-    fn delete_block_entity(&mut self, pos: BlockPos) {
-        let chunk_index = match self.get_chunk_index_for_block(pos.x, pos.z) {
-            Some(idx) => idx,
-            None => return,
-        };
-        let chunk = &mut self.chunks[chunk_index];
-        chunk.delete_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF));
+/// A tower of `height` torches above each of the `levers`, every second block apart. The top
+/// torches power a line of dust on the block of a wall torch, which lights a lamp. The torches
+/// are lit as their inputs say, as they would be in a running world.
+fn torch_towers(levers: &[bool], height: usize) -> (SchematicWorld, (BlockPos, BlockPos)) {
+    let mut world = SchematicWorld::new(2);
+    let top = 2 * height as i32;
+    let mut any_top = false;
+    for (x, &powered) in levers.iter().enumerate() {
+        let x = 2 * x as i32;
+        let lever = Lever { face: LeverFace::Wall, facing: BlockDirection::North, powered };
+        world.set_block(BlockPos::new(x, 1, 0), Block::Lever { lever });
+        let mut lit = powered;
+        for y in (1..top).step_by(2) {
+            lit = !lit;
+            world.set_block(BlockPos::new(x, y, 1), Block::Stone {});
+            world.set_block(BlockPos::new(x, y + 1, 1), Block::RedstoneTorch { lit });
+        }
+        any_top |= lit;
+    }
+    let end = 2 * levers.len() as i32 - 2;
+    for x in 0..=end {
+        let side = |connected: bool| if connected { RedstoneWireSide::Side } else { RedstoneWireSide::None };
+        let wire = RedstoneWire::new(side(x % 2 == 0), side(false), side(x < end), side(x > 0), 0);
+        world.set_block(BlockPos::new(x, top - 1, 2), Block::Stone {});
+        world.set_block(BlockPos::new(x, top, 2), Block::RedstoneWire { wire });
     }
+    let x = levers.len() as i32 - 1;
+    world.set_block(BlockPos::new(x, top - 1, 3), Block::RedstoneWallTorch { lit: !any_top, facing: BlockDirection::South });
+    world.set_block(BlockPos::new(x, top - 1, 4), Block::RedstoneLamp { lit: false });
+    (world, (BlockPos::new(0, 0, 0), BlockPos::new(end, top, 4)))
+}
 
-    fn get_block_entity(&self, pos: BlockPos) -> Option<&BlockEntity> {
-        let chunk_index = match self.get_chunk_index_for_block(pos.x, pos.z) {
-            Some(idx) => idx,
-            None => return None,
+/// Checks that observers and target blocks give the same lamp outputs in the world, the direct
+/// backend and the FPGA model while the lever flips at random
+pub fn observers() {
+    let mut rng = diff::XorShift::new(0x0b5e_7e55);
+    let lever = BlockPos::new(0, 1, 0);
+    for (circuit, observers) in [1, 1, 2, 0].into_iter().enumerate() {
+        let (world, lamp) = observer_circuit(circuit);
+        let compile = |options: &CompilerOptions| {
+            let input = CompilerInput { world: &Mutex::new(world.clone()), bounds: (BlockPos::new(0, 0, 0), lamp) };
+            make_default_pass_manager::<SchematicWorld>().run_passes(options, &input)
         };
-        let chunk = &self.chunks[chunk_index];
-        chunk.get_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF))
-    }
+        let graph = compile(&CompilerOptions { optimize: true, ..Default::default() });
+        let count = graph.node_weights().filter(|node| node.ty == NodeType::Observer).count();
+        assert_eq!(count, observers, "circuit {}", circuit);
 
-    fn set_block_entity(&mut self, pos: BlockPos, block_entity: BlockEntity) {
-        let chunk_index = match self.get_chunk_index_for_block(pos.x, pos.z) {
-            Some(idx) => idx,
-            None => return,
+        let (sender, _receive) = mpsc::channel();
+        let mut backend = Backend::from_graph(sender, "observers".to_string(), "0,0".to_string(), None, graph, CompilerOptions::default());
+        let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+            unreachable!();
         };
-        let chunk = &mut self.chunks[chunk_index];
-        chunk.set_block_entity(BlockPos::new(pos.x & 0xF, pos.y, pos.z & 0xF), block_entity);
-    }
+        let model = Model::new(&compile(&sim_options())).unwrap();
+        let mut sim = Simulation::new(&model.btor);
+        let (input, probe) = (model.input(lever).unwrap(), model.probe(lamp).unwrap());
 
-    fn get_chunk(&self, x: i32, z: i32) -> Option<&Chunk> {
-        self.chunks.get(self.get_chunk_index_for_chunk(x, z))
+        let (mut world, mut on) = (world, false);
+        for tick in 0..200 {
+            if rng.next() % 5 == 0 {
+                on = !on;
+                mchprs_redstone::on_use(world.get_block(lever), &mut world, lever);
+            }
+            direct.set_input(lever, on);
+            sim.set(input, on as u64);
+            let outputs = [
+                mchprs_redstone::redstone_lamp_should_be_lit(&world, lamp),
+                direct.output_driven(lamp).unwrap(),
+                sim.get(probe) == 1,
+            ];
+            assert!(outputs.iter().all(|&lit| lit == outputs[0]), "circuit {} differs in tick {}: {:?}", circuit, tick, outputs);
+            world.tick();
+            direct.tick();
+            sim.step();
+        }
     }
+}
 
-    fn get_chunk_mut(&mut self, x: i32, z: i32) -> Option<&mut Chunk> {
-        let chunk_idx = self.get_chunk_index_for_chunk(x, z);
-        self.chunks.get_mut(chunk_idx)
-    }
+/// A floor lever at the origin lighting a lamp in a row through observers facing west, returns
+/// the world and the lamp. The circuits are an observer watching the lever, one watching a
+/// repeater and pulsing a torch off, two in a chain behind a torch, which turns off late in the
+/// tick, and dust powering a target block with a torch on it.
+fn observer_circuit(circuit: usize) -> (SchematicWorld, BlockPos) {
+    let mut world = SchematicWorld::new(2);
+    let at = |x| BlockPos::new(x, 1, 0);
+    let observer = Block::Observer { facing: BlockFacing::West, powered: false };
+    let torch = Block::RedstoneWallTorch { lit: true, facing: BlockDirection::East };
+    let lever = Lever { face: LeverFace::Floor, facing: BlockDirection::East, powered: false };
+    world.set_block(at(0), Block::Lever { lever });
+    let lamp = match circuit {
+        0 => {
+            world.set_block(at(1), observer);
+            at(2)
+        }
+        1 => {
+            let repeater = RedstoneRepeater { delay: 1, facing: BlockDirection::West, locked: false, powered: false };
+            world.set_block(at(1), Block::RedstoneRepeater { repeater });
+            world.set_block(at(2), observer);
+            world.set_block(at(3), Block::Stone {});
+            world.set_block(at(4), torch);
+            at(5)
+        }
+        2 => {
+            let lever = Lever { face: LeverFace::Wall, facing: BlockDirection::West, powered: false };
+            world.set_block(at(0), Block::Lever { lever });
+            world.set_block(at(1), Block::Stone {});
+            world.set_block(at(2), torch);
+            world.set_block(at(3), observer);
+            world.set_block(at(4), observer);
+            at(5)
+        }
+        _ => {
+            world.set_block(BlockPos::new(1, 0, 0), Block::Stone {});
+            world.set_block(at(2), Block::Target {});
+            world.set_block(at(3), torch);
+            let wire = mchprs_redstone::wire::get_state_for_placement(&world, at(1));
+            world.set_block(at(1), Block::RedstoneWire { wire });
+            at(4)
+        }
+    };
+    let lit = mchprs_redstone::redstone_lamp_should_be_lit(&world, lamp);
+    world.set_block(lamp, Block::RedstoneLamp { lit });
+    (world, lamp)
+}
 
-    fn schedule_tick(&mut self, pos: BlockPos, delay: u32, priority: TickPriority) {
-        self.to_be_ticked.push(TickEntry {
-            pos,
-            ticks_left: delay,
-            tick_priority: priority,
+/// Compiles the world from `world` with `pass` turned on or off
+fn compile_with(
+    world: impl Fn() -> (SchematicWorld, (BlockPos, BlockPos)),
+    options: &CompilerOptions,
+    pass: &str,
+    enabled: bool,
+) -> (CompileGraph, CompileReport) {
+    let (world, bounds) = world();
+    let input = CompilerInput { world: &Mutex::new(world), bounds };
+    let mut options = options.clone();
+    options.passes.push((pass.to_string(), enabled));
+    make_default_pass_manager::<SchematicWorld>().run_passes_with_report(&options, &input)
+}
+
+/// Checks that `pass` changes neither the direct backend nor the formal model of the FPGA build
+/// of the world from `world`, while the levers in `inputs` flip about every fifth tick from the
+/// state given with them. The direct backends are compared by the `lamp` they drive.
+fn assert_equivalent(
+    world: impl Fn() -> (SchematicWorld, (BlockPos, BlockPos)),
+    inputs: &[(BlockPos, bool)],
+    lamp: BlockPos,
+    pass: &str,
+) {
+    let optimized = CompilerOptions { optimize: true, ..Default::default() };
+    let mut backends = [true, false].map(|enabled| {
+        let (sender, _receive) = mpsc::channel();
+        let graph = compile_with(&world, &optimized, pass, enabled).0;
+        Backend::from_graph(sender, pass.to_string(), "0,0".to_string(), None, graph, CompilerOptions::default())
+    });
+    let fpga = |enabled| compile_with(&world, &sim_options(), pass, enabled).0;
+    let model = Model::miter(&fpga(true), &fpga(false)).unwrap();
+    let mut sim = Simulation::new(&model.btor);
+
+    let mut rng = diff::XorShift::new(0x2545_f491 ^ lamp.x as u64);
+    let mut on: Vec<bool> = inputs.iter().map(|&(_, on)| on).collect();
+    for tick in 0..400 {
+        for (&(pos, _), on) in inputs.iter().zip(&mut on) {
+            if rng.next() % 5 == 0 {
+                *on = !*on;
+            }
+            sim.set(model.input(pos).unwrap(), *on as u64);
+        }
+        assert_eq!(sim.bad(), Vec::<&str>::new(), "{} changes the FPGA build of {:?} in tick {}", pass, inputs, tick);
+        sim.step();
+
+        let outputs = backends.each_mut().map(|backend| {
+            let BackendDispatcher::DirectBackend(direct) = backend.backend() else {
+                unreachable!();
+            };
+            for (&(pos, _), &on) in inputs.iter().zip(&on) {
+                direct.set_input(pos, on);
+            }
+            let output = direct.output_driven(lamp);
+            direct.tick();
+            output
         });
+        assert_eq!(outputs[0], outputs[1], "{} changes the direct backend of {:?} in tick {}", pass, inputs, tick);
     }
+}
+
+fn sim_options() -> CompilerOptions {
+    let mut co = CompilerOptions::fpga();
+    co.sim_test = true;
+    co
+}
+
+/// Pastes `build.schem` at the origin, returns the world and the area to compile
+fn load_world(path: &Path) -> (SchematicWorld, (BlockPos, BlockPos)) {
+    let schem = schematic::load_schematic(path.join("build.schem").to_str().unwrap(), true).unwrap();
+    SchematicWorld::from_schematic(&schem)
+}
+
+fn compile_direct(world: &Mutex<SchematicWorld>, bounds: (BlockPos, BlockPos)) -> Backend {
+    let (sender, _receive) = mpsc::channel();
+    let ticks = world.lock().unwrap().to_be_ticked.clone();
+    Backend::new(
+        sender,
+        "direct".to_string(),
+        "0,0".to_string(),
+        None,
+        world,
+        bounds,
+        CompilerOptions::default(),
+        ticks,
+    )
+}
+
+fn generate_rs(path: &Path, out: &Path) -> Linker {
+    let co = sim_options();
+    let (sender, _receive) = mpsc::channel();
+    let (world, bounds) = load_world(path);
+    let ticks = world.to_be_ticked.clone();
+    let m_world = Mutex::new(world);
 
-    fn pending_tick_at(&mut self, pos: BlockPos) -> bool {
-        self.to_be_ticked.iter().any(|e| e.pos == pos)
+    // In sim test mode the fpga backend writes `<name>/roc.sv`
+    let mut b = Backend::new(
+        sender,
+        out.to_str().unwrap().to_string(),
+        "0,0".to_string(),
+        None,
+        &m_world,
+        bounds,
+        co.clone(),
+        ticks,
+    );
+
+    match b.backend() {
+        BackendDispatcher::FPGABackend(fpga) => {
+            fpga.link.clone()
+        }
+        _ => panic!("no linker in backend")
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,171 @@
+mod fpga;
 mod roc;
+mod worker;
+
+/// Simulation, direct backend, in-world, exported graph, netlist, yosys, formal model and differential tests per directory in `tests/roc/sim_tests`
+macro_rules! sim_tests {
+    ($($case:ident),* $(,)?) => {
+        paste::paste! {
+            $(
+                #[test]
+                fn [<sim_ $case>]() {
+                    roc::run_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<direct_ $case>]() {
+                    roc::run_direct_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<world_ $case>]() {
+                    roc::run_world_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<graph_ $case>]() {
+                    roc::run_graph_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<netlist_ $case>]() {
+                    roc::netlist_case(stringify!($case));
+                }
+
+                #[test]
+                #[ignore = "needs yosys"]
+                fn [<yosys_ $case>]() {
+                    roc::yosys_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<formal_ $case>]() {
+                    roc::formal_case(stringify!($case));
+                }
+
+                #[test]
+                fn [<diff_ $case>]() {
+                    roc::diff::diff_case(stringify!($case));
+                }
+            )*
+        }
+    };
+}
+
+// One `sim_tests!` entry per directory in `tests/roc/sim_tests`, written by build.rs
+include!(concat!(env!("OUT_DIR"), "/sim_tests.rs"));
+
+#[test]
+fn roc_record() {
+    roc::record();
+}
+
+#[test]
+fn roc_schematic_world() {
+    roc::schematic_world();
+}
+
+#[test]
+fn roc_graph_file_checks() {
+    roc::graph_file_checks();
+}
+
+#[test]
+fn roc_netlist_hex_lamp() {
+    roc::netlist_hex_lamp();
+}
+
+#[test]
+fn roc_graph_html() {
+    roc::graph_html();
+}
+
+#[test]
+fn roc_buses() {
+    roc::buses();
+}
+
+#[test]
+fn roc_annotations() {
+    roc::annotations();
+}
+
+#[test]
+fn roc_pipelines() {
+    roc::pipelines();
+}
+
+#[test]
+fn roc_compile_report() {
+    roc::compile_report();
+}
+
+#[test]
+fn roc_validate() {
+    roc::validate();
+}
+
+#[test]
+fn roc_same_tick_cycles() {
+    roc::same_tick_cycles();
+}
+
+#[test]
+fn roc_timing() {
+    roc::timing();
+}
+
+#[test]
+fn roc_collapse_repeaters() {
+    roc::collapse_repeaters();
+}
+
+#[test]
+fn roc_collapsed_blocks() {
+    roc::collapsed_blocks();
+}
+
+#[test]
+fn roc_minimise_logic() {
+    roc::minimise_logic();
+}
+
+#[test]
+fn roc_minimise_logic_world() {
+    roc::minimise_logic_world();
+}
+
+#[test]
+fn roc_observers() {
+    roc::observers();
+}
+
+#[test]
+fn worker_local_build() {
+    worker::local_build();
+}
+
+#[test]
+fn worker_rejects_escaping_paths() {
+    worker::rejects_escaping_paths();
+}
+
+#[test]
+fn worker_pool_queue() {
+    worker::pool_queue();
+}
+
+#[test]
+fn fpga_input_states() {
+    fpga::input_states();
+}
+
+#[test]
+fn fpga_reconnect_backoff() {
+    fpga::reconnect_backoff();
+}
 
 #[test]
-fn sim_test() {
-    roc::run_simulations();
-}
\ No newline at end of file
+fn fpga_programmer_config() {
+    fpga::programmer_config();
+}
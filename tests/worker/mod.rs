@@ -0,0 +1,80 @@
+use mchprs_backend::fpga::worker::{BuildBundle, BundleFile, Toolchain, Worker, WorkerConfig, WorkerPool};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+/// Starts a worker with the mock toolchain on a free local port
+fn start_local_worker(name: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let workdir = std::env::temp_dir().join(format!("roc_worker_{}", name));
+    let worker = Arc::new(Worker::new(Toolchain::Mock, workdir));
+    thread::spawn(move || worker.serve(listener));
+    address
+}
+
+fn test_bundle(name: &str) -> BuildBundle {
+    let mut bundle = BuildBundle::new(name, &format!("bin/0-0/{}/prj", name));
+    bundle.files.push(BundleFile {
+        path: format!("bin/0-0/{}/prj/prj.tcl", name),
+        data: format!("project_new {}\n", name).into_bytes(),
+    });
+    bundle.files.push(BundleFile {
+        path: format!("bin/0-0/{}/redstone.sv", name),
+        data: b"module RoC(); endmodule".to_vec(),
+    });
+    bundle
+}
+
+fn out_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("roc_worker_out_{}", name));
+    _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+pub fn local_build() {
+    let address = start_local_worker("local_build");
+    let pool = WorkerPool::new(vec![WorkerConfig { name: "local".to_string(), address }]);
+    let out = out_dir("local_build");
+
+    let mut logs = Vec::new();
+    pool.build(&test_bundle("local_build"), &out, &mut |line| logs.push(line.to_string()))
+        .unwrap();
+
+    assert!(!logs.is_empty(), "worker did not stream any logs");
+    let sof = std::fs::read(out.join("RoC.sof")).unwrap();
+    assert!(sof.ends_with(b"project_new local_build\n"));
+    assert!(out.join("RoC.fit.summary").exists());
+}
+
+pub fn rejects_escaping_paths() {
+    let address = start_local_worker("escaping_paths");
+    let pool = WorkerPool::new(vec![WorkerConfig { name: "local".to_string(), address }]);
+
+    let mut bundle = test_bundle("escaping_paths");
+    bundle.files.push(BundleFile { path: "../outside.txt".to_string(), data: Vec::new() });
+    let res = pool.build(&bundle, &out_dir("escaping_paths"), &mut |_| {});
+    assert!(res.is_err());
+}
+
+pub fn pool_queue() {
+    let address = start_local_worker("pool_queue");
+    let pool = Arc::new(WorkerPool::new(vec![WorkerConfig { name: "local".to_string(), address }]));
+
+    // More builds than workers, the rest have to wait for their turn
+    let builds: Vec<_> = (0..3)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                let name = format!("pool_queue{}", i);
+                let out = out_dir(&name);
+                pool.build(&test_bundle(&name), &out, &mut |_| {}).unwrap();
+                out.join("RoC.sof").exists()
+            })
+        })
+        .collect();
+    for build in builds {
+        assert!(build.join().unwrap());
+    }
+}